@@ -0,0 +1,63 @@
+use std::hint::black_box;
+use std::io::{self, Write};
+use bet365::disassembler::disassembler::{Disassembler, DEFAULT_STRING_KEY};
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion};
+
+/// Rounds of NEW VALUE 'document' -> reg1; GET PROPERTY reg0[reg1] -> reg2; LOAD IMM8 7 -> reg3;
+/// MOV IMM32 0x01020304 -> reg4; ADD reg3 + reg4 -> reg5; CALL FUNCTION reg2(reg1, reg3, reg5) -> reg6,
+/// then HALT. Straight-line code, so both walks visit every byte once.
+fn program(rounds: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for _ in 0..rounds {
+        bytes.extend([23, 1, 0, 8]);
+        bytes.extend(b"document".iter().map(|byte| byte ^ DEFAULT_STRING_KEY));
+        bytes.extend([251, 2, 0, 1, 181, 3, 7, 241, 4, 1, 2, 3, 4, 243, 5, 3, 4, 215, 6, 2, 3, 1, 3, 5]);
+    }
+    bytes.push(166);
+    bytes
+}
+
+fn measure(c: &mut Criterion) {
+    let bytes: Vec<u8> = program(10_000);
+    let disasm: Disassembler = Disassembler::from_bytes(bytes.clone());
+
+    let mut group: BenchmarkGroup<WallTime> = c.benchmark_group("walk");
+    group.bench_function("measure", |b| {
+        b.iter(|| {
+            let mut offset: usize = 0;
+            while offset < bytes.len() {
+                offset += disasm.measure(black_box(offset)).unwrap();
+            }
+            offset
+        })
+    });
+    group.bench_function("execute", |b| {
+        b.iter_batched(|| Disassembler::from_bytes(bytes.clone()), |mut disasm| disasm.execute(|_| Ok(())).unwrap().instruction_count, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+/// The cost of emitting each decoded line with its offset, as the binary prints it, over building the listing alone.
+fn emit(c: &mut Criterion) {
+    let bytes: Vec<u8> = program(10_000);
+
+    let mut group: BenchmarkGroup<WallTime> = c.benchmark_group("emit");
+    group.bench_function("discard", |b| {
+        b.iter_batched(|| Disassembler::from_bytes(bytes.clone()), |mut disasm| disasm.execute(|_| Ok(())).unwrap().instruction_count, BatchSize::LargeInput)
+    });
+    group.bench_function("write", |b| {
+        b.iter_batched(
+            || Disassembler::from_bytes(bytes.clone()),
+            |mut disasm| {
+                let mut out: io::Sink = io::sink();
+                disasm.execute(|line| writeln!(out, "{:#06x}    {}", line.start, line.text)).unwrap().instruction_count
+            },
+            BatchSize::LargeInput
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, measure, emit);
+criterion_main!(benches);
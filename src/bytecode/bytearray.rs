@@ -1,6 +1,180 @@
-use base64::{engine::general_purpose, Engine as _};
+use std::error::Error;
+use std::fmt;
+use base64::{engine::general_purpose, DecodeError, Engine as _};
+use log::debug;
 
-pub fn decode_bytecode(mut b64string: String) -> Vec<u8> {
-    b64string.retain(|c| !c.is_whitespace());
-    general_purpose::STANDARD.decode(b64string).unwrap()
-}
\ No newline at end of file
+const SNIPPET_RADIUS: usize = 12;
+
+#[derive(Debug)]
+pub struct BytecodeError {
+    /// Byte offset into the input of the character the decoder rejected, if it named one.
+    pub position: Option<usize>,
+    /// The input around `position` (or its tail when there is none), escaped.
+    pub snippet: String,
+    pub source: DecodeError
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.position, &self.source) {
+            (Some(position), DecodeError::InvalidByte(_, byte) | DecodeError::InvalidLastSymbol(_, byte)) => {
+                write!(f, "invalid base64 at offset {position}: unexpected '{}' near '{}'", (*byte as char).escape_debug(), self.snippet)
+            }
+            _ => write!(f, "invalid base64: {} (input ends with '{}')", self.source, self.snippet)
+        }
+    }
+}
+
+impl Error for BytecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+pub fn strip_wrappers(input: &str) -> (usize, &str) {
+    let trimmed: &str = input.trim_start();
+    let mut start: usize = input.len() - trimmed.len();
+    let mut inner: &str = trimmed.trim_end();
+    let separated: &str = inner.trim_end_matches([',', ';']).trim_end();
+    if separated.len() < inner.len() {
+        debug!("stripped trailing '{}'", &inner[separated.len()..].trim());
+        inner = separated;
+    }
+
+    for quote in ['"', '\'', '`'] {
+        if let Some(unquoted) = inner.strip_prefix(quote).and_then(|inner| inner.strip_suffix(quote)) {
+            debug!("stripped surrounding {quote} quotes");
+            start += quote.len_utf8();
+            inner = unquoted;
+            break;
+        }
+    }
+    if inner.starts_with("data:") {
+        // A bare prefix has lost its comma to the trim above, so it has no payload at all.
+        let skipped: usize = match inner.find(";base64,") {
+            Some(comma) => comma + ";base64,".len(),
+            None if !inner.contains(',') => inner.len(),
+            None => 0
+        };
+        if skipped > 0 {
+            debug!("stripped data URI prefix '{}'", &inner[..skipped]);
+        }
+        start += skipped;
+        inner = &inner[skipped..];
+    }
+    (start, inner)
+}
+
+/// Drops whitespace and resolves the JSON escapes `\n`, `\r`, `\t`, `\/` and `\\`, returning the
+/// base64 text and, for each of its characters, the byte offset it came from in the original input.
+fn compact(inner: &str, start: usize) -> (String, Vec<usize>) {
+    let mut compact: String = String::with_capacity(inner.len());
+    let mut positions: Vec<usize> = Vec::with_capacity(inner.len());
+    let (mut escapes, mut whitespace): (usize, usize) = (0, 0);
+
+    let mut chars = inner.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let c: char = match (c, chars.peek()) {
+            ('\\', Some(&(_, escaped @ ('n' | 'r' | 't' | '/' | '\\')))) => {
+                chars.next();
+                escapes += 1;
+                match escaped {
+                    'n' | 'r' | 't' => continue,
+                    escaped => escaped
+                }
+            }
+            (c, _) if c.is_whitespace() => {
+                whitespace += 1;
+                continue;
+            }
+            (c, _) => c
+        };
+        compact.push(c);
+        positions.push(start + index);
+    }
+
+    if escapes > 0 {
+        debug!("resolved {escapes} JSON escapes");
+    }
+    if whitespace > 0 {
+        debug!("removed {whitespace} whitespace characters");
+    }
+    (compact, positions)
+}
+
+fn snippet(input: &str, from: usize, to: usize) -> String {
+    let from: usize = (0..=from).rev().find(|&index| input.is_char_boundary(index)).unwrap_or(0);
+    let to: usize = (to.min(input.len())..=input.len()).find(|&index| input.is_char_boundary(index)).unwrap_or(input.len());
+    input[from..to].escape_debug().to_string()
+}
+
+fn locate(input: &str, positions: &[usize], source: DecodeError) -> BytecodeError {
+    let position: Option<usize> = match source {
+        DecodeError::InvalidByte(offset, _) | DecodeError::InvalidLastSymbol(offset, _) => positions.get(offset).copied(),
+        _ => None
+    };
+
+    let snippet: String = match position {
+        Some(position) => snippet(input, position.saturating_sub(SNIPPET_RADIUS), position + SNIPPET_RADIUS),
+        None => snippet(input.trim_end(), input.trim_end().len().saturating_sub(2 * SNIPPET_RADIUS), input.len())
+    };
+    BytecodeError { position, snippet, source }
+}
+
+pub fn decode_bytecode(b64string: impl AsRef<str>) -> Result<Vec<u8>, BytecodeError> {
+    let input: &str = b64string.as_ref();
+    let (start, inner) = strip_wrappers(input);
+    let (compact, positions) = compact(inner, start);
+    general_purpose::STANDARD.decode(compact).map_err(|source| locate(input, &positions, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_each_wrapper_alone() {
+        assert_eq!(strip_wrappers("  AQID \n"), (2, "AQID"));
+        assert_eq!(strip_wrappers("\"AQID\""), (1, "AQID"));
+        assert_eq!(strip_wrappers("'AQID'"), (1, "AQID"));
+        assert_eq!(strip_wrappers("`AQID`"), (1, "AQID"));
+        assert_eq!(strip_wrappers("data:application/octet-stream;base64,AQID"), (37, "AQID"));
+        assert_eq!(strip_wrappers("AQID,"), (0, "AQID"));
+        assert_eq!(strip_wrappers("AQID ;"), (0, "AQID"));
+        // A data URI prefix with nothing after it.
+        assert_eq!(strip_wrappers("data:application/octet-stream;base64,"), (36, ""));
+    }
+
+    #[test]
+    fn strips_wrappers_in_combination() {
+        assert_eq!(strip_wrappers(" \"data:;base64,AQID\", "), (15, "AQID"));
+        assert_eq!(decode_bytecode(" \"data:;base64,AQ\\nID\\/\\/\\/\\/\",\n").unwrap(), [1, 2, 3, 255, 255, 255]);
+    }
+
+    #[test]
+    fn compact_resolves_escapes_and_keeps_input_positions() {
+        assert_eq!(compact("AQ\\/I D\\n\\\\", 5), ("AQ/ID\\".to_string(), vec![5, 6, 7, 9, 11, 14]));
+        // An unknown escape is left as it is and rejected by the decoder.
+        assert_eq!(compact("\\u", 0).0, "\\u");
+    }
+
+    #[test]
+    fn errors_point_at_the_rejected_character_in_the_input() {
+        let err: BytecodeError = decode_bytecode("AQID#AAA").unwrap_err();
+        assert_eq!((err.position, err.snippet.as_str()), (Some(4), "AQID#AAA"));
+        assert_eq!(err.to_string(), "invalid base64 at offset 4: unexpected '#' near 'AQID#AAA'");
+
+        // The quote and the escaped newline before the '#' still count.
+        let err: BytecodeError = decode_bytecode("\"AQ\\nID#A\"").unwrap_err();
+        assert_eq!(err.position, Some(7));
+    }
+
+    #[test]
+    fn errors_without_a_position_show_the_end_of_the_input() {
+        let long: String = "AQID".repeat(10) + "AQ=";
+        let err: BytecodeError = decode_bytecode(&long).unwrap_err();
+        assert_eq!(err.position, None);
+        assert_eq!(err.snippet, &long[long.len() - 24..]);
+        assert!(err.to_string().ends_with(&format!("(input ends with '{}')", err.snippet)));
+    }
+}
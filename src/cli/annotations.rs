@@ -0,0 +1,35 @@
+use std::fs;
+use crate::cli::args::{parse_offset, Args};
+use crate::cli::error::CliError;
+use bet365::disassembler::disassembler::Disassembler;
+
+pub fn load(path: &str) -> Result<Vec<(usize, String)>, CliError> {
+    let source: String = fs::read_to_string(path)
+        .map_err(|err| CliError::Input(format!("could not read annotations '{path}': {err}")))?;
+
+    let mut annotations: Vec<(usize, String)> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((offset, comment)) = line.split_once('\t') else {
+            return Err(CliError::Input(format!("{path}:{}: expected 'OFFSET<TAB>comment'", index + 1)));
+        };
+        let offset: usize = parse_offset("annotation", offset.trim()).map_err(|err| CliError::Input(format!("{path}:{}: {err}", index + 1)))?;
+        annotations.push((offset, comment.trim().to_string()));
+    }
+    Ok(annotations)
+}
+
+pub fn check(args: &Args, disasm: &Disassembler) {
+    let Some(path) = &args.annotations else {
+        return;
+    };
+    for (offset, nearest) in disasm.unmatched_annotations() {
+        match nearest {
+            Some(nearest) => eprintln!("warning: {path}: offset {offset:#06x} is inside an instruction; the nearest instruction boundary is {nearest:#06x}"),
+            None => eprintln!("warning: {path}: offset {offset:#06x} was not reached by the disassembly")
+        }
+    }
+}
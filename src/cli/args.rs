@@ -0,0 +1,676 @@
+use std::env;
+use crate::cli::config::{self, DEFAULT_CONFIG_PATH};
+use crate::cli::format::Format;
+use crate::cli::input::Input;
+use bet365::disassembler::disassembler::{Resync, DEFAULT_MAX_VISITED, DEFAULT_STRING_KEY};
+use bet365::disassembler::emulator::DEFAULT_MAX_STEPS;
+use bet365::disassembler::expressions::DEFAULT_MAX_DEPTH;
+use bet365::disassembler::instructions::DEFAULT_PROFILE;
+use bet365::disassembler::profiles::{DETECT_WINDOW, PROFILES};
+use bet365::disassembler::registers::TrackingMode;
+
+pub const DEFAULT_BYTECODE_PATH: &str = "src/bytecode/bytecode.txt";
+/// Unchanged instructions `diff` shows around each change.
+pub const DEFAULT_DIFF_CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Disasm,
+    Strings,
+    Stats,
+    Cfg,
+    Callgraph,
+    Apis,
+    Grep,
+    Diff,
+    InferMap
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "disasm" => Some(Command::Disasm),
+            "strings" => Some(Command::Strings),
+            "stats" => Some(Command::Stats),
+            "cfg" => Some(Command::Cfg),
+            "callgraph" => Some(Command::Callgraph),
+            "apis" => Some(Command::Apis),
+            "grep" => Some(Command::Grep),
+            "diff" => Some(Command::Diff),
+            "infer-map" => Some(Command::InferMap),
+            _ => None
+        }
+    }
+}
+
+/// Registers to cross-reference with `--xref`.
+#[derive(Clone, PartialEq, Eq)]
+pub enum XrefQuery {
+    All,
+    Registers(Vec<u8>)
+}
+
+pub struct Args {
+    pub command: Command,
+    pub inputs: Vec<String>,
+    pub pattern: Option<String>,
+    pub regex: bool,
+    pub context: usize,
+    pub min_length: usize,
+    pub roots: bool,
+    pub uses: bool,
+    pub output: Option<String>,
+    pub quiet: bool,
+    pub no_color: bool,
+    pub start: usize,
+    pub end: Option<usize>,
+    pub max_instructions: Option<usize>,
+    pub follow_jumps: bool,
+    pub max_visited: usize,
+    pub functions: Vec<usize>,
+    pub function_bytes: Option<usize>,
+    pub lenient: bool,
+    pub resync: Option<Resync>,
+    pub string_key: u8,
+    pub opcode_map: Option<String>,
+    pub profile: Option<String>,
+    pub profile_maps: Vec<String>,
+    pub annotations: Option<String>,
+    pub bytes: bool,
+    pub no_addresses: bool,
+    pub legacy_addresses: bool,
+    pub raw_strings: bool,
+    pub labels: bool,
+    /// Maximum depth of collapsed expressions, when collapsing.
+    pub collapse: Option<usize>,
+    pub liveness: bool,
+    pub loops: bool,
+    pub try_regions: bool,
+    pub indent_frames: bool,
+    pub rename: bool,
+    pub rename_map: Option<String>,
+    /// Step limit of the emulator, when emulating.
+    pub emulate: Option<usize>,
+    pub sections: bool,
+    pub show_unreachable: bool,
+    pub xref: Option<XrefQuery>,
+    pub registers: bool,
+    pub tracking: TrackingMode,
+    pub naive_tracking: bool,
+    pub fold_constants: bool,
+    pub time: bool,
+    pub format: Format,
+    pub progress: bool,
+    pub watch: bool,
+    pub interactive: bool,
+    pub help: bool
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut cli: Vec<String> = env::args().skip(1).collect();
+        let args: Args = Self::parse_from(cli.clone().into_iter())?;
+
+        let defaults: Vec<String> = config::defaults(&cli, !args.pipe_mode())?;
+        if defaults.is_empty() {
+            return Ok(args);
+        }
+        let at: usize = usize::from(cli.first().is_some_and(|first| Command::from_name(first).is_some()));
+        cli.splice(at..at, defaults);
+        Self::parse_from(cli.into_iter())
+    }
+
+    pub fn resolve_inputs(&self) -> Vec<Input> {
+        if self.inputs.is_empty() {
+            return vec![Input::resolve(None, DEFAULT_BYTECODE_PATH)];
+        }
+        self.inputs.iter().map(|path| Input::resolve(Some(path), DEFAULT_BYTECODE_PATH)).collect()
+    }
+
+    pub fn pipe_mode(&self) -> bool {
+        self.output.is_none() && matches!(self.resolve_inputs().as_slice(), [Input::Stdin])
+    }
+
+    pub fn parse_from<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let command: Command = match args.peek().and_then(|first| Command::from_name(first)) {
+            Some(command) => {
+                args.next();
+                command
+            }
+            None => Command::Disasm
+        };
+
+        let mut inputs: Vec<String> = Vec::new();
+        let mut regex: bool = false;
+        let mut context: Option<usize> = None;
+        let mut min_length: usize = 0;
+        let mut roots: bool = false;
+        let mut uses: bool = false;
+        let mut output: Option<String> = None;
+        let mut quiet: bool = false;
+        let mut no_color: bool = false;
+        let mut start: usize = 0;
+        let mut end: Option<usize> = None;
+        let mut max_instructions: Option<usize> = None;
+        let mut follow_jumps: bool = false;
+        let mut max_visited: usize = DEFAULT_MAX_VISITED;
+        let mut functions: Vec<usize> = Vec::new();
+        let mut function_bytes: Option<usize> = None;
+        let mut lenient: bool = false;
+        let mut resync: Option<Resync> = None;
+        let mut string_key: u8 = DEFAULT_STRING_KEY;
+        let mut opcode_map: Option<String> = None;
+        let mut profile: Option<String> = None;
+        let mut profile_maps: Vec<String> = Vec::new();
+        let mut annotations: Option<String> = None;
+        let mut bytes: bool = false;
+        let mut no_addresses: bool = false;
+        let mut legacy_addresses: bool = false;
+        let mut raw_strings: bool = false;
+        let mut labels: bool = false;
+        let mut collapse: Option<usize> = None;
+        let mut liveness: bool = false;
+        let mut loops: bool = false;
+        let mut try_regions: bool = false;
+        let mut indent_frames: bool = false;
+        let mut rename: bool = false;
+        let mut rename_map: Option<String> = None;
+        let mut emulate: Option<usize> = None;
+        let mut sections: bool = false;
+        let mut show_unreachable: bool = false;
+        let mut xref: Option<XrefQuery> = None;
+        let mut registers: bool = false;
+        let mut time: bool = false;
+        let mut format: Format = Format::Text;
+        let mut tracking: Option<TrackingMode> = None;
+        let mut naive_tracking: bool = false;
+        let mut fold_constants: bool = false;
+        let mut progress: bool = false;
+        let mut watch: bool = false;
+        let mut interactive: bool = false;
+        let mut help: bool = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => help = true,
+                "-o" | "--output" => output = Some(Self::value(&arg, &mut args)?),
+                "-q" | "--quiet" => quiet = true,
+                "--no-color" => no_color = true,
+                "--start" | "--entry" => start = parse_offset(&arg, &Self::value(&arg, &mut args)?)?,
+                "--end" => end = Some(parse_offset(&arg, &Self::value(&arg, &mut args)?)?),
+                "--follow-jumps" => follow_jumps = true,
+                "--max-visited" => {
+                    max_visited = parse_count(&arg, &Self::value(&arg, &mut args)?)?;
+                    if max_visited == 0 {
+                        return Err("--max-visited must be at least 1".to_string());
+                    }
+                }
+                "--function" => functions.push(parse_offset(&arg, &Self::value(&arg, &mut args)?)?),
+                "--function-bytes" => function_bytes = Some(parse_count(&arg, &Self::value(&arg, &mut args)?)?),
+                "--lenient" => lenient = true,
+                "--fail-fast" => lenient = false,
+                "--resync" => {
+                    resync.get_or_insert_default();
+                }
+                "--resync-window" => {
+                    let window: usize = parse_count(&arg, &Self::value(&arg, &mut args)?)?;
+                    if window == 0 {
+                        return Err("--resync-window must be at least 1".to_string());
+                    }
+                    resync.get_or_insert_default().window = window;
+                }
+                "--resync-limit" => resync.get_or_insert_default().scan_limit = parse_count(&arg, &Self::value(&arg, &mut args)?)?,
+                "--config" => {
+                    Self::value(&arg, &mut args)?;
+                }
+                "--string-key" => string_key = parse_byte(&arg, &Self::value(&arg, &mut args)?)?,
+                "--opcode-map" => opcode_map = Some(Self::value(&arg, &mut args)?),
+                "--profile" => profile = Some(Self::value(&arg, &mut args)?),
+                "--profile-map" => profile_maps.push(Self::value(&arg, &mut args)?),
+                "--annotations" => annotations = Some(Self::value(&arg, &mut args)?),
+                "-b" | "--bytes" => bytes = true,
+                "--no-addresses" => no_addresses = true,
+                "--legacy-addresses" => legacy_addresses = true,
+                "--raw-strings" => raw_strings = true,
+                "--labels" => labels = true,
+                "--liveness" => liveness = true,
+                "--loops" => loops = true,
+                "--try-regions" => try_regions = true,
+                "--indent-frames" => indent_frames = true,
+                "--rename" => rename = true,
+                "--rename-map" => {
+                    rename_map = Some(Self::value(&arg, &mut args)?);
+                    rename = true;
+                }
+                "--emulate" => emulate = emulate.or(Some(DEFAULT_MAX_STEPS)),
+                "--emulate-steps" => emulate = Some(parse_count(&arg, &Self::value(&arg, &mut args)?)?),
+                "--collapse" => collapse = collapse.or(Some(DEFAULT_MAX_DEPTH)),
+                "--collapse-depth" => {
+                    let depth: usize = parse_count(&arg, &Self::value(&arg, &mut args)?)?;
+                    if depth == 0 {
+                        return Err("--collapse-depth must be at least 1".to_string());
+                    }
+                    collapse = Some(depth);
+                }
+                "--sections" => sections = true,
+                "--show-unreachable" => show_unreachable = true,
+                "--xref" => {
+                    let value: String = Self::value(&arg, &mut args)?;
+                    match (value.as_str(), &mut xref) {
+                        ("all", _) => xref = Some(XrefQuery::All),
+                        (_, Some(XrefQuery::All)) => {}
+                        (reg, _) => {
+                            let reg: u8 = parse_byte(&arg, reg.strip_prefix("reg").unwrap_or(reg))?;
+                            match &mut xref {
+                                Some(XrefQuery::Registers(regs)) => regs.push(reg),
+                                _ => xref = Some(XrefQuery::Registers(vec![reg]))
+                            }
+                        }
+                    }
+                }
+                "-r" | "--registers" => registers = true,
+                "--naive-tracking" => naive_tracking = true,
+                "--fold-constants" => fold_constants = true,
+                "--time" => time = true,
+                "--track-registers" => {
+                    let name: String = Self::value(&arg, &mut args)?;
+                    tracking = Some(TrackingMode::from_name(&name).ok_or_else(|| format!("unknown register tracking mode '{name}' (expected off, strings or constants)"))?);
+                }
+                "-f" | "--format" => {
+                    let name: String = Self::value(&arg, &mut args)?;
+                    format = Format::from_name(&name).ok_or_else(|| format!("unknown format '{name}' (expected text, json or csv)"))?;
+                }
+                "-E" | "--regex" => regex = true,
+                "-C" | "--context" => context = Some(parse_count(&arg, &Self::value(&arg, &mut args)?)?),
+                "--min-length" => min_length = parse_count(&arg, &Self::value(&arg, &mut args)?)?,
+                "--roots" => roots = true,
+                "--uses" => uses = true,
+                "--progress" => progress = true,
+                "-w" | "--watch" => watch = true,
+                "-i" | "--interactive" => interactive = true,
+                "-n" | "--max-instructions" => max_instructions = Some(parse_count(&arg, &Self::value(&arg, &mut args)?)?),
+                _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{arg}'")),
+                _ => inputs.push(arg)
+            }
+        }
+
+        let pattern: Option<String> = if command == Command::Grep {
+            if inputs.is_empty() {
+                return Err("grep requires a PATTERN".to_string());
+            }
+            Some(inputs.remove(0))
+        } else {
+            None
+        };
+
+        if profile.is_some() && opcode_map.is_some() {
+            return Err("--profile cannot be combined with --opcode-map".to_string());
+        }
+        if profile.is_none() && !profile_maps.is_empty() {
+            return Err("--profile-map requires --profile".to_string());
+        }
+        if command == Command::Diff {
+            if inputs.len() != 2 {
+                return Err("diff requires two inputs, OLD and NEW".to_string());
+            }
+            if watch || interactive {
+                return Err("diff cannot be combined with --watch or --interactive".to_string());
+            }
+            lenient = true;
+        }
+        if command == Command::InferMap {
+            if inputs.len() != 2 {
+                return Err("infer-map requires two inputs, REFERENCE and UNKNOWN".to_string());
+            }
+            if watch || interactive {
+                return Err("infer-map cannot be combined with --watch or --interactive".to_string());
+            }
+            lenient = true;
+        }
+        if inputs.iter().filter(|input| input.as_str() == "-").count() > 1 {
+            return Err("stdin ('-') can only be given once".to_string());
+        }
+        if watch && inputs.len() > 1 {
+            return Err("--watch only supports a single input".to_string());
+        }
+        if follow_jumps && !functions.is_empty() {
+            return Err("--follow-jumps cannot be combined with --function".to_string());
+        }
+        if xref.is_some() && (follow_jumps || !functions.is_empty()) {
+            return Err("--xref cannot be combined with --follow-jumps or --function".to_string());
+        }
+        if show_unreachable && (follow_jumps || !functions.is_empty()) {
+            return Err("--show-unreachable cannot be combined with --follow-jumps or --function".to_string());
+        }
+        if sections && !functions.is_empty() {
+            return Err("--sections cannot be combined with --function".to_string());
+        }
+        let tracking: TrackingMode = match (tracking, fold_constants) {
+            (None, true) => TrackingMode::Constants,
+            (Some(mode), true) if mode != TrackingMode::Constants => return Err("--fold-constants needs --track-registers constants".to_string()),
+            (mode, _) => mode.unwrap_or(TrackingMode::Strings)
+        };
+        if interactive && (watch || inputs.len() > 1) {
+            return Err("--interactive only supports a single input and cannot be combined with --watch".to_string());
+        }
+
+        Ok(Self {
+            command,
+            inputs,
+            pattern,
+            regex,
+            context: context.unwrap_or(if command == Command::Diff { DEFAULT_DIFF_CONTEXT } else { 0 }),
+            min_length,
+            roots,
+            uses,
+            output,
+            quiet,
+            no_color,
+            start,
+            end,
+            max_instructions,
+            follow_jumps,
+            max_visited,
+            functions,
+            function_bytes,
+            lenient,
+            resync,
+            string_key,
+            opcode_map,
+            profile,
+            profile_maps,
+            annotations,
+            bytes,
+            no_addresses,
+            legacy_addresses,
+            raw_strings,
+            labels,
+            collapse,
+            liveness,
+            loops,
+            try_regions,
+            indent_frames,
+            rename,
+            rename_map,
+            emulate,
+            sections,
+            show_unreachable,
+            xref,
+            registers,
+            tracking,
+            naive_tracking,
+            fold_constants,
+            time,
+            format,
+            progress,
+            watch,
+            interactive,
+            help
+        })
+    }
+
+    fn value<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<String, String> {
+        args.next().ok_or_else(|| format!("option '{flag}' requires a value"))
+    }
+}
+
+pub fn parse_offset(flag: &str, value: &str) -> Result<usize, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse::<usize>()
+    };
+    parsed.map_err(|_| format!("invalid offset '{value}' for '{flag}' (expected decimal or 0x hex)"))
+}
+
+pub fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|_| format!("invalid count '{value}' for '{flag}'"))
+}
+
+pub fn parse_byte(flag: &str, value: &str) -> Result<u8, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse::<u8>()
+    };
+    parsed.map_err(|_| format!("invalid byte '{value}' for '{flag}' (expected 0-255 or 0x hex)"))
+}
+
+pub fn usage() -> String {
+    let Resync { window, scan_limit, .. } = Resync::default();
+    let profiles: String = std::iter::once(DEFAULT_PROFILE).chain(PROFILES.iter().map(|profile| profile.name)).collect::<Vec<&str>>().join(", ");
+    format!("\
+bet365 VM bytecode disassembler
+
+Usage: bet365 [COMMAND] [OPTIONS] [INPUT]...
+
+Commands:
+  disasm        Print the full disassembly listing (default)
+  strings       List the distinct string constants loaded by NEW VALUE with
+                their first offset and occurrence count (json also lists
+                every definition and use), and the strings loops build
+                by appending characters, marked derived
+  stats         Print opcode frequency counts, operand bytes and coverage;
+                unknown opcode bytes are counted and skipped (text or json)
+  cfg           Print control-flow instructions and their successors
+  callgraph     Print the calls between functions as a DOT digraph (or a
+                json adjacency list), including calls to globals by name
+  apis          Report reads, writes and calls of browser APIs (navigator,
+                screen, canvas, WebGL, storage, ...) by category, following
+                property chains such as navigator.userAgent, with the
+                offsets of each access; * marks property names recovered
+                by emulation rather than tracked (text or json)
+  grep PATTERN  Print only instructions whose text contains PATTERN
+  diff OLD NEW  Compare two bytecode files instruction by instruction, with
+                functions paired by their strings and sizes and targets
+                named rather than numbered, then summarize the instructions
+                and strings added, removed and changed (text or json;
+                always --lenient)
+  infer-map REFERENCE UNKNOWN
+                Infer the opcode bytes of a build whose opcodes were
+                reshuffled: find the operand layout of each byte that lets
+                UNKNOWN decode cleanly between its string loads, then tell
+                handlers with the same operands apart by how often and where
+                REFERENCE (decoded with the current table) uses them.
+                Writes an --opcode-map file with a confidence per opcode;
+                uncertain entries are commented out (json writes a report
+                instead)
+
+Arguments:
+  [INPUT]...    Paths to files containing base64 encoded bytecode, or '-' to
+                read it from stdin. When omitted, stdin is used if it is not a
+                terminal, otherwise {DEFAULT_BYTECODE_PATH}.
+                Multiple inputs are disassembled in turn. When reading stdin
+                without --output, no file is read or written unless an
+                option such as --config or --opcode-map names it
+
+Options:
+  -o, --output <PATH>  Write the listing to PATH instead of stdout (the timing
+                       summary always goes to stderr). With several inputs, each
+                       one is written to PATH with the input name inserted
+                       before the extension (out.lst -> out.<input>.lst)
+  -q, --quiet          Do not print the listing to the console, only the
+                       instruction count and timing summary
+      --no-color       Disable colored output (also disabled by setting NO_COLOR
+                       or when stdout is not a terminal)
+      --start, --entry <OFFSET>
+                       Begin disassembly at byte OFFSET (decimal or 0x hex),
+                       e.g. to skip a header or constant pool before the code
+      --end <OFFSET>   Stop disassembly at byte OFFSET (decimal or 0x hex)
+  -n, --max-instructions <N>
+                       Stop after N instructions have been decoded
+      --follow-jumps   Only decode code reachable from the start offset by
+                       following jump, call, function and try/catch targets;
+                       bytes never reached are shown as data regions
+      --max-visited <N>
+                       Stop --follow-jumps after visiting N instruction offsets
+                       and print what was decoded (default {DEFAULT_MAX_VISITED})
+      --function <ENTRY>
+                       Disassemble only the function starting at byte ENTRY
+                       (as printed by NEW FUNCTION / JUMP FRAME), stopping at
+                       its RET or HALT. May be given several times
+      --function-bytes <N>
+                       Stop each --function after at most N bytes
+      --fail-fast      Stop at the first unknown opcode (default; overrides an
+                       earlier --lenient)
+      --lenient        Print unknown opcode bytes as DB lines and keep decoding
+                       at the next byte
+      --resync         With --lenient, skip an unknown opcode and the junk after
+                       it as one DB region, resuming where several instructions
+                       in a row decode cleanly with plausible registers and
+                       targets; falls back to the next byte if none is found
+      --resync-window <N>
+                       Instructions that must decode in a row to resync
+                       (default {window}; implies --resync)
+      --resync-limit <BYTES>
+                       How far past the unknown byte to search (default
+                       {scan_limit}; implies --resync)
+      --string-key <KEY>
+                       XOR key used to decode string constants (default
+                       {DEFAULT_STRING_KEY}); varies between VM builds
+      --opcode-map <PATH>
+                       Build the opcode table from PATH instead of the built-in
+                       one. PATH is a JSON object ({{\"243\": \"add\", ...}}) or
+                       TOML lines (243 = \"add\") mapping opcode bytes to handler
+                       names; unmapped bytes are reported as unknown opcodes
+      --profile <NAME>
+                       Decode with the named opcode table: a built-in profile
+                       ({profiles}) or one from --profile-map. 'auto' tries each
+                       on the first {DETECT_WINDOW} bytes from the start offset and picks
+                       the one that decodes most cleanly
+      --profile-map <PATH>
+                       Add the opcode map at PATH to the profiles --profile
+                       chooses from, named by its 'name' key or file name (may
+                       be repeated)
+      --annotations <PATH>
+                       Append comments to the listing from PATH, a file of
+                       'OFFSET<TAB>comment' lines keyed by instruction start
+  -b, --bytes          Show the raw bytes of each instruction next to it
+      --no-addresses   Omit the offset column and print branch targets relative
+                       to the instruction (.+N / .-N) for diff-friendly output
+      --legacy-addresses
+                       Print the old '0x<decimal end offset>' column and
+                       decimal branch targets instead of hex start offsets
+      --raw-strings    Print decoded strings as-is instead of escaping quotes,
+                       backslashes and non-printable bytes (\\n, \\t, \\xNN)
+      --labels         Name every jump, call, function and try/catch target
+                       (L_, func_, catch_, finally_ plus the hex offset), print
+                       'name:' before the target and 'name (target)' in the
+                       operands; json gets a labels array
+      --collapse       Substitute temporaries that are written and read once
+                       in a basic block into the instruction reading them,
+                       and show the result as a comment, e.g.
+                       '; reg12 = reg2[\"navigator\"][\"userAgent\"](reg5)'
+      --collapse-depth <N>
+                       Nest at most N collapsed instructions in one
+                       expression (default 4); implies --collapse
+      --liveness       After each instruction, show the registers whose value
+                       may still be read, e.g. '; live {{reg10, reg34}}'
+      --loops          Mark loop headers ('; loop L3 header') and back-edges
+                       ('; back-edge to L3'), and print the loops, their
+                       nesting and body sizes; irreducible cycles are
+                       reported separately
+      --try-regions    Bracket each TRY CATCH: '; try {{' after it, then
+                       '; }} catch (regN) {{', '; }} finally {{' and '; }} // end try'
+                       at its offsets, indented by nesting
+      --indent-frames  Indent each instruction two spaces per JUMP FRAME
+                       decoded before it and not yet closed by a RET; warns
+                       about a RET with no frame open and about frames still
+                       open at HALT. json instructions get a 'depth'
+      --rename         Name registers per function: 'win', 'doc', ... for one
+                       loaded from a well-known global, 'i', 'j', ... for loop
+                       counters, 't0', 't1', ... for ones written once; the
+                       rest stay 'regN'. Applies to the listing, --collapse,
+                       --liveness and --try-regions; json reg operands get a
+                       'name' next to the register number
+      --rename-map <PATH>
+                       Pin names from PATH, a file of 'FUNCTION REG NAME'
+                       lines; FUNCTION is an entry offset, '*' for everywhere
+                       or 'outside' for code outside functions. Implies
+                       --rename
+      --emulate        Run the code from its first instruction with concrete
+                       values and show what each instruction computed, e.g.
+                       '; = 'ns_gen5'' or '; taken'; stops at a branch on a
+                       value it cannot know, and at RET, HALT or TRY CATCH
+      --emulate-steps <N>
+                       Stop emulating after N instructions (default 100000);
+                       implies --emulate
+      --xref <REG>     Instead of the listing, print every instruction that
+                       writes (def, or modify for SET PROPERTY's object) or
+                       reads (use) register REG (14 or reg14). May be given
+                       several times; 'all' lists every register
+      --sections       Split the listing into one section per function entry
+                       named by NEW FUNCTION / JUMP FRAME, each running to its
+                       RET or HALT or the next entry, with a header line; json
+                       gets a functions array
+      --show-unreachable
+                       Mark instructions that no path from the start or a
+                       function entry reaches, and print the dead byte count
+                       and largest dead region; json gets an unreachable array
+  -r, --registers      After disassembly, print every register holding a
+                       tracked value and the highest register used
+      --track-registers <MODE>
+                       Register values substituted into GET/SET PROPERTY and
+                       CALL operands: strings (default, NEW VALUE only),
+                       constants (also MOV IMM32, LOAD IMM8 and LOAD DOUBLE),
+                       or off for raw regN operands
+      --naive-tracking Keep tracked register values across jump targets and
+                       unconditional jumps instead of forgetting them there
+      --fold-constants Compute ADD, SUB, MUL, DIV, MOD, AND, OR, XOR, SHL, SHR
+                       and USHR of two known numbers, track the result and
+                       show it as a '; = N' comment; implies constants
+                       tracking
+      --time           After disassembly, print how often each opcode was
+                       dispatched and the time spent in its handler
+  -f, --format <FORMAT>
+                       Listing format: text (default), json or csv. json and
+                       csv include the start offset, opcode and operands of
+                       every instruction; json is a versioned object with an
+                       instructions array and the error, if any
+  -E, --regex          (grep) Treat PATTERN as a regular expression
+  -C, --context <N>    (grep) Also print N instructions around each match;
+                       (diff) print N unchanged instructions around each
+                       change (default {DEFAULT_DIFF_CONTEXT})
+      --min-length <N> (strings) Skip strings shorter than N characters
+      --uses           (strings) Under each string, list every NEW VALUE that
+                       loads it and every GET/SET PROPERTY, CALL FUNCTION and
+                       CALL APPLY operand that reads it
+      --roots          (callgraph) List the functions that no call, JUMP FRAME
+                       or NEW FUNCTION reachable from the start offset leads to
+      --progress       Report progress on stderr (only when stderr is a
+                       terminal and the listing is not printed to it)
+  -w, --watch          Re-run the disassembly every time INPUT is modified
+  -i, --interactive    Step through the disassembly one instruction at a time,
+                       reading commands from stdin (h for help)
+      --config <PATH>  Read default options from PATH instead of
+                       ./{DEFAULT_CONFIG_PATH}. Each line is 'option = value'
+                       using the long option name (e.g. string-key = 51,
+                       bytes = true, function = [100, 200]); options given on
+                       the command line override it
+  -h, --help           Print this help and exit
+
+Exit codes:
+  0  success
+  1  the listing could not be written
+  2  invalid arguments or unreadable input
+  3  the input is not valid base64 bytecode
+  4  an unknown opcode was reached (the partial listing is still printed)
+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn show_unreachable_conflicts_with_non_linear_listings() {
+        let message: &str = "--show-unreachable cannot be combined with --follow-jumps or --function";
+        for args in [&["--follow-jumps", "--show-unreachable"][..], &["--function", "0", "--show-unreachable"], &["stats", "--follow-jumps", "--show-unreachable"]] {
+            assert_eq!(parse(args).err().as_deref(), Some(message), "{args:?}");
+        }
+        assert!(parse(&["--show-unreachable"]).is_ok());
+    }
+}
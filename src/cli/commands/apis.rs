@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::apis::{ApiCategory, ApiUsage, ApiUse};
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::listing::Listing;
+
+/// The path as written in the report: calls with a known argument show it, as in `createElement("canvas")`.
+fn label(api: &ApiUse) -> String {
+    match api.argument {
+        Some(argument) => format!("{}({})", api.path, format::json_string(argument)),
+        None => api.path.to_string()
+    }
+}
+
+fn write_text(out: &mut dyn Write, usage: &ApiUsage, categories: &[(ApiCategory, Vec<ApiUse>)]) -> io::Result<()> {
+    let apis: usize = categories.iter().map(|(_, apis)| apis.len()).sum();
+    writeln!(out, "; {} accesses to {apis} APIs in {} categories", usage.len(), categories.len())?;
+    let width: usize = categories.iter().flat_map(|(_, apis)| apis).map(|api| label(api).len()).max().unwrap_or(0);
+    for (category, apis) in categories {
+        let accesses: usize = apis.iter().map(|api| api.accesses.len()).sum();
+        writeln!(out)?;
+        writeln!(out, "{} ({accesses} accesses)", category.as_str())?;
+        for api in apis {
+            let offsets: Vec<String> = api
+                .accesses
+                .iter()
+                .map(|access| format!("{:#06x}{}", access.offset, if access.computed { "*" } else { "" }))
+                .collect();
+            writeln!(out, "  {:<5}  {:<width$}  {}", api.kind.as_str(), label(api), offsets.join(" "))?;
+        }
+    }
+    if usage.accesses.iter().any(|access| access.computed) {
+        writeln!(out)?;
+        writeln!(out, "; * a property name on the path was recovered by emulation, not tracked")?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut dyn Write, usage: &ApiUsage, categories: &[(ApiCategory, Vec<ApiUse>)]) -> io::Result<()> {
+    let categories: Vec<String> = categories
+        .iter()
+        .map(|(category, apis)| {
+            let apis: Vec<String> = apis
+                .iter()
+                .map(|api| {
+                    let offsets: Vec<String> = api.accesses.iter().map(|access| access.offset.to_string()).collect();
+                    let computed: Vec<String> = api.accesses.iter().filter(|access| access.computed).map(|access| access.offset.to_string()).collect();
+                    format!(
+                        "{{\"path\":{},\"kind\":\"{}\",\"argument\":{},\"offsets\":[{}],\"computed\":[{}]}}",
+                        format::json_string(api.path),
+                        api.kind.as_str(),
+                        api.argument.map_or_else(|| "null".to_string(), format::json_string),
+                        offsets.join(","),
+                        computed.join(",")
+                    )
+                })
+                .collect();
+            format!("{{\"category\":\"{}\",\"apis\":[{}]}}", category.as_str(), apis.join(","))
+        })
+        .collect();
+    writeln!(out, "{{\"accesses\":{},\"categories\":[{}]}}", usage.len(), categories.join(","))
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    let usage: ApiUsage = ApiUsage::new(&listing);
+    let categories: Vec<(ApiCategory, Vec<ApiUse>)> = usage.by_category();
+
+    let written: io::Result<()> = match args.format {
+        Format::Json => write_json(&mut out, &usage, &categories),
+        _ => write_text(&mut out, &usage, &categories)
+    };
+    run::finish(input, output, result, written.and_then(|()| out.flush()))?;
+
+    Ok(listing.instruction_count)
+}
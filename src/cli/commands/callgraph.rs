@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::callgraph::{CallGraph, CallKind, Node};
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::functions::Function;
+use bet365::disassembler::listing::Listing;
+
+/// Externals get a prefix so a global named like a function cannot collide with it.
+fn node_id(node: &Node) -> String {
+    match node {
+        Node::External(name) => format!("ext:{name}"),
+        node => node.to_string()
+    }
+}
+
+fn edges(graph: &CallGraph) -> BTreeMap<(&Node, &Node, CallKind), usize> {
+    let mut edges: BTreeMap<(&Node, &Node, CallKind), usize> = BTreeMap::new();
+    for call in &graph.calls {
+        *edges.entry((&call.from, &call.to, call.kind)).or_default() += 1;
+    }
+    edges
+}
+
+fn write_dot(out: &mut dyn Write, graph: &CallGraph) -> io::Result<()> {
+    writeln!(out, "digraph calls {{")?;
+    writeln!(out, "  {} [shape=box];", format::json_string(&node_id(&Node::TopLevel)))?;
+    for function in &graph.functions.functions {
+        let label: String = format!("{}\\n{} args, {} bytes", function.name(), function.args, function.size());
+        writeln!(out, "  {} [label=\"{label}\"];", format::json_string(&node_id(&Node::Function(function.entry))))?;
+    }
+    let mut externals: Vec<&Node> = graph.calls.iter().map(|call| &call.to).filter(|node| matches!(node, Node::External(_))).collect();
+    externals.sort();
+    externals.dedup();
+    for node in externals {
+        writeln!(out, "  {} [label={}, shape=plaintext];", format::json_string(&node_id(node)), format::json_string(&node.to_string()))?;
+    }
+
+    for ((from, to, kind), count) in edges(graph) {
+        let style: &str = match kind {
+            CallKind::Call => "solid",
+            CallKind::Frame => "bold",
+            CallKind::Reference => "dashed"
+        };
+        let label: String = if count > 1 { format!("{} x{count}", kind.as_str()) } else { kind.as_str().to_string() };
+        writeln!(out, "  {} -> {} [label=\"{label}\", style={style}];", format::json_string(&node_id(from)), format::json_string(&node_id(to)))?;
+    }
+    writeln!(out, "}}")
+}
+
+fn function_json(function: &Function) -> String {
+    format!(
+        "{{\"id\":{},\"entry\":{},\"end\":{},\"args\":{},\"size\":{}}}",
+        format::json_string(&node_id(&Node::Function(function.entry))),
+        function.entry,
+        function.end,
+        function.args,
+        function.size()
+    )
+}
+
+fn write_json(out: &mut dyn Write, graph: &CallGraph) -> io::Result<()> {
+    let mut adjacency: BTreeMap<&Node, Vec<String>> = BTreeMap::new();
+    for ((from, to, kind), count) in edges(graph) {
+        adjacency.entry(from).or_default().push(format!(
+            "{{\"to\":{},\"kind\":\"{}\",\"count\":{count}}}",
+            format::json_string(&node_id(to)),
+            kind.as_str()
+        ));
+    }
+    let adjacency: Vec<String> = adjacency
+        .into_iter()
+        .map(|(from, edges)| format!("{}:[{}]", format::json_string(&node_id(from)), edges.join(",")))
+        .collect();
+    let functions: Vec<String> = graph.functions.functions.iter().map(function_json).collect();
+    writeln!(
+        out,
+        "{{\"functions\":[{}],\"calls\":{{{}}},\"unresolved\":{}}}",
+        functions.join(","),
+        adjacency.join(","),
+        graph.unresolved.len()
+    )
+}
+
+fn write_roots(out: &mut dyn Write, format: Format, graph: &CallGraph, root: &Node) -> io::Result<()> {
+    let unreachable: Vec<&Function> = graph.unreachable(root);
+    if format == Format::Json {
+        let functions: Vec<String> = unreachable.iter().map(|function| function_json(function)).collect();
+        return writeln!(out, "{{\"root\":{},\"unreachable\":[{}]}}", format::json_string(&node_id(root)), functions.join(","));
+    }
+
+    let bytes: usize = unreachable.iter().map(|function| function.size()).sum();
+    writeln!(out, "; {} of {} functions ({bytes} bytes) unreachable from {root}", unreachable.len(), graph.functions.len())?;
+    for function in unreachable {
+        writeln!(out, "{:#06x}  {:<16} {:>6} bytes  {} args", function.entry, function.name(), function.size(), function.args)?;
+    }
+    Ok(())
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    let graph: CallGraph = CallGraph::new(&listing);
+
+    let written: io::Result<()> = match (args.roots, args.format) {
+        (true, format) => write_roots(&mut out, format, &graph, &graph.node_at(args.start)),
+        (false, Format::Json) => write_json(&mut out, &graph),
+        (false, _) => write_dot(&mut out, &graph)
+    };
+    run::finish(input, output, result, written.and_then(|()| out.flush()))?;
+    if !graph.unresolved.is_empty() {
+        eprintln!("warning: {} calls through registers that could not be traced to a function or name", graph.unresolved.len());
+    }
+
+    Ok(listing.instruction_count)
+}
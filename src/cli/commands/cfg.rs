@@ -0,0 +1,56 @@
+use std::io::Write;
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::disassembler::{Disassembler, TraceLine};
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::opcodes::{OpCategory, OpCodes};
+use bet365::disassembler::operands::Operand;
+
+fn edges(line: &TraceLine) -> Vec<String> {
+    let targets: Vec<u32> = line
+        .operands
+        .iter()
+        .filter_map(|operand| match operand {
+            Operand::Target(target) => Some(*target),
+            _ => None
+        })
+        .collect();
+
+    match (line.opcode, targets.as_slice()) {
+        (OpCodes::Jump, [target]) => vec![format!("{target:#x} (jump)")],
+        (OpCodes::JumpIfFalse, [target]) => vec![format!("{target:#x} (false)"), format!("{:#06x} (true)", line.ptr)],
+        (OpCodes::JumpIfTrue, [target]) => vec![format!("{target:#x} (true)"), format!("{:#06x} (false)", line.ptr)],
+        (OpCodes::JumpFrame, [target]) => vec![format!("{target:#x} (call)"), format!("{:#06x} (return)", line.ptr)],
+        (OpCodes::NewFunction, [target]) => vec![format!("{target:#x} (function)"), format!("{:#06x} (next)", line.ptr)],
+        (OpCodes::TryCatch, [catch, finally, next]) => vec![
+            format!("{:#06x} (try)", line.ptr),
+            format!("{catch:#x} (catch)"),
+            format!("{finally:#x} (finally)"),
+            format!("{next:#x} (continue)")
+        ],
+        _ => Vec::new()
+    }
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let result = disasm.execute(|line| {
+        let is_flow: bool = line.opcode.category() == OpCategory::ControlFlow || matches!(line.opcode, OpCodes::NewFunction);
+        if !is_flow {
+            return Ok(());
+        }
+
+        let operands: Vec<String> = line.operands.iter().map(|operand| operand.to_string()).collect();
+        let edges: Vec<String> = edges(line);
+        let successors: String = if edges.is_empty() { "(end)".to_string() } else { edges.join(", ") };
+        writeln!(out, "  {:#06x}  {:<14} {:<40} -> {successors}", line.start, line.opcode.as_str(), operands.join(" "))
+    });
+
+    run::finish(input, output, result.map(drop).map_err(DisassemblerError::from), out.flush())?;
+
+    Ok(disasm.trace.len())
+}
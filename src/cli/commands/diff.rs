@@ -0,0 +1,162 @@
+use std::io::{self, Write};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::diff::{Counts, Hunk, LineKind, ListingDiff, Unit, UnitDiff};
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::listing::Listing;
+
+/// Functions paired, paired and changed, added and removed.
+fn function_counts(diff: &ListingDiff) -> (usize, usize, usize, usize) {
+    let functions = |unit: &Option<Unit>| matches!(unit, Some(Unit::Function(_)));
+    let paired: Vec<&UnitDiff> = diff.units.iter().filter(|unit| functions(&unit.old) && functions(&unit.new)).collect();
+    (
+        paired.len(),
+        paired.iter().filter(|unit| unit.is_changed()).count(),
+        diff.units.iter().filter(|unit| unit.old.is_none()).count(),
+        diff.units.iter().filter(|unit| unit.new.is_none()).count()
+    )
+}
+
+fn heading(unit: &UnitDiff) -> String {
+    match (unit.old, unit.new) {
+        (Some(old), Some(new)) if old.entry() != new.entry() => format!("{} -> {}", old.name(), new.name()),
+        (Some(_), Some(_)) => unit.name(),
+        (Some(old), None) => format!("{} (removed)", old.name()),
+        (None, Some(new)) => format!("{} (added)", new.name()),
+        (None, None) => String::new()
+    }
+}
+
+fn write_text(out: &mut dyn Write, diff: &ListingDiff, names: (&str, &str), args: &Args) -> io::Result<()> {
+    writeln!(out, "--- {}", names.0)?;
+    writeln!(out, "+++ {}", names.1)?;
+    for unit in diff.changed() {
+        for hunk in unit.hunks(args.context) {
+            let Hunk { old_start, old_count, new_start, new_count, lines } = hunk;
+            writeln!(out, "@@ -{old_start:#06x},{old_count} +{new_start:#06x},{new_count} @@ {}", heading(unit))?;
+            for line in &unit.lines[lines] {
+                writeln!(out, "{}{}", line.kind.prefix(), line.text)?;
+            }
+        }
+    }
+
+    let Counts { added, removed, changed } = diff.counts();
+    let (paired, changed_functions, added_functions, removed_functions) = function_counts(diff);
+    writeln!(out)?;
+    writeln!(out, "instructions: {added} added, {removed} removed, {changed} changed")?;
+    writeln!(out, "functions: {paired} paired, {changed_functions} changed, {added_functions} added, {removed_functions} removed")?;
+    writeln!(out, "strings: {} added, {} removed", diff.strings_added.len(), diff.strings_removed.len())?;
+    for value in &diff.strings_added {
+        writeln!(out, "+{}", format::quoted(value, args.raw_strings))?;
+    }
+    for value in &diff.strings_removed {
+        writeln!(out, "-{}", format::quoted(value, args.raw_strings))?;
+    }
+    Ok(())
+}
+
+fn unit_json(unit: Option<Unit>) -> String {
+    match unit {
+        Some(unit) => {
+            let entry: String = unit.entry().map_or("null".to_string(), |entry| entry.to_string());
+            format!("{{\"name\":{},\"entry\":{entry}}}", format::json_string(&unit.name()))
+        }
+        None => "null".to_string()
+    }
+}
+
+fn offset_json(offset: Option<usize>) -> String {
+    offset.map_or("null".to_string(), |offset| offset.to_string())
+}
+
+fn write_json(out: &mut dyn Write, diff: &ListingDiff, names: (&str, &str), args: &Args) -> io::Result<()> {
+    let units: Vec<String> = diff
+        .changed()
+        .map(|unit| {
+            let hunks: Vec<String> = unit
+                .hunks(args.context)
+                .into_iter()
+                .map(|hunk| {
+                    let lines: Vec<String> = unit.lines[hunk.lines.clone()]
+                        .iter()
+                        .map(|line| {
+                            let kind: &str = match line.kind {
+                                LineKind::Same => "same",
+                                LineKind::Removed => "removed",
+                                LineKind::Added => "added"
+                            };
+                            format!(
+                                "{{\"kind\":\"{kind}\",\"old\":{},\"new\":{},\"text\":{}}}",
+                                offset_json(line.old),
+                                offset_json(line.new),
+                                format::json_string(&line.text)
+                            )
+                        })
+                        .collect();
+                    format!(
+                        "{{\"old_start\":{},\"old_count\":{},\"new_start\":{},\"new_count\":{},\"lines\":[{}]}}",
+                        hunk.old_start,
+                        hunk.old_count,
+                        hunk.new_start,
+                        hunk.new_count,
+                        lines.join(",")
+                    )
+                })
+                .collect();
+            let Counts { added, removed, changed } = unit.counts();
+            format!(
+                "{{\"old\":{},\"new\":{},\"added\":{added},\"removed\":{removed},\"changed\":{changed},\"hunks\":[{}]}}",
+                unit_json(unit.old),
+                unit_json(unit.new),
+                hunks.join(",")
+            )
+        })
+        .collect();
+    let strings = |values: &[String]| values.iter().map(|value| format::json_string(value)).collect::<Vec<String>>().join(",");
+    let Counts { added, removed, changed } = diff.counts();
+    let (paired, changed_functions, added_functions, removed_functions) = function_counts(diff);
+    let separator: &str = if units.is_empty() { "" } else { "\n" };
+    writeln!(
+        out,
+        "{{\"old\":{},\"new\":{},\"summary\":{{\"added\":{added},\"removed\":{removed},\"changed\":{changed},\
+         \"functions\":{{\"paired\":{paired},\"changed\":{changed_functions},\"added\":{added_functions},\"removed\":{removed_functions}}}}},\
+         \"strings\":{{\"added\":[{}],\"removed\":[{}]}},\"units\":[{separator}{}{separator}]}}",
+        format::json_string(names.0),
+        format::json_string(names.1),
+        strings(&diff.strings_added),
+        strings(&diff.strings_removed),
+        units.join(",\n")
+    )
+}
+
+fn disassemble(args: &Args, input: &Input) -> Result<(Disassembler, Listing, Result<(), DisassemblerError>), CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    Ok((disasm, listing, result))
+}
+
+pub fn run(args: &Args, old: &Input, new: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let (old_disasm, old_listing, old_result) = disassemble(args, old)?;
+    let (new_disasm, new_listing, new_result) = disassemble(args, new)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let diff: ListingDiff = ListingDiff::new(&old_listing, &new_listing);
+    let names: (&str, &str) = (old.name(), new.name());
+    let written: io::Result<()> = match args.format {
+        Format::Json => write_json(&mut out, &diff, names, args),
+        _ => write_text(&mut out, &diff, names, args)
+    };
+    run::finish(old, output, old_result, written.and_then(|()| out.flush()))?;
+    run::finish(new, output, new_result, Ok(()))?;
+    run::check_string_key(args, &old_disasm);
+    run::check_string_key(args, &new_disasm);
+
+    Ok(old_listing.instruction_count + new_listing.instruction_count)
+}
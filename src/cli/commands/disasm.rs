@@ -0,0 +1,449 @@
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+use crate::cli::annotations;
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format;
+use crate::cli::input::Input;
+use crate::cli::printer::{self, Addresses, Printer};
+use crate::cli::progress::Progress;
+use crate::cli::rename_map;
+use crate::cli::run::{self, report};
+use bet365::disassembler::cfg::Cfg;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::{DisassemblerError, ExecuteError};
+use bet365::disassembler::emulator::{Emulation, Emulator};
+use bet365::disassembler::exceptions::TryRegions;
+use bet365::disassembler::expressions::Expressions;
+use bet365::disassembler::follow::{self, Traversal};
+use bet365::disassembler::functions::{Function, Functions};
+use bet365::disassembler::instructions::{OpcodeTable, DEFAULT_PROFILE};
+use bet365::disassembler::listing::{Listing, Termination};
+use bet365::disassembler::liveness::Liveness;
+use bet365::disassembler::loops::Loops;
+use bet365::disassembler::names::{Pin, PinScope, RegisterNames};
+use bet365::disassembler::opcodes::OpCodes;
+use bet365::disassembler::reachability::Reachability;
+use bet365::disassembler::registers::RegValue;
+
+const MAX_TARGET_WARNINGS: usize = 10;
+const MAX_FRAME_WARNINGS: usize = 10;
+
+fn check_targets(listing: &Listing, printer: &mut Printer) {
+    for warning in &listing.target_warnings {
+        printer.warn(warning);
+    }
+    for warning in listing.target_warnings.iter().take(MAX_TARGET_WARNINGS) {
+        eprintln!("warning: {warning}");
+    }
+    if listing.target_warnings.len() > MAX_TARGET_WARNINGS {
+        eprintln!(
+            "warning: {} more suspicious targets; the opcode table or operand widths may not match this build",
+            listing.target_warnings.len() - MAX_TARGET_WARNINGS
+        );
+    }
+}
+
+/// With --indent-frames, warns about RETs without a JUMP FRAME and frames still open at HALT or at the end.
+fn check_frames(args: &Args, listing: &Listing, printer: &mut Printer) {
+    if !args.indent_frames {
+        return;
+    }
+    for warning in &listing.frame_warnings {
+        printer.warn_frames(warning);
+    }
+    for warning in listing.frame_warnings.iter().take(MAX_FRAME_WARNINGS) {
+        eprintln!("warning: {warning}");
+    }
+    if listing.frame_warnings.len() > MAX_FRAME_WARNINGS {
+        eprintln!("warning: {} more RETs without a JUMP FRAME or frames left open", listing.frame_warnings.len() - MAX_FRAME_WARNINGS);
+    }
+}
+
+/// Brackets the try regions of `listing` in the output and warns about the ones that cannot be structured.
+fn set_try_regions(listing: &Listing, printer: &mut Printer) {
+    let regions: TryRegions = listing.try_regions();
+    for warning in &regions.warnings {
+        eprintln!("warning: {warning}");
+    }
+    printer.set_try_regions(regions);
+}
+
+/// Register names for `listing` with --rename. With --function, `entry` is the function being listed: its
+/// code is outside every function of the listing, so the --rename-map pins for it apply there.
+fn register_names(args: &Args, listing: &Listing, entry: Option<usize>) -> Result<Option<RegisterNames>, CliError> {
+    if !args.rename {
+        return Ok(None);
+    }
+    let mut pins: Vec<Pin> = match &args.rename_map {
+        Some(path) => rename_map::load(path)?,
+        None => Vec::new()
+    };
+    match (entry, &args.rename_map) {
+        (Some(entry), _) => {
+            pins.retain(|pin| pin.scope != PinScope::Outside);
+            for pin in pins.iter_mut().filter(|pin| pin.scope == PinScope::Function(entry)) {
+                pin.scope = PinScope::Outside;
+            }
+        }
+        (None, Some(path)) => rename_map::check(path, &pins, &listing.functions()),
+        (None, None) => {}
+    }
+    Ok(Some(RegisterNames::new(listing, &pins)))
+}
+
+fn expressions(listing: &Listing, depth: usize, names: Option<&RegisterNames>) -> Expressions {
+    match names {
+        Some(names) => Expressions::named(listing, depth, names),
+        None => Expressions::new(listing, depth)
+    }
+}
+
+/// Sets `printer` up for `listing`: warnings, labels, register names and every analysis the flags ask for.
+/// `entry` is the function being listed with --function. Returns the reachability with --show-unreachable,
+/// for the summary after the listing.
+fn analyze(args: &Args, listing: &Listing, entry: Option<usize>, printer: &mut Printer) -> Result<Option<Reachability>, CliError> {
+    check_targets(listing, printer);
+    check_frames(args, listing, printer);
+    if args.labels {
+        printer.set_symbols(listing.symbols());
+    }
+    let names: Option<RegisterNames> = register_names(args, listing, entry)?;
+    if let Some(depth) = args.collapse {
+        printer.set_expressions(expressions(listing, depth, names.as_ref()));
+    }
+    if args.liveness {
+        printer.set_liveness(Liveness::new(&Cfg::new(listing)));
+    }
+    if args.loops {
+        printer.set_loops(Loops::new(&Cfg::new(listing)));
+    }
+    if args.try_regions {
+        set_try_regions(listing, printer);
+    }
+    if let Some(steps) = args.emulate {
+        printer.set_emulation(Emulator::new().run(listing, steps));
+    }
+    if let Some(names) = names {
+        printer.set_names(names);
+    }
+    let reachability: Option<Reachability> = args.show_unreachable.then(|| listing.reachability());
+    if let Some(reachability) = &reachability {
+        printer.set_reachability(reachability.clone());
+    }
+    Ok(reachability)
+}
+
+/// Prints a header whenever the listing moves into another function, or out of all of them (--sections).
+struct Sections {
+    functions: Functions,
+    current: Option<Option<usize>>
+}
+
+impl Sections {
+    fn new(listing: &Listing, printer: &mut Printer) -> Self {
+        let functions: Functions = listing.functions();
+        for overlap in &functions.overlaps {
+            eprintln!("warning: {overlap}");
+        }
+        printer.functions(&functions);
+        Self { functions, current: None }
+    }
+
+    fn enter(&mut self, offset: usize, printer: &mut Printer) -> io::Result<()> {
+        let function: Option<Function> = self.functions.containing(offset).copied();
+        let section: Option<usize> = function.map(|function| function.entry);
+        if self.current == Some(section) {
+            return Ok(());
+        }
+        if self.current.is_some() {
+            printer.label("")?;
+        }
+        self.current = Some(section);
+        match function {
+            Some(function) => {
+                let plural: &str = if function.args == 1 { "" } else { "s" };
+                printer.label(&format!("; ===== {} (entry {:#06x}, {} arg{plural}) =====", function.name(), function.entry, function.args))
+            }
+            None => printer.label(&format!("; ===== outside functions ({offset:#06x}) ====="))
+        }
+    }
+
+    fn print(&mut self, listing: &Listing, disasm: &Disassembler, printer: &mut Printer) -> io::Result<()> {
+        for instruction in &listing.instructions {
+            self.enter(instruction.offset, printer)?;
+            printer.print_instruction(instruction, disasm)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let start = Instant::now();
+    let mut disasm: Disassembler = run::load(args, input)?;
+
+    let mut printer: Printer = match output {
+        Some(path) => Printer::new(Box::new(run::create_file(path)?), args.format, false, args.bytes, Addresses::from_args(args)),
+        None if args.quiet => Printer::new(Box::new(io::sink()), args.format, false, args.bytes, Addresses::from_args(args)),
+        None => Printer::new(Box::new(run::stdout()), args.format, printer::should_color(args.no_color), args.bytes, Addresses::from_args(args))
+    };
+    printer.set_indent_frames(args.indent_frames);
+
+    let listing_on_terminal: bool = output.is_none() && !args.quiet && io::stdout().is_terminal();
+    let mut progress: Progress = Progress::new(args.progress, listing_on_terminal, args.start, disasm.remaining());
+
+    if args.follow_jumps {
+        let result: Result<usize, CliError> = disassemble_reachable(args, input, &mut disasm, &mut printer);
+        let flushed = printer.finish();
+        let decoded: usize = result?;
+        run::finish(input, output, Ok(()), flushed)?;
+        report_loops(printer.loops());
+        report_emulations(printer.emulations());
+        return summarize(args, &disasm, decoded, None, start);
+    }
+
+    if !args.functions.is_empty() {
+        let result: Result<usize, CliError> = disassemble_functions(args, input, &mut disasm, &mut printer);
+        let flushed = printer.finish();
+        let decoded: usize = result?;
+        run::finish(input, output, Ok(()), flushed)?;
+        report_loops(printer.loops());
+        report_emulations(printer.emulations());
+        return summarize(args, &disasm, decoded, None, start);
+    }
+
+    let result: Result<Listing, ExecuteError> = disasm.execute(|line| {
+        progress.tick(line.ptr);
+        Ok(())
+    });
+    progress.finish();
+
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match result {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    if let Err(err) = &result {
+        printer.fail(err);
+    }
+    let reachability: Option<Reachability> = analyze(args, &listing, None, &mut printer)?;
+    let printed: io::Result<()> = match args.sections {
+        true => Sections::new(&listing, &mut printer).print(&listing, &disasm, &mut printer),
+        false => printer.print_listing(&listing, &disasm)
+    }
+    .and_then(|()| printer.finish());
+    run::finish(input, output, result, printed)?;
+
+    if let Some(offset) = disasm.overrun {
+        eprintln!("warning: instruction at offset {offset:#06x} extends past --end {}; stopped before it", args.end.unwrap_or_default());
+    }
+    if let Some(reachability) = &reachability {
+        report_unreachable(reachability);
+    }
+    report_loops(printer.loops());
+    report_emulations(printer.emulations());
+
+    summarize(args, &disasm, listing.instruction_count, listing.termination, start)
+}
+
+fn disassemble_functions(args: &Args, input: &Input, disasm: &mut Disassembler, printer: &mut Printer) -> Result<usize, CliError> {
+    let mut decoded: usize = 0;
+    for (index, &entry) in args.functions.iter().enumerate() {
+        disasm
+            .seek_function(entry, args.function_bytes)
+            .map_err(|err| CliError::Input(format!("{}: {err}", input.name())))?;
+
+        if index > 0 {
+            printer.label("").map_err(|err| run::write_error(args.output.as_deref(), err))?;
+        }
+        let label: String = if args.legacy_addresses { format!("entry({entry}):") } else { format!("entry({entry:#x}):") };
+        printer.label(&label).map_err(|err| run::write_error(args.output.as_deref(), err))?;
+
+        let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+            Ok(listing) => (listing, Ok(())),
+            Err(err) => (*err.listing, Err(err.error))
+        };
+        analyze(args, &listing, Some(entry), printer)?;
+        for instruction in &listing.instructions {
+            printer
+                .print_instruction(instruction, disasm)
+                .map_err(|err| run::write_error(args.output.as_deref(), err))?;
+        }
+        decoded += listing.instruction_count;
+        if let Err(err) = &result {
+            printer.fail(err);
+        }
+
+        match result {
+            Ok(()) => {}
+            Err(DisassemblerError::UnknownOpcode { offset, byte }) if offset == entry => {
+                return Err(CliError::UnknownOpcode(format!(
+                    "{}: function entry {entry:#06x} starts with unknown opcode {byte} (0x{byte:02x}); the entry offset is probably wrong",
+                    input.name()
+                )));
+            }
+            Err(err) => return run::finish(input, args.output.as_deref(), Err(err), Ok(())).map(|()| decoded)
+        }
+
+        if disasm.overrun.is_some() {
+            eprintln!("warning: function entry({entry:#06x}) did not return within {} bytes", args.function_bytes.unwrap_or_default());
+        }
+    }
+    Ok(decoded)
+}
+
+fn disassemble_reachable(args: &Args, input: &Input, disasm: &mut Disassembler, printer: &mut Printer) -> Result<usize, CliError> {
+    let write_error = |err| run::write_error(args.output.as_deref(), err);
+    let mut traversal: Traversal = follow::follow_jumps(disasm, args.start);
+
+    if traversal.instructions.is_empty() && !traversal.errors.is_empty() {
+        let err: DisassemblerError = traversal.errors.remove(0);
+        printer.fail(&err);
+        return run::finish(input, args.output.as_deref(), Err(err), Ok(())).map(|()| 0);
+    }
+
+    let listing: Listing = traversal.listing(disasm);
+    analyze(args, &listing, None, printer)?;
+    let mut sections: Option<Sections> = args.sections.then(|| Sections::new(&listing, printer));
+    let gaps: Vec<(usize, usize)> = traversal.gaps(args.start, disasm.end());
+    let mut gaps = gaps.iter().peekable();
+    for instruction in &listing.instructions {
+        while let Some((from, to)) = gaps.next_if(|(from, _)| *from < instruction.offset) {
+            printer.label(&format!("; data {from:#06x}..{to:#06x} ({} bytes not reached)", to - from)).map_err(write_error)?;
+        }
+        if let Some(sections) = &mut sections {
+            sections.enter(instruction.offset, printer).map_err(write_error)?;
+        }
+        printer.print_instruction(instruction, disasm).map_err(write_error)?;
+    }
+    for (from, to) in gaps {
+        printer.label(&format!("; data {from:#06x}..{to:#06x} ({} bytes not reached)", to - from)).map_err(write_error)?;
+    }
+
+    for err in &traversal.errors {
+        eprintln!("warning: {}: {err}; that path was abandoned", input.name());
+    }
+    if traversal.exhausted {
+        eprintln!(
+            "warning: {}: stopped following control flow after visiting {} offsets (--max-visited); the listing is incomplete",
+            input.name(),
+            disasm.max_visited
+        );
+    }
+    for (offset, target) in traversal.skipped_targets.iter().filter(|(_, target)| (*target as usize) < disasm.bytes().len()) {
+        eprintln!("warning: instruction at offset {offset:#06x} targets {target:#x}, outside the disassembled range");
+    }
+    Ok(listing.instruction_count)
+}
+
+fn report_unreachable(reachability: &Reachability) {
+    let plural: &str = if reachability.regions.len() == 1 { "" } else { "s" };
+    report(&format!("; {} unreachable bytes in {} region{plural}", reachability.dead_bytes(), reachability.regions.len()));
+    if let Some(region) = reachability.largest() {
+        let plural: &str = if region.instructions == 1 { "" } else { "s" };
+        report(&format!(
+            "; largest dead region: {:#06x}..{:#06x} ({} bytes, {} instruction{plural})",
+            region.start,
+            region.end,
+            region.size(),
+            region.instructions
+        ));
+    }
+}
+
+fn report_emulations(emulations: &[Emulation]) {
+    for emulation in emulations {
+        let plural: &str = if emulation.steps == 1 { "" } else { "s" };
+        match &emulation.stop {
+            Some((offset, reason)) => report(&format!(
+                "; emulated {} step{plural} from {:#06x}, stopped at {offset:#06x} on {reason}",
+                emulation.steps,
+                emulation.start
+            )),
+            None => report("; nothing to emulate")
+        }
+    }
+}
+
+fn report_loops(sets: &[Loops]) {
+    let plural = |count: usize, noun: &str| if count == 1 { format!("{count} {noun}") } else { format!("{count} {noun}s") };
+    for loops in sets {
+        match loops.len() {
+            0 => report("; no loops"),
+            1 => report("; 1 loop"),
+            count => report(&format!("; {count} loops, nested up to {} deep", loops.max_depth()))
+        }
+        for found in &loops.loops {
+            let parent: String = found.parent.map(|parent| format!(", inside {}", loops.loops[parent].name())).unwrap_or_default();
+            report(&format!(
+                ";   {:<4} header {:#06x}  depth {}{parent}: {}, {}, {}, {}",
+                found.name(),
+                found.offset,
+                found.depth,
+                plural(found.body_blocks.len(), "block"),
+                plural(found.size, "byte"),
+                plural(found.instructions, "instruction"),
+                plural(found.exits.len(), "exit")
+            ));
+        }
+        for edge in &loops.irreducible {
+            report(&format!(
+                "; irreducible: the edge {:#06x} -> {:#06x} closes a cycle that can be entered at more than one block (not a natural loop)",
+                edge.offset,
+                edge.target
+            ));
+        }
+    }
+}
+
+fn summarize(args: &Args, disasm: &Disassembler, decoded: usize, termination: Option<Termination>, start: Instant) -> Result<usize, CliError> {
+    run::check_string_key(args, disasm);
+    annotations::check(args, disasm);
+
+    if args.registers {
+        let live: Vec<(u8, &RegValue)> = disasm.live_registers();
+        report(&format!("; {} registers with a tracked value (only the last assignment is shown)", live.len()));
+        for (reg, value) in live {
+            report(&format!(";   reg{reg:<4} {}", format::reg_value(value, args.raw_strings)));
+        }
+        if let Some(max) = disasm.max_register() {
+            report(&format!("; highest register used: reg{max}"));
+        }
+    }
+
+    if args.time {
+        let mut timings: Vec<(u8, OpCodes, usize, Duration)> = disasm.opcode_timings();
+        timings.sort_by(|a, b| b.3.cmp(&a.3).then(a.0.cmp(&b.0)));
+        report(&format!("; {:<16} {:>4} {:>8} {:>12} {:>10}", "opcode", "byte", "count", "total us", "avg ns"));
+        for (byte, opcode, count, total) in timings {
+            let average: u128 = total.as_nanos() / count as u128;
+            report(&format!(
+                "; {:<16} {byte:>4} {count:>8} {:>12.1} {average:>10}",
+                opcode.as_str(),
+                total.as_secs_f64() * 1e6
+            ));
+        }
+    }
+
+    let table: &OpcodeTable = disasm.table();
+    if table.name != DEFAULT_PROFILE {
+        report(&format!("opcode profile: {} ({} opcode bytes)", table.name, table.len()));
+        if let Some(notes) = &table.notes {
+            report(&format!("  {notes}"));
+        }
+    }
+    if disasm.unknown_skipped > 0 {
+        report(&format!("skipped {} unknown opcode bytes", disasm.unknown_skipped));
+    }
+    if args.quiet {
+        report(&format!("decoded {decoded} instructions"));
+    }
+    if let Some(max) = args.max_instructions && args.functions.is_empty() && disasm.overrun.is_none() && disasm.remaining() > 0 {
+        report(&format!("stopped after {max} instructions, {} bytes left unprocessed", disasm.remaining()));
+    }
+    match termination {
+        Some(termination) => report(&format!("disassemble took: {:?} ({termination})", start.elapsed())),
+        None => report(&format!("disassemble took: {:?}", start.elapsed()))
+    }
+
+    Ok(decoded)
+}
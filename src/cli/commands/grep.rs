@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use regex::Regex;
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::input::Input;
+use crate::cli::printer::Addresses;
+use crate::cli::run;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+
+enum Matcher {
+    Substring(String),
+    Regex(Regex)
+}
+
+impl Matcher {
+    fn new(args: &Args) -> Result<Self, CliError> {
+        let pattern: &str = args.pattern.as_deref().ok_or_else(|| CliError::Input("grep requires a PATTERN".to_string()))?;
+        if args.regex {
+            Regex::new(pattern)
+                .map(Matcher::Regex)
+                .map_err(|err| CliError::Input(format!("invalid regex '{pattern}': {err}")))
+        } else {
+            Ok(Matcher::Substring(pattern.to_string()))
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Substring(pattern) => text.contains(pattern.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text)
+        }
+    }
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let matcher: Matcher = Matcher::new(args)?;
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let context: usize = args.context;
+    let mut before: VecDeque<String> = VecDeque::with_capacity(context);
+    let mut after: usize = 0;
+    let addresses: Addresses = Addresses::from_args(args);
+    let mut matches: usize = 0;
+    let mut printed_any: bool = false;
+    let mut gap: bool = false;
+
+    let result = disasm.execute(|line| {
+        let formatted = || format!("{}{}", addresses.prefix(line), line.text);
+
+        if matcher.is_match(line.text) {
+            matches += 1;
+            if gap && printed_any && context > 0 {
+                writeln!(out, "--")?;
+            }
+            for previous in before.drain(..) {
+                writeln!(out, "{previous}")?;
+            }
+            writeln!(out, "{}", formatted())?;
+            printed_any = true;
+            gap = false;
+            after = context;
+        } else if after > 0 {
+            writeln!(out, "{}", formatted())?;
+            after -= 1;
+        } else if context > 0 {
+            if before.len() == context {
+                before.pop_front();
+                gap = true;
+            }
+            before.push_back(formatted());
+        }
+        Ok(())
+    });
+
+    run::finish(input, output, result.map(drop).map_err(DisassemblerError::from), out.flush())?;
+
+    eprintln!("{matches} matching instructions out of {}", disasm.trace.len());
+    Ok(disasm.trace.len())
+}
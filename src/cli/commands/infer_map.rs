@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::inference::{InferredOpcode, OpcodeInference};
+use bet365::disassembler::listing::Listing;
+
+/// Entries less certain than this are written commented out, to be checked by hand.
+pub const MIN_CONFIDENCE: f64 = 0.5;
+
+fn alternatives(inferred: &InferredOpcode) -> String {
+    let names: Vec<String> = inferred.alternatives.iter().take(3).map(|opcode| opcode.handler_name()).collect();
+    if names.is_empty() { String::new() } else { format!("; or {}", names.join(", ")) }
+}
+
+fn write_text(out: &mut dyn Write, inference: &OpcodeInference, names: (&str, &str), profile: &str) -> io::Result<()> {
+    let coverage: f64 = inference.decoded_bytes as f64 * 100.0 / inference.range_bytes.max(1) as f64;
+    writeln!(out, "# opcode map for {} inferred from {} ({profile} table)", names.1, names.0)?;
+    writeln!(out, "# {} instructions decode {} of {} bytes ({coverage:.1}%)", inference.instructions, inference.decoded_bytes, inference.range_bytes)?;
+    if let Some(offset) = inference.stopped_at {
+        writeln!(out, "# stopped at offset {offset:#06x}, where no handler left decodes cleanly")?;
+    }
+    if inference.targets > 0 {
+        let landed: f64 = inference.targets_on_instructions as f64 * 100.0 / inference.targets as f64;
+        writeln!(out, "# {landed:.1}% of {} jump, call and try/catch targets land on an instruction", inference.targets)?;
+    }
+    writeln!(out, "# entries with confidence below {MIN_CONFIDENCE:.2} are commented out")?;
+    writeln!(out, "name = \"inferred\"")?;
+    for inferred in &inference.opcodes {
+        let comment: &str = if inferred.confidence < MIN_CONFIDENCE { "# " } else { "" };
+        writeln!(
+            out,
+            "{comment}{} = \"{}\"  # confidence {:.2}, {} uses{}",
+            inferred.byte,
+            inferred.opcode.handler_name(),
+            inferred.confidence,
+            inferred.count,
+            alternatives(inferred)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut dyn Write, inference: &OpcodeInference, names: (&str, &str), profile: &str) -> io::Result<()> {
+    let opcodes: Vec<String> = inference
+        .opcodes
+        .iter()
+        .map(|inferred| {
+            let alternatives: Vec<String> = inferred.alternatives.iter().map(|opcode| format::json_string(&opcode.handler_name())).collect();
+            format!(
+                "{{\"byte\":{},\"handler\":{},\"confidence\":{},\"count\":{},\"alternatives\":[{}]}}",
+                inferred.byte,
+                format::json_string(&inferred.opcode.handler_name()),
+                format::json_number(inferred.confidence),
+                inferred.count,
+                alternatives.join(",")
+            )
+        })
+        .collect();
+    writeln!(
+        out,
+        "{{\"reference\":{},\"input\":{},\"profile\":{},\"instructions\":{},\"decoded_bytes\":{},\"range_bytes\":{},\"stopped_at\":{},\
+         \"targets\":{},\"targets_on_instructions\":{},\"opcodes\":[{}]}}",
+        format::json_string(names.0),
+        format::json_string(names.1),
+        format::json_string(profile),
+        inference.instructions,
+        inference.decoded_bytes,
+        inference.range_bytes,
+        inference.stopped_at.map_or("null".to_string(), |offset| offset.to_string()),
+        inference.targets,
+        inference.targets_on_instructions,
+        opcodes.join(",")
+    )
+}
+
+pub fn run(args: &Args, reference: &Input, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut known: Disassembler = run::load(args, reference)?;
+    let listing: Listing = match known.execute(|_| Ok(())) {
+        Ok(listing) => listing,
+        Err(err) => {
+            eprintln!("warning: {}: {}; inferring from the {} instructions before it", reference.name(), err.error, err.listing.instruction_count);
+            *err.listing
+        }
+    };
+    run::check_string_key(args, &known);
+
+    let unknown: Disassembler = run::load(args, input)?;
+    let inference: OpcodeInference = OpcodeInference::new(&listing.instructions, unknown.bytes(), unknown.position(), unknown.end(), args.string_key);
+
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+    let names: (&str, &str) = (reference.name(), input.name());
+    let written: io::Result<()> = match args.format {
+        Format::Json => write_json(&mut out, &inference, names, &known.table().name),
+        _ => write_text(&mut out, &inference, names, &known.table().name)
+    };
+    written.and_then(|()| out.flush()).map_err(|err| run::write_error(output, err))?;
+
+    Ok(inference.instructions)
+}
@@ -0,0 +1,10 @@
+pub mod apis;
+pub mod callgraph;
+pub mod cfg;
+pub mod diff;
+pub mod disasm;
+pub mod grep;
+pub mod infer_map;
+pub mod stats;
+pub mod strings;
+pub mod xref;
\ No newline at end of file
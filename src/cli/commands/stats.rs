@@ -0,0 +1,120 @@
+use std::io::Write;
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::disassembler::{Disassembler, UnknownOpcodePolicy};
+use bet365::disassembler::error::DisassemblerError;
+
+#[derive(Clone, Copy)]
+struct OpcodeStats {
+    name: Option<&'static str>,
+    count: usize,
+    operand_bytes: usize
+}
+
+struct Row {
+    byte: u8,
+    name: String,
+    known: bool,
+    count: usize,
+    operand_bytes: usize
+}
+
+fn write_text(out: &mut dyn Write, rows: &[Row], total: usize, unknown: usize, decoded_bytes: usize, range_bytes: usize) -> std::io::Result<()> {
+    writeln!(out, "{:>8}  {:>6}  {:<10}  {:>13}  OPCODE", "COUNT", "%", "BYTE", "OPERAND BYTES")?;
+    for row in rows {
+        let percent: f64 = row.count as f64 * 100.0 / total.max(1) as f64;
+        writeln!(
+            out,
+            "{:>8}  {percent:>5.1}%  0x{:02x} ({:>3})  {:>13}  {}",
+            row.count, row.byte, row.byte, row.operand_bytes, row.name
+        )?;
+    }
+    writeln!(out, "{:>8}  total instructions", total - unknown)?;
+    if unknown > 0 {
+        writeln!(out, "{unknown:>8}  unknown opcode bytes skipped")?;
+    }
+    let coverage: f64 = decoded_bytes as f64 * 100.0 / range_bytes.max(1) as f64;
+    writeln!(out, "{decoded_bytes:>8}  of {range_bytes} bytes covered by decoded instructions ({coverage:.1}%)")
+}
+
+fn write_json(out: &mut dyn Write, rows: &[Row], total: usize, unknown: usize, decoded_bytes: usize, range_bytes: usize) -> std::io::Result<()> {
+    let opcodes: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "{{\"byte\":{},\"name\":{},\"known\":{},\"count\":{},\"operand_bytes\":{}}}",
+                row.byte,
+                format::json_string(&row.name),
+                row.known,
+                row.count,
+                row.operand_bytes
+            )
+        })
+        .collect();
+    let coverage: f64 = decoded_bytes as f64 / range_bytes.max(1) as f64;
+    writeln!(
+        out,
+        "{{\"instructions\":{},\"unknown\":{unknown},\"decoded_bytes\":{decoded_bytes},\"range_bytes\":{range_bytes},\"coverage\":{},\"opcodes\":[{}]}}",
+        total - unknown,
+        format::json_number(coverage),
+        opcodes.join(",")
+    )
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    disasm.unknown_policy = UnknownOpcodePolicy::FailFast;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+    let range_bytes: usize = disasm.remaining();
+
+    let mut stats: Vec<OpcodeStats> = vec![OpcodeStats { name: None, count: 0, operand_bytes: 0 }; 256];
+    let mut decoded: usize = 0;
+    let mut decoded_bytes: usize = 0;
+    while disasm.max_instructions.is_none_or(|max| decoded < max) {
+        match disasm.step() {
+            Ok(Some(line)) => {
+                let entry: &mut OpcodeStats = &mut stats[line.bytes[0] as usize];
+                entry.name = Some(line.opcode.as_str());
+                entry.count += 1;
+                entry.operand_bytes += line.bytes.len() - 1;
+                decoded += 1;
+                decoded_bytes += line.bytes.len();
+            }
+            Ok(None) => break,
+            Err(DisassemblerError::UnknownOpcode { offset, byte }) => {
+                stats[byte as usize].count += 1;
+                if disasm.seek(offset + 1).is_err() {
+                    break;
+                }
+            }
+            Err(err) => return run::finish(input, output, Err(err), Ok(())).map(|()| decoded)
+        }
+    }
+
+    let mut rows: Vec<Row> = stats
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.count > 0)
+        .map(|(byte, entry)| Row {
+            byte: byte as u8,
+            name: entry.name.map_or_else(|| format!("UNKNOWN(0x{byte:02x})"), str::to_string),
+            known: entry.name.is_some(),
+            count: entry.count,
+            operand_bytes: entry.operand_bytes
+        })
+        .collect();
+    rows.sort_by(|a, b| b.count.cmp(&a.count).then(a.byte.cmp(&b.byte)));
+
+    let total: usize = rows.iter().map(|row| row.count).sum();
+    let unknown: usize = rows.iter().filter(|row| !row.known).map(|row| row.count).sum();
+    let result = match args.format {
+        Format::Json => write_json(&mut out, &rows, total, unknown, decoded_bytes, range_bytes),
+        _ => write_text(&mut out, &rows, total, unknown, decoded_bytes, range_bytes)
+    };
+    result.and_then(|()| out.flush()).map_err(|err| run::write_error(output, err))?;
+
+    Ok(decoded)
+}
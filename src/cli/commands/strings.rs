@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::derived::{DerivedString, DerivedStrings, DEFAULT_MAX_ITERATIONS};
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::listing::Listing;
+use bet365::disassembler::strings::{Definition, StringEntry, StringTable};
+
+/// A derived string where the text report shows a literal's value.
+fn derived_text(derived: &DerivedString, args: &Args) -> String {
+    match &derived.result {
+        Ok(value) => format::quoted(value, args.raw_strings),
+        Err(dynamic) => format!("dynamic ({dynamic})")
+    }
+}
+
+fn write_text(out: &mut dyn Write, entries: &[&StringEntry], derived: &[&DerivedString], args: &Args) -> io::Result<()> {
+    // Derived strings are listed at their loop's offset among the literals' first definitions.
+    let mut derived: std::iter::Peekable<std::slice::Iter<&DerivedString>> = derived.iter().peekable();
+    for entry in entries {
+        while let Some(found) = derived.next_if(|found| found.offset < entry.definitions[0].offset) {
+            write_derived(out, found, args)?;
+        }
+        let value: String = format::quoted_bytes(&entry.value, &entry.bytes, args.raw_strings);
+        if !args.uses {
+            let first: &Definition = &entry.definitions[0];
+            writeln!(out, "  {:#06x}  {:>5}x  reg{:<4} {value}", first.offset, entry.definitions.len(), first.reg)?;
+            continue;
+        }
+
+        writeln!(out, "{value}")?;
+        let mut rows: Vec<(usize, &str, String)> = entry.definitions.iter().map(|definition| (definition.offset, "def", format!("reg{}", definition.reg))).collect();
+        rows.extend(entry.uses.iter().map(|usage| (usage.offset, "use", usage.to_string())));
+        rows.sort_by_key(|(offset, _, _)| *offset);
+        for (offset, kind, detail) in rows {
+            writeln!(out, "  {offset:#06x}  {kind}  {detail}")?;
+        }
+    }
+    for found in derived {
+        write_derived(out, found, args)?;
+    }
+    Ok(())
+}
+
+fn write_derived(out: &mut dyn Write, derived: &DerivedString, args: &Args) -> io::Result<()> {
+    let value: String = derived_text(derived, args);
+    if args.uses {
+        writeln!(out, "{value}")?;
+        writeln!(out, "  {:#06x}  loop  derived in reg{}", derived.offset, derived.reg)
+    } else {
+        writeln!(out, "  {:#06x}  derived reg{:<4} {value}", derived.offset, derived.reg)
+    }
+}
+
+fn write_json(out: &mut dyn Write, entries: &[&StringEntry], derived: &[&DerivedString]) -> io::Result<()> {
+    let strings: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let bytes: Vec<String> = entry.bytes.iter().map(|byte| byte.to_string()).collect();
+            let definitions: Vec<String> = entry
+                .definitions
+                .iter()
+                .map(|definition| format!("{{\"offset\":{},\"reg\":{}}}", definition.offset, definition.reg))
+                .collect();
+            let uses: Vec<String> = entry
+                .uses
+                .iter()
+                .map(|usage| format!("{{\"offset\":{},\"opcode\":\"{}\",\"role\":\"{}\"}}", usage.offset, usage.opcode.as_str(), usage.role.as_str()))
+                .collect();
+            format!(
+                "{{\"value\":{},\"bytes\":[{}],\"definitions\":[{}],\"uses\":[{}]}}",
+                format::json_string(&entry.value),
+                bytes.join(","),
+                definitions.join(","),
+                uses.join(",")
+            )
+        })
+        .collect();
+    let derived: Vec<String> = derived
+        .iter()
+        .map(|derived| {
+            let result: String = match &derived.result {
+                Ok(value) => format!("\"value\":{}", format::json_string(value)),
+                Err(dynamic) => format!("\"dynamic\":{}", format::json_string(&dynamic.to_string()))
+            };
+            format!("{{\"offset\":{},\"reg\":{},{result}}}", derived.offset, derived.reg)
+        })
+        .collect();
+    let separator: &str = if strings.is_empty() { "" } else { "\n" };
+    let derived_separator: &str = if derived.is_empty() { "" } else { "\n" };
+    writeln!(
+        out,
+        "{{\"strings\":[{separator}{}{separator}],\"derived\":[{derived_separator}{}{derived_separator}]}}",
+        strings.join(",\n"),
+        derived.join(",\n")
+    )
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    let table: StringTable = StringTable::new(&listing);
+    let entries: Vec<&StringEntry> = table.entries.iter().filter(|entry| entry.value.chars().count() >= args.min_length).collect();
+    let derived_strings: DerivedStrings = DerivedStrings::new(&listing, DEFAULT_MAX_ITERATIONS);
+    // Dynamic strings have no length to filter on, so they are always listed.
+    let derived: Vec<&DerivedString> = derived_strings
+        .strings
+        .iter()
+        .filter(|derived| !matches!(&derived.result, Ok(value) if value.chars().count() < args.min_length))
+        .collect();
+
+    let written: io::Result<()> = match args.format {
+        Format::Json => write_json(&mut out, &entries, &derived),
+        _ => write_text(&mut out, &entries, &derived, args)
+    };
+    run::finish(input, output, result, written.and_then(|()| out.flush()))?;
+    run::check_string_key(args, &disasm);
+
+    Ok(listing.instruction_count)
+}
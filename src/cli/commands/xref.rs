@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use crate::cli::args::{Args, XrefQuery};
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::run;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::instruction::Instruction;
+use bet365::disassembler::listing::{Listing, RegisterXref};
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 { format!("{count} {noun}") } else { format!("{count} {noun}s") }
+}
+
+fn write_text(out: &mut dyn Write, xrefs: &BTreeMap<u8, Vec<RegisterXref>>, listing: &Listing, disasm: &Disassembler) -> io::Result<()> {
+    let instructions: BTreeMap<usize, &Instruction> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction)).collect();
+    for (index, (reg, xrefs)) in xrefs.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        let writes: usize = xrefs.iter().filter(|xref| xref.access.writes()).count();
+        let reads: usize = xrefs.iter().filter(|xref| xref.access.reads()).count();
+        writeln!(out, "reg{reg}: {}, {}", plural(writes, "write"), plural(reads, "read"))?;
+        for xref in xrefs {
+            let text: String = instructions[&xref.offset].text(disasm.targets, disasm.raw_strings);
+            writeln!(out, "  {:#06x}  {:<6}  {text}", xref.offset, xref.access.as_str())?;
+        }
+    }
+    Ok(())
+}
+
+fn xref_json(xref: &RegisterXref) -> String {
+    format!("{{\"offset\":{},\"opcode\":{},\"access\":\"{}\"}}", xref.offset, format::json_string(xref.opcode.as_str()), xref.access.as_str())
+}
+
+fn write_json(out: &mut dyn Write, xrefs: &BTreeMap<u8, Vec<RegisterXref>>) -> io::Result<()> {
+    let registers: Vec<String> = xrefs
+        .iter()
+        .map(|(reg, xrefs)| {
+            let writes: Vec<String> = xrefs.iter().filter(|xref| xref.access.writes()).map(xref_json).collect();
+            let reads: Vec<String> = xrefs.iter().filter(|xref| xref.access.reads()).map(xref_json).collect();
+            format!("{{\"reg\":{reg},\"writes\":[{}],\"reads\":[{}]}}", writes.join(","), reads.join(","))
+        })
+        .collect();
+    let separator: &str = if registers.is_empty() { "" } else { "\n" };
+    writeln!(out, "{{\"registers\":[{separator}{}{separator}]}}", registers.join(",\n"))
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut out: Box<dyn Write> = run::open_output(output)?;
+
+    let (listing, result): (Listing, Result<(), DisassemblerError>) = match disasm.execute(|_| Ok(())) {
+        Ok(listing) => (listing, Ok(())),
+        Err(err) => (*err.listing, Err(err.error))
+    };
+    let xrefs: BTreeMap<u8, Vec<RegisterXref>> = match &args.xref {
+        Some(XrefQuery::Registers(regs)) => regs.iter().map(|&reg| (reg, listing.xrefs(reg))).collect(),
+        _ => listing.register_xrefs()
+    };
+
+    let written: io::Result<()> = match args.format {
+        Format::Json => write_json(&mut out, &xrefs),
+        _ => write_text(&mut out, &xrefs, &listing, &disasm)
+    };
+    run::finish(input, output, result, written.and_then(|()| out.flush()))?;
+
+    Ok(listing.instruction_count)
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_CONFIG_PATH: &str = "bet365disasm.toml";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Flag,
+    Value,
+    Path,
+    List,
+    PathList
+}
+
+const KEYS: &[(&str, Kind)] = &[
+    ("output", Kind::Value),
+    ("quiet", Kind::Flag),
+    ("no-color", Kind::Flag),
+    ("start", Kind::Value),
+    ("entry", Kind::Value),
+    ("end", Kind::Value),
+    ("max-instructions", Kind::Value),
+    ("follow-jumps", Kind::Flag),
+    ("max-visited", Kind::Value),
+    ("function", Kind::List),
+    ("function-bytes", Kind::Value),
+    ("lenient", Kind::Flag),
+    ("fail-fast", Kind::Flag),
+    ("resync", Kind::Flag),
+    ("resync-window", Kind::Value),
+    ("resync-limit", Kind::Value),
+    ("string-key", Kind::Value),
+    ("opcode-map", Kind::Path),
+    ("profile", Kind::Value),
+    ("profile-map", Kind::PathList),
+    ("annotations", Kind::Path),
+    ("bytes", Kind::Flag),
+    ("no-addresses", Kind::Flag),
+    ("legacy-addresses", Kind::Flag),
+    ("raw-strings", Kind::Flag),
+    ("labels", Kind::Flag),
+    ("collapse", Kind::Flag),
+    ("collapse-depth", Kind::Value),
+    ("liveness", Kind::Flag),
+    ("loops", Kind::Flag),
+    ("try-regions", Kind::Flag),
+    ("indent-frames", Kind::Flag),
+    ("rename", Kind::Flag),
+    ("rename-map", Kind::Path),
+    ("emulate", Kind::Flag),
+    ("emulate-steps", Kind::Value),
+    ("sections", Kind::Flag),
+    ("show-unreachable", Kind::Flag),
+    ("xref", Kind::List),
+    ("registers", Kind::Flag),
+    ("track-registers", Kind::Value),
+    ("naive-tracking", Kind::Flag),
+    ("fold-constants", Kind::Flag),
+    ("time", Kind::Flag),
+    ("format", Kind::Value),
+    ("regex", Kind::Flag),
+    ("context", Kind::Value),
+    ("min-length", Kind::Value),
+    ("roots", Kind::Flag),
+    ("uses", Kind::Flag),
+    ("progress", Kind::Flag)
+];
+
+fn unquote(value: &str) -> Option<String> {
+    let inner: &str = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out: String = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?),
+            '"' => return None,
+            c => out.push(c)
+        }
+    }
+    Some(out)
+}
+
+fn scalar(value: &str) -> Option<String> {
+    match unquote(value) {
+        Some(value) => Some(value),
+        None if !value.is_empty() && !value.contains(['"', '[', ']', ' ']) => Some(value.to_string()),
+        None => None
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string: bool = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub fn load(path: &str) -> Result<Vec<String>, String> {
+    let source: String = fs::read_to_string(path).map_err(|err| format!("could not read config '{path}': {err}"))?;
+    let base: &Path = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut args: Vec<String> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line: &str = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let at: String = format!("{path}:{}", index + 1);
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{at}: expected 'key = value'"));
+        };
+        let key: String = key.trim().trim_matches('"').replace('_', "-");
+        let value: &str = value.trim();
+        let Some(&(_, kind)) = KEYS.iter().find(|(name, _)| *name == key) else {
+            let valid: Vec<&str> = KEYS.iter().map(|(name, _)| *name).collect();
+            return Err(format!("{at}: unknown key '{key}' (valid keys: {})", valid.join(", ")));
+        };
+        let flag: String = format!("--{key}");
+
+        match kind {
+            Kind::Flag => match value {
+                "true" => args.push(flag),
+                "false" => {}
+                _ => return Err(format!("{at}: '{key}' must be true or false"))
+            },
+            Kind::Value | Kind::Path => {
+                let Some(mut value) = scalar(value) else {
+                    return Err(format!("{at}: invalid value for '{key}'"));
+                };
+                if kind == Kind::Path && Path::new(&value).is_relative() {
+                    value = base.join(&value).to_string_lossy().into_owned();
+                }
+                args.push(flag);
+                args.push(value);
+            }
+            Kind::List | Kind::PathList => {
+                let Some(items) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) else {
+                    return Err(format!("{at}: '{key}' must be a list, e.g. {key} = [1, 2]"));
+                };
+                for item in items.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+                    let Some(mut item) = scalar(item) else {
+                        return Err(format!("{at}: invalid value in '{key}'"));
+                    };
+                    if kind == Kind::PathList && Path::new(&item).is_relative() {
+                        item = base.join(&item).to_string_lossy().into_owned();
+                    }
+                    args.push(flag.clone());
+                    args.push(item);
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+pub fn defaults(cli: &[String], discover: bool) -> Result<Vec<String>, String> {
+    if let Some(index) = cli.iter().position(|arg| arg == "--config") {
+        let path: &String = cli.get(index + 1).ok_or("option '--config' requires a value")?;
+        return load(path);
+    }
+    if discover && Path::new(DEFAULT_CONFIG_PATH).is_file() {
+        return load(DEFAULT_CONFIG_PATH);
+    }
+    Ok(Vec::new())
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+pub enum CliError {
+    Input(String),
+    Decode(String),
+    UnknownOpcode(String),
+    Output(String)
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Output(_) => 1,
+            CliError::Input(_) => 2,
+            CliError::Decode(_) => 3,
+            CliError::UnknownOpcode(_) => 4
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Input(message)
+            | CliError::Decode(message)
+            | CliError::UnknownOpcode(message)
+            | CliError::Output(message) => write!(f, "{message}")
+        }
+    }
+}
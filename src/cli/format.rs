@@ -0,0 +1,91 @@
+use bet365::disassembler::codec::{escape_bytes, escape_string};
+use bet365::disassembler::disassembler::TraceLine;
+use bet365::disassembler::registers::RegValue;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None
+        }
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut out: String = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value}")
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn quoted(value: &str, raw: bool) -> String {
+    if raw {
+        format!("'{value}'")
+    } else {
+        format!("'{}'", escape_string(value))
+    }
+}
+
+pub fn quoted_bytes(value: &str, bytes: &[u8], raw: bool) -> String {
+    if raw {
+        format!("'{value}'")
+    } else {
+        format!("'{}'", escape_bytes(bytes))
+    }
+}
+
+pub fn reg_value(value: &RegValue, raw: bool) -> String {
+    match value {
+        RegValue::Str(value) => quoted(value, raw),
+        value => value.to_string()
+    }
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub const CSV_HEADER: &str = "offset,opcode,operands,text";
+
+pub fn csv_line(line: &TraceLine) -> String {
+    let operands: Vec<String> = line.operands.iter().map(|operand| operand.to_string()).collect();
+    format!(
+        "{},{},{},{}",
+        line.start,
+        csv_field(line.opcode.as_str()),
+        csv_field(&operands.join(" ")),
+        csv_field(line.text)
+    )
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+
+pub enum Input {
+    File(String),
+    Stdin
+}
+
+impl Input {
+    pub fn resolve(path: Option<&str>, default_path: &str) -> Self {
+        match path {
+            Some("-") => Input::Stdin,
+            Some(path) => Input::File(path.to_string()),
+            None if !io::stdin().is_terminal() => Input::Stdin,
+            None => Input::File(default_path.to_string())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Input::File(path) => path,
+            Input::Stdin => "<stdin>"
+        }
+    }
+
+    pub fn read_to_string(&self) -> io::Result<String> {
+        match self {
+            Input::File(path) => fs::read_to_string(path),
+            Input::Stdin => {
+                let mut buf: String = String::new();
+                io::stdin().lock().read_to_string(&mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, BufRead, Write};
+use crate::cli::args::{parse_count, parse_offset, Args};
+use crate::cli::error::CliError;
+use crate::cli::format::{self, Format};
+use crate::cli::input::Input;
+use crate::cli::printer::{self, Addresses, Printer};
+use crate::cli::run;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+
+const HELP: &str = "\
+  s [N]       step N instructions (default 1; an empty line steps once)
+  c           continue to the end of the range
+  r <N>       print the tracked value of register N
+  g <OFFSET>  move to byte OFFSET (decimal or 0x hex)
+  q           quit";
+
+fn step(disasm: &mut Disassembler, printer: &mut Printer) -> Result<bool, CliError> {
+    match disasm.step() {
+        Ok(Some(line)) => {
+            printer.print(&line).map_err(|err| run::write_error(None, err))?;
+            Ok(true)
+        }
+        Ok(None) => {
+            match disasm.overrun {
+                Some(offset) => println!("instruction at offset {offset:#06x} extends past the end of the range"),
+                None => println!("end of range")
+            }
+            Ok(false)
+        }
+        Err(err @ DisassemblerError::UnknownOpcode { .. }) => {
+            println!("{err}; use 'g' to move past it");
+            Ok(false)
+        }
+        Err(DisassemblerError::Io(err)) => Err(run::write_error(None, err)),
+        Err(err) => Err(CliError::Decode(err.to_string()))
+    }
+}
+
+fn command(line: &str, disasm: &mut Disassembler, printer: &mut Printer) -> Result<bool, CliError> {
+    let mut words = line.split_whitespace();
+    let name: &str = words.next().unwrap_or("s");
+    let value: Option<&str> = words.next();
+
+    match (name, value) {
+        ("s", count) => {
+            let count: usize = match count.map(|count| parse_count("s", count)) {
+                Some(Ok(count)) => count,
+                Some(Err(err)) => {
+                    println!("{err}");
+                    return Ok(true);
+                }
+                None => 1
+            };
+            for _ in 0..count {
+                if !step(disasm, printer)? {
+                    break;
+                }
+            }
+        }
+        ("c", None) => while step(disasm, printer)? {},
+        ("r", Some(reg)) => match reg.trim_start_matches("reg").parse::<u8>() {
+            Ok(reg) => match disasm.registers.value(reg) {
+                Some(value) => println!("reg{reg} = {}", format::reg_value(value, disasm.raw_strings)),
+                None => println!("reg{reg} has no tracked value")
+            },
+            Err(_) => println!("invalid register '{reg}' (expected 0-255)")
+        },
+        ("g", Some(offset)) => match parse_offset("g", offset).and_then(|offset| disasm.seek(offset).map_err(|err| err.to_string())) {
+            Ok(()) => {}
+            Err(err) => println!("{err}")
+        },
+        ("q", None) => return Ok(false),
+        ("h" | "?", None) => println!("{HELP}"),
+        _ => println!("unknown command '{}' (h for help)", line.trim())
+    }
+    Ok(true)
+}
+
+pub fn run(args: &Args, input: &Input) -> Result<(), CliError> {
+    if let Input::Stdin = input {
+        return Err(CliError::Input("--interactive reads commands from stdin; pass the bytecode as a file".to_string()));
+    }
+
+    let mut disasm: Disassembler = run::load(args, input)?;
+    let mut printer: Printer = Printer::new(Box::new(io::stdout()), Format::Text, printer::should_color(args.no_color), args.bytes, Addresses::from_args(args));
+
+    println!("{}: {} bytes to decode, h for help", input.name(), disasm.remaining());
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().map_err(|err| run::write_error(None, err))?;
+
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        let line: String = line.map_err(|err| CliError::Input(format!("could not read command: {err}")))?;
+        if !command(&line, &mut disasm, &mut printer)? {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub mod annotations;
+pub mod args;
+pub mod commands;
+pub mod config;
+pub mod error;
+pub mod format;
+pub mod input;
+pub mod interactive;
+pub mod opcode_map;
+pub mod printer;
+pub mod progress;
+pub mod rename_map;
+pub mod run;
+pub mod schema;
+pub mod watch;
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use crate::cli::args::parse_byte;
+use crate::cli::error::CliError;
+use bet365::disassembler::instructions::OpcodeTable;
+
+pub fn load(path: &str) -> Result<OpcodeTable, CliError> {
+    let source: String = fs::read_to_string(path)
+        .map_err(|err| CliError::Input(format!("could not read opcode map '{path}': {err}")))?;
+
+    let entries: Vec<(String, String)> = if source.trim_start().starts_with('{') {
+        parse_json(&source)
+    } else {
+        parse_toml(&source)
+    }
+    .map_err(|err| CliError::Input(format!("{path}: {err}")))?;
+
+    let mut profile: String = Path::new(path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut notes: Option<String> = None;
+    let mut pairs: Vec<(u8, &str)> = Vec::new();
+    for (key, value) in &entries {
+        match key.as_str() {
+            "name" => profile = value.clone(),
+            "notes" => notes = Some(value.clone()),
+            _ => {
+                let byte: u8 = parse_byte("opcode", key).map_err(|err| CliError::Input(format!("{path}: {err}")))?;
+                pairs.push((byte, value));
+            }
+        }
+    }
+    let table: OpcodeTable = OpcodeTable::from_pairs(&pairs).map_err(|err| CliError::Input(format!("{path}: {err}")))?;
+    Ok(table.with_name(&profile, notes))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected '{expected}', found '{c}'")),
+        None => Err(format!("expected '{expected}', found end of file"))
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut string: String = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string())
+            },
+            Some(c) => string.push(c),
+            None => return Err("unterminated string".to_string())
+        }
+    }
+}
+
+fn parse_json(source: &str) -> Result<Vec<(String, String)>, String> {
+    let mut chars: Peekable<Chars> = source.chars().peekable();
+    let mut entries: Vec<(String, String)> = Vec::new();
+
+    expect(&mut chars, '{')?;
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_none() {
+        loop {
+            let key: String = parse_string(&mut chars)?;
+            expect(&mut chars, ':')?;
+            let value: String = parse_string(&mut chars)?;
+            entries.push((key, value));
+
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                Some(c) => return Err(format!("expected ',' or '}}', found '{c}'")),
+                None => return Err("unterminated object".to_string())
+            }
+        }
+    }
+
+    skip_whitespace(&mut chars);
+    if let Some(c) = chars.next() {
+        return Err(format!("unexpected '{c}' after the mapping object"));
+    }
+    Ok(entries)
+}
+
+fn parse_toml(source: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line: &str = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected 'OPCODE = \"handler\"'", index + 1));
+        };
+        let key: &str = key.trim().trim_matches('"');
+        let value: String = parse_string(&mut value.chars().peekable()).map_err(|err| format!("line {}: {err}", index + 1))?;
+        entries.push((key.to_string(), value));
+    }
+    Ok(entries)
+}
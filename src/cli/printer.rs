@@ -0,0 +1,413 @@
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use crate::cli::args::Args;
+use crate::cli::format::{self, Format};
+use crate::cli::schema::{JsonError, JsonFunction, JsonInstruction, JsonLabel, JsonListing, JsonRegion, JsonWarning};
+use bet365::disassembler::disassembler::{Disassembler, TraceLine};
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::emulator::Emulation;
+use bet365::disassembler::exceptions::TryRegions;
+use bet365::disassembler::expressions::Expressions;
+use bet365::disassembler::functions::Functions;
+use bet365::disassembler::instruction::{Instruction, TargetFormat};
+use bet365::disassembler::listing::{FrameWarning, Listing, TargetWarning};
+use bet365::disassembler::liveness::Liveness;
+use bet365::disassembler::loops::Loops;
+use bet365::disassembler::names::RegisterNames;
+use bet365::disassembler::opcodes::OpCategory;
+use bet365::disassembler::reachability::Reachability;
+use bet365::disassembler::symbols::Symbols;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+
+const BYTES_PER_ROW: usize = 8;
+const MAX_BYTE_ROWS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Addresses {
+    Off,
+    Hex,
+    Legacy
+}
+
+impl Addresses {
+    pub fn from_args(args: &Args) -> Self {
+        if args.no_addresses {
+            Addresses::Off
+        } else if args.legacy_addresses {
+            Addresses::Legacy
+        } else {
+            Addresses::Hex
+        }
+    }
+
+    pub fn targets(self) -> TargetFormat {
+        match self {
+            Addresses::Off => TargetFormat::Relative,
+            Addresses::Hex => TargetFormat::Hex,
+            Addresses::Legacy => TargetFormat::Decimal
+        }
+    }
+
+    pub fn prefix(self, line: &TraceLine) -> String {
+        let mut prefix: String = String::new();
+        self.write_prefix(line, &mut prefix);
+        prefix
+    }
+
+    pub fn write_prefix(self, line: &TraceLine, out: &mut String) {
+        let _ = match self {
+            Addresses::Off => Ok(()),
+            Addresses::Hex => write!(out, "{:#06x}    ", line.start),
+            Addresses::Legacy => write!(out, "0x{}    ", line.ptr)
+        };
+    }
+}
+
+pub struct Printer {
+    out: Box<dyn Write>,
+    format: Format,
+    color: bool,
+    show_bytes: bool,
+    addresses: Addresses,
+    lines: usize,
+    address: String,
+    symbols: Option<Symbols>,
+    reachability: Option<Reachability>,
+    expressions: Option<Expressions>,
+    liveness: Option<Liveness>,
+    loops: Vec<Loops>,
+    try_regions: Option<TryRegions>,
+    names: Option<RegisterNames>,
+    indent_frames: bool,
+    emulations: Vec<Emulation>,
+    json: JsonListing
+}
+
+fn category_color(category: OpCategory) -> &'static str {
+    match category {
+        OpCategory::Constant => "\x1b[32m",
+        OpCategory::Property => "\x1b[34m",
+        OpCategory::Call => "\x1b[94m",
+        OpCategory::Arithmetic => "\x1b[35m",
+        OpCategory::Bitwise => "\x1b[95m",
+        OpCategory::Comparison => "\x1b[36m",
+        OpCategory::ControlFlow => "\x1b[33m",
+        OpCategory::Data => "\x1b[31m"
+    }
+}
+
+pub fn should_color(no_color: bool) -> bool {
+    let env_disabled: bool = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && !env_disabled && io::stdout().is_terminal()
+}
+
+fn hex_rows(bytes: &[u8]) -> Vec<String> {
+    let mut rows: Vec<String> = bytes
+        .chunks(BYTES_PER_ROW)
+        .take(MAX_BYTE_ROWS)
+        .map(|chunk| chunk.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<String>>().join(" "))
+        .collect();
+
+    let shown: usize = BYTES_PER_ROW * MAX_BYTE_ROWS;
+    if bytes.len() > shown {
+        let last: usize = rows.len() - 1;
+        rows[last].push_str(&format!(" .. +{} bytes", bytes.len() - shown));
+    }
+    rows
+}
+
+impl Printer {
+    pub fn new(out: Box<dyn Write>, format: Format, color: bool, show_bytes: bool, addresses: Addresses) -> Self {
+        Self {
+            out,
+            format,
+            color: color && format == Format::Text,
+            show_bytes,
+            addresses,
+            lines: 0,
+            address: String::new(),
+            symbols: None,
+            reachability: None,
+            expressions: None,
+            liveness: None,
+            loops: Vec::new(),
+            try_regions: None,
+            names: None,
+            indent_frames: false,
+            emulations: Vec::new(),
+            json: JsonListing::default()
+        }
+    }
+
+    pub fn label(&mut self, text: &str) -> io::Result<()> {
+        if self.format != Format::Text {
+            return Ok(());
+        }
+        let (dim, reset): (&str, &str) = if self.color { (DIM, RESET) } else { ("", "") };
+        writeln!(self.out, "{dim}{text}{reset}")
+    }
+
+    pub fn print(&mut self, line: &TraceLine) -> io::Result<()> {
+        self.lines += 1;
+        match self.format {
+            Format::Text => self.print_text(line),
+            Format::Json => {
+                let mut instruction: JsonInstruction = JsonInstruction::from(line);
+                if let Some(names) = &self.names {
+                    instruction.name_registers(|reg| names.display(line.start, reg));
+                }
+                if self.indent_frames {
+                    instruction.depth = Some(line.depth);
+                }
+                self.json.instructions.push(instruction);
+                Ok(())
+            }
+            Format::Csv => {
+                if self.lines == 1 {
+                    writeln!(self.out, "{}", format::CSV_HEADER)?;
+                }
+                writeln!(self.out, "{}", format::csv_line(line))
+            }
+        }
+    }
+
+    pub fn fail(&mut self, err: &DisassemblerError) {
+        self.json.error = Some(JsonError::from(err));
+    }
+
+    pub fn warn(&mut self, warning: &TargetWarning) {
+        self.json.warnings.push(JsonWarning::from(warning));
+    }
+
+    pub fn warn_frames(&mut self, warning: &FrameWarning) {
+        self.json.warnings.push(JsonWarning::from(warning));
+    }
+
+    pub fn functions(&mut self, functions: &Functions) {
+        self.json.functions.extend(functions.functions.iter().map(JsonFunction::from));
+        self.json.warnings.extend(functions.overlaps.iter().map(JsonWarning::from));
+    }
+
+    /// Labels the targets of the instructions printed from now on; the labels are added to the json listing.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.json.labels.extend(symbols.labels().map(JsonLabel::from));
+        self.symbols = Some(symbols);
+    }
+
+    /// Marks dead instructions printed from now on; the dead regions are added to the json listing.
+    pub fn set_reachability(&mut self, reachability: Reachability) {
+        self.json.unreachable.extend(reachability.regions.iter().map(JsonRegion::from));
+        self.reachability = Some(reachability);
+    }
+
+    /// Shows the collapsed expression rooted at each instruction printed from now on as a comment.
+    pub fn set_expressions(&mut self, expressions: Expressions) {
+        self.expressions = Some(expressions);
+    }
+
+    /// Lists the registers live after each instruction printed from now on as a comment.
+    pub fn set_liveness(&mut self, liveness: Liveness) {
+        self.liveness = Some(liveness);
+    }
+
+    /// Marks loop headers, back-edges and irreducible edges in the instructions printed from now on.
+    pub fn set_loops(&mut self, loops: Loops) {
+        self.loops.push(loops);
+    }
+
+    /// Every set of loops given to `set_loops`, oldest first.
+    pub fn loops(&self) -> &[Loops] {
+        &self.loops
+    }
+
+    /// Shows what each instruction printed from now on computed when emulated.
+    pub fn set_emulation(&mut self, emulation: Emulation) {
+        self.emulations.push(emulation);
+    }
+
+    /// Every emulation given to `set_emulation`, oldest first.
+    pub fn emulations(&self) -> &[Emulation] {
+        &self.emulations
+    }
+
+    /// Brackets the try bodies, handlers and continuations in the instructions printed from now on.
+    pub fn set_try_regions(&mut self, regions: TryRegions) {
+        self.try_regions = Some(regions);
+    }
+
+    /// Writes registers by their name in `names` in the instructions printed from now on.
+    pub fn set_names(&mut self, names: RegisterNames) {
+        self.names = Some(names);
+    }
+
+    /// Indents the instructions printed from now on by their JUMP FRAME depth, and gives json instructions a depth.
+    pub fn set_indent_frames(&mut self, indent: bool) {
+        self.indent_frames = indent;
+    }
+
+    /// `reg` as written at `offset`.
+    fn reg(&self, offset: usize, reg: u8) -> String {
+        match &self.names {
+            Some(names) => names.display(offset, reg),
+            None => format!("reg{reg}")
+        }
+    }
+
+    /// The `} catch`, `} finally` and `} // end try` lines due before `offset`, innermost region first.
+    fn try_markers(&self, offset: usize) -> Vec<String> {
+        let Some(regions) = &self.try_regions else {
+            return Vec::new();
+        };
+        let mut markers: Vec<(std::cmp::Reverse<usize>, usize, String)> = Vec::new();
+        for region in &regions.regions {
+            let indent: String = "  ".repeat(region.depth);
+            let depth = std::cmp::Reverse(region.depth);
+            if region.catch_at == offset {
+                markers.push((depth, 0, format!("; {indent}}} catch ({}) {{", self.reg(region.catch_at, region.reg))));
+            }
+            if region.finally_at == offset {
+                markers.push((depth, 1, format!("; {indent}}} finally {{")));
+            }
+            if region.continue_at == offset {
+                markers.push((depth, 2, format!("; {indent}}} // end try")));
+            }
+        }
+        markers.sort();
+        markers.into_iter().map(|(_, _, marker)| marker).collect()
+    }
+
+    pub fn print_instruction(&mut self, instruction: &Instruction, disasm: &Disassembler) -> io::Result<()> {
+        for marker in self.try_markers(instruction.offset) {
+            self.label(&marker)?;
+        }
+        if let Some(label) = self.symbols.as_ref().and_then(|symbols| symbols.get(instruction.offset)).map(|label| format!("{label}:")) {
+            self.label(&label)?;
+        }
+        let mut text: String = match &self.names {
+            Some(names) => instruction.named_text(disasm.targets, disasm.raw_strings, self.symbols.as_ref(), names),
+            None => instruction.labeled_text(disasm.targets, disasm.raw_strings, self.symbols.as_ref())
+        };
+        let mut notes: Vec<String> = Vec::new();
+        if self.reachability.as_ref().is_some_and(|reachability| reachability.is_dead(instruction.offset)) {
+            notes.push("unreachable".to_string());
+        }
+        if let Some(loops) = self.loops.last() {
+            notes.extend(loops.header_at(instruction.offset).map(|found| format!("loop {} header", found.name())));
+            notes.extend(loops.back_edges_at(instruction.offset).map(|found| format!("back-edge to {}", found.name())));
+            notes.extend(loops.irreducible_at(instruction.offset).map(|edge| format!("irreducible edge to {:#06x}", edge.target)));
+        }
+        if let Some(expression) = self.expressions.as_ref().and_then(|expressions| expressions.get(instruction.offset)) {
+            notes.push(expression.text.clone());
+        }
+        if let Some(produced) = self.emulations.last().and_then(|emulation| emulation.get(instruction.offset)).filter(|produced| produced.is_concrete() && instruction.opcode.category() != OpCategory::Constant) {
+            notes.push(produced.to_string());
+        }
+        if let Some(live) = self.liveness.as_ref().and_then(|liveness| liveness.live_after(instruction.offset)) {
+            let live: Vec<String> = live.iter().map(|reg| self.reg(instruction.offset, reg)).collect();
+            notes.push(format!("live {{{}}}", live.join(", ")));
+        }
+        if !notes.is_empty() {
+            text.push_str(if instruction.comment.is_some() { "; " } else { "    ; " });
+            text.push_str(&notes.join("; "));
+        }
+        self.print(&TraceLine::new(instruction, disasm.bytes(), &text))?;
+        if let Some(region) = self.try_regions.as_ref().and_then(|regions| regions.at(instruction.offset)) {
+            let marker: String = format!("; {}try {{", "  ".repeat(region.depth));
+            self.label(&marker)?;
+        }
+        Ok(())
+    }
+
+    pub fn print_listing(&mut self, listing: &Listing, disasm: &Disassembler) -> io::Result<()> {
+        for instruction in &listing.instructions {
+            self.print_instruction(instruction, disasm)?;
+        }
+        Ok(())
+    }
+
+    fn print_text(&mut self, line: &TraceLine) -> io::Result<()> {
+        let (dim, color, reset): (&str, &str, &str) = if self.color {
+            (DIM, category_color(line.opcode.category()), RESET)
+        } else {
+            ("", "", "")
+        };
+        self.address.clear();
+        self.addresses.write_prefix(line, &mut self.address);
+        let address: &str = &self.address;
+        let indent: String = if self.indent_frames { "  ".repeat(line.depth) } else { String::new() };
+
+        if !self.show_bytes {
+            return writeln!(self.out, "{dim}{address}{reset}{indent}{color}{}{reset}", line.text);
+        }
+
+        let rows: Vec<String> = hex_rows(line.bytes);
+        let width: usize = BYTES_PER_ROW * 3 - 1;
+        writeln!(self.out, "{dim}{address}{:<width$}{reset}    {indent}{color}{}{reset}", rows[0], line.text)?;
+        for row in &rows[1..] {
+            writeln!(self.out, "{dim}{:indent$}{row}{reset}", "", indent = address.len())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        match (self.format, self.lines) {
+            (Format::Json, _) => {
+                serde_json::to_writer(&mut self.out, &self.json)?;
+                writeln!(self.out)?;
+            }
+            (Format::Csv, 0) => writeln!(self.out, "{}", format::CSV_HEADER)?,
+            _ => {}
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+    use bet365::disassembler::opcodes::OpCodes;
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn addresses_are_start_offsets_of_mixed_length_instructions() {
+        let bytes: Vec<u8> = vec![
+            181, 1, 7, // LOAD IMM8 reg1, 7
+            23, 2, 0, 3, b'f' ^ 50, b'o' ^ 50, b'o' ^ 50, // NEW VALUE reg2, 'foo'
+            215, 3, 2, 2, 4, 5, // CALL FUNCTION reg3, reg2, [reg4,reg5]
+            51, 6, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0, // LOAD DOUBLE reg6, 1.5
+            88, 7, 0, // PUSH ARGS reg7, []
+            166 // HALT
+        ];
+        let buffer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        let mut printer: Printer = Printer::new(Box::new(Shared(buffer.clone())), Format::Text, false, false, Addresses::Hex);
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes);
+        let listing: Listing = disasm.execute(|line| printer.print(line)).unwrap();
+        printer.finish().unwrap();
+
+        let layout: Vec<(usize, OpCodes, usize)> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction.opcode, instruction.length)).collect();
+        assert_eq!(
+            layout,
+            [(0, OpCodes::LoadImm8, 3), (3, OpCodes::NewValue, 7), (10, OpCodes::CallFunction, 6), (16, OpCodes::LoadDouble, 10), (26, OpCodes::PushArgs, 3), (29, OpCodes::Halt, 1)]
+        );
+        let output: String = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let addresses: Vec<&str> = output.lines().map(|line| line.split_whitespace().next().unwrap()).collect();
+        assert_eq!(addresses, ["0x0000", "0x0003", "0x000a", "0x0010", "0x001a", "0x001d"]);
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const CHECK_EVERY: usize = 1024;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct Progress {
+    enabled: bool,
+    start: usize,
+    total: usize,
+    ticks: usize,
+    last: Instant
+}
+
+impl Progress {
+    pub fn new(requested: bool, listing_on_terminal: bool, start: usize, total: usize) -> Self {
+        Self {
+            enabled: requested && !listing_on_terminal && io::stderr().is_terminal(),
+            start,
+            total,
+            ticks: 0,
+            last: Instant::now()
+        }
+    }
+
+    pub fn tick(&mut self, ptr: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        self.ticks += 1;
+        if !self.ticks.is_multiple_of(CHECK_EVERY) || self.last.elapsed() < REFRESH_INTERVAL {
+            return;
+        }
+        self.last = Instant::now();
+
+        let done: usize = ptr.saturating_sub(self.start);
+        let percent: f64 = done as f64 * 100.0 / self.total.max(1) as f64;
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{percent:5.1}% ({done}/{} bytes)", self.total);
+        let _ = stderr.flush();
+    }
+
+    pub fn finish(&mut self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
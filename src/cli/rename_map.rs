@@ -0,0 +1,49 @@
+use std::fs;
+use crate::cli::args::{parse_byte, parse_offset};
+use crate::cli::error::CliError;
+use bet365::disassembler::functions::Functions;
+use bet365::disassembler::names::{Pin, PinScope};
+
+fn identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+pub fn load(path: &str) -> Result<Vec<Pin>, CliError> {
+    let source: String = fs::read_to_string(path)
+        .map_err(|err| CliError::Input(format!("could not read rename map '{path}': {err}")))?;
+
+    let mut pins: Vec<Pin> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let at = |err: String| CliError::Input(format!("{path}:{}: {err}", index + 1));
+        let [scope, reg, name] = line.split_whitespace().collect::<Vec<&str>>()[..] else {
+            return Err(at("expected 'FUNCTION REG NAME'".to_string()));
+        };
+        let scope: PinScope = match scope {
+            "*" => PinScope::All,
+            "outside" => PinScope::Outside,
+            entry => PinScope::Function(parse_offset("rename map", entry).map_err(at)?)
+        };
+        let reg: u8 = parse_byte("rename map", reg.strip_prefix("reg").unwrap_or(reg)).map_err(at)?;
+        if !identifier(name) {
+            return Err(at(format!("invalid name '{name}' (expected letters, digits, '_' or '$', not starting with a digit)")));
+        }
+        pins.push(Pin { scope, reg, name: name.to_string() });
+    }
+    Ok(pins)
+}
+
+/// Warns about pins for a function entry the listing does not have.
+pub fn check(path: &str, pins: &[Pin], functions: &Functions) {
+    for pin in pins {
+        if let PinScope::Function(entry) = pin.scope
+            && !functions.functions.iter().any(|function| function.entry == entry)
+        {
+            eprintln!("warning: {path}: no function has entry {entry:#06x}; the name '{}' for reg{} is unused", pin.name, pin.reg);
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use crate::cli::args::{Args, Command};
+use crate::cli::commands::{apis, callgraph, cfg, diff, disasm, grep, infer_map, stats, strings, xref};
+use crate::cli::error::CliError;
+use crate::cli::annotations;
+use crate::cli::input::Input;
+use crate::cli::opcode_map;
+use crate::cli::printer::Addresses;
+use bet365::bytecode::bytearray::{decode_bytecode, strip_wrappers};
+use bet365::disassembler::builder::DisassemblerBuilder;
+use bet365::disassembler::disassembler::Disassembler;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::instructions::OpcodeTable;
+use bet365::disassembler::profiles::{self, ProfileScore, AUTO_PROFILE, DETECT_WINDOW};
+
+const UNPRINTABLE_WARN_RATIO: f64 = 0.3;
+/// `--profile auto` warns when even the best profile scores below this.
+const MIN_PROFILE_SCORE: f32 = 0.5;
+
+/// Prints a summary line. These always go to stderr, so stdout carries only the listing and stays
+/// parseable with `--format json` or `csv`.
+pub fn report(line: &str) {
+    eprintln!("{line}");
+}
+
+pub fn guarded<T>(input: &Input, run: impl FnOnce() -> Result<T, CliError>) -> Result<T, CliError> {
+    match panic::catch_unwind(AssertUnwindSafe(run)) {
+        Ok(result) => result,
+        Err(_) => Err(CliError::Decode(format!("{}: disassembly aborted", input.name())))
+    }
+}
+
+pub fn load(args: &Args, input: &Input) -> Result<Disassembler, CliError> {
+    let bytec: String = input
+        .read_to_string()
+        .map_err(|err| CliError::Input(format!("could not read '{}': {err}", input.name())))?;
+
+    let bytes: Vec<u8> = match decode_bytecode(&bytec) {
+        Ok(bytes) if !bytes.is_empty() => bytes,
+        decoded => {
+            let (_, payload) = strip_wrappers(&bytec);
+            let characters: usize = payload.chars().filter(|c| !c.is_whitespace()).count();
+            if characters > 0 && payload.chars().all(|c| c == '=' || c.is_whitespace()) {
+                return Err(CliError::Input(format!("{}: input is only base64 padding ({characters} '=' characters)", input.name())));
+            }
+            if characters == 0 && bytec.trim_start().trim_start_matches(['"', '\'', '`']).starts_with("data:") {
+                return Err(CliError::Input(format!("{}: input is a data: URL prefix with no base64 payload", input.name())));
+            }
+            decoded.map_err(|err| CliError::Decode(format!("{}: {err}", input.name())))?;
+            return Err(CliError::Input(format!("{}: decoded bytecode is empty (input was {characters} characters of base64)", input.name())));
+        }
+    };
+
+    let mut builder: DisassemblerBuilder = DisassemblerBuilder::new()
+        .string_key(args.string_key)
+        .lenient(args.lenient)
+        .resync(args.resync)
+        .entry(args.start)
+        .end(args.end)
+        .max_instructions(args.max_instructions)
+        .max_visited(args.max_visited)
+        .targets(Addresses::from_args(args).targets())
+        .raw_strings(args.raw_strings)
+        .timing(args.time)
+        .register_tracking(args.tracking)
+        .naive_tracking(args.naive_tracking)
+        .fold_constants(args.fold_constants);
+    if let Some(path) = &args.opcode_map {
+        builder = builder.opcode_table(opcode_map::load(path)?);
+    }
+    if let Some(name) = &args.profile {
+        builder = builder.opcode_table(profile(args, input, name, &bytes)?);
+    }
+    if let Some(path) = &args.annotations {
+        builder = builder.annotations(annotations::load(path)?);
+    }
+    let disasm: Disassembler = builder
+        .build(bytes)
+        .map_err(|err| CliError::Input(format!("{}: {err}", input.name())))?;
+    Ok(disasm)
+}
+
+/// The built-in or `--profile-map` profile called `name`, or for `auto` the one that decodes the start of
+/// `bytes` best.
+fn profile(args: &Args, input: &Input, name: &str, bytes: &[u8]) -> Result<OpcodeTable, CliError> {
+    let mut candidates: Vec<OpcodeTable> = profiles::builtin().map_err(|err| CliError::Input(format!("built-in profile: {err}")))?;
+    for path in &args.profile_maps {
+        let table: OpcodeTable = opcode_map::load(path)?;
+        if table.name == AUTO_PROFILE || candidates.iter().any(|candidate| candidate.name == table.name) {
+            return Err(CliError::Input(format!("{path}: profile '{}' is already defined", table.name)));
+        }
+        candidates.push(table);
+    }
+
+    if name != AUTO_PROFILE {
+        let names: Vec<String> = candidates.iter().map(|candidate| candidate.name.clone()).collect();
+        return candidates
+            .into_iter()
+            .find(|candidate| candidate.name == name)
+            .ok_or_else(|| CliError::Input(format!("unknown profile '{name}' (expected {AUTO_PROFILE} or one of: {})", names.join(", "))));
+    }
+    let scores: Vec<ProfileScore> = profiles::detect(&candidates, bytes, args.start, DETECT_WINDOW, args.string_key)
+        .map_err(|err| CliError::Input(format!("{}: {err}", input.name())))?;
+    let others: Vec<String> = scores[1..].iter().map(|score| format!("{} {:.2}", score.name, score.score)).collect();
+    let best: &ProfileScore = &scores[0];
+    eprintln!(
+        "{}: --profile auto chose {} (score {:.2}: {:.0}% clean, {:.0}% coverage, {:.0}% printable){}",
+        input.name(),
+        best.name,
+        best.score,
+        best.clean * 100.0,
+        best.coverage * 100.0,
+        best.printable * 100.0,
+        if others.is_empty() { String::new() } else { format!("; {}", others.join(", ")) }
+    );
+    if best.score < MIN_PROFILE_SCORE {
+        eprintln!("warning: {}: no profile decodes the start cleanly; infer-map can recover the opcode bytes of an unknown build", input.name());
+    }
+    Ok(candidates.into_iter().find(|candidate| candidate.name == best.name).unwrap_or_default())
+}
+
+pub fn check_string_key(args: &Args, disasm: &Disassembler) {
+    if let Some(ratio) = disasm.unprintable_ratio() && ratio > UNPRINTABLE_WARN_RATIO {
+        eprintln!(
+            "warning: {:.0}% of decoded string characters are control characters or invalid UTF-8; --string-key {} is probably wrong for this build",
+            ratio * 100.0,
+            args.string_key
+        );
+    }
+}
+
+pub fn create_file(path: &str) -> Result<BufWriter<File>, CliError> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|err| CliError::Output(format!("could not create '{path}': {err}")))
+}
+
+pub fn stdout() -> BufWriter<StdoutLock<'static>> {
+    BufWriter::new(io::stdout().lock())
+}
+
+pub fn open_output(output: Option<&str>) -> Result<Box<dyn Write>, CliError> {
+    match output {
+        Some(path) => Ok(Box::new(create_file(path)?)),
+        None => Ok(Box::new(stdout()))
+    }
+}
+
+pub fn write_error(output: Option<&str>, err: io::Error) -> CliError {
+    CliError::Output(format!("could not write '{}': {err}", output.unwrap_or("<stdout>")))
+}
+
+pub fn finish(input: &Input, output: Option<&str>, result: Result<(), DisassemblerError>, flushed: io::Result<()>) -> Result<(), CliError> {
+    flushed.map_err(|err| write_error(output, err))?;
+    match result {
+        Ok(()) => Ok(()),
+        Err(DisassemblerError::Io(err)) => Err(write_error(output, err)),
+        Err(err @ (DisassemblerError::InvalidConfig(_) | DisassemblerError::OutOfBounds { .. })) => Err(CliError::Input(format!("{}: {err}", input.name()))),
+        Err(err @ (DisassemblerError::Base64(_) | DisassemblerError::Truncated { .. } | DisassemblerError::InvalidLength { .. })) => Err(CliError::Decode(format!("{}: {err}", input.name()))),
+        Err(err @ DisassemblerError::UnknownOpcode { .. }) => Err(CliError::UnknownOpcode(format!("{}: {err}", input.name())))
+    }
+}
+
+fn derive_output(output: &str, input: &Input) -> String {
+    let output_path: &Path = Path::new(output);
+    let input_stem: String = match input {
+        Input::File(path) => Path::new(path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        Input::Stdin => "stdin".to_string()
+    };
+    let stem: String = output_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let file_name: String = match output_path.extension() {
+        Some(ext) => format!("{stem}.{input_stem}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{input_stem}")
+    };
+    output_path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+pub fn run(args: &Args, input: &Input, output: Option<&str>) -> Result<usize, CliError> {
+    match args.command {
+        Command::Disasm if args.xref.is_some() => xref::run(args, input, output),
+        Command::Disasm => disasm::run(args, input, output),
+        Command::Strings => strings::run(args, input, output),
+        Command::Stats => stats::run(args, input, output),
+        Command::Cfg => cfg::run(args, input, output),
+        Command::Callgraph => callgraph::run(args, input, output),
+        Command::Apis => apis::run(args, input, output),
+        Command::Grep => grep::run(args, input, output),
+        Command::Diff => Err(CliError::Input("diff requires two inputs, OLD and NEW".to_string())),
+        Command::InferMap => Err(CliError::Input("infer-map requires two inputs, REFERENCE and UNKNOWN".to_string()))
+    }
+}
+
+pub fn run_all(args: &Args, inputs: &[Input]) -> Result<(), CliError> {
+    if let (Command::Diff, [old, new]) = (args.command, inputs) {
+        return diff::run(args, old, new, args.output.as_deref()).map(|_| ());
+    }
+    if let (Command::InferMap, [reference, unknown]) = (args.command, inputs) {
+        return infer_map::run(args, reference, unknown, args.output.as_deref()).map(|_| ());
+    }
+    if let [input] = inputs {
+        return run(args, input, args.output.as_deref()).map(|_| ());
+    }
+
+    let mut total: usize = 0;
+    let mut failed: Vec<CliError> = Vec::new();
+    for input in inputs {
+        report(&format!("==> {} <==", input.name()));
+
+        let output: Option<String> = args.output.as_deref().map(|output| derive_output(output, input));
+        match guarded(input, || run(args, input, output.as_deref())) {
+            Ok(count) => total += count,
+            Err(err) => {
+                eprintln!("error: {err}");
+                failed.push(err);
+            }
+        }
+        report("");
+    }
+
+    report(&format!("processed {} files, {total} instructions, {} failed", inputs.len(), failed.len()));
+    match failed.into_iter().next() {
+        None => Ok(()),
+        Some(first) => Err(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use super::*;
+
+    fn load_text(name: &str, text: &str) -> Result<Disassembler, CliError> {
+        let path: String = env::temp_dir().join(format!("bet365-run-{}-{name}.txt", std::process::id())).to_string_lossy().into_owned();
+        fs::write(&path, text).unwrap();
+        let args: Args = Args::parse_from(std::iter::empty()).unwrap();
+        let result: Result<Disassembler, CliError> = load(&args, &Input::File(path.clone()));
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn input_error(name: &str, text: &str) -> String {
+        match load_text(name, text) {
+            Err(CliError::Input(message)) => message,
+            Err(err) => panic!("{name}: expected an input error, got {err}"),
+            Ok(_) => panic!("{name}: expected an input error, got a disassembler")
+        }
+    }
+
+    #[test]
+    fn empty_padding_and_bare_data_urls_are_input_errors() {
+        assert!(input_error("empty", "  \n").ends_with("decoded bytecode is empty (input was 0 characters of base64)"));
+        assert!(input_error("padding", "\"====\"").ends_with("input is only base64 padding (4 '=' characters)"));
+        assert!(input_error("data", "data:application/octet-stream;base64,").ends_with("input is a data: URL prefix with no base64 payload"));
+        assert!(input_error("bare-data", "'data:'").ends_with("input is a data: URL prefix with no base64 payload"));
+    }
+
+    #[test]
+    fn invalid_base64_is_a_decode_error_and_valid_input_loads() {
+        assert!(matches!(load_text("invalid", "tQ*Hpg=="), Err(CliError::Decode(_))));
+        let disasm: Disassembler = load_text("valid", "data:application/octet-stream;base64,tQEHpg==").unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(disasm.bytes(), [181, 1, 7, 166]);
+    }
+}
@@ -0,0 +1,267 @@
+use serde::{Serialize, Serializer};
+use bet365::disassembler::codec::format_double;
+use bet365::disassembler::disassembler::TraceLine;
+use bet365::disassembler::error::DisassemblerError;
+use bet365::disassembler::functions::{Function, Overlap};
+use bet365::disassembler::listing::{FrameWarning, TargetWarning};
+use bet365::disassembler::operands::Operand;
+use bet365::disassembler::reachability::DeadRegion;
+use bet365::disassembler::symbols::Label;
+
+/// Version of the `--format json` listing. Bump it when a field is removed,
+/// renamed or changes type; adding a field or an operand type does not bump it.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// JSON has no NaN or infinities, so those are written as the strings the listing prints for them,
+/// as the library's `serde` feature does.
+fn double<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.is_finite() {
+        serializer.serialize_f64(*value)
+    } else {
+        serializer.serialize_str(&format_double(*value))
+    }
+}
+
+/// Register operands carry their `--rename` name, if any.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonOperand {
+    Reg {
+        value: u8,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>
+    },
+    RegList {
+        value: Vec<u8>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        names: Option<Vec<String>>
+    },
+    Byte { value: u8 },
+    Int { value: u32 },
+    Double {
+        #[serde(serialize_with = "double")]
+        value: f64
+    },
+    Str { value: String, bytes: Vec<u8> },
+    Target { value: u32 }
+}
+
+#[derive(Serialize)]
+pub struct JsonInstruction {
+    pub offset: usize,
+    pub opcode: String,
+    pub operands: Vec<JsonOperand>,
+    pub text: String,
+    pub comment: Option<String>,
+    /// With --indent-frames, the instruction's JUMP FRAME depth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>
+}
+
+#[derive(Serialize)]
+pub struct JsonError {
+    pub kind: &'static str,
+    pub message: String,
+    pub offset: Option<usize>
+}
+
+#[derive(Serialize)]
+pub struct JsonWarning {
+    pub kind: &'static str,
+    pub message: String,
+    pub offset: usize,
+    pub target: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct JsonLabel {
+    pub offset: usize,
+    pub name: String,
+    pub kind: &'static str
+}
+
+#[derive(Serialize)]
+pub struct JsonFunction {
+    pub entry: usize,
+    pub end: usize,
+    pub args: usize,
+    pub size: usize
+}
+
+#[derive(Serialize)]
+pub struct JsonRegion {
+    pub start: usize,
+    pub end: usize,
+    pub size: usize,
+    pub instructions: usize
+}
+
+#[derive(Serialize)]
+pub struct JsonListing {
+    pub schema_version: u32,
+    pub instructions: Vec<JsonInstruction>,
+    pub labels: Vec<JsonLabel>,
+    pub functions: Vec<JsonFunction>,
+    pub unreachable: Vec<JsonRegion>,
+    pub warnings: Vec<JsonWarning>,
+    pub error: Option<JsonError>
+}
+
+impl From<&Operand> for JsonOperand {
+    fn from(operand: &Operand) -> Self {
+        match operand {
+            Operand::Reg(reg) => JsonOperand::Reg { value: *reg, name: None },
+            Operand::RegList(regs) => JsonOperand::RegList { value: regs.clone(), names: None },
+            Operand::Byte(value) => JsonOperand::Byte { value: *value },
+            Operand::Int(value) => JsonOperand::Int { value: *value },
+            Operand::Double(value) => JsonOperand::Double { value: *value },
+            Operand::Str(value, bytes) => JsonOperand::Str { value: value.clone(), bytes: bytes.clone() },
+            Operand::Target(target) => JsonOperand::Target { value: *target }
+        }
+    }
+}
+
+impl JsonInstruction {
+    /// Gives every register operand its display name.
+    pub fn name_registers(&mut self, name: impl Fn(u8) -> String) {
+        for operand in &mut self.operands {
+            match operand {
+                JsonOperand::Reg { value, name: names } => *names = Some(name(*value)),
+                JsonOperand::RegList { value, names } => *names = Some(value.iter().map(|&reg| name(reg)).collect()),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl From<&TraceLine<'_>> for JsonInstruction {
+    fn from(line: &TraceLine) -> Self {
+        Self {
+            offset: line.start,
+            opcode: line.opcode.as_str().to_string(),
+            operands: line.operands.iter().map(JsonOperand::from).collect(),
+            text: line.text.to_string(),
+            comment: line.comment.map(str::to_string),
+            depth: None
+        }
+    }
+}
+
+impl From<&DisassemblerError> for JsonError {
+    fn from(err: &DisassemblerError) -> Self {
+        let (kind, offset): (&'static str, Option<usize>) = match err {
+            DisassemblerError::Io(_) => ("io", None),
+            DisassemblerError::Base64(err) => ("base64", err.position),
+            DisassemblerError::Truncated { offset, .. } => ("truncated", Some(*offset)),
+            DisassemblerError::InvalidLength { offset, .. } => ("invalid_length", Some(*offset)),
+            DisassemblerError::InvalidConfig(_) => ("invalid_config", None),
+            DisassemblerError::OutOfBounds { offset, .. } => ("out_of_bounds", Some(*offset)),
+            DisassemblerError::UnknownOpcode { offset, .. } => ("unknown_opcode", Some(*offset))
+        };
+        Self { kind, message: err.to_string(), offset }
+    }
+}
+
+impl From<&TargetWarning> for JsonWarning {
+    fn from(warning: &TargetWarning) -> Self {
+        Self { kind: warning.kind(), message: warning.to_string(), offset: warning.offset(), target: Some(warning.target()) }
+    }
+}
+
+impl From<&FrameWarning> for JsonWarning {
+    fn from(warning: &FrameWarning) -> Self {
+        Self { kind: warning.kind(), message: warning.to_string(), offset: warning.offset(), target: None }
+    }
+}
+
+impl From<&Label> for JsonLabel {
+    fn from(label: &Label) -> Self {
+        Self { offset: label.offset, name: label.name.clone(), kind: label.kind.as_str() }
+    }
+}
+
+impl From<&Function> for JsonFunction {
+    fn from(function: &Function) -> Self {
+        Self { entry: function.entry, end: function.end, args: function.args, size: function.size() }
+    }
+}
+
+impl From<&DeadRegion> for JsonRegion {
+    fn from(region: &DeadRegion) -> Self {
+        Self { start: region.start, end: region.end, size: region.size(), instructions: region.instructions }
+    }
+}
+
+impl From<&Overlap> for JsonWarning {
+    fn from(overlap: &Overlap) -> Self {
+        Self { kind: "function_overlap", message: overlap.to_string(), offset: overlap.entry, target: Some(overlap.next as u32) }
+    }
+}
+
+impl Default for JsonListing {
+    fn default() -> Self {
+        Self { schema_version: SCHEMA_VERSION, instructions: Vec::new(), labels: Vec::new(), functions: Vec::new(), unreachable: Vec::new(), warnings: Vec::new(), error: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use base64::{engine::general_purpose, Engine as _};
+    use super::*;
+    use crate::cli::args::Args;
+    use crate::cli::commands::disasm;
+    use crate::cli::error::CliError;
+    use crate::cli::input::Input;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/listing.json");
+
+    /// Every operand type, a label, a function, a dead region, all three kinds of warning and a truncated tail.
+    const PROGRAM: [u8; 68] = [
+        171, 1, 0, 0, 0, 60, 0, // 0: NEW FUNCTION reg1, entry(60), []
+        51, 2, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0, // 7: LOAD DOUBLE reg2, 1.5
+        241, 3, 0, 1, 0x11, 0x70, // 17: MOV IMM32 reg3, 70000
+        23, 4, 0, 9, 65, 83, 75, 18, 16, 90, 91, 16, 56, // 23: NEW VALUE reg4, 'say "hi"\n'
+        93, 0, 0, 0, 44, // 36: JUMP 44
+        181, 9, 1, // 41: LOAD IMM8 reg9, 1 (dead)
+        83, 4, 0, 0, 0x27, 0x0f, // 44: JUMP IF TRUE reg4, 9999 (out of range)
+        49, 0, 0, 0, 60, 6, 2, 2, 3, // 50: JUMP FRAME entry(60), 6, [reg2,reg3]
+        166, // 59: HALT with the frame still open
+        181, 5, 127, // 60: LOAD IMM8 reg5, 127
+        17, 5, 0, // 63: RET reg5, []
+        241, 1 // 66: MOV IMM32 cut short
+    ];
+
+    /// Runs `disasm` with `--format json` and `flags` on `bytes` and returns what it wrote.
+    fn json_output(name: &str, bytes: &[u8], flags: &[&str]) -> (Result<usize, CliError>, String) {
+        let base: String = env::temp_dir().join(format!("bet365-schema-{}-{name}", std::process::id())).to_string_lossy().into_owned();
+        let (input, output): (String, String) = (format!("{base}.b64"), format!("{base}.json"));
+        fs::write(&input, general_purpose::STANDARD.encode(bytes)).unwrap();
+        let cli: Vec<String> = ["--format", "json"].iter().chain(flags).chain([&input.as_str()]).map(|arg| arg.to_string()).collect();
+        let args: Args = Args::parse_from(cli.into_iter()).unwrap();
+        let result: Result<usize, CliError> = disasm::run(&args, &Input::File(input.clone()), Some(&output));
+        let json: String = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+        (result, json)
+    }
+
+    #[test]
+    fn listing_matches_the_golden_fixture() {
+        let (result, json): (Result<usize, CliError>, String) = json_output("golden", &PROGRAM, &["--labels", "--sections", "--show-unreachable", "--indent-frames"]);
+        assert!(matches!(result, Err(CliError::Decode(_))), "the truncated tail is a decode error");
+        assert!(json.starts_with(&format!("{{\"schema_version\":{SCHEMA_VERSION},")));
+        assert_eq!(json, FIXTURE, "--format json output changed; if that is intended, update tests/fixtures/listing.json and SCHEMA_VERSION");
+    }
+
+    #[test]
+    fn non_finite_doubles_are_strings() {
+        // LOAD DOUBLE NaN -> reg1, -Infinity -> reg2; HALT.
+        let bytes: Vec<u8> = [&[51, 1, 0x7f, 0xf8, 0, 0, 0, 0, 0, 0][..], &[51, 2, 0xff, 0xf0, 0, 0, 0, 0, 0, 0], &[166]].concat();
+        let (result, json): (Result<usize, CliError>, String) = json_output("doubles", &bytes, &[]);
+        assert!(result.is_ok());
+        assert!(json.contains(r#"{"type":"double","value":"NaN"}"#), "{json}");
+        assert!(json.contains(r#"{"type":"double","value":"-Infinity"}"#), "{json}");
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::cli::args::Args;
+use crate::cli::error::CliError;
+use crate::cli::input::Input;
+use crate::cli::run::{self, report};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn timestamp() -> String {
+    let secs: u64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let day: u64 = secs % 86400;
+    format!("{:02}:{:02}:{:02} UTC", day / 3600, day % 3600 / 60, day % 60)
+}
+
+pub fn watch(args: &Args, input: &Input) -> Result<(), CliError> {
+    let path: &str = match input {
+        Input::File(path) => path,
+        Input::Stdin => return Err(CliError::Input("--watch requires an input file, not stdin".to_string()))
+    };
+
+    let mut last_seen: Option<SystemTime> = None;
+    loop {
+        let current: Option<SystemTime> = modified(path);
+        if current.is_some() && current != last_seen {
+            last_seen = current;
+            report(&format!("==== {path} @ {} ====", timestamp()));
+
+            if let Err(err) = run::guarded(input, || run::run(args, input, args.output.as_deref())) {
+                eprintln!("error: {err}");
+            }
+            eprintln!("watching '{path}' for changes (Ctrl-C to stop)");
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+use crate::disassembler::cfg::{BlockId, Cfg, EdgeKind};
+use crate::disassembler::codec::quote_string;
+use crate::disassembler::derived::{DerivedStrings, DEFAULT_MAX_ITERATIONS};
+use crate::disassembler::emulator::{Emulator, Value};
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::loops::Loops;
+use crate::disassembler::opcodes::{OpCategory, OpCodes};
+use crate::disassembler::operands::{Access, Operand};
+use crate::disassembler::registers::{destination, RegValue};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ApiCategory {
+    Window,
+    Document,
+    Navigator,
+    Screen,
+    Location,
+    Canvas,
+    WebGl,
+    Audio,
+    Time,
+    Storage,
+    Network,
+    Crypto
+}
+
+impl ApiCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiCategory::Window => "window",
+            ApiCategory::Document => "document",
+            ApiCategory::Navigator => "navigator",
+            ApiCategory::Screen => "screen",
+            ApiCategory::Location => "location",
+            ApiCategory::Canvas => "canvas",
+            ApiCategory::WebGl => "webgl",
+            ApiCategory::Audio => "audio",
+            ApiCategory::Time => "time",
+            ApiCategory::Storage => "storage",
+            ApiCategory::Network => "network",
+            ApiCategory::Crypto => "crypto"
+        }
+    }
+}
+
+/// Globals whose accesses are reported, and the category they are listed under. Properties read from
+/// them are followed (`navigator.userAgent`), so only the roots are listed.
+pub const KNOWN_APIS: &[(&str, ApiCategory)] = &[
+    ("window", ApiCategory::Window),
+    ("self", ApiCategory::Window),
+    ("globalThis", ApiCategory::Window),
+    ("top", ApiCategory::Window),
+    ("parent", ApiCategory::Window),
+    ("frames", ApiCategory::Window),
+    ("document", ApiCategory::Document),
+    ("navigator", ApiCategory::Navigator),
+    ("screen", ApiCategory::Screen),
+    ("devicePixelRatio", ApiCategory::Screen),
+    ("innerWidth", ApiCategory::Screen),
+    ("innerHeight", ApiCategory::Screen),
+    ("outerWidth", ApiCategory::Screen),
+    ("outerHeight", ApiCategory::Screen),
+    ("screenX", ApiCategory::Screen),
+    ("screenY", ApiCategory::Screen),
+    ("matchMedia", ApiCategory::Screen),
+    ("location", ApiCategory::Location),
+    ("history", ApiCategory::Location),
+    ("HTMLCanvasElement", ApiCategory::Canvas),
+    ("CanvasRenderingContext2D", ApiCategory::Canvas),
+    ("OffscreenCanvas", ApiCategory::Canvas),
+    ("WebGLRenderingContext", ApiCategory::WebGl),
+    ("WebGL2RenderingContext", ApiCategory::WebGl),
+    ("AudioContext", ApiCategory::Audio),
+    ("webkitAudioContext", ApiCategory::Audio),
+    ("OfflineAudioContext", ApiCategory::Audio),
+    ("webkitOfflineAudioContext", ApiCategory::Audio),
+    ("Date", ApiCategory::Time),
+    ("performance", ApiCategory::Time),
+    ("Intl", ApiCategory::Time),
+    ("localStorage", ApiCategory::Storage),
+    ("sessionStorage", ApiCategory::Storage),
+    ("indexedDB", ApiCategory::Storage),
+    ("openDatabase", ApiCategory::Storage),
+    ("XMLHttpRequest", ApiCategory::Network),
+    ("fetch", ApiCategory::Network),
+    ("WebSocket", ApiCategory::Network),
+    ("RTCPeerConnection", ApiCategory::Network),
+    ("webkitRTCPeerConnection", ApiCategory::Network),
+    ("mozRTCPeerConnection", ApiCategory::Network),
+    ("crypto", ApiCategory::Crypto)
+];
+
+/// Calls that return an object worth following, by callee path and first argument, and the root the
+/// result is followed as. The root must be in `KNOWN_APIS`.
+pub const CREATED: &[(&str, &str, &str)] = &[
+    ("document.createElement", "canvas", "HTMLCanvasElement"),
+    ("HTMLCanvasElement.getContext", "2d", "CanvasRenderingContext2D"),
+    ("HTMLCanvasElement.getContext", "webgl", "WebGLRenderingContext"),
+    ("HTMLCanvasElement.getContext", "experimental-webgl", "WebGLRenderingContext"),
+    ("HTMLCanvasElement.getContext", "webgl2", "WebGL2RenderingContext")
+];
+
+/// Roots that name the global object itself; `window.navigator` is reported as `navigator`.
+pub(crate) const WINDOW_ALIASES: &[&str] = &["window", "self", "globalThis"];
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ApiAccessKind {
+    /// GET PROPERTY.
+    Read,
+    /// SET PROPERTY.
+    Write,
+    /// CALL FUNCTION or CALL APPLY.
+    Call
+}
+
+impl ApiAccessKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiAccessKind::Read => "read",
+            ApiAccessKind::Write => "write",
+            ApiAccessKind::Call => "call"
+        }
+    }
+}
+
+/// One instruction that touches a known API.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApiAccess {
+    pub offset: usize,
+    /// The property chain from its root, as in `navigator.userAgent` or `navigator.plugins[0]`.
+    pub path: String,
+    pub category: ApiCategory,
+    pub kind: ApiAccessKind,
+    /// The first argument of a call, when it is known.
+    pub argument: Option<String>,
+    /// Whether a property name on the path was not known to the register tracker, but computed by
+    /// emulation or built by a loop.
+    pub computed: bool
+}
+
+/// Accesses to one path with the same kind and argument.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ApiUse<'a> {
+    pub path: &'a str,
+    pub kind: ApiAccessKind,
+    pub argument: Option<&'a str>,
+    /// In offset order.
+    pub accesses: Vec<&'a ApiAccess>
+}
+
+/// The property chain a register holds.
+#[derive(Clone, PartialEq, Debug)]
+struct Chain {
+    path: String,
+    category: ApiCategory,
+    /// Whether the chain is a bare `window` alias, so that a known root read from it starts a new chain.
+    alias: bool,
+    computed: bool
+}
+
+impl Chain {
+    fn root(root: &str, computed: bool) -> Option<Chain> {
+        let category: ApiCategory = category(root)?;
+        Some(Chain { path: root.to_string(), category, alias: WINDOW_ALIASES.contains(&root), computed })
+    }
+
+    fn property(&self, key: &RegValue, computed: bool) -> Chain {
+        let segment: String = match key {
+            RegValue::Str(name) if identifier(name) => format!(".{name}"),
+            RegValue::Str(name) => format!("[{}]", quote_string(name)),
+            key => format!("[{key}]")
+        };
+        Chain { path: format!("{}{segment}", self.path), category: self.category, alias: false, computed: self.computed || computed }
+    }
+}
+
+fn category(root: &str) -> Option<ApiCategory> {
+    KNOWN_APIS.iter().find(|(name, _)| *name == root).map(|&(_, category)| category)
+}
+
+fn identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// A register value known at some point, and whether it was computed rather than loaded by a constant.
+#[derive(Clone, PartialEq, Debug)]
+struct Known {
+    value: RegValue,
+    computed: bool
+}
+
+/// What is known about the registers between two instructions.
+#[derive(Clone, Default, PartialEq, Debug)]
+struct State {
+    chains: BTreeMap<u8, Chain>,
+    values: BTreeMap<u8, Known>
+}
+
+impl State {
+    /// What every predecessor leaves behind.
+    fn meet(states: &[&State]) -> State {
+        let Some((first, rest)) = states.split_first() else {
+            return State::default();
+        };
+        State { chains: agree(&first.chains, rest, |state| &state.chains), values: agree(&first.values, rest, |state| &state.values) }
+    }
+
+    /// The value of operand `index` of `instruction`: the tracker's, else one carried in from earlier
+    /// instructions.
+    fn key(&self, instruction: &Instruction, index: usize, reg: u8) -> Option<Known> {
+        match instruction.resolved.get(index) {
+            Some(Some(value)) => Some(Known { value: value.clone(), computed: false }),
+            _ => self.values.get(&reg).cloned()
+        }
+    }
+
+    /// The first entry of a call's argument list; list entries are never tracked.
+    fn argument(&self, args: &[u8]) -> Option<String> {
+        let known: &Known = self.values.get(args.first()?)?;
+        Some(known.value.render(true))
+    }
+}
+
+fn agree<T: Clone + PartialEq>(first: &BTreeMap<u8, T>, rest: &[&State], field: impl Fn(&State) -> &BTreeMap<u8, T>) -> BTreeMap<u8, T> {
+    first.iter().filter(|(reg, value)| rest.iter().all(|state| field(state).get(reg) == Some(value))).map(|(&reg, value)| (reg, value.clone())).collect()
+}
+
+/// Reads, writes and calls of the browser APIs in `KNOWN_APIS`. A GET PROPERTY whose key names a known
+/// root starts a chain, which follows the register through further GET PROPERTY reads and through the
+/// calls in `CREATED`. Keys and call arguments come from the register tracker, else from emulating each
+/// block, else from `DerivedStrings` for the blocks a string-building loop exits to. Chains and values
+/// live in registers until they are overwritten, and carry into a block when every predecessor comes
+/// earlier in the listing and leaves the same thing behind; function entries, loop headers and exception
+/// handlers start empty.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApiUsage {
+    /// In offset order.
+    pub accesses: Vec<ApiAccess>
+}
+
+impl ApiUsage {
+    pub fn new(listing: &Listing) -> Self {
+        let cfg: Cfg = Cfg::new(listing);
+        let loops: Loops = Loops::new(&cfg);
+        let derived: DerivedStrings = DerivedStrings::new(listing, DEFAULT_MAX_ITERATIONS);
+
+        let mut exits: BTreeMap<BlockId, Vec<(u8, &str)>> = BTreeMap::new();
+        for string in &derived.strings {
+            let (Ok(value), Some(lp)) = (&string.result, loops.header_at(string.offset)) else {
+                continue;
+            };
+            for &exit in &lp.exits {
+                exits.entry(exit).or_default().push((string.reg, value));
+            }
+        }
+
+        let mut accesses: Vec<ApiAccess> = Vec::new();
+        let mut ends: Vec<Option<State>> = vec![None; cfg.blocks().len()];
+        for block in cfg.blocks() {
+            let incoming: Option<Vec<&State>> = cfg
+                .predecessors(block.id)
+                .map(|edge| if matches!(edge.kind, EdgeKind::FallThrough | EdgeKind::Branch) { ends[edge.from].as_ref() } else { None })
+                .collect();
+            let mut state: State = incoming.map_or_else(State::default, |states| State::meet(&states));
+            for (reg, value) in exits.remove(&block.id).unwrap_or_default() {
+                state.values.insert(reg, Known { value: RegValue::Str(value.to_string()), computed: true });
+            }
+            let mut emulator: Emulator = Emulator::new();
+
+            for instruction in &block.instructions {
+                let chains: &BTreeMap<u8, Chain> = &state.chains;
+                let mut access = |chain: &Chain, kind: ApiAccessKind, argument: Option<String>| {
+                    accesses.push(ApiAccess { offset: instruction.offset, path: chain.path.clone(), category: chain.category, kind, argument, computed: chain.computed });
+                };
+                let result: Option<Chain> = match (instruction.opcode, instruction.operands.as_slice()) {
+                    (OpCodes::GetProperty, [_, Operand::Reg(obj), Operand::Reg(key)]) => {
+                        let chain: Option<Chain> = state.key(instruction, 2, *key).and_then(|key| match (chains.get(obj), &key.value) {
+                            (Some(chain), RegValue::Str(name)) if chain.alias && category(name).is_some() => Chain::root(name, chain.computed || key.computed),
+                            (Some(chain), value) => Some(chain.property(value, key.computed)),
+                            (None, RegValue::Str(name)) => Chain::root(name, key.computed),
+                            (None, _) => None
+                        });
+                        if let Some(chain) = &chain {
+                            access(chain, ApiAccessKind::Read, None);
+                        }
+                        chain
+                    }
+                    (OpCodes::SetProperty, [Operand::Reg(obj), Operand::Reg(key), _]) => {
+                        let written: Option<Chain> = state.key(instruction, 1, *key).and_then(|key| match (chains.get(obj), &key.value) {
+                            (Some(chain), value) => Some(chain.property(value, key.computed)),
+                            (None, RegValue::Str(name)) => Chain::root(name, key.computed),
+                            (None, _) => None
+                        });
+                        if let Some(chain) = &written {
+                            access(chain, ApiAccessKind::Write, None);
+                        }
+                        None
+                    }
+                    (OpCodes::CallFunction, [_, Operand::Reg(callee), Operand::RegList(args)]) | (OpCodes::CallApply, [_, Operand::Reg(callee), _, Operand::RegList(args)]) => {
+                        chains.get(callee).and_then(|chain| {
+                            let argument: Option<String> = state.argument(args);
+                            access(chain, ApiAccessKind::Call, argument.clone());
+                            CREATED
+                                .iter()
+                                .find(|&&(path, expected, _)| path == chain.path && argument.as_deref() == Some(expected))
+                                .and_then(|&(_, _, root)| Chain::root(root, chain.computed))
+                        })
+                    }
+                    _ => None
+                };
+
+                emulator.apply(instruction);
+                for (reg, access) in instruction.accesses() {
+                    if access == Access::Def {
+                        state.chains.remove(&reg);
+                        state.values.remove(&reg);
+                    }
+                }
+                if let Some(reg) = destination(instruction) {
+                    if let Some(chain) = result {
+                        state.chains.insert(reg, chain);
+                    }
+                    let computed: bool = instruction.opcode.category() != OpCategory::Constant;
+                    match emulator.value(reg) {
+                        Value::Str(value) => state.values.insert(reg, Known { value: RegValue::Str(value.clone()), computed }),
+                        Value::Number(value) => state.values.insert(reg, Known { value: RegValue::Double(*value), computed }),
+                        _ => None
+                    };
+                }
+            }
+            ends[block.id] = Some(state);
+        }
+        Self { accesses }
+    }
+
+    /// Accesses grouped by category in `ApiCategory` order, then by path, kind and argument.
+    pub fn by_category(&self) -> Vec<(ApiCategory, Vec<ApiUse<'_>>)> {
+        let mut grouped: BTreeMap<(ApiCategory, &str, ApiAccessKind, Option<&str>), Vec<&ApiAccess>> = BTreeMap::new();
+        for access in &self.accesses {
+            grouped.entry((access.category, &access.path, access.kind, access.argument.as_deref())).or_default().push(access);
+        }
+
+        let mut categories: Vec<(ApiCategory, Vec<ApiUse>)> = Vec::new();
+        for ((category, path, kind, argument), accesses) in grouped {
+            let api: ApiUse = ApiUse { path, kind, argument, accesses };
+            match categories.last_mut() {
+                Some((last, apis)) if *last == category => apis.push(api),
+                _ => categories.push((category, vec![api]))
+            }
+        }
+        categories
+    }
+
+    pub fn len(&self) -> usize {
+        self.accesses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accesses.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::{decode, new_value};
+
+    /// `window.navigator.userAgent`, then `document.createElement('canvas')` with `width` written on the
+    /// result, each key in a register of its own.
+    #[test]
+    fn follows_chains_through_window_and_created_objects() {
+        let mut bytes: Vec<u8> = Vec::new();
+        for (reg, key, obj) in [(2, "window", 1), (4, "navigator", 3), (6, "userAgent", 5), (8, "document", 1), (10, "createElement", 9)] {
+            bytes.extend(new_value(reg, key));
+            bytes.extend([251, reg + 1, obj, reg]);
+        }
+        bytes.extend(new_value(12, "canvas"));
+        bytes.extend([215, 13, 11, 1, 12]);
+        bytes.extend(new_value(14, "width"));
+        bytes.extend([99, 13, 14, 12, 166]);
+        let listing: Listing = decode(bytes);
+
+        let usage: ApiUsage = ApiUsage::new(&listing);
+        let accesses: Vec<(&str, ApiCategory, ApiAccessKind, Option<&str>)> = usage
+            .accesses
+            .iter()
+            .map(|access| (access.path.as_str(), access.category, access.kind, access.argument.as_deref()))
+            .collect();
+        assert_eq!(accesses, [
+            ("window", ApiCategory::Window, ApiAccessKind::Read, None),
+            ("navigator", ApiCategory::Navigator, ApiAccessKind::Read, None),
+            ("navigator.userAgent", ApiCategory::Navigator, ApiAccessKind::Read, None),
+            ("document", ApiCategory::Document, ApiAccessKind::Read, None),
+            ("document.createElement", ApiCategory::Document, ApiAccessKind::Read, None),
+            ("document.createElement", ApiCategory::Document, ApiAccessKind::Call, Some("canvas")),
+            ("HTMLCanvasElement.width", ApiCategory::Canvas, ApiAccessKind::Write, None)
+        ]);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::disassembler::disassembler::{Disassembler, Resync, UnknownOpcodePolicy, DEFAULT_MAX_VISITED, DEFAULT_STRING_KEY};
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::instruction::TargetFormat;
+use crate::disassembler::instructions::OpcodeTable;
+use crate::disassembler::registers::{RegisterTracker, TrackingMode, DEFAULT_REGISTER_COUNT};
+use crate::disassembler::sink::InstructionSink;
+
+pub struct DisassemblerBuilder {
+    string_key: u8,
+    table: Option<OpcodeTable>,
+    lenient: bool,
+    resync: Option<Resync>,
+    entry: usize,
+    end: Option<usize>,
+    max_instructions: Option<usize>,
+    max_visited: usize,
+    targets: TargetFormat,
+    raw_strings: bool,
+    annotations: Vec<(usize, String)>,
+    timing: bool,
+    tracking: TrackingMode,
+    naive_tracking: bool,
+    fold_constants: bool,
+    register_count: usize,
+    sink: Option<Box<dyn InstructionSink + Send>>
+}
+
+impl Default for DisassemblerBuilder {
+    fn default() -> Self {
+        Self {
+            string_key: DEFAULT_STRING_KEY,
+            table: None,
+            lenient: false,
+            resync: None,
+            entry: 0,
+            end: None,
+            max_instructions: None,
+            max_visited: DEFAULT_MAX_VISITED,
+            targets: TargetFormat::Hex,
+            raw_strings: false,
+            annotations: Vec::new(),
+            timing: false,
+            tracking: TrackingMode::Strings,
+            naive_tracking: false,
+            fold_constants: false,
+            register_count: DEFAULT_REGISTER_COUNT,
+            sink: None
+        }
+    }
+}
+
+impl DisassemblerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string_key(mut self, key: u8) -> Self {
+        self.string_key = key;
+        self
+    }
+
+    pub fn opcode_table(mut self, table: OpcodeTable) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn resync(mut self, resync: Option<Resync>) -> Self {
+        self.resync = resync;
+        self
+    }
+
+    pub fn entry(mut self, entry: usize) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    pub fn end(mut self, end: Option<usize>) -> Self {
+        self.end = end;
+        self
+    }
+
+    pub fn max_instructions(mut self, max: Option<usize>) -> Self {
+        self.max_instructions = max;
+        self
+    }
+
+    pub fn max_visited(mut self, max: usize) -> Self {
+        self.max_visited = max;
+        self
+    }
+
+    pub fn targets(mut self, targets: TargetFormat) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    pub fn raw_strings(mut self, raw: bool) -> Self {
+        self.raw_strings = raw;
+        self
+    }
+
+    pub fn annotations(mut self, annotations: Vec<(usize, String)>) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    pub fn timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn register_tracking(mut self, mode: TrackingMode) -> Self {
+        self.tracking = mode;
+        self
+    }
+
+    pub fn naive_tracking(mut self, naive: bool) -> Self {
+        self.naive_tracking = naive;
+        self
+    }
+
+    pub fn fold_constants(mut self, fold: bool) -> Self {
+        self.fold_constants = fold;
+        self
+    }
+
+    pub fn register_count(mut self, count: usize) -> Self {
+        self.register_count = count;
+        self
+    }
+
+    pub fn sink(mut self, sink: Box<dyn InstructionSink + Send>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    pub fn build(self, bytes: Vec<u8>) -> Result<Disassembler, DisassemblerError> {
+        if self.max_instructions == Some(0) {
+            return Err(DisassemblerError::InvalidConfig("max instructions must be at least 1".to_string()));
+        }
+        if self.max_visited == 0 {
+            return Err(DisassemblerError::InvalidConfig("max visited offsets must be at least 1".to_string()));
+        }
+        if self.fold_constants && self.tracking != TrackingMode::Constants {
+            return Err(DisassemblerError::InvalidConfig("constant folding needs constants register tracking".to_string()));
+        }
+        if self.register_count == 0 {
+            return Err(DisassemblerError::InvalidConfig("register count must be at least 1".to_string()));
+        }
+
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes);
+        disasm.set_range(self.entry, self.end)?;
+        if let Some(table) = self.table {
+            disasm.set_table(table);
+        }
+        disasm.string_key = self.string_key;
+        if self.lenient {
+            disasm.unknown_policy = UnknownOpcodePolicy::Lenient;
+        }
+        disasm.resync = self.resync;
+        disasm.max_instructions = self.max_instructions;
+        disasm.max_visited = self.max_visited;
+        disasm.targets = self.targets;
+        disasm.raw_strings = self.raw_strings;
+        if !self.annotations.is_empty() {
+            disasm.set_annotations(self.annotations);
+        }
+        if self.timing {
+            disasm.enable_timing();
+        }
+        disasm.registers = RegisterTracker::with_count(self.tracking, self.register_count);
+        disasm.registers.naive = self.naive_tracking;
+        disasm.registers.fold = self.fold_constants;
+        disasm.sink = self.sink;
+        Ok(disasm)
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::disassembler::functions::{Function, Functions};
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+use crate::disassembler::registers::{destination, RegValue};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Node {
+    /// Code outside every function, where execution starts.
+    TopLevel,
+    /// A function found by `Functions`, by entry.
+    Function(usize),
+    /// A callee looked up by name, e.g. `setTimeout` from `GET PROPERTY reg10[setTimeout]`.
+    External(String)
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::TopLevel => write!(f, "top_level"),
+            Node::Function(entry) => write!(f, "function_{entry:04x}"),
+            Node::External(name) => write!(f, "{name}")
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallKind {
+    /// CALL FUNCTION or CALL APPLY on a register holding a function or a looked-up name.
+    Call,
+    /// JUMP FRAME straight into a function entry.
+    Frame,
+    /// NEW FUNCTION creating a closure, which may be called later or handed to a callback.
+    Reference
+}
+
+impl CallKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CallKind::Call => "call",
+            CallKind::Frame => "frame",
+            CallKind::Reference => "reference"
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Call {
+    pub from: Node,
+    pub to: Node,
+    pub kind: CallKind,
+    /// Offset of the calling instruction.
+    pub offset: usize
+}
+
+/// Calls between the functions of a listing. Callee registers are followed from the NEW FUNCTION or
+/// GET PROPERTY that set them to the CALL FUNCTION / CALL APPLY that uses them, within a straight run
+/// of code: values are forgotten at every jump target and when the register is overwritten.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallGraph {
+    pub functions: Functions,
+    /// In listing order.
+    pub calls: Vec<Call>,
+    /// CALL FUNCTION / CALL APPLY whose callee could not be traced, by offset.
+    pub unresolved: Vec<usize>
+}
+
+impl CallGraph {
+    pub fn new(listing: &Listing) -> Self {
+        let functions: Functions = listing.functions();
+        let known: BTreeSet<usize> = functions.functions.iter().map(|function| function.entry).collect();
+        let targets: BTreeSet<usize> = listing.labels.iter().copied().collect();
+        let mut calls: Vec<Call> = Vec::new();
+        let mut unresolved: Vec<usize> = Vec::new();
+
+        let mut callees: BTreeMap<u8, Node> = BTreeMap::new();
+        let mut section: Option<Node> = None;
+        for instruction in &listing.instructions {
+            let from: Node = functions.containing(instruction.offset).map_or(Node::TopLevel, |function| Node::Function(function.entry));
+            if section.as_ref() != Some(&from) || targets.contains(&instruction.offset) {
+                callees.clear();
+                section = Some(from.clone());
+            }
+
+            let offset: usize = instruction.offset;
+            let mut call = |to: Node, kind: CallKind| calls.push(Call { from: from.clone(), to, kind, offset });
+            let callee: Option<Node> = match (instruction.opcode, instruction.operands.as_slice()) {
+                (OpCodes::NewFunction, [_, Operand::Target(entry), ..]) if known.contains(&(*entry as usize)) => {
+                    call(Node::Function(*entry as usize), CallKind::Reference);
+                    Some(Node::Function(*entry as usize))
+                }
+                (OpCodes::JumpFrame, [Operand::Target(entry), ..]) if known.contains(&(*entry as usize)) => {
+                    call(Node::Function(*entry as usize), CallKind::Frame);
+                    None
+                }
+                (OpCodes::GetProperty, _) => match instruction.resolved.get(2) {
+                    Some(Some(RegValue::Str(name))) => Some(Node::External(name.clone())),
+                    _ => None
+                },
+                (OpCodes::CallFunction | OpCodes::CallApply, [_, Operand::Reg(reg), ..]) => {
+                    match callees.get(reg) {
+                        Some(to) => call(to.clone(), CallKind::Call),
+                        None => unresolved.push(offset)
+                    }
+                    None
+                }
+                _ => None
+            };
+
+            if let Some(reg) = destination(instruction) {
+                match callee {
+                    Some(callee) => callees.insert(reg, callee),
+                    None => callees.remove(&reg)
+                };
+            }
+        }
+        Self { functions, calls, unresolved }
+    }
+
+    /// Calls made by `node`, in listing order.
+    pub fn callees<'a>(&'a self, node: &'a Node) -> impl Iterator<Item = &'a Call> {
+        self.calls.iter().filter(move |call| &call.from == node)
+    }
+
+    /// Calls into `node`, in listing order.
+    pub fn callers<'a>(&'a self, node: &'a Node) -> impl Iterator<Item = &'a Call> {
+        self.calls.iter().filter(move |call| &call.to == node)
+    }
+
+    /// Every node reachable from `root` over calls of any kind, `root` included.
+    pub fn reachable(&self, root: &Node) -> BTreeSet<Node> {
+        let mut seen: BTreeSet<Node> = BTreeSet::from([root.clone()]);
+        let mut queue: Vec<Node> = vec![root.clone()];
+        while let Some(node) = queue.pop() {
+            for call in self.callees(&node) {
+                if seen.insert(call.to.clone()) {
+                    queue.push(call.to.clone());
+                }
+            }
+        }
+        seen
+    }
+
+    /// Functions that no chain of calls, frames or references from `root` reaches.
+    pub fn unreachable(&self, root: &Node) -> Vec<&Function> {
+        let reachable: BTreeSet<Node> = self.reachable(root);
+        self.functions.functions.iter().filter(|function| !reachable.contains(&Node::Function(function.entry))).collect()
+    }
+
+    /// The node whose code contains `offset`.
+    pub fn node_at(&self, offset: usize) -> Node {
+        self.functions.containing(offset).map_or(Node::TopLevel, |function| Node::Function(function.entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::testing::{decode, new_value};
+
+    /// NEW FUNCTION reg1 for 46; NEW FUNCTION reg2 for 52; CALL FUNCTION reg1() -> reg3; GET PROPERTY
+    /// reg0['setTimeout'] -> reg5; CALL FUNCTION reg5(reg2) -> reg6; CALL FUNCTION reg9() -> reg7; HALT;
+    /// then the function at 46: LOAD IMM8 1 -> reg1; RET reg1 []; and the one at 52: JUMP FRAME 46; RET reg1 [].
+    fn program() -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![171, 1, 0, 0, 0, 46, 0, 171, 2, 0, 0, 0, 52, 0, 215, 3, 1, 0];
+        bytes.extend(new_value(4, "setTimeout"));
+        bytes.extend([251, 5, 0, 4, 215, 6, 5, 1, 2, 215, 7, 9, 0, 166, 181, 1, 1, 17, 1, 0, 49, 0, 0, 0, 46, 0, 0, 17, 1, 0]);
+        bytes
+    }
+
+    #[test]
+    fn traces_calls_frames_and_references() {
+        let listing: Listing = decode(program());
+        let graph: CallGraph = CallGraph::new(&listing);
+        let call = |from: Node, to: Node, kind: CallKind, offset: usize| Call { from, to, kind, offset };
+        assert_eq!(graph.calls, [
+            call(Node::TopLevel, Node::Function(46), CallKind::Reference, 0),
+            call(Node::TopLevel, Node::Function(52), CallKind::Reference, 7),
+            call(Node::TopLevel, Node::Function(46), CallKind::Call, 14),
+            call(Node::TopLevel, Node::External("setTimeout".to_string()), CallKind::Call, 36),
+            call(Node::Function(52), Node::Function(46), CallKind::Frame, 52)
+        ]);
+        assert_eq!(graph.unresolved, [41]);
+        assert_eq!(graph.node_at(50).to_string(), "function_002e");
+
+        assert!(graph.unreachable(&Node::TopLevel).is_empty());
+        let unreachable: Vec<usize> = graph.unreachable(&Node::Function(46)).iter().map(|function| function.entry).collect();
+        assert_eq!(unreachable, [52]);
+        assert_eq!(graph.reachable(&Node::Function(52)), BTreeSet::from([Node::Function(46), Node::Function(52)]));
+    }
+}
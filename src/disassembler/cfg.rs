@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::follow::falls_through;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+
+/// Index of a block in `Cfg::blocks`; blocks are numbered in offset order.
+pub type BlockId = usize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// JUMP, or the taken side of JUMP IF TRUE / JUMP IF FALSE.
+    Branch,
+    /// The entry named by JUMP FRAME or NEW FUNCTION.
+    Call,
+    /// The catch, finally and continue offsets of TRY CATCH.
+    Exception
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edge {
+    pub from: BlockId,
+    pub to: BlockId,
+    pub kind: EdgeKind
+}
+
+/// A run of instructions entered only at its first and left only after its last.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    pub id: BlockId,
+    /// Offset of the first instruction.
+    pub start: usize,
+    /// Offset just past the last instruction.
+    pub end: usize,
+    pub instructions: Vec<Instruction>
+}
+
+impl Block {
+    pub fn last(&self) -> &Instruction {
+        &self.instructions[self.instructions.len() - 1]
+    }
+}
+
+/// Control-flow graph of a `Listing`. Blocks start at the first instruction, at every target that
+/// starts a decoded instruction and after every branch, call, TRY CATCH, RET, HALT and THROW.
+/// Targets past the end or inside an instruction get no edge; see `Listing::target_warnings`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cfg {
+    blocks: Vec<Block>,
+    edges: Vec<Edge>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>
+}
+
+fn jumps(instruction: &Instruction) -> Vec<(u32, EdgeKind)> {
+    match (instruction.opcode, instruction.operands.as_slice()) {
+        (OpCodes::Jump, [Operand::Target(target)]) | (OpCodes::JumpIfFalse | OpCodes::JumpIfTrue, [_, Operand::Target(target)]) => {
+            vec![(*target, EdgeKind::Branch)]
+        }
+        (OpCodes::NewFunction, [_, Operand::Target(entry), ..]) | (OpCodes::JumpFrame, [Operand::Target(entry), ..]) => {
+            vec![(*entry, EdgeKind::Call)]
+        }
+        (OpCodes::TryCatch, [_, Operand::Target(catch), Operand::Target(finally), Operand::Target(next)]) => {
+            vec![(*catch, EdgeKind::Exception), (*finally, EdgeKind::Exception), (*next, EdgeKind::Exception)]
+        }
+        _ => Vec::new()
+    }
+}
+
+impl Cfg {
+    pub fn new(listing: &Listing) -> Self {
+        let instructions: BTreeMap<usize, &Instruction> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction)).collect();
+
+        let mut leaders: BTreeSet<usize> = instructions.keys().next().copied().into_iter().collect();
+        for instruction in instructions.values() {
+            let targets: Vec<(u32, EdgeKind)> = jumps(instruction);
+            leaders.extend(targets.iter().map(|(target, _)| *target as usize).filter(|target| instructions.contains_key(target)));
+            if !targets.is_empty() || !falls_through(instruction.opcode) {
+                leaders.insert(instruction.end());
+            }
+        }
+
+        let mut cfg: Cfg = Cfg::default();
+        let mut block_at: BTreeMap<usize, BlockId> = BTreeMap::new();
+        let mut previous_end: Option<usize> = None;
+        for (&offset, &instruction) in &instructions {
+            if leaders.contains(&offset) || previous_end != Some(offset) {
+                let id: BlockId = cfg.blocks.len();
+                block_at.insert(offset, id);
+                cfg.blocks.push(Block { id, start: offset, end: offset, instructions: Vec::new() });
+            }
+            let block: &mut Block = cfg.blocks.last_mut().expect("a block was opened above");
+            block.end = instruction.end();
+            block.instructions.push(instruction.clone());
+            previous_end = Some(instruction.end());
+        }
+
+        cfg.successors = vec![Vec::new(); cfg.blocks.len()];
+        cfg.predecessors = vec![Vec::new(); cfg.blocks.len()];
+        for from in 0..cfg.blocks.len() {
+            let last: &Instruction = cfg.blocks[from].last();
+            let mut edges: Vec<(usize, EdgeKind)> = jumps(last).into_iter().map(|(target, kind)| (target as usize, kind)).collect();
+            if falls_through(last.opcode) {
+                edges.insert(0, (last.end(), EdgeKind::FallThrough));
+            }
+            for (target, kind) in edges {
+                if let Some(&to) = block_at.get(&target) {
+                    cfg.add_edge(Edge { from, to, kind });
+                }
+            }
+        }
+        cfg
+    }
+
+    fn add_edge(&mut self, edge: Edge) {
+        if self.successors[edge.from].iter().any(|&index| self.edges[index] == edge) {
+            return;
+        }
+        self.successors[edge.from].push(self.edges.len());
+        self.predecessors[edge.to].push(self.edges.len());
+        self.edges.push(edge);
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn block(&self, id: BlockId) -> Option<&Block> {
+        self.blocks.get(id)
+    }
+
+    /// The block containing the instruction that starts at or covers `offset`.
+    pub fn block_at(&self, offset: usize) -> Option<BlockId> {
+        let index: usize = self.blocks.partition_point(|block| block.start <= offset);
+        index.checked_sub(1).filter(|&id| offset < self.blocks[id].end)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Edges leaving `block`: fall-through first, then the targets in operand order.
+    pub fn successors(&self, block: BlockId) -> impl Iterator<Item = &Edge> {
+        self.successors.get(block).into_iter().flatten().map(|&edge| &self.edges[edge])
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> impl Iterator<Item = &Edge> {
+        self.predecessors.get(block).into_iter().flatten().map(|&edge| &self.edges[edge])
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl From<&Listing> for Cfg {
+    fn from(listing: &Listing) -> Self {
+        Cfg::new(listing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    /// LOAD IMM8 reg1; JUMP IF FALSE reg1 -> 0xd; LOAD IMM8 reg2; HALT; 0xd: LOAD IMM8 reg2; HALT.
+    const PROGRAM: [u8; 17] = [181, 1, 7, 39, 1, 0, 0, 0, 13, 181, 2, 1, 166, 181, 2, 2, 166];
+
+    fn cfg(bytes: Vec<u8>) -> Cfg {
+        let listing: Listing = decode(bytes);
+        Cfg::new(&listing)
+    }
+
+    #[test]
+    fn splits_blocks_after_branches_and_at_targets() {
+        let cfg: Cfg = cfg(PROGRAM.to_vec());
+        let bounds: Vec<(usize, usize, usize)> = cfg.blocks().iter().map(|block| (block.start, block.end, block.instructions.len())).collect();
+        assert_eq!(bounds, [(0, 9, 2), (9, 13, 2), (13, 17, 2)]);
+        assert_eq!(cfg.block_at(10), Some(1));
+        assert_eq!(cfg.block_at(17), None);
+    }
+
+    #[test]
+    fn conditional_jump_has_fall_through_then_branch() {
+        let cfg: Cfg = cfg(PROGRAM.to_vec());
+        let successors: Vec<(BlockId, EdgeKind)> = cfg.successors(0).map(|edge| (edge.to, edge.kind)).collect();
+        assert_eq!(successors, [(1, EdgeKind::FallThrough), (2, EdgeKind::Branch)]);
+        assert_eq!(cfg.successors(1).count(), 0);
+        let predecessors: Vec<(BlockId, EdgeKind)> = cfg.predecessors(2).map(|edge| (edge.from, edge.kind)).collect();
+        assert_eq!(predecessors, [(0, EdgeKind::Branch)]);
+    }
+
+    #[test]
+    fn targets_inside_an_instruction_get_no_edge() {
+        // JUMP 0x6, which lands on the immediate of LOAD IMM8 reg1 at 0x5.
+        let cfg: Cfg = cfg(vec![93, 0, 0, 0, 6, 181, 1, 7, 166]);
+        assert_eq!(cfg.len(), 2);
+        assert_eq!(cfg.edges().len(), 0);
+    }
+}
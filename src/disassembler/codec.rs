@@ -0,0 +1,222 @@
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::operands::OperandKind;
+
+pub struct ByteReader<'a> {
+    pub bytes: &'a [u8],
+    pub pos: usize
+}
+
+pub fn escape_string(value: &str) -> String {
+    escape_quoted(value, '\'')
+}
+
+/// `value` in double quotes, escaped like `escape_string` except that `"` is escaped instead of `'`.
+pub fn quote_string(value: &str) -> String {
+    format!("\"{}\"", escape_quoted(value, '"'))
+}
+
+fn escape_quoted(value: &str, quote: char) -> String {
+    let mut escaped: String = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' => escaped.push_str("\\\\"),
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Escapes the valid UTF-8 in `bytes` like `escape_string` and every byte that isn't as `\xNN`.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    escape_chunks(bytes, '\'')
+}
+
+/// `bytes` in double quotes, escaped like `escape_bytes` except that `"` is escaped instead of `'`.
+pub fn quote_bytes(bytes: &[u8]) -> String {
+    format!("\"{}\"", escape_chunks(bytes, '"'))
+}
+
+fn escape_chunks(bytes: &[u8], quote: char) -> String {
+    let mut escaped: String = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(&escape_quoted(chunk.valid(), quote));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+// Formats like JavaScript's Number#toString, except that -0 keeps its sign.
+pub fn format_double(value: f64) -> String {
+    let magnitude: f64 = value.abs();
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "Infinity".to_string(),
+        f64::NEG_INFINITY => "-Infinity".to_string(),
+        value if magnitude >= 1e21 || (magnitude < 1e-6 && magnitude > 0.0) => {
+            let formatted: String = format!("{value:e}");
+            match formatted.split_once('e') {
+                Some((mantissa, exponent)) if !exponent.starts_with('-') => format!("{mantissa}e+{exponent}"),
+                _ => formatted
+            }
+        }
+        value => value.to_string()
+    }
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn at(bytes: &'a [u8], pos: usize) -> Self {
+        Self { bytes, pos }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.pos)
+    }
+
+    fn take(&mut self, needed: usize) -> Result<&'a [u8], DisassemblerError> {
+        if needed > self.remaining() {
+            return Err(DisassemblerError::Truncated { offset: self.pos, needed, available: self.remaining(), instruction: None });
+        }
+        let bytes: &'a [u8] = &self.bytes[self.pos..self.pos + needed];
+        self.pos += needed;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<(), DisassemblerError> {
+        self.take(count).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DisassemblerError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16, DisassemblerError> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok((bytes[0] as u16) << 8 | (bytes[1] as u16))
+    }
+
+    pub fn u32_be(&mut self) -> Result<u32, DisassemblerError> {
+        let bytes: &[u8] = self.take(4)?;
+        Ok((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | (bytes[3] as u32))
+    }
+
+    pub fn skip_operand(&mut self, kind: OperandKind) -> Result<(), DisassemblerError> {
+        match kind {
+            OperandKind::Reg | OperandKind::Byte => self.skip(1),
+            OperandKind::Int | OperandKind::Target => self.skip(4),
+            OperandKind::Double => self.skip(8),
+            OperandKind::Str => {
+                let len: usize = self.string_len()?;
+                self.skip(len)
+            }
+            OperandKind::RegList => {
+                let count: u8 = self.u8()?;
+                self.skip(count as usize)
+            }
+        }
+    }
+
+    pub fn string_len(&mut self) -> Result<usize, DisassemblerError> {
+        let offset: usize = self.pos;
+        let length: usize = self.u16_be()? as usize;
+        if length > self.remaining() {
+            return Err(DisassemblerError::InvalidLength { offset, length, available: self.remaining(), instruction: None });
+        }
+        Ok(length)
+    }
+
+    pub fn xored_string(&mut self, key: u8) -> Result<(String, Vec<u8>), DisassemblerError> {
+        let string_len: usize = self.string_len()?;
+        let bytes: Vec<u8> = self.take(string_len)?.iter().map(|byte| byte ^ key).collect();
+        Ok((String::from_utf8_lossy(&bytes).into_owned(), bytes))
+    }
+
+    pub fn f64_ieee(&mut self) -> Result<f64, DisassemblerError> {
+        let bytes: &[u8] = self.take(8)?;
+        let bits: u64 = bytes.iter().fold(0, |bits, &byte| bits << 8 | byte as u64);
+        Ok(f64::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_big_endian_primitives() {
+        let bytes: [u8; 15] = [0xab, 0x12, 0x34, 0xde, 0xad, 0xbe, 0xef, 0xc0, 0x09, 0x21, 0xfb, 0x54, 0x44, 0x2d, 0x18];
+        let mut reader: ByteReader = ByteReader::new(&bytes);
+        assert_eq!(reader.u8().unwrap(), 0xab);
+        assert_eq!(reader.u16_be().unwrap(), 0x1234);
+        assert_eq!(reader.u32_be().unwrap(), 0xdeadbeef);
+        assert_eq!(reader.f64_ieee().unwrap(), -std::f64::consts::PI);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn short_reads_fail_without_moving() {
+        let bytes: [u8; 3] = [1, 2, 3];
+        let mut reader: ByteReader = ByteReader::at(&bytes, 1);
+        assert!(matches!(reader.u32_be(), Err(DisassemblerError::Truncated { offset: 1, needed: 4, available: 2, instruction: None })));
+        assert_eq!(reader.pos, 1);
+        assert_eq!(reader.u16_be().unwrap(), 0x0203);
+        assert!(matches!(reader.u8(), Err(DisassemblerError::Truncated { offset: 3, needed: 1, available: 0, .. })));
+    }
+
+    #[test]
+    fn doubles_decode_from_their_bits_and_format_like_javascript() {
+        let cases: [(u64, &str); 8] = [
+            (0x7ff8_0000_0000_0000, "NaN"),
+            (0x7ff0_0000_0000_0000, "Infinity"),
+            (0xfff0_0000_0000_0000, "-Infinity"),
+            (0x8000_0000_0000_0000, "-0"),
+            (0x3ff8_0000_0000_0000, "1.5"),
+            (0x4415_af1d_78b5_8c40, "100000000000000000000"),
+            (0x444b_1ae4_d6e2_ef50, "1e+21"),
+            (0x3e7a_d7f2_9abc_af48, "1e-7")
+        ];
+        for (bits, expected) in cases {
+            let bytes: [u8; 8] = bits.to_be_bytes();
+            let value: f64 = ByteReader::new(&bytes).f64_ieee().unwrap();
+            assert_eq!(value.to_bits(), bits);
+            assert_eq!(format_double(value), expected, "{bits:#018x}");
+        }
+    }
+
+    #[test]
+    fn xored_string_decodes_with_the_key() {
+        let bytes: [u8; 5] = [0, 3, b'h' ^ 7, b'i' ^ 7, 0xff ^ 7];
+        let (text, raw) = ByteReader::new(&bytes).xored_string(7).unwrap();
+        assert_eq!(raw, [b'h', b'i', 0xff]);
+        assert_eq!(text, "hi\u{fffd}");
+    }
+
+    #[test]
+    fn string_length_past_the_end_is_rejected() {
+        let bytes: [u8; 4] = [0, 5, 1, 2];
+        assert!(matches!(ByteReader::new(&bytes).xored_string(0), Err(DisassemblerError::InvalidLength { offset: 0, length: 5, available: 2, .. })));
+    }
+
+    #[test]
+    fn skip_operand_matches_the_encoded_width() {
+        let bytes: [u8; 7] = [2, 9, 9, 0, 1, b'x', 0];
+        let mut reader: ByteReader = ByteReader::new(&bytes);
+        reader.skip_operand(OperandKind::RegList).unwrap();
+        assert_eq!(reader.pos, 3);
+        reader.skip_operand(OperandKind::Str).unwrap();
+        assert_eq!(reader.pos, 6);
+        assert!(reader.skip_operand(OperandKind::Int).is_err());
+    }
+}
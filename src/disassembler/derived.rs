@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use crate::disassembler::cfg::{BlockId, Cfg, EdgeKind};
+use crate::disassembler::emulator::{Effect, Emulation, Emulator, StopReason, Value};
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::loops::{Loop, Loops};
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 10_000;
+
+/// Blocks followed back from a loop's entry to find the values its registers start with.
+const MAX_ENTRY_BLOCKS: usize = 64;
+
+/// Why a string a loop builds could not be computed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dynamic {
+    /// Emulation stopped inside the loop, usually on a branch whose condition depends on an unknown input.
+    Stopped { offset: usize, reason: StopReason },
+    /// The loop calls something other than `String.fromCharCode` or `charCodeAt`.
+    Call { offset: usize },
+    /// The loop was still running after this many iterations.
+    IterationLimit { iterations: usize },
+    /// The loop finished, but appended something unknown; the text says what.
+    Opaque { text: String }
+}
+
+impl fmt::Display for Dynamic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dynamic::Stopped { offset, reason } => write!(f, "stopped at offset {offset:#06x} on {reason}"),
+            Dynamic::Call { offset } => write!(f, "calls an unknown function at offset {offset:#06x}"),
+            Dynamic::IterationLimit { iterations } => write!(f, "still running after {iterations} iterations"),
+            Dynamic::Opaque { text } => write!(f, "appends {text}")
+        }
+    }
+}
+
+/// A string a loop builds by appending to one register.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DerivedString {
+    /// Offset of the loop header.
+    pub offset: usize,
+    /// The register the string is built in.
+    pub reg: u8,
+    /// The string once the loop is done, or why it is not known.
+    pub result: Result<String, Dynamic>
+}
+
+/// Strings built at run time by loops whose only effect is appending to a register with ADD. Each loop
+/// is emulated from its header, with registers holding what the straight-line code leading into it
+/// computed, until it exits. Loops that store properties, call into the VM, return or throw are left
+/// out; a loop that depends on anything the emulator cannot compute is reported as dynamic.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DerivedStrings {
+    /// In loop order, then by register.
+    pub strings: Vec<DerivedString>
+}
+
+fn intra(kind: EdgeKind) -> bool {
+    kind != EdgeKind::Call
+}
+
+/// Whether the instruction can do something besides computing a register value.
+fn has_effects(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.opcode,
+        OpCodes::SetProperty
+            | OpCodes::JumpFrame
+            | OpCodes::NewFunction
+            | OpCodes::Ret
+            | OpCodes::Throw
+            | OpCodes::TryCatch
+            | OpCodes::Halt
+            | OpCodes::Db
+    )
+}
+
+/// Registers the loop appends to: the destination of an ADD that also reads it.
+fn accumulators<'a>(body: impl Iterator<Item = &'a Instruction>) -> BTreeSet<u8> {
+    body.filter_map(|instruction| match (instruction.opcode, instruction.operands.as_slice()) {
+        (OpCodes::Add, [Operand::Reg(reg), Operand::Reg(lhs), Operand::Reg(rhs)]) if reg == lhs || reg == rhs => Some(*reg),
+        _ => None
+    })
+    .collect()
+}
+
+/// The blocks that lead into `found` without a choice of path, in execution order: its only outside
+/// predecessor, that block's only predecessor, and so on.
+fn entry_path(cfg: &Cfg, found: &Loop) -> Vec<BlockId> {
+    let outside: BTreeSet<BlockId> = cfg
+        .predecessors(found.header)
+        .filter(|edge| intra(edge.kind) && found.body_blocks.binary_search(&edge.from).is_err())
+        .map(|edge| edge.from)
+        .collect();
+    let mut path: Vec<BlockId> = Vec::new();
+    let mut next: Option<BlockId> = (outside.len() == 1).then(|| outside.into_iter().next()).flatten();
+    while let Some(block) = next
+        && path.len() < MAX_ENTRY_BLOCKS
+        && !path.contains(&block)
+        && found.body_blocks.binary_search(&block).is_err()
+    {
+        path.push(block);
+        let preds: BTreeSet<BlockId> = cfg.predecessors(block).filter(|edge| intra(edge.kind)).map(|edge| edge.from).collect();
+        next = (preds.len() == 1).then(|| preds.into_iter().next()).flatten();
+    }
+    path.reverse();
+    path
+}
+
+impl DerivedStrings {
+    pub fn new(listing: &Listing, max_iterations: usize) -> Self {
+        let cfg: Cfg = Cfg::new(listing);
+        let loops: Loops = Loops::new(&cfg);
+        let mut derived: DerivedStrings = DerivedStrings::default();
+        // Loops are emulated outermost first, so an inner loop whose string the outer one finishes is not
+        // also reported as dynamic.
+        let mut order: Vec<&Loop> = loops.loops.iter().collect();
+        order.sort_by_key(|found| (found.depth, found.offset));
+        let mut known: Vec<(&Loop, u8)> = Vec::new();
+        for found in order {
+            let body: Vec<&Instruction> = found.body_blocks.iter().flat_map(|&block| &cfg.blocks()[block].instructions).collect();
+            let regs: BTreeSet<u8> = accumulators(body.iter().copied());
+            if regs.is_empty() || body.iter().any(|instruction| has_effects(instruction)) {
+                continue;
+            }
+
+            let mut emulator: Emulator = Emulator::new();
+            for &block in &entry_path(&cfg, found) {
+                for instruction in &cfg.blocks()[block].instructions {
+                    emulator.apply(instruction);
+                }
+            }
+            let initial: Vec<Value> = regs.iter().map(|&reg| emulator.value(reg).clone()).collect();
+            let inside = |offset: usize| cfg.block_at(offset).is_some_and(|block| found.body_blocks.binary_search(&block).is_ok());
+            let emulation: Emulation = emulator.run_from(listing, found.offset, max_iterations.saturating_mul(found.instructions.max(1)), inside);
+            let finished: bool = matches!(emulation.stop, Some((_, StopReason::LeftRegion { .. })));
+            let unknown_call: Option<usize> = body
+                .iter()
+                .filter(|instruction| matches!(instruction.opcode, OpCodes::CallFunction | OpCodes::CallApply))
+                .map(|instruction| instruction.offset)
+                .find(|&offset| matches!(emulation.get(offset), Some(produced) if matches!(&produced.last, Effect::Value(value, _) if value.is_opaque())));
+
+            for (&reg, initial) in regs.iter().zip(initial) {
+                let last: &Value = emulator.value(reg);
+                if !(matches!(initial, Value::Str(_)) || (finished && matches!(last, Value::Str(_)))) {
+                    continue;
+                }
+                let result: Result<String, Dynamic> = match (unknown_call, &emulation.stop, last) {
+                    (Some(offset), _, _) => Err(Dynamic::Call { offset }),
+                    (_, Some((_, StopReason::StepLimit)), _) => Err(Dynamic::IterationLimit { iterations: max_iterations }),
+                    (_, Some((offset, reason)), _) if !finished => Err(Dynamic::Stopped { offset: *offset, reason: reason.clone() }),
+                    // The loop never appended anything.
+                    (_, _, Value::Str(_)) if initial == *last => continue,
+                    (_, _, Value::Str(value)) => Ok(value.clone()),
+                    (_, _, Value::Opaque(text)) => Err(Dynamic::Opaque { text: text.clone() }),
+                    _ => continue
+                };
+                let inside_known: bool = known.iter().any(|(outer, outer_reg)| *outer_reg == reg && outer.body_blocks.binary_search(&found.header).is_ok());
+                if result.is_err() && inside_known {
+                    continue;
+                }
+                if result.is_ok() {
+                    known.push((found, reg));
+                }
+                derived.strings.push(DerivedString { offset: found.offset, reg, result });
+            }
+        }
+        derived.strings.sort_by_key(|string| (string.offset, string.reg));
+        derived
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassembler::DEFAULT_STRING_KEY;
+    use crate::disassembler::testing::decode;
+
+    /// reg1 = "", reg2 = "x", reg3 = 0, `bound` = 3, reg5 = 1, then the loop at 18: ADD reg1 + reg2 -> reg1;
+    /// ADD reg3 + reg5 -> reg3; LESS THAN reg3 < reg4 -> reg6; JUMP IF TRUE reg6 18; HALT.
+    fn program(bound: u8) -> Vec<u8> {
+        vec![
+            23, 1, 0, 0, 23, 2, 0, 1, b'x' ^ DEFAULT_STRING_KEY, 181, 3, 0, 181, bound, 3, 181, 5, 1,
+            243, 1, 1, 2, 243, 3, 3, 5, 20, 6, 3, 4, 83, 6, 0, 0, 0, 18, 166
+        ]
+    }
+
+    fn derived(bytes: Vec<u8>) -> Vec<DerivedString> {
+        let listing: Listing = decode(bytes);
+        DerivedStrings::new(&listing, DEFAULT_MAX_ITERATIONS).strings
+    }
+
+    #[test]
+    fn runs_an_appending_loop_to_its_exit() {
+        assert_eq!(derived(program(4)), [DerivedString { offset: 18, reg: 1, result: Ok("xxx".to_string()) }]);
+    }
+
+    #[test]
+    fn a_loop_on_an_unknown_bound_is_dynamic() {
+        // The bound goes to reg7, so reg4 is never written.
+        let strings: Vec<DerivedString> = derived(program(7));
+        let reason: StopReason = StopReason::OpaqueBranch { reg: 6 };
+        assert_eq!(strings, [DerivedString { offset: 18, reg: 1, result: Err(Dynamic::Stopped { offset: 30, reason }) }]);
+        assert_eq!(strings[0].result.as_ref().unwrap_err().to_string(), "stopped at offset 0x001e on a branch on reg6, whose value is opaque");
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::functions::{Function, Functions};
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+use crate::disassembler::strings::StringTable;
+
+/// Lowest similarity at which two functions with different strings or sizes still count as the same.
+const MIN_SIMILARITY: f64 = 0.5;
+/// Cells of the edit graph a unit's diff may keep; past it the whole unit is shown as replaced.
+const MAX_TRACE: usize = 1 << 24;
+
+/// A piece of a listing compared on its own: the code outside every function, or one function.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unit {
+    TopLevel,
+    Function(Function)
+}
+
+impl Unit {
+    pub fn name(&self) -> String {
+        match self {
+            Unit::TopLevel => "outside functions".to_string(),
+            Unit::Function(function) => function.name()
+        }
+    }
+
+    pub fn entry(&self) -> Option<usize> {
+        match self {
+            Unit::TopLevel => None,
+            Unit::Function(function) => Some(function.entry)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineKind {
+    Same,
+    Removed,
+    Added
+}
+
+impl LineKind {
+    /// The unified-diff prefix.
+    pub fn prefix(self) -> char {
+        match self {
+            LineKind::Same => ' ',
+            LineKind::Removed => '-',
+            LineKind::Added => '+'
+        }
+    }
+}
+
+/// One instruction of the diff, with its offset in each file it is in.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    /// The instruction with its targets named instead of numbered, as compared.
+    pub text: String
+}
+
+/// A run of changed lines with the unchanged lines around them, as in `@@ -old,count +new,count @@`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hunk {
+    /// Offset of the hunk's first instruction in the old file, or where it would be when it has none.
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    /// Indexes into `UnitDiff::lines`.
+    pub lines: std::ops::Range<usize>
+}
+
+/// Instructions added, removed and changed. A removed instruction directly followed by an added one
+/// counts as changed.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Counts {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.changed += other.changed;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Counts::default()
+    }
+}
+
+/// A unit of the old file against the unit matched to it in the new one. A unit only one file has is
+/// entirely removed or added.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnitDiff {
+    pub old: Option<Unit>,
+    pub new: Option<Unit>,
+    pub lines: Vec<DiffLine>
+}
+
+impl UnitDiff {
+    pub fn name(&self) -> String {
+        self.old.or(self.new).map(|unit| unit.name()).unwrap_or_default()
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.lines.iter().any(|line| line.kind != LineKind::Same)
+    }
+
+    pub fn counts(&self) -> Counts {
+        let mut counts: Counts = Counts::default();
+        let (mut removed, mut added): (usize, usize) = (0, 0);
+        for kind in self.lines.iter().map(|line| line.kind).chain([LineKind::Same]) {
+            match kind {
+                LineKind::Removed => removed += 1,
+                LineKind::Added => added += 1,
+                LineKind::Same => {
+                    let changed: usize = removed.min(added);
+                    counts.add(Counts { added: added - changed, removed: removed - changed, changed });
+                    (removed, added) = (0, 0);
+                }
+            }
+        }
+        counts
+    }
+
+    /// The changed lines grouped with up to `context` unchanged lines on each side; hunks whose
+    /// context would overlap are merged.
+    pub fn hunks(&self, context: usize) -> Vec<Hunk> {
+        let changed: Vec<usize> = (0..self.lines.len()).filter(|&index| self.lines[index].kind != LineKind::Same).collect();
+        let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+        for index in changed {
+            let (start, end): (usize, usize) = (index.saturating_sub(context), (index + context + 1).min(self.lines.len()));
+            match ranges.last_mut() {
+                Some(last) if start <= last.end => last.end = end,
+                _ => ranges.push(start..end)
+            }
+        }
+        ranges
+            .into_iter()
+            .map(|lines| {
+                let side = |offset: fn(&DiffLine) -> Option<usize>, unit: Option<Unit>| {
+                    let count: usize = self.lines[lines.clone()].iter().filter(|line| offset(line).is_some()).count();
+                    let start: usize = self.lines[lines.start..]
+                        .iter()
+                        .find_map(offset)
+                        .or_else(|| self.lines[..lines.start].iter().rev().find_map(offset))
+                        .or_else(|| unit.and_then(|unit| unit.entry()))
+                        .unwrap_or_default();
+                    (start, count)
+                };
+                let (old_start, old_count): (usize, usize) = side(|line| line.old, self.old);
+                let (new_start, new_count): (usize, usize) = side(|line| line.new, self.new);
+                Hunk { old_start, old_count, new_start, new_count, lines }
+            })
+            .collect()
+    }
+}
+
+/// The functions of one file, each with its instructions, plus the code outside them.
+struct Split<'a> {
+    functions: Vec<(Function, Vec<&'a Instruction>)>,
+    top_level: Vec<&'a Instruction>
+}
+
+fn split(listing: &Listing) -> Split<'_> {
+    let functions: Functions = Functions::new(listing);
+    let mut split: Split = Split { functions: functions.functions.iter().map(|&function| (function, Vec::new())).collect(), top_level: Vec::new() };
+    let mut instructions: Vec<&Instruction> = listing.instructions.iter().collect();
+    instructions.sort_by_key(|instruction| instruction.offset);
+    instructions.dedup_by_key(|instruction| instruction.offset);
+    for instruction in instructions {
+        match functions.containing(instruction.offset) {
+            Some(function) => {
+                let index: usize = split.functions.partition_point(|(other, _)| other.entry < function.entry);
+                split.functions[index].1.push(instruction);
+            }
+            None => split.top_level.push(instruction)
+        }
+    }
+    split
+}
+
+/// A function's strings and instruction count, which functions are paired by.
+type Key = (BTreeSet<String>, usize);
+
+fn strings(instructions: &[&Instruction]) -> BTreeSet<String> {
+    instructions
+        .iter()
+        .filter_map(|instruction| match (instruction.opcode, instruction.operands.as_slice()) {
+            (OpCodes::NewValue, [_, Operand::Str(value, _)]) => Some(value.clone()),
+            _ => None
+        })
+        .collect()
+}
+
+/// How alike two functions are from their strings and sizes: 1 for the same strings and size.
+fn similarity(old: &Key, new: &Key) -> f64 {
+    let shared: usize = old.0.intersection(&new.0).count();
+    let all: usize = old.0.len() + new.0.len() - shared;
+    let strings: f64 = if all == 0 { 1.0 } else { shared as f64 / all as f64 };
+    let sizes: f64 = old.1.min(new.1) as f64 / old.1.max(new.1).max(1) as f64;
+    strings * sizes
+}
+
+/// Pairs old functions with new ones by their strings and instruction counts, never by entry offset.
+/// Functions that agree on both are paired first, in order; the rest pair greedily by `similarity`.
+fn match_functions(old: &Split, new: &Split) -> Vec<(usize, usize)> {
+    let keys = |split: &Split| -> Vec<Key> {
+        split.functions.iter().map(|(_, instructions)| (strings(instructions), instructions.len())).collect()
+    };
+    let (old_keys, new_keys): (Vec<Key>, Vec<Key>) = (keys(old), keys(new));
+
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut unmatched: BTreeMap<&Key, Vec<usize>> = BTreeMap::new();
+    for (index, key) in new_keys.iter().enumerate().rev() {
+        unmatched.entry(key).or_default().push(index);
+    }
+    let mut old_left: Vec<usize> = Vec::new();
+    for (index, key) in old_keys.iter().enumerate() {
+        match unmatched.get_mut(key).and_then(|candidates| candidates.pop()) {
+            Some(new_index) => pairs.push((index, new_index)),
+            None => old_left.push(index)
+        }
+    }
+    let mut new_left: BTreeSet<usize> = unmatched.into_values().flatten().collect();
+
+    let mut candidates: Vec<(f64, usize, usize)> = old_left
+        .iter()
+        .flat_map(|&old_index| new_left.iter().map(move |&new_index| (old_index, new_index)))
+        .map(|(old_index, new_index)| (similarity(&old_keys[old_index], &new_keys[new_index]), old_index, new_index))
+        .filter(|(score, _, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    let mut old_done: BTreeSet<usize> = BTreeSet::new();
+    for (_, old_index, new_index) in candidates {
+        if !old_done.contains(&old_index) && new_left.remove(&new_index) {
+            old_done.insert(old_index);
+            pairs.push((old_index, new_index));
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// The instructions of a unit as compared: targets inside the unit become `L0`, `L1`, ... in offset
+/// order, function entries become the function's name in `names`, and anything else stays relative.
+fn normalize(instructions: &[&Instruction], names: &BTreeMap<usize, String>) -> Vec<String> {
+    let starts: BTreeSet<usize> = instructions.iter().map(|instruction| instruction.offset).collect();
+    let local: Vec<usize> = instructions
+        .iter()
+        .flat_map(|instruction| instruction.targets())
+        .map(|target| target as usize)
+        .filter(|target| starts.contains(target) && !names.contains_key(target))
+        .collect::<BTreeSet<usize>>()
+        .into_iter()
+        .collect();
+    instructions
+        .iter()
+        .map(|instruction| {
+            instruction.render_targets(true, |target| {
+                let target: usize = target as usize;
+                match (names.get(&target), local.binary_search(&target)) {
+                    (Some(name), _) => name.clone(),
+                    (None, Ok(label)) => format!("L{label}"),
+                    (None, Err(_)) => format!(".{:+}", target as i64 - instruction.offset as i64)
+                }
+            })
+        })
+        .collect()
+}
+
+/// Myers' shortest edit script between `old` and `new`, or `None` when it would need more than
+/// `MAX_TRACE` cells.
+fn edit_script(old: &[String], new: &[String]) -> Option<Vec<(LineKind, usize, usize)>> {
+    let (n, m): (isize, isize) = (old.len() as isize, new.len() as isize);
+    let max: isize = n + m;
+    // Diagonals -max-1 ..= max+1, as step d reads both neighbours of -d ..= d.
+    let width: usize = 2 * max as usize + 3;
+    let mut v: Vec<isize> = vec![0; width];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let at = |k: isize| (k + max + 1) as usize;
+
+    'search: for d in 0..=max {
+        if (trace.len() + 1).saturating_mul(width) > MAX_TRACE {
+            return None;
+        }
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x: isize = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { v[at(k + 1)] } else { v[at(k - 1)] + 1 };
+            let mut y: isize = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script: Vec<(LineKind, usize, usize)> = Vec::new();
+    let (mut x, mut y): (isize, isize) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let (d, k): (isize, isize) = (d as isize, x - y);
+        let previous: isize = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { k + 1 } else { k - 1 };
+        let (previous_x, previous_y): (isize, isize) = (v[at(previous)], v[at(previous)] - previous);
+        while x > previous_x && y > previous_y {
+            (x, y) = (x - 1, y - 1);
+            script.push((LineKind::Same, x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                script.push((LineKind::Added, x as usize, (y - 1) as usize));
+            } else {
+                script.push((LineKind::Removed, (x - 1) as usize, y as usize));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    script.reverse();
+    Some(script)
+}
+
+fn diff_lines(old: (&[&Instruction], Vec<String>), new: (&[&Instruction], Vec<String>)) -> Vec<DiffLine> {
+    let (old_instructions, old_text) = old;
+    let (new_instructions, new_text) = new;
+    let removed = |index: usize| DiffLine { kind: LineKind::Removed, old: Some(old_instructions[index].offset), new: None, text: old_text[index].clone() };
+    let added = |index: usize| DiffLine { kind: LineKind::Added, old: None, new: Some(new_instructions[index].offset), text: new_text[index].clone() };
+    match edit_script(&old_text, &new_text) {
+        Some(script) => script
+            .into_iter()
+            .map(|(kind, x, y)| match kind {
+                LineKind::Same => DiffLine {
+                    kind,
+                    old: Some(old_instructions[x].offset),
+                    new: Some(new_instructions[y].offset),
+                    text: old_text[x].clone()
+                },
+                LineKind::Removed => removed(x),
+                LineKind::Added => added(y)
+            })
+            .collect(),
+        None => (0..old_text.len()).map(removed).chain((0..new_text.len()).map(added)).collect()
+    }
+}
+
+/// An instruction-level comparison of two listings that ignores where code sits. Functions are paired
+/// by their strings and sizes rather than their entries, and each pair is diffed on its own, as is the
+/// code outside functions; a function only one file has is entirely added or removed. Instructions are
+/// compared as text with their targets replaced by names, so code that only moved compares equal. A
+/// call to a paired function is written with the old file's name for it in both files.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListingDiff {
+    /// The code outside functions, then the paired and removed functions in old order, then the added
+    /// ones in new order.
+    pub units: Vec<UnitDiff>,
+    /// Distinct NEW VALUE strings only the new file loads, and only the old file loads.
+    pub strings_added: Vec<String>,
+    pub strings_removed: Vec<String>
+}
+
+impl ListingDiff {
+    pub fn new(old: &Listing, new: &Listing) -> Self {
+        let (old_split, new_split): (Split, Split) = (split(old), split(new));
+        let pairs: Vec<(usize, usize)> = match_functions(&old_split, &new_split);
+
+        let old_names: BTreeMap<usize, String> = old_split.functions.iter().map(|(function, _)| (function.entry, function.name())).collect();
+        let mut new_names: BTreeMap<usize, String> =
+            new_split.functions.iter().map(|(function, _)| (function.entry, format!("new_{}", function.name()))).collect();
+        for &(old_index, new_index) in &pairs {
+            new_names.insert(new_split.functions[new_index].0.entry, old_split.functions[old_index].0.name());
+        }
+        let old_text = |instructions: &[&Instruction]| normalize(instructions, &old_names);
+        let new_text = |instructions: &[&Instruction]| normalize(instructions, &new_names);
+
+        let mut diff: ListingDiff = ListingDiff::default();
+        diff.units.push(UnitDiff {
+            old: Some(Unit::TopLevel),
+            new: Some(Unit::TopLevel),
+            lines: diff_lines((&old_split.top_level, old_text(&old_split.top_level)), (&new_split.top_level, new_text(&new_split.top_level)))
+        });
+        let paired: BTreeMap<usize, usize> = pairs.iter().copied().collect();
+        for (old_index, (function, instructions)) in old_split.functions.iter().enumerate() {
+            let unit: UnitDiff = match paired.get(&old_index) {
+                Some(&new_index) => {
+                    let (new_function, new_instructions) = &new_split.functions[new_index];
+                    UnitDiff {
+                        old: Some(Unit::Function(*function)),
+                        new: Some(Unit::Function(*new_function)),
+                        lines: diff_lines((instructions, old_text(instructions)), (new_instructions, new_text(new_instructions)))
+                    }
+                }
+                None => UnitDiff { old: Some(Unit::Function(*function)), new: None, lines: diff_lines((instructions, old_text(instructions)), (&[], Vec::new())) }
+            };
+            diff.units.push(unit);
+        }
+        let new_paired: BTreeSet<usize> = pairs.iter().map(|&(_, new_index)| new_index).collect();
+        for (new_index, (function, instructions)) in new_split.functions.iter().enumerate() {
+            if !new_paired.contains(&new_index) {
+                diff.units.push(UnitDiff { old: None, new: Some(Unit::Function(*function)), lines: diff_lines((&[], Vec::new()), (instructions, new_text(instructions))) });
+            }
+        }
+
+        let values = |listing: &Listing| -> BTreeSet<String> { StringTable::new(listing).entries.into_iter().map(|entry| entry.value).collect() };
+        let (old_strings, new_strings): (BTreeSet<String>, BTreeSet<String>) = (values(old), values(new));
+        diff.strings_added = new_strings.difference(&old_strings).cloned().collect();
+        diff.strings_removed = old_strings.difference(&new_strings).cloned().collect();
+        diff
+    }
+
+    pub fn counts(&self) -> Counts {
+        let mut counts: Counts = Counts::default();
+        for unit in &self.units {
+            counts.add(unit.counts());
+        }
+        counts
+    }
+
+    /// The units that differ.
+    pub fn changed(&self) -> impl Iterator<Item = &UnitDiff> {
+        self.units.iter().filter(|unit| unit.is_changed() || unit.old.is_none() || unit.new.is_none())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed().next().is_none() && self.strings_added.is_empty() && self.strings_removed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::testing::{decode, new_value};
+
+    /// NEW VALUE `text` -> reg5, then `extra`, NEW FUNCTION reg1 for the function after HALT with (reg2, reg3);
+    /// HALT; then the function: JUMP IF FALSE reg2 over RET reg2 []; LOAD IMM8 `result` -> reg4; RET reg4 [].
+    fn program(text: &str, extra: &[u8], result: u8) -> Vec<u8> {
+        let entry: u8 = (14 + text.len() + extra.len()) as u8;
+        let mut bytes: Vec<u8> = new_value(5, text);
+        bytes.extend(extra);
+        bytes.extend([171, 1, 0, 0, 0, entry, 2, 2, 3, 166, 39, 2, 0, 0, 0, entry + 9, 17, 2, 0, 181, 4, result, 17, 4, 0]);
+        bytes
+    }
+
+    #[test]
+    fn pairs_a_moved_function_and_diffs_it_in_place() {
+        let old: Listing = decode(program("a", &[], 1));
+        let new: Listing = decode(program("b", &[181, 6, 7], 2));
+        let diff: ListingDiff = ListingDiff::new(&old, &new);
+
+        assert_eq!(diff.units.len(), 2);
+        assert_eq!(diff.units[0].counts(), Counts { added: 1, removed: 0, changed: 1 });
+        let function: &UnitDiff = &diff.units[1];
+        assert_eq!((function.old.and_then(|unit| unit.entry()), function.new.and_then(|unit| unit.entry())), (Some(15), Some(18)));
+        let kinds: Vec<LineKind> = function.lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, [LineKind::Same, LineKind::Same, LineKind::Removed, LineKind::Added, LineKind::Same]);
+        assert_eq!(function.hunks(0), [Hunk { old_start: 24, old_count: 1, new_start: 27, new_count: 1, lines: 2..4 }]);
+
+        assert_eq!(diff.counts(), Counts { added: 1, removed: 0, changed: 2 });
+        assert_eq!(diff.strings_added, ["b"]);
+        assert_eq!(diff.strings_removed, ["a"]);
+    }
+}
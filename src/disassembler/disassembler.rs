@@ -1,129 +1,905 @@
-use std::f64;
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+use log::{debug, error, trace, warn};
+#[cfg(feature = "b64")]
 use crate::bytecode::bytearray::{decode_bytecode};
-use crate::disassembler::instructions::Instructions;
+use crate::disassembler::codec::ByteReader;
+use crate::disassembler::error::{DisassemblerError, ExecuteError};
+use crate::disassembler::instruction::{Instruction, TargetFormat};
+use crate::disassembler::instructions::{InstructionEntry, OpcodeTable};
+use crate::disassembler::listing::{Listing, Termination};
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Operand, OperandKind};
+use crate::disassembler::registers::{count_edges, Labels, RegValue, RegisterTracker, TrackingMode};
+use crate::disassembler::sink::InstructionSink;
+use crate::disassembler::state::DisassemblerState;
+
+pub const DEFAULT_STRING_KEY: u8 = 50;
+pub const DEFAULT_MAX_VISITED: usize = 1_000_000;
+const OUT_OF_RANGE: &str = "!! out of range";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnknownOpcodePolicy {
+    FailFast,
+    Lenient
+}
+
+/// How lenient mode looks for the next real instruction after an unknown opcode byte.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resync {
+    /// Instructions that must decode cleanly, one after another, at the resume offset.
+    pub window: usize,
+    /// How many bytes past the unknown byte are searched before falling back to skipping just it.
+    pub scan_limit: usize,
+    /// Highest register number a candidate instruction may use (the sample goes up to reg242).
+    pub max_register: u8
+}
+
+impl Default for Resync {
+    fn default() -> Self {
+        Self { window: 4, scan_limit: 256, max_register: 250 }
+    }
+}
 
 pub struct Disassembler {
     bytearray: Vec<u8>,
     ptr: usize,
-    pub registers: Vec<String>,
-    pub trace: Vec<String>
+    start: usize,
+    end: usize,
+    pub overrun: Option<usize>,
+    pub max_instructions: Option<usize>,
+    pub max_visited: usize,
+    pub targets: TargetFormat,
+    pub raw_strings: bool,
+    pub stop_at_return: bool,
+    pub unknown_policy: UnknownOpcodePolicy,
+    pub unknown_skipped: usize,
+    pub resync: Option<Resync>,
+    pub string_key: u8,
+    string_chars: usize,
+    unprintable_chars: usize,
+    frames: usize,
+    pub registers: RegisterTracker,
+    pub trace: Vec<String>,
+    pub instructions: Vec<Instruction>,
+    table: OpcodeTable,
+    annotations: BTreeMap<usize, Annotation>,
+    timings: Option<Vec<(usize, Duration)>>,
+    pub(crate) sink: Option<Box<dyn InstructionSink + Send>>
 }
 
+struct Annotation {
+    comment: String,
+    matched: bool,
+    nearest: Option<usize>
+}
 
-fn left_pad(input: &str, target_length: usize, pad_char: Option<&str>) -> String {
-    let pad_char = pad_char.unwrap_or(" ");
-    if input.len() >= target_length {
-        return input.to_string();
-    }
+pub struct TraceLine<'a> {
+    pub start: usize,
+    pub ptr: usize,
+    pub bytes: &'a [u8],
+    pub opcode: OpCodes,
+    pub text: &'a str,
+    pub operands: &'a [Operand],
+    pub comment: Option<&'a str>,
+    /// See `Instruction::depth`.
+    pub depth: usize
+}
 
-    let mut pad = pad_char.to_string();
-    let pad_length = target_length - input.len();
 
-    if pad_length > pad.len() {
-        let repeat_count = pad_length / pad.len();
-        pad = pad.repeat(repeat_count + 1);
+pub struct Iter<'a> {
+    disasm: &'a mut Disassembler,
+    failed: bool
+}
+
+impl<'a> TraceLine<'a> {
+    pub fn new(instruction: &'a Instruction, bytes: &'a [u8], text: &'a str) -> Self {
+        Self {
+            start: instruction.offset,
+            ptr: instruction.end(),
+            bytes: &bytes[instruction.offset..instruction.end()],
+            opcode: instruction.opcode,
+            text,
+            operands: &instruction.operands,
+            comment: instruction.comment.as_deref(),
+            depth: instruction.depth
+        }
     }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Instruction, DisassemblerError>;
 
-    let final_pad = &pad[..pad_length];
-    format!("{}{}", final_pad, input)
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.disasm.step() {
+            Ok(Some(_)) => self.disasm.instructions.last().cloned().map(Ok),
+            Ok(None) => None,
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
 }
 
 impl Disassembler {
-    pub fn new(bytecode: String) -> Self {
-        let bytearray: Vec<u8> = decode_bytecode(bytecode);
+    #[cfg(feature = "b64")]
+    pub fn new(bytecode: String, table: Option<OpcodeTable>) -> Result<Self, DisassemblerError> {
+        let mut disasm: Disassembler = Self::from_bytes(decode_bytecode(bytecode)?);
+        if let Some(table) = table {
+            disasm.set_table(table);
+        }
+        Ok(disasm)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytearray: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut bytearray)?;
+        Ok(Self::from_bytes(bytearray))
+    }
+
+    pub fn from_bytes(bytearray: Vec<u8>) -> Self {
+        let end: usize = bytearray.len();
 
         Self {
-            bytearray: bytearray,
+            bytearray,
             ptr: 0,
-            registers: vec![String::from("_free_reg_"); 256],
-            trace: Vec::new()
+            start: 0,
+            end,
+            overrun: None,
+            max_instructions: None,
+            max_visited: DEFAULT_MAX_VISITED,
+            targets: TargetFormat::Hex,
+            raw_strings: false,
+            stop_at_return: false,
+            unknown_policy: UnknownOpcodePolicy::FailFast,
+            unknown_skipped: 0,
+            resync: None,
+            string_key: DEFAULT_STRING_KEY,
+            string_chars: 0,
+            unprintable_chars: 0,
+            frames: 0,
+            registers: RegisterTracker::new(TrackingMode::Strings),
+            trace: Vec::new(),
+            instructions: Vec::new(),
+            table: OpcodeTable::default(),
+            annotations: BTreeMap::new(),
+            timings: None,
+            sink: None
         }
     }
 
-    pub fn get_byte(&mut self) -> u8 {
-        let byte = self.bytearray[self.ptr];
-        self.ptr += 1;
-        byte
+    pub fn snapshot(&self) -> DisassemblerState {
+        DisassemblerState {
+            ptr: self.ptr,
+            end: self.end,
+            string_key: self.string_key,
+            unknown_policy: self.unknown_policy,
+            targets: self.targets,
+            raw_strings: self.raw_strings,
+            tracking: self.registers.mode,
+            registers: self.registers.values().to_vec(),
+            origins: self.registers.origins().to_vec(),
+            pending: self.registers.pending().clone(),
+            previous: self.registers.previous(),
+            naive_tracking: self.registers.naive,
+            fold_constants: self.registers.fold,
+            unknown_skipped: self.unknown_skipped,
+            resync: self.resync,
+            string_chars: self.string_chars,
+            unprintable_chars: self.unprintable_chars,
+            frames: self.frames,
+            table: self.table.name.clone()
+        }
     }
 
-    fn get_pointer_byte(&mut self) -> u32 {
-        let byte1 = (self.bytearray[self.ptr] as u32) << 8;
-        self.ptr += 1;
-        let byte2 = byte1 | (self.bytearray[self.ptr] as u32);
+    /// Continues from `state` with `table`, which must be the table the snapshot was taken with.
+    pub fn restore(bytes: Vec<u8>, state: DisassemblerState, table: OpcodeTable) -> Result<Self, DisassemblerError> {
+        let len: usize = bytes.len();
+        if state.end > len {
+            return Err(DisassemblerError::OutOfBounds { offset: state.end, len });
+        }
+        if table.name != state.table {
+            return Err(DisassemblerError::InvalidConfig(format!("snapshot was taken with opcode table '{}', not '{}'", state.table, table.name)));
+        }
+
+        let mut disasm: Disassembler = Self::from_bytes(bytes);
+        disasm.set_table(table);
+        disasm.set_position(state.ptr)?;
+        disasm.end = state.end;
+        disasm.string_key = state.string_key;
+        disasm.unknown_policy = state.unknown_policy;
+        disasm.targets = state.targets;
+        disasm.raw_strings = state.raw_strings;
+        disasm.registers = RegisterTracker::from_values(state.tracking, state.registers);
+        disasm.registers.restore_flow(state.origins, state.pending, state.previous);
+        disasm.registers.naive = state.naive_tracking;
+        disasm.registers.fold = state.fold_constants;
+        disasm.unknown_skipped = state.unknown_skipped;
+        disasm.resync = state.resync;
+        disasm.string_chars = state.string_chars;
+        disasm.unprintable_chars = state.unprintable_chars;
+        disasm.frames = state.frames;
+        Ok(disasm)
+    }
+
+    pub fn set_range(&mut self, start: usize, end: Option<usize>) -> Result<(), DisassemblerError> {
+        let len: usize = self.bytearray.len();
+        let end: usize = end.unwrap_or(len);
+
+        if start >= len {
+            return Err(DisassemblerError::InvalidConfig(format!("start offset {start} is past the end of the bytecode ({len} bytes)")));
+        }
+        if end > len {
+            return Err(DisassemblerError::InvalidConfig(format!("end offset {end} is past the end of the bytecode ({len} bytes)")));
+        }
+        if start >= end {
+            return Err(DisassemblerError::InvalidConfig(format!("start offset {start} must be before end offset {end}")));
+        }
+
+        self.ptr = start;
+        self.end = end;
+        Ok(())
+    }
+
+    pub fn set_table(&mut self, table: OpcodeTable) {
+        self.table = table;
+    }
+
+    pub fn table(&self) -> &OpcodeTable {
+        &self.table
+    }
+
+    pub fn set_annotations(&mut self, annotations: Vec<(usize, String)>) {
+        self.annotations = annotations
+            .into_iter()
+            .map(|(offset, comment)| (offset, Annotation { comment, matched: false, nearest: None }))
+            .collect();
+    }
+
+    pub fn unmatched_annotations(&self) -> Vec<(usize, Option<usize>)> {
+        self.annotations
+            .iter()
+            .filter(|(_, annotation)| !annotation.matched)
+            .map(|(offset, annotation)| (*offset, annotation.nearest))
+            .collect()
+    }
+
+    pub fn enable_timing(&mut self) {
+        self.timings = Some(vec![(0, Duration::ZERO); 256]);
+    }
+
+    pub fn opcode_timings(&self) -> Vec<(u8, OpCodes, usize, Duration)> {
+        let Some(timings) = &self.timings else {
+            return Vec::new();
+        };
+        timings
+            .iter()
+            .enumerate()
+            .filter(|(_, (count, _))| *count > 0)
+            .filter_map(|(byte, &(count, total))| {
+                let byte: u8 = byte as u8;
+                self.table.get(byte).map(|(opcode, _)| (byte, opcode, count, total))
+            })
+            .collect()
+    }
+
+    pub fn seek_function(&mut self, entry: usize, budget: Option<usize>) -> Result<(), DisassemblerError> {
+        let len: usize = self.bytearray.len();
+        if entry >= len {
+            return Err(DisassemblerError::InvalidConfig(format!("function entry {entry:#06x} is past the end of the bytecode ({len} bytes)")));
+        }
+
+        self.ptr = entry;
+        self.end = budget.map_or(len, |budget| len.min(entry.saturating_add(budget)));
+        self.overrun = None;
+        self.stop_at_return = true;
+        // The function runs in the frame its caller opened, which its RET closes.
+        self.frames = 1;
+        Ok(())
+    }
+
+    pub fn record(&mut self, opcode: OpCodes, operands: Vec<Operand>) {
+        let resolved: Vec<Option<RegValue>> = self.registers.resolve(&operands);
+        let mut instruction: Instruction = Instruction::new(self.start, opcode, operands, resolved);
+        instruction.origins = self.registers.resolve_origins(&instruction.operands);
+        instruction.depth = self.frames;
+        match opcode {
+            OpCodes::JumpFrame => self.frames += 1,
+            OpCodes::Ret if self.frames == 0 => warn!("RET at offset {:#06x} without an open JUMP FRAME", self.start),
+            OpCodes::Ret => self.frames -= 1,
+            OpCodes::Halt => {
+                if self.frames > 0 {
+                    warn!("HALT at offset {:#06x} with {} JUMP FRAMEs still open", self.start, self.frames);
+                }
+                self.frames = 0;
+            }
+            _ => {}
+        }
+        self.instructions.push(instruction);
+    }
+
+    pub fn listing(&self) -> Listing {
+        Listing::new(self.bytearray.len(), self.string_key, self.instructions.clone())
+    }
+
+    pub fn live_registers(&self) -> Vec<(u8, &RegValue)> {
+        self.registers.live()
+    }
+
+    pub fn max_register(&self) -> Option<u8> {
+        self.registers.max_register()
+    }
+
+    pub fn unprintable_ratio(&self) -> Option<f64> {
+        if self.string_chars == 0 {
+            return None;
+        }
+        Some(self.unprintable_chars as f64 / self.string_chars as f64)
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytearray
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.end.saturating_sub(self.ptr)
+    }
+
+    pub fn position(&self) -> usize {
+        self.ptr
+    }
+
+    pub fn set_position(&mut self, offset: usize) -> Result<(), DisassemblerError> {
+        let len: usize = self.bytearray.len();
+        if offset > len {
+            return Err(DisassemblerError::OutOfBounds { offset, len });
+        }
+        self.ptr = offset;
+        self.overrun = None;
+        Ok(())
+    }
+
+    pub fn measure(&self, offset: usize) -> Result<usize, DisassemblerError> {
+        let mut reader: ByteReader = ByteReader::at(&self.bytearray, offset);
+        let byte: u8 = reader.u8()?;
+        let Some((opcode, _)) = self.table.get(byte) else {
+            return Err(DisassemblerError::UnknownOpcode { offset, byte });
+        };
+        for &kind in opcode.layout() {
+            reader.skip_operand(kind)?;
+        }
+        Ok(reader.pos - offset)
+    }
+
+    fn plausible(&self, offset: usize, max_register: u8) -> bool {
+        let Some((opcode, _)) = self.table.get(self.bytearray[offset]) else {
+            return false;
+        };
+        let mut reader: ByteReader = ByteReader::at(&self.bytearray, offset + 1);
+        opcode.layout().iter().all(|&kind| match kind {
+            OperandKind::Reg => reader.u8().is_ok_and(|reg| reg <= max_register),
+            OperandKind::RegList => match reader.u8() {
+                Ok(count) => (0..count).all(|_| reader.u8().is_ok_and(|reg| reg <= max_register)),
+                Err(_) => false
+            },
+            OperandKind::Target => reader.u32_be().is_ok_and(|target| (target as usize) < self.bytearray.len()),
+            kind => reader.skip_operand(kind).is_ok()
+        })
+    }
+
+    /// First offset after `start` where `resync.window` plausible instructions decode back to back.
+    /// Running cleanly into the end of the range also counts.
+    pub fn resync_point(&self, start: usize, resync: Resync) -> Option<usize> {
+        let limit: usize = (start + 1 + resync.scan_limit).min(self.end);
+        (start + 1..=limit).find(|&resume| {
+            let mut at: usize = resume;
+            for _ in 0..resync.window {
+                if at == self.end {
+                    return true;
+                }
+                match self.measure(at) {
+                    Ok(length) if at + length <= self.end && self.plausible(at, resync.max_register) => at += length,
+                    _ => return false
+                }
+            }
+            true
+        })
+    }
+
+    pub fn peek_byte(&self) -> Result<u8, DisassemblerError> {
+        ByteReader::at(&self.bytearray, self.ptr).u8()
+    }
+
+    fn read<T>(&mut self, read: impl FnOnce(&mut ByteReader) -> Result<T, DisassemblerError>) -> Result<T, DisassemblerError> {
+        let mut reader: ByteReader = ByteReader::at(&self.bytearray, self.ptr);
+        let value: T = read(&mut reader)?;
+        let pos: usize = reader.pos;
+        trace!("read {:02x?} at {}, ptr -> {pos}", &self.bytearray[self.ptr..pos], self.ptr);
+        self.ptr = pos;
+        Ok(value)
+    }
+
+    pub fn get_byte(&mut self) -> Result<u8, DisassemblerError> {
+        self.read(|reader| reader.u8())
+    }
+
+    pub fn decode_value(&mut self) -> Result<(String, Vec<u8>), DisassemblerError> {
+        let key: u8 = self.string_key;
+        let (string, bytes) = self.read(|reader| reader.xored_string(key))?;
+
+        let mut unprintable: usize = 0;
+        for c in string.chars() {
+            if c == char::REPLACEMENT_CHARACTER || (c.is_control() && !matches!(c, '\t' | '\n' | '\r')) {
+                unprintable += 1;
+            }
+            self.string_chars += 1;
+        }
+        if unprintable > 0 {
+            warn!("string at offset {:#06x} has {unprintable} unprintable chars with key {key}", self.start);
+        }
+        self.unprintable_chars += unprintable;
+        Ok((string, bytes))
+    }
+
+    pub fn read_double(&mut self) -> Result<f64, DisassemblerError> {
+        self.read(|reader| reader.f64_ieee())
+    }
+
+    pub fn get_u32_be(&mut self) -> Result<u32, DisassemblerError> {
+        self.read(|reader| reader.u32_be())
+    }
+
+    pub fn seek(&mut self, offset: usize) -> Result<(), DisassemblerError> {
+        if offset >= self.end {
+            return Err(DisassemblerError::OutOfBounds { offset, len: self.end });
+        }
+        self.ptr = offset;
+        self.overrun = None;
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<Option<TraceLine<'_>>, DisassemblerError> {
+        if self.ptr >= self.end {
+            return Ok(None);
+        }
+
+        let start: usize = self.ptr;
+        self.start = start;
+        if self.registers.needs_labels() {
+            let labels: Labels = self.scan_labels();
+            self.registers.set_labels(labels);
+        }
+        self.registers.enter(start);
+        let offset = self.bytearray[self.ptr];
         self.ptr += 1;
-        byte2
+
+        let entry: Option<InstructionEntry> = self.table.get(offset);
+        let opcode: OpCodes = match entry {
+            Some((opcode, handler)) => {
+                let started: Option<Instant> = self.timings.is_some().then(Instant::now);
+                debug!("{start:#06x}: dispatch {offset} -> {opcode}");
+                if let Err(err) = handler(self) {
+                    let err: DisassemblerError = err.within(start, opcode);
+                    error!("{err}");
+                    self.ptr = start;
+                    return Err(err);
+                }
+                if let (Some(started), Some(timings)) = (started, &mut self.timings) {
+                    let timing: &mut (usize, Duration) = &mut timings[offset as usize];
+                    timing.0 += 1;
+                    timing.1 += started.elapsed();
+                }
+                opcode
+            }
+            None if self.unknown_policy == UnknownOpcodePolicy::Lenient => {
+                if let Some(resume) = self.resync.and_then(|resync| self.resync_point(start, resync)) {
+                    warn!("{start:#06x}: unknown opcode {offset} (0x{offset:02x}), skipping {} bytes to resync at {resume:#06x}", resume - start);
+                    self.ptr = resume;
+                } else {
+                    warn!("{start:#06x}: unknown opcode {offset} (0x{offset:02x}), emitting DB");
+                }
+                self.unknown_skipped += self.ptr - start;
+                self.record(OpCodes::Db, vec![Operand::Byte(offset)]);
+                OpCodes::Db
+            }
+            None => {
+                let err: DisassemblerError = DisassemblerError::UnknownOpcode { offset: start, byte: offset };
+                error!("{err}");
+                self.ptr = start;
+                return Err(err);
+            }
+        };
+
+        if self.ptr > self.end {
+            self.instructions.pop();
+            self.ptr = start;
+            self.overrun = Some(start);
+            return Ok(None);
+        }
+
+        debug_assert!(opcode == OpCodes::Db || self.measure(start).ok() == Some(self.ptr - start), "length of {opcode} disagrees with its layout");
+        let ptr: usize = self.ptr;
+        for (offset, annotation) in self.annotations.range_mut(start + 1..ptr) {
+            annotation.nearest = Some(if offset - start <= ptr - offset { start } else { ptr });
+        }
+        let comment: Option<String> = self.annotations.get_mut(&start).map(|annotation| {
+            annotation.matched = true;
+            annotation.comment.clone()
+        });
+
+        let instruction: &mut Instruction = self.instructions.last_mut().unwrap();
+        debug_assert!(instruction.operands.iter().map(Operand::kind).eq(opcode.layout().iter().copied()), "operands of {opcode} disagree with its layout");
+        debug_assert_eq!(opcode.access().len(), opcode.layout().len(), "access table of {opcode} disagrees with its layout");
+        instruction.length = ptr - start;
+        instruction.comment = comment;
+        let mut out_of_range: bool = false;
+        for target in instruction.targets().filter(|&target| target as usize >= self.bytearray.len()) {
+            warn!("{opcode} at offset {start:#06x} targets {target:#x}, past the end of the bytecode");
+            out_of_range = true;
+        }
+        if out_of_range {
+            instruction.comment = Some(match instruction.comment.take() {
+                Some(comment) => format!("{comment}; {OUT_OF_RANGE}"),
+                None => OUT_OF_RANGE.to_string()
+            });
+        }
+        if let Some(value) = self.registers.fold(instruction) {
+            instruction.comment = Some(match instruction.comment.take() {
+                Some(comment) => format!("{comment}; = {value}"),
+                None => format!("= {value}")
+            });
+        }
+        self.registers.observe(instruction);
+        self.trace.push(instruction.text(self.targets, self.raw_strings));
+
+        let instruction: &Instruction = &self.instructions[self.instructions.len() - 1];
+        Ok(Some(TraceLine::new(instruction, &self.bytearray, &self.trace[self.trace.len() - 1])))
     }
 
-    pub fn decode_value(&mut self) -> String {
-        let string_len: u32 = self.get_pointer_byte();
-        let mut string: String = String::new();
+    fn scan_labels(&self) -> Labels {
+        let mut scan: Disassembler = Disassembler::from_bytes(self.bytearray.clone());
+        scan.set_table(self.table.clone());
+        scan.unknown_policy = UnknownOpcodePolicy::Lenient;
+        scan.resync = self.resync;
+        scan.registers = RegisterTracker::new(TrackingMode::Off);
 
-        for _ in 0..string_len {
-            let byte = self.get_byte();
+        let mut labels: Labels = Labels::new();
+        while let Ok(Some(_)) = scan.step() {
+            count_edges(&mut labels, &scan.instructions[0]);
+            scan.trace.clear();
+            scan.instructions.clear();
+        }
+        debug!("{} jump targets found for register tracking", labels.len());
+        labels
+    }
 
-            let c: char = std::char::from_u32((byte as u32) ^ 50).unwrap();
-            string.push_str(&c.to_string());
+    pub fn iter(&mut self) -> Iter<'_> {
+        Iter { disasm: self, failed: false }
+    }
+
+    pub fn execute<F>(&mut self, mut emit: F) -> Result<Listing, ExecuteError>
+    where
+        F: FnMut(&TraceLine) -> io::Result<()>
+    {
+        let recorded: usize = self.instructions.len();
+        let mut decoded: usize = 0;
+        while self.max_instructions.is_none_or(|max| decoded < max) {
+            let line: Option<TraceLine> = match self.step() {
+                Ok(line) => line,
+                Err(err) => return Err(self.execute_error(err, recorded))
+            };
+            let Some(line) = line else {
+                break;
+            };
+            let opcode: OpCodes = line.opcode;
+            if let Err(err) = emit(&line) {
+                error!("emitting the instruction at offset {:#06x}: {err}", self.start);
+                return Err(self.execute_error(err.into(), recorded));
+            }
+            decoded += 1;
+
+            if self.stop_at_return && matches!(opcode, OpCodes::Ret | OpCodes::Halt) {
+                break;
+            }
         }
-        string
+        Ok(self.listing_since(recorded, None))
     }
 
-    pub fn read_double(&mut self) -> f64 {
-        let mut bit_string = String::new();
-        for _ in 0..8 {
-            let byte: u8 = self.get_byte();
-            let bits: String = format!("{:b}", byte);
-            bit_string.push_str(&left_pad(&bits, 8, Some("0")));
+    fn termination(&self, listing: &Listing, error: Option<&DisassemblerError>) -> Termination {
+        let remaining: usize = self.end.saturating_sub(self.ptr);
+        if let Some(offset) = self.overrun {
+            return Termination::MidInstruction { offset, remaining: self.end - offset };
         }
+        match (error, listing.instructions.last()) {
+            (Some(DisassemblerError::Truncated { .. } | DisassemblerError::InvalidLength { .. }), _) => {
+                Termination::MidInstruction { offset: self.ptr, remaining }
+            }
+            (_, Some(instruction)) if instruction.opcode == OpCodes::Halt => Termination::Halt { offset: instruction.offset, remaining },
+            (None, _) if remaining == 0 => Termination::Boundary { offset: self.ptr },
+            _ => Termination::Stopped { offset: self.ptr, remaining }
+        }
+    }
 
-        let sign: f64 = if &bit_string[0..1] == "1" { -1.0 } else { 1.0 };
+    fn listing_since(&self, recorded: usize, error: Option<&DisassemblerError>) -> Listing {
+        let mut listing: Listing = Listing::new(self.bytearray.len(), self.string_key, self.instructions[recorded..].to_vec());
+        listing.termination = Some(self.termination(&listing, error));
+        listing
+    }
 
-        let exponent_bits: &str = &bit_string[1..12];
-        let mut exponent: i32 = i32::from_str_radix(exponent_bits, 2).unwrap();
+    fn execute_error(&self, error: DisassemblerError, recorded: usize) -> ExecuteError {
+        let listing: Listing = self.listing_since(recorded, Some(&error));
+        ExecuteError { error, offset: self.ptr, listing: Box::new(listing) }
+    }
 
-        let mantissa_bits: &str = &bit_string[12..];
+    pub fn run(&mut self) -> Result<(), DisassemblerError> {
+        let Some(mut sink) = self.sink.take() else {
+            return self.execute(|_| Ok(())).map(drop).map_err(DisassemblerError::from);
+        };
+        let result: Result<(), DisassemblerError> = self.execute_with(sink.as_mut());
+        self.sink = Some(sink);
+        result
+    }
 
-        let mantissa_string: String;
-        if exponent == 0 {
-            if !mantissa_bits.contains('1') {
-                return 0.0;
+    pub fn execute_with(&mut self, sink: &mut dyn InstructionSink) -> Result<(), DisassemblerError> {
+        let traced: usize = self.trace.len();
+        let recorded: usize = self.instructions.len();
+        let mut decoded: usize = 0;
+
+        while self.max_instructions.is_none_or(|max| decoded < max) {
+            match self.step() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(err) => {
+                    sink.on_error(&err);
+                    return Err(err);
+                }
+            }
+
+            let instruction: &Instruction = &self.instructions[self.instructions.len() - 1];
+            let written: io::Result<()> = sink.on_instruction(instruction.offset, instruction);
+            let opcode: OpCodes = instruction.opcode;
+            self.trace.truncate(traced);
+            self.instructions.truncate(recorded);
+            if let Err(err) = written {
+                error!("sink rejected the instruction at offset {:#06x}: {err}", self.start);
+                let err: DisassemblerError = DisassemblerError::Io(err);
+                sink.on_error(&err);
+                return Err(err);
+            }
+            decoded += 1;
+
+            if self.stop_at_return && matches!(opcode, OpCodes::Ret | OpCodes::Halt) {
+                break;
             }
-            exponent = -1022;
-            mantissa_string = format!("0{}", mantissa_bits);
-        } else {
-            exponent -= 1023;
-            mantissa_string = format!("1{}", mantissa_bits);
         }
+        Ok(sink.on_finish()?)
+    }
+}
+
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Send + Sync>() {}
+    assert_send::<Disassembler>();
+    assert_sync::<OpcodeTable>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::testing::{decode, decode_err, new_value, run};
+
+    /// LOAD IMM8 reg1, 7; NEW VALUE reg2, 'ab' (XORed with the default key); MOV IMM32 reg3, 0x01020304; HALT
+    const PROGRAM: [u8; 16] = [181, 1, 7, 23, 2, 0, 2, b'a' ^ 50, b'b' ^ 50, 241, 3, 1, 2, 3, 4, 166];
+
+    #[test]
+    fn from_bytes_decodes_handcrafted_bytes() {
+        let listing: Listing = decode(PROGRAM.to_vec());
+        let opcodes: Vec<OpCodes> = listing.instructions.iter().map(|instruction| instruction.opcode).collect();
+        assert_eq!(opcodes, [OpCodes::LoadImm8, OpCodes::NewValue, OpCodes::MovImm32, OpCodes::Halt]);
+        assert!(matches!(listing.instructions[0].operands[..], [Operand::Reg(1), Operand::Byte(7)]));
+        assert!(matches!(&listing.instructions[1].operands[..], [Operand::Reg(2), Operand::Str(text, _)] if text == "ab"));
+        assert!(matches!(listing.instructions[2].operands[..], [Operand::Reg(3), Operand::Int(0x01020304)]));
+        assert!(matches!(listing.termination, Some(Termination::Halt { offset: 15, remaining: 0 })));
+        assert_eq!(listing.termination.unwrap().to_string(), "ended at HALT at offset 0x000f");
+    }
 
-        let mut mantissa = 0.0;
-        let mut frac = 1.0;
-        for c in mantissa_string.chars() {
-            mantissa += frac * c.to_digit(10).unwrap() as f64;
-            frac /= 2.0;
+    #[test]
+    fn termination_tells_a_boundary_from_a_cut_instruction() {
+        let listing: Listing = decode(PROGRAM[..9].to_vec());
+        assert_eq!(listing.termination, Some(Termination::Boundary { offset: 9 }));
+        assert_eq!(listing.termination.unwrap().to_string(), "ended on an instruction boundary at offset 0x0009");
+
+        let err: ExecuteError = decode_err(PROGRAM[..12].to_vec());
+        assert_eq!(err.listing.termination, Some(Termination::MidInstruction { offset: 9, remaining: 3 }));
+        assert_eq!(err.listing.termination.unwrap().to_string(), "ended mid-instruction at offset 0x0009, 3 bytes left");
+    }
+
+    #[test]
+    fn from_reader_matches_from_bytes() {
+        let mut from_reader: Disassembler = Disassembler::from_reader(&PROGRAM[..]).unwrap();
+        let mut from_bytes: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        assert_eq!(from_reader.bytes(), &PROGRAM);
+        run(&mut from_reader);
+        run(&mut from_bytes);
+        assert_eq!(from_reader.trace, from_bytes.trace);
+    }
+
+    /// LOAD IMM8 reg1, 7; HALT with the unknown byte 0 inserted at `offset`.
+    fn with_unknown(offset: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![181, 1, 7, 166];
+        bytes.insert(offset, 0);
+        bytes
+    }
+
+    #[test]
+    fn lenient_mode_emits_db_for_unknown_bytes() {
+        for (offset, expected) in [(0, [OpCodes::Db, OpCodes::LoadImm8, OpCodes::Halt]), (3, [OpCodes::LoadImm8, OpCodes::Db, OpCodes::Halt])] {
+            let mut disasm: Disassembler = Disassembler::from_bytes(with_unknown(offset));
+            disasm.unknown_policy = UnknownOpcodePolicy::Lenient;
+            let listing: Listing = run(&mut disasm);
+            assert_eq!(listing.instructions.iter().map(|instruction| instruction.opcode).collect::<Vec<OpCodes>>(), expected);
+            let db: &Instruction = listing.instruction_at(offset).unwrap();
+            assert!(matches!((db.opcode, db.length, &db.operands[..]), (OpCodes::Db, 1, [Operand::Byte(0)])));
+            assert_eq!(disasm.unknown_skipped, 1);
         }
+    }
 
-        sign * mantissa * f64::powi(2.0, exponent)
+    #[test]
+    fn fail_fast_stops_at_the_unknown_byte_and_keeps_the_partial_listing() {
+        for (offset, decoded) in [(0, 0), (3, 1)] {
+            let err: ExecuteError = decode_err(with_unknown(offset));
+            assert!(matches!(err.error, DisassemblerError::UnknownOpcode { offset: at, byte: 0 } if at == offset));
+            assert_eq!(err.offset, offset);
+            assert_eq!(err.listing.instruction_count, decoded);
+        }
     }
 
-    pub fn get_int24(&mut self) -> u32 {
-        let byte: u32 = (self.get_byte() as u32) << 24 | (self.get_byte() as u32) << 16 | (self.get_byte() as u32) << 8 | (self.get_byte() as u32);
-        byte
+    #[test]
+    fn resync_skips_to_the_next_run_of_plausible_instructions() {
+        let bytes: Vec<u8> = vec![0, 0xee, 0xee, 181, 1, 7, 181, 2, 8, 181, 3, 9, 181, 4, 10, 166];
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes.clone());
+        disasm.unknown_policy = UnknownOpcodePolicy::Lenient;
+        disasm.resync = Some(Resync::default());
+        let listing: Listing = run(&mut disasm);
+        let db: &Instruction = &listing.instructions[0];
+        assert!(matches!((db.offset, db.opcode, db.length, &db.operands[..]), (0, OpCodes::Db, 3, [Operand::Byte(0)])));
+        assert_eq!(listing.instructions[1].offset, 3);
+        assert_eq!(listing.instruction_count, 6);
+        assert_eq!(disasm.unknown_skipped, 3);
+
+        let mut plain: Disassembler = Disassembler::from_bytes(bytes);
+        plain.unknown_policy = UnknownOpcodePolicy::Lenient;
+        let listing: Listing = run(&mut plain);
+        assert_eq!(listing.instructions.iter().take_while(|instruction| instruction.opcode == OpCodes::Db).count(), 3);
+        assert_eq!(plain.unknown_skipped, 3);
     }
 
-    pub fn execute(&mut self) {
-        let instructions = Instructions::get_instructions();
-        while self.ptr < self.bytearray.len() {
-            let offset = self.bytearray[self.ptr];
-            self.ptr += 1;
+    #[test]
+    fn resync_point_requires_a_full_window() {
+        // Only two LOAD IMM8s decode before another unknown byte, so a window of three rejects offset 1.
+        let disasm: Disassembler = Disassembler::from_bytes(vec![0, 181, 1, 7, 181, 2, 8, 0, 181, 3, 9, 181, 4, 10, 181, 5, 11]);
+        let resync: Resync = Resync { window: 3, ..Resync::default() };
+        assert_eq!(disasm.resync_point(0, resync), Some(8));
+        assert_eq!(disasm.resync_point(0, Resync { window: 2, ..resync }), Some(1));
+    }
 
-            if let Some(opcode) = instructions.get(&offset) {
-                opcode(self);
+    #[test]
+    fn set_position_to_the_end_decodes_nothing() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        disasm.set_position(PROGRAM.len()).unwrap();
+        assert_eq!(disasm.position(), 16);
+        assert_eq!(disasm.remaining(), 0);
+        assert!(matches!(disasm.peek_byte(), Err(DisassemblerError::Truncated { offset: 16, needed: 1, available: 0, .. })));
+        assert!(disasm.step().unwrap().is_none());
+        assert_eq!(run(&mut disasm).instruction_count, 0);
+    }
+
+    #[test]
+    fn set_position_past_the_end_is_rejected_and_keeps_the_position() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        disasm.set_position(9).unwrap();
+        assert!(matches!(disasm.set_position(17), Err(DisassemblerError::OutOfBounds { offset: 17, len: 16 })));
+        assert_eq!(disasm.position(), 9);
+        assert_eq!(disasm.peek_byte().unwrap(), 241);
+    }
+
+    #[test]
+    fn seek_rejects_the_end_of_the_range() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        disasm.set_range(0, Some(9)).unwrap();
+        assert!(matches!(disasm.seek(9), Err(DisassemblerError::OutOfBounds { offset: 9, len: 9 })));
+        disasm.seek(3).unwrap();
+        let listing: Listing = run(&mut disasm);
+        assert_eq!(listing.instructions.iter().map(|instruction| instruction.opcode).collect::<Vec<OpCodes>>(), [OpCodes::NewValue]);
+    }
+
+    /// Tracked strings, a property read of them, an open JUMP FRAME and its RET, so the snapshot has state to carry.
+    fn stateful_program() -> Vec<u8> {
+        let mut bytes: Vec<u8> = new_value(1, "document");
+        bytes.extend(new_value(2, "cookie"));
+        bytes.extend([251, 3, 1, 2]);
+        bytes.extend([49, 0, 0, 0, 0, 4, 1, 3]);
+        bytes.extend([181, 5, 9]);
+        bytes.extend([17, 3, 0]);
+        bytes.push(166);
+        bytes
+    }
+
+    /// A forward JUMP IF FALSE over a write to reg2, so the GET PROPERTY at its target sees the merge of both paths.
+    fn branching_program() -> Vec<u8> {
+        let mut bytes: Vec<u8> = new_value(1, "a");
+        bytes.extend(new_value(2, "b"));
+        bytes.extend([39, 1, 0, 0, 0, 21]);
+        bytes.extend(new_value(2, "c"));
+        bytes.extend([251, 3, 1, 2, 166]);
+        bytes
+    }
+
+    fn describe(instructions: &[Instruction]) -> Vec<String> {
+        instructions.iter().map(|instruction| format!("{instruction:?}")).collect()
+    }
 
-                let last_instr = self.trace[self.trace.len() - 1].clone();
-                let new_instr = format!("0x{}    {}", self.ptr, last_instr);
-                println!("{}", new_instr);
-            } else {
-                panic!("{}", format!("Unknown Opcode: {}", offset))
+    #[test]
+    fn restored_snapshot_continues_like_an_uninterrupted_run() {
+        for bytes in [stateful_program(), branching_program()] {
+            let mut whole: Disassembler = Disassembler::from_bytes(bytes.clone());
+            let expected: Vec<String> = describe(&run(&mut whole).instructions);
+
+            for split in 1..expected.len() {
+                let mut first: Disassembler = Disassembler::from_bytes(bytes.clone());
+                first.max_instructions = Some(split);
+                let mut lines: Vec<String> = describe(&run(&mut first).instructions);
+
+                let mut second: Disassembler = Disassembler::restore(bytes.clone(), first.snapshot(), OpcodeTable::default()).unwrap();
+                lines.extend(describe(&run(&mut second).instructions));
+                assert_eq!(lines, expected, "split after {split} instructions");
             }
         }
-    }    
-}
\ No newline at end of file
+    }
+
+    #[test]
+    fn merge_after_a_forward_jump_forgets_disagreeing_values() {
+        let listing: Listing = decode(branching_program());
+        let read: &Instruction = listing.instruction_at(21).unwrap();
+        assert!(matches!(&read.resolved[..], [None, Some(RegValue::Str(object)), None] if object == "a"));
+    }
+
+    #[test]
+    fn restore_rejects_an_end_past_the_bytes() {
+        let mut state: DisassemblerState = Disassembler::from_bytes(PROGRAM.to_vec()).snapshot();
+        state.end = 17;
+        assert!(matches!(Disassembler::restore(PROGRAM.to_vec(), state, OpcodeTable::default()), Err(DisassemblerError::OutOfBounds { offset: 17, len: 16 })));
+    }
+
+    #[test]
+    fn restore_continues_with_the_snapshot_table() {
+        // LOAD IMM8 and HALT moved to bytes 1 and 2: LOAD IMM8 7 -> reg1 twice, then HALT.
+        let bytes: Vec<u8> = vec![1, 1, 7, 1, 1, 7, 2];
+        let table: OpcodeTable = OpcodeTable::from_pairs(&[(1, "load_imm8"), (2, "halt")]).unwrap().with_name("moved", None);
+        let mut first: Disassembler = Disassembler::from_bytes(bytes.clone());
+        first.set_table(table.clone());
+        first.max_instructions = Some(1);
+        run(&mut first);
+
+        let state: DisassemblerState = first.snapshot();
+        assert_eq!(state.table, "moved");
+        assert!(matches!(
+            Disassembler::restore(bytes.clone(), state.clone(), OpcodeTable::default()),
+            Err(DisassemblerError::InvalidConfig(message)) if message == "snapshot was taken with opcode table 'moved', not 'default'"
+        ));
+        let mut second: Disassembler = Disassembler::restore(bytes, state, table).unwrap();
+        let opcodes: Vec<OpCodes> = run(&mut second).instructions.iter().map(|instruction| instruction.opcode).collect();
+        assert_eq!(opcodes, [OpCodes::LoadImm8, OpCodes::Halt]);
+    }
+}
@@ -0,0 +1,739 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::disassembler::apis::WINDOW_ALIASES;
+use crate::disassembler::codec::{escape_string, format_double};
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+use crate::disassembler::registers::{evaluate, to_uint32, DEFAULT_REGISTER_COUNT};
+
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// Longest description an opaque value keeps; longer ones are cut.
+const MAX_OPAQUE_TEXT: usize = 60;
+/// Elements of an array shown before the rest is elided.
+const MAX_SHOWN_ELEMENTS: usize = 8;
+/// Highest array index SET PROPERTY grows an array to; a write past it makes the array escape instead.
+const MAX_ARRAY_INDEX: usize = 1 << 16;
+
+/// A register value during emulation.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Undefined,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    /// An array built by PUSH ARGS, by index into the emulator's objects.
+    Object(usize),
+    Native(Native),
+    /// Something the emulator cannot compute, such as a register it never saw written or the result of
+    /// a call; the text says where it came from.
+    Opaque(String)
+}
+
+impl Value {
+    fn opaque(text: String) -> Value {
+        match text.char_indices().nth(MAX_OPAQUE_TEXT) {
+            Some((end, _)) => Value::Opaque(format!("{}...", &text[..end])),
+            None => Value::Opaque(text)
+        }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, Value::Opaque(_))
+    }
+}
+
+/// The few host functions the emulator runs itself, for strings built a character at a time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Native {
+    /// The global `String`: the `String` property of the global object, which the VM reads from a
+    /// register it never writes.
+    String,
+    FromCharCode,
+    CharCodeAt
+}
+
+impl Native {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Native::String => "String",
+            Native::FromCharCode => "String.fromCharCode",
+            Native::CharCodeAt => "String.prototype.charCodeAt"
+        }
+    }
+}
+
+/// JavaScript's Number::toString, which writes negative zero as 0.
+fn number_string(value: f64) -> String {
+    if value == 0.0 { "0".to_string() } else { format_double(value) }
+}
+
+/// JavaScript's ToNumber for a string: surrounding whitespace is ignored, an empty string is 0 and
+/// anything that is not a decimal, hex, octal or binary literal or Infinity is NaN.
+fn string_number(value: &str) -> f64 {
+    let value: &str = value.trim();
+    let radix: Option<(u32, &str)> = [("0x", 16), ("0X", 16), ("0o", 8), ("0O", 8), ("0b", 2), ("0B", 2)]
+        .iter()
+        .find_map(|(prefix, radix)| value.strip_prefix(prefix).map(|digits| (*radix, digits)));
+    match (value, radix) {
+        ("", _) => 0.0,
+        (_, Some((radix, digits))) => {
+            if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+                return f64::NAN;
+            }
+            digits.chars().fold(0.0, |number, c| number * radix as f64 + c.to_digit(radix).unwrap_or_default() as f64)
+        }
+        ("Infinity" | "+Infinity", _) => f64::INFINITY,
+        ("-Infinity", _) => f64::NEG_INFINITY,
+        (value, None) if value.chars().all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E')) => value.parse().unwrap_or(f64::NAN),
+        _ => f64::NAN
+    }
+}
+
+/// The UTF-16 code units of a string, which is what JavaScript indexes and measures.
+fn units(value: &str) -> Vec<u16> {
+    value.encode_utf16().collect()
+}
+
+/// Why emulation stopped.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StopReason {
+    Halt,
+    /// RET returns to a caller the emulator did not come from.
+    Ret,
+    Throw,
+    /// Whether the handlers of a TRY CATCH run depends on exceptions the emulator cannot see.
+    TryCatch,
+    /// A conditional jump on a register with an opaque value.
+    OpaqueBranch { reg: u8 },
+    UnknownOpcode,
+    /// Execution continued at an offset that does not start an instruction of the listing.
+    OutsideListing { target: usize },
+    /// Execution continued at an offset outside the region given to `Emulator::run_from`.
+    LeftRegion { target: usize },
+    StepLimit
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Halt => write!(f, "HALT"),
+            StopReason::Ret => write!(f, "RET to an unknown caller"),
+            StopReason::Throw => write!(f, "THROW"),
+            StopReason::TryCatch => write!(f, "TRY CATCH, whose handlers depend on exceptions it cannot see"),
+            StopReason::OpaqueBranch { reg } => write!(f, "a branch on reg{reg}, whose value is opaque"),
+            StopReason::UnknownOpcode => write!(f, "an unknown opcode"),
+            StopReason::OutsideListing { target } => write!(f, "going on at {target:#06x}, which does not start an instruction"),
+            StopReason::LeftRegion { target } => write!(f, "leaving for {target:#06x}"),
+            StopReason::StepLimit => write!(f, "the step limit")
+        }
+    }
+}
+
+/// What an instruction did the last time it ran.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Effect {
+    /// The value written to its destination register, rendered when it was written.
+    Value(Value, String),
+    /// Whether a JUMP IF FALSE / JUMP IF TRUE jumped.
+    Branch(bool)
+}
+
+/// Every run of one instruction.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Produced {
+    pub last: Effect,
+    pub runs: usize,
+    /// Whether some run had another effect than `last`.
+    pub varies: bool
+}
+
+impl Produced {
+    /// Whether the last effect is worth showing: a branch or a value that is not opaque.
+    pub fn is_concrete(&self) -> bool {
+        !matches!(&self.last, Effect::Value(value, _) if value.is_opaque())
+    }
+}
+
+impl fmt::Display for Produced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.last {
+            Effect::Value(_, text) => write!(f, "= {text}")?,
+            Effect::Branch(true) => write!(f, "taken")?,
+            Effect::Branch(false) => write!(f, "not taken")?
+        }
+        if self.varies {
+            write!(f, " (last of {} runs)", self.runs)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Object {
+    elements: Vec<Value>,
+    properties: BTreeMap<String, Value>,
+    /// Handed to code the emulator does not run, which may have changed it since.
+    escaped: bool
+}
+
+/// The result of running a listing from its first instruction with concrete values.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Emulation {
+    /// Offset of the first instruction run.
+    pub start: usize,
+    pub steps: usize,
+    /// The instruction emulation stopped at, or ran last for `OutsideListing` and `LeftRegion`, and why;
+    /// `None` for an empty listing.
+    pub stop: Option<(usize, StopReason)>,
+    produced: BTreeMap<usize, Produced>
+}
+
+impl Emulation {
+    /// What the instruction at `offset` did, if it ran.
+    pub fn get(&self, offset: usize) -> Option<&Produced> {
+        self.produced.get(&offset)
+    }
+}
+
+/// A small interpreter for the parts of the VM that need no host: loads, arithmetic, comparisons, string
+/// building and arrays made by PUSH ARGS. Registers start out opaque, and so is everything read from an
+/// object the emulator did not build or returned by a call, JUMP FRAME included, other than calls to
+/// `String.fromCharCode` and `charCodeAt` (see `Native`). Numbers follow
+/// JavaScript: doubles, 32-bit bitwise operators and NaN. LESS THAN ALT and LTE ALT run like LESS THAN
+/// and LTE. INIT MEMORY stays opaque, as what its byte means is not settled (the register tracker does
+/// not load it either). A conditional jump is followed only when its condition is known; otherwise
+/// emulation stops.
+pub struct Emulator {
+    registers: Vec<Value>,
+    /// Registers taken to hold the global object: those nothing wrote, and `window`, `self` or
+    /// `globalThis` read from one of them.
+    globals: Vec<bool>,
+    objects: Vec<Object>
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self {
+            registers: (0..DEFAULT_REGISTER_COUNT).map(|reg| Value::Opaque(format!("reg{reg}"))).collect(),
+            globals: vec![true; DEFAULT_REGISTER_COUNT],
+            objects: Vec::new()
+        }
+    }
+
+    pub fn value(&self, reg: u8) -> &Value {
+        &self.registers[reg as usize]
+    }
+
+    fn set(&mut self, reg: u8, value: Value) {
+        self.registers[reg as usize] = value;
+        self.globals[reg as usize] = false;
+    }
+
+    /// Runs `listing` from its first instruction until it stops or has taken `max_steps` steps.
+    pub fn run(&mut self, listing: &Listing, max_steps: usize) -> Emulation {
+        match listing.instructions.first() {
+            Some(first) => self.run_from(listing, first.offset, max_steps, |_| true),
+            None => Emulation::default()
+        }
+    }
+
+    /// Runs `listing` from the instruction at `start` for as long as execution stays at offsets `inside`
+    /// accepts, stopping with `LeftRegion` when it goes elsewhere.
+    pub fn run_from(&mut self, listing: &Listing, start: usize, max_steps: usize, inside: impl Fn(usize) -> bool) -> Emulation {
+        let instructions: BTreeMap<usize, &Instruction> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction)).collect();
+        let mut emulation: Emulation = Emulation { start, ..Emulation::default() };
+
+        let (mut offset, mut previous): (usize, usize) = (start, start);
+        loop {
+            if offset != start && !inside(offset) {
+                emulation.stop = Some((previous, StopReason::LeftRegion { target: offset }));
+                break;
+            }
+            let Some(&instruction) = instructions.get(&offset) else {
+                emulation.stop = Some((previous, StopReason::OutsideListing { target: offset }));
+                break;
+            };
+            previous = offset;
+            if emulation.steps == max_steps {
+                emulation.stop = Some((offset, StopReason::StepLimit));
+                break;
+            }
+            emulation.steps += 1;
+            let (effect, next): (Option<Effect>, Result<usize, StopReason>) = self.step(instruction);
+            if let Some(effect) = effect {
+                match emulation.produced.get_mut(&offset) {
+                    Some(produced) => {
+                        produced.varies |= produced.last != effect;
+                        produced.last = effect;
+                        produced.runs += 1;
+                    }
+                    None => {
+                        emulation.produced.insert(offset, Produced { last: effect, runs: 1, varies: false });
+                    }
+                }
+            }
+            match next {
+                Ok(next) => offset = next,
+                Err(reason) => {
+                    emulation.stop = Some((offset, reason));
+                    break;
+                }
+            }
+        }
+        emulation
+    }
+
+    /// Runs `instruction` for what it does to registers and objects, whatever it does to control flow.
+    pub fn apply(&mut self, instruction: &Instruction) -> Option<Effect> {
+        self.step(instruction).0
+    }
+
+    fn step(&mut self, instruction: &Instruction) -> (Option<Effect>, Result<usize, StopReason>) {
+        let next: usize = instruction.end();
+        let written: Value = match (instruction.opcode, instruction.operands.as_slice()) {
+            (OpCodes::InitMemory, [Operand::Reg(reg), Operand::Byte(value)]) => self.write(*reg, Value::Opaque(format!("INIT MEMORY {value}"))),
+            (OpCodes::LoadImm8, [Operand::Reg(reg), Operand::Byte(value)]) => self.write(*reg, Value::Number(*value as f64)),
+            (OpCodes::MovImm32, [Operand::Reg(reg), Operand::Int(value)]) => self.write(*reg, Value::Number(*value as f64)),
+            (OpCodes::LoadDouble, [Operand::Reg(reg), Operand::Double(value)]) => self.write(*reg, Value::Number(*value)),
+            (OpCodes::NewValue, [Operand::Reg(reg), Operand::Str(value, _)]) => self.write(*reg, Value::Str(value.clone())),
+            (OpCodes::PushArgs, [Operand::Reg(reg), Operand::RegList(args)]) => {
+                let elements: Vec<Value> = args.iter().map(|&arg| self.value(arg).clone()).collect();
+                self.objects.push(Object { elements, ..Object::default() });
+                self.write(*reg, Value::Object(self.objects.len() - 1))
+            }
+            (OpCodes::GetProperty, [Operand::Reg(reg), Operand::Reg(object), Operand::Reg(key)]) => {
+                let value: Value = self.get_property(*object, *key);
+                let alias: bool = self.globals[*object as usize] && self.property_key(*key).is_some_and(|name| WINDOW_ALIASES.contains(&name.as_str()));
+                let written: Value = self.write(*reg, value);
+                self.globals[*reg as usize] = alias;
+                written
+            }
+            (OpCodes::SetProperty, [Operand::Reg(object), Operand::Reg(key), Operand::Reg(value)]) => {
+                self.set_property(*object, *key, *value);
+                return (None, Ok(next));
+            }
+            (OpCodes::CallFunction, [Operand::Reg(reg), Operand::Reg(function), Operand::RegList(args)]) => {
+                let value: Value = match self.call_native(*function, None, args) {
+                    Some(value) => value,
+                    None => {
+                        self.escape(args.iter().copied());
+                        Value::opaque(format!("{}(...)", self.describe(*function)))
+                    }
+                };
+                self.write(*reg, value)
+            }
+            (OpCodes::CallApply, [Operand::Reg(reg), Operand::Reg(function), Operand::Reg(this), Operand::RegList(args)]) => {
+                let value: Value = match self.call_native(*function, Some(*this), args) {
+                    Some(value) => value,
+                    None => {
+                        self.escape(args.iter().copied().chain([*this]));
+                        Value::opaque(format!("{}(...)", self.describe(*function)))
+                    }
+                };
+                self.write(*reg, value)
+            }
+            (OpCodes::JumpFrame, [Operand::Target(entry), Operand::Byte(reg), Operand::RegList(params)]) => {
+                self.escape(params.iter().copied());
+                self.write(*reg, Value::Opaque(format!("function_{entry:04x}(...)")))
+            }
+            (OpCodes::NewFunction, [Operand::Reg(reg), Operand::Target(entry), _]) => self.write(*reg, Value::Opaque(format!("function_{entry:04x}"))),
+            (OpCodes::JumpIfFalse | OpCodes::JumpIfTrue, [Operand::Reg(reg), Operand::Target(target)]) => {
+                let Some(condition) = self.truthy(self.value(*reg)) else {
+                    return (None, Err(StopReason::OpaqueBranch { reg: *reg }));
+                };
+                let taken: bool = condition == (instruction.opcode == OpCodes::JumpIfTrue);
+                return (Some(Effect::Branch(taken)), Ok(if taken { *target as usize } else { next }));
+            }
+            (OpCodes::Jump, [Operand::Target(target)]) => return (None, Ok(*target as usize)),
+            (OpCodes::Halt, _) => return (None, Err(StopReason::Halt)),
+            (OpCodes::Ret, _) => return (None, Err(StopReason::Ret)),
+            (OpCodes::Throw, _) => return (None, Err(StopReason::Throw)),
+            (OpCodes::TryCatch, _) => return (None, Err(StopReason::TryCatch)),
+            (OpCodes::Db, _) => return (None, Err(StopReason::UnknownOpcode)),
+            (opcode, [Operand::Reg(reg), Operand::Reg(lhs), Operand::Reg(rhs)]) => {
+                let value: Value = self.binary(opcode, *lhs, *rhs);
+                self.write(*reg, value)
+            }
+            _ => return (None, Err(StopReason::UnknownOpcode))
+        };
+        let text: String = self.render(&written);
+        (Some(Effect::Value(written, text)), Ok(next))
+    }
+
+    fn write(&mut self, reg: u8, value: Value) -> Value {
+        self.set(reg, value.clone());
+        value
+    }
+
+    /// The result of calling the native function in `function`, or `None` when it is not one the
+    /// emulator runs with these arguments.
+    fn call_native(&self, function: u8, this: Option<u8>, args: &[u8]) -> Option<Value> {
+        let Value::Native(native) = self.value(function) else {
+            return None;
+        };
+        match native {
+            Native::String => None,
+            Native::FromCharCode => {
+                let units: Option<Vec<u16>> = args.iter().map(|&arg| self.number(self.value(arg)).map(|number| to_uint32(number) as u16)).collect();
+                Some(Value::Str(String::from_utf16_lossy(&units?)))
+            }
+            Native::CharCodeAt => {
+                let units: Vec<u16> = units(&self.string(self.value(this?))?);
+                let index: f64 = match args.first() {
+                    Some(&arg) => self.number(self.value(arg))?.trunc(),
+                    None => 0.0
+                };
+                let unit: Option<&u16> = if index >= 0.0 { units.get(index as usize) } else { None };
+                Some(Value::Number(unit.map_or(f64::NAN, |&unit| unit as f64)))
+            }
+        }
+    }
+
+    /// Objects in these registers may be changed by code the emulator does not run.
+    fn escape(&mut self, regs: impl Iterator<Item = u8>) {
+        for reg in regs {
+            if let Value::Object(index) = self.registers[reg as usize] {
+                self.objects[index].escaped = true;
+            }
+        }
+    }
+
+    fn describe(&self, reg: u8) -> String {
+        match self.value(reg) {
+            Value::Opaque(text) => text.clone(),
+            value => self.render(value)
+        }
+    }
+
+    fn render(&self, value: &Value) -> String {
+        match value {
+            Value::Undefined => "undefined".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => number_string(*value),
+            Value::Str(value) => format!("'{}'", escape_string(value)),
+            Value::Object(index) => {
+                let elements: &[Value] = &self.objects[*index].elements;
+                let mut shown: Vec<String> = elements
+                    .iter()
+                    .take(MAX_SHOWN_ELEMENTS)
+                    .map(|element| match element {
+                        Value::Object(_) => "[...]".to_string(),
+                        element => self.render(element)
+                    })
+                    .collect();
+                if elements.len() > MAX_SHOWN_ELEMENTS {
+                    shown.push(format!("... {} more", elements.len() - MAX_SHOWN_ELEMENTS));
+                }
+                format!("[{}]", shown.join(", "))
+            }
+            Value::Native(native) => native.as_str().to_string(),
+            Value::Opaque(text) => format!("opaque {text}")
+        }
+    }
+
+    /// ToPrimitive: an array becomes its elements joined with commas. `None` for opaque values and
+    /// for arrays that escaped or hold something opaque.
+    fn primitive(&self, value: &Value) -> Option<Value> {
+        match value {
+            Value::Opaque(_) | Value::Native(_) => None,
+            Value::Object(index) => {
+                let object: &Object = &self.objects[*index];
+                if object.escaped {
+                    return None;
+                }
+                let parts: Option<Vec<String>> = object
+                    .elements
+                    .iter()
+                    .map(|element| match element {
+                        Value::Undefined => Some(String::new()),
+                        element => self.string(element)
+                    })
+                    .collect();
+                Some(Value::Str(parts?.join(",")))
+            }
+            value => Some(value.clone())
+        }
+    }
+
+    fn number(&self, value: &Value) -> Option<f64> {
+        Some(match self.primitive(value)? {
+            Value::Undefined => f64::NAN,
+            Value::Bool(value) => value as u8 as f64,
+            Value::Number(value) => value,
+            Value::Str(value) => string_number(&value),
+            Value::Object(_) | Value::Native(_) | Value::Opaque(_) => return None
+        })
+    }
+
+    fn string(&self, value: &Value) -> Option<String> {
+        Some(match self.primitive(value)? {
+            Value::Undefined => "undefined".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => number_string(value),
+            Value::Str(value) => value,
+            Value::Object(_) | Value::Native(_) | Value::Opaque(_) => return None
+        })
+    }
+
+    fn truthy(&self, value: &Value) -> Option<bool> {
+        Some(match value {
+            Value::Undefined => false,
+            Value::Bool(value) => *value,
+            Value::Number(value) => *value != 0.0 && !value.is_nan(),
+            Value::Str(value) => !value.is_empty(),
+            Value::Object(_) | Value::Native(_) => true,
+            Value::Opaque(_) => return None
+        })
+    }
+
+    fn binary(&self, opcode: OpCodes, lhs: u8, rhs: u8) -> Value {
+        let (left, right): (&Value, &Value) = (self.value(lhs), self.value(rhs));
+        let symbolic = || Value::opaque(format!("{} {} {}", self.describe(lhs), opcode.as_str(), self.describe(rhs)));
+        match opcode {
+            OpCodes::StrictEqual | OpCodes::StrictNotEqual => match self.strict_equal(left, right) {
+                Some(equal) => Value::Bool(equal == (opcode == OpCodes::StrictEqual)),
+                None => symbolic()
+            },
+            OpCodes::Equal | OpCodes::NotEqual => match self.loose_equal(left, right) {
+                Some(equal) => Value::Bool(equal == (opcode == OpCodes::Equal)),
+                None => symbolic()
+            },
+            OpCodes::LessThan | OpCodes::LessThanAlt => self.less_than(left, right).map_or_else(symbolic, |less| Value::Bool(less == Some(true))),
+            // a <= b is !(b < a), except that a comparison involving NaN is false either way.
+            OpCodes::Lte | OpCodes::LteAlt => self.less_than(right, left).map_or_else(symbolic, |less| Value::Bool(less == Some(false))),
+            OpCodes::Add => {
+                let (Some(left), Some(right)) = (self.primitive(left), self.primitive(right)) else {
+                    return symbolic();
+                };
+                if matches!(left, Value::Str(_)) || matches!(right, Value::Str(_)) {
+                    match (self.string(&left), self.string(&right)) {
+                        (Some(left), Some(right)) => Value::Str(left + &right),
+                        _ => symbolic()
+                    }
+                } else {
+                    match (self.number(&left), self.number(&right)) {
+                        (Some(left), Some(right)) => Value::Number(left + right),
+                        _ => symbolic()
+                    }
+                }
+            }
+            opcode => match (self.number(left), self.number(right)) {
+                (Some(left), Some(right)) => evaluate(opcode, left, right).map_or_else(symbolic, Value::Number),
+                _ => symbolic()
+            }
+        }
+    }
+
+    fn strict_equal(&self, left: &Value, right: &Value) -> Option<bool> {
+        match (left, right) {
+            (Value::Opaque(_), _) | (_, Value::Opaque(_)) => None,
+            // NaN is not equal to itself, and 0 equals -0.
+            (Value::Number(left), Value::Number(right)) => Some(left == right),
+            (left, right) => Some(left == right)
+        }
+    }
+
+    fn loose_equal(&self, left: &Value, right: &Value) -> Option<bool> {
+        match (left, right) {
+            (Value::Opaque(_), _) | (_, Value::Opaque(_)) => None,
+            (Value::Object(_) | Value::Native(_), Value::Object(_) | Value::Native(_)) | (Value::Undefined, _) | (_, Value::Undefined) => self.strict_equal(left, right),
+            (Value::Str(left), Value::Str(right)) => Some(left == right),
+            (Value::Object(_), _) | (_, Value::Object(_)) => {
+                let (left, right): (Value, Value) = (self.primitive(left)?, self.primitive(right)?);
+                self.loose_equal(&left, &right)
+            }
+            (left, right) => Some(self.number(left)? == self.number(right)?)
+        }
+    }
+
+    /// JavaScript's abstract `left < right`: `Some(None)` when a NaN makes it undefined, `None` when an
+    /// operand is opaque.
+    fn less_than(&self, left: &Value, right: &Value) -> Option<Option<bool>> {
+        let (left, right): (Value, Value) = (self.primitive(left)?, self.primitive(right)?);
+        if let (Value::Str(left), Value::Str(right)) = (&left, &right) {
+            return Some(Some(units(left) < units(right)));
+        }
+        let (left, right): (f64, f64) = (self.number(&left)?, self.number(&right)?);
+        Some(left.partial_cmp(&right).map(|order| order.is_lt()))
+    }
+
+    fn property_key(&self, key: u8) -> Option<String> {
+        self.string(self.value(key))
+    }
+
+    fn get_property(&self, object: u8, key: u8) -> Value {
+        let symbolic = || {
+            let key: String = self.property_key(key).unwrap_or_else(|| self.describe(key));
+            Value::opaque(format!("{}[{key}]", self.describe(object)))
+        };
+        let Some(name) = self.property_key(key) else {
+            return symbolic();
+        };
+        let index: Option<usize> = name.parse::<usize>().ok().filter(|index| index.to_string() == name);
+        match self.value(object) {
+            Value::Str(value) => {
+                let units: Vec<u16> = units(value);
+                match (name.as_str(), index) {
+                    ("length", _) => Value::Number(units.len() as f64),
+                    ("charCodeAt", _) => Value::Native(Native::CharCodeAt),
+                    (_, Some(index)) => units.get(index).map_or(Value::Undefined, |unit| Value::Str(String::from_utf16_lossy(&[*unit]))),
+                    _ => symbolic()
+                }
+            }
+            Value::Object(handle) if !self.objects[*handle].escaped => {
+                let found: &Object = &self.objects[*handle];
+                match (name.as_str(), index) {
+                    ("length", _) => Value::Number(found.elements.len() as f64),
+                    (_, Some(index)) => found.elements.get(index).cloned().unwrap_or(Value::Undefined),
+                    // Anything else may come from Array.prototype.
+                    _ => found.properties.get(&name).cloned().unwrap_or_else(symbolic)
+                }
+            }
+            Value::Opaque(_) if name == "String" && self.globals[object as usize] => Value::Native(Native::String),
+            Value::Native(Native::String) if name == "fromCharCode" => Value::Native(Native::FromCharCode),
+            _ => symbolic()
+        }
+    }
+
+    fn set_property(&mut self, object: u8, key: u8, value: u8) {
+        let stored: Value = self.value(value).clone();
+        let Value::Object(handle) = *self.value(object) else {
+            // Stored into something the emulator does not model, where anything may change it.
+            self.escape([value].into_iter());
+            return;
+        };
+        let name: Option<String> = self.property_key(key);
+        let found: &mut Object = &mut self.objects[handle];
+        if found.escaped {
+            self.escape([value].into_iter());
+            return;
+        }
+        let index: Option<usize> = name.as_ref().and_then(|name| name.parse::<usize>().ok().filter(|index| index.to_string() == *name));
+        match (name, index) {
+            (_, Some(index)) if index <= MAX_ARRAY_INDEX => {
+                if index >= found.elements.len() {
+                    found.elements.resize(index + 1, Value::Undefined);
+                }
+                found.elements[index] = stored;
+            }
+            (Some(name), None) if name != "length" => {
+                found.properties.insert(name, stored);
+            }
+            // A length change or an unknown key: stop trusting the array.
+            _ => {
+                found.escaped = true;
+                self.escape([value].into_iter());
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::{decode, new_value};
+
+    /// NEW VALUE `text` -> `reg`, encoded with the default string key.
+    fn emulate(bytes: Vec<u8>) -> (Emulator, Emulation) {
+        let listing: Listing = decode(bytes);
+        let mut emulator: Emulator = Emulator::new();
+        let emulation: Emulation = emulator.run(&listing, DEFAULT_MAX_STEPS);
+        (emulator, emulation)
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_int32() {
+        // MOV IMM32 0xffffffff -> reg1; LOAD IMM8 0 -> reg2; OR reg1 | reg2 -> reg3; USHR reg3 >>> reg2 -> reg4;
+        // LOAD IMM8 33 -> reg5; LOAD IMM8 1 -> reg6; SHL reg6 << reg5 -> reg7; HALT.
+        let bytes: Vec<u8> = vec![241, 1, 255, 255, 255, 255, 181, 2, 0, 65, 3, 1, 2, 40, 4, 3, 2, 181, 5, 33, 181, 6, 1, 53, 7, 6, 5, 166];
+        let (emulator, emulation): (Emulator, Emulation) = emulate(bytes);
+        assert_eq!(emulator.value(3), &Value::Number(-1.0));
+        assert_eq!(emulator.value(4), &Value::Number(4294967295.0));
+        // Shift counts are taken modulo 32.
+        assert_eq!(emulator.value(7), &Value::Number(2.0));
+        assert_eq!(emulation.stop, Some((27, StopReason::Halt)));
+    }
+
+    #[test]
+    fn nan_propagates_and_is_not_equal_to_itself() {
+        // LOAD IMM8 0 -> reg1; DIV reg1 / reg1 -> reg2; LOAD IMM8 1 -> reg3; ADD reg2 + reg3 -> reg4;
+        // STRICT EQUAL reg2 === reg2 -> reg5; HALT.
+        let bytes: Vec<u8> = vec![181, 1, 0, 55, 2, 1, 1, 181, 3, 1, 243, 4, 2, 3, 161, 5, 2, 2, 166];
+        let (emulator, emulation): (Emulator, Emulation) = emulate(bytes);
+        assert!(matches!(emulator.value(4), Value::Number(value) if value.is_nan()));
+        assert_eq!(emulator.value(5), &Value::Bool(false));
+        assert_eq!(emulation.get(10).unwrap().to_string(), "= NaN");
+    }
+
+    #[test]
+    fn add_concatenates_when_either_side_is_a_string() {
+        // NEW VALUE "ab" -> reg1; LOAD IMM8 1 -> reg2; ADD reg1 + reg2 -> reg3; ADD reg2 + reg2 -> reg4; HALT.
+        let bytes: Vec<u8> = [new_value(1, "ab"), vec![181, 2, 1, 243, 3, 1, 2, 243, 4, 2, 2, 166]].concat();
+        let (emulator, _): (Emulator, Emulation) = emulate(bytes);
+        assert_eq!(emulator.value(3), &Value::Str("ab1".to_string()));
+        assert_eq!(emulator.value(4), &Value::Number(2.0));
+    }
+
+    #[test]
+    fn runs_from_char_code_and_char_code_at() {
+        let bytes: Vec<u8> = [
+            new_value(1, "String"),
+            vec![251, 2, 0, 1], // GET PROPERTY reg0[reg1] -> reg2, from the global object in reg0
+            new_value(3, "fromCharCode"),
+            vec![251, 4, 2, 3, 181, 5, 72], // GET PROPERTY reg2[reg3] -> reg4; LOAD IMM8 72 -> reg5
+            vec![215, 6, 4, 1, 5],          // CALL FUNCTION reg4(reg5) -> reg6
+            new_value(7, "charCodeAt"),
+            vec![251, 8, 6, 7, 181, 9, 0], // GET PROPERTY reg6[reg7] -> reg8; LOAD IMM8 0 -> reg9
+            vec![90, 10, 8, 6, 1, 9, 166]  // CALL APPLY reg8 on reg6 with (reg9) -> reg10; HALT
+        ]
+        .concat();
+        let (emulator, emulation): (Emulator, Emulation) = emulate(bytes);
+        assert_eq!(emulator.value(2), &Value::Native(Native::String));
+        assert_eq!(emulator.value(6), &Value::Str("H".to_string()));
+        assert_eq!(emulator.value(10), &Value::Number(72.0));
+        assert_eq!(emulation.stop.map(|(_, reason)| reason), Some(StopReason::Halt));
+    }
+
+    #[test]
+    fn only_the_global_object_has_the_global_string() {
+        let bytes: Vec<u8> = [
+            vec![215, 1, 0, 0], // CALL FUNCTION reg0() -> reg1
+            new_value(2, "String"),
+            vec![251, 3, 1, 2], // GET PROPERTY reg1[reg2] -> reg3
+            new_value(4, "window"),
+            vec![251, 5, 0, 4, 251, 6, 5, 2, 166] // GET PROPERTY reg0[reg4] -> reg5; GET PROPERTY reg5[reg2] -> reg6; HALT
+        ]
+        .concat();
+        let (emulator, _): (Emulator, Emulation) = emulate(bytes);
+        assert!(emulator.value(3).is_opaque());
+        assert_eq!(emulator.value(6), &Value::Native(Native::String));
+    }
+
+    #[test]
+    fn stops_at_a_branch_on_an_opaque_value() {
+        // LOAD IMM8 1 -> reg1; JUMP IF FALSE reg1 0; JUMP IF FALSE reg20 0; HALT.
+        let bytes: Vec<u8> = vec![181, 1, 1, 39, 1, 0, 0, 0, 0, 39, 20, 0, 0, 0, 0, 166];
+        let (_, emulation): (Emulator, Emulation) = emulate(bytes);
+        assert_eq!(emulation.get(3).map(|produced| &produced.last), Some(&Effect::Branch(false)));
+        assert_eq!(emulation.stop, Some((9, StopReason::OpaqueBranch { reg: 20 })));
+        assert_eq!(emulation.steps, 3);
+    }
+}
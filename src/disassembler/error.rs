@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+#[cfg(feature = "b64")]
+use crate::bytecode::bytearray::BytecodeError;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+
+#[derive(Debug)]
+pub enum DisassemblerError {
+    Io(io::Error),
+    #[cfg(feature = "b64")]
+    Base64(BytecodeError),
+    Truncated { offset: usize, needed: usize, available: usize, instruction: Option<(usize, OpCodes)> },
+    InvalidLength { offset: usize, length: usize, available: usize, instruction: Option<(usize, OpCodes)> },
+    InvalidConfig(String),
+    OutOfBounds { offset: usize, len: usize },
+    UnknownOpcode { offset: usize, byte: u8 }
+}
+
+impl fmt::Display for DisassemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisassemblerError::Io(err) => write!(f, "{err}"),
+            #[cfg(feature = "b64")]
+            DisassemblerError::Base64(err) => write!(f, "{err}"),
+            DisassemblerError::Truncated { offset, needed, available, instruction } => {
+                write!(f, "bytecode is truncated at offset {offset:#06x} ({needed} bytes needed, {available} available)")?;
+                match instruction {
+                    Some((start, opcode)) => write!(f, " (decoding {opcode} at offset {start:#06x})"),
+                    None => Ok(())
+                }
+            }
+            DisassemblerError::InvalidLength { offset, length, available, instruction } => {
+                write!(f, "string length {length} at offset {offset:#06x} exceeds the {available} bytes that follow")?;
+                match instruction {
+                    Some((start, opcode)) => write!(f, " (decoding {opcode} at offset {start:#06x})"),
+                    None => Ok(())
+                }
+            }
+            DisassemblerError::InvalidConfig(message) => write!(f, "{message}"),
+            DisassemblerError::OutOfBounds { offset, len } => write!(f, "offset {offset:#06x} is past the end of the bytecode ({len} bytes)"),
+            DisassemblerError::UnknownOpcode { offset, byte } => write!(f, "unknown opcode {byte} (0x{byte:02x}) at offset {offset:#06x}")
+        }
+    }
+}
+
+impl DisassemblerError {
+    pub(crate) fn within(self, start: usize, opcode: OpCodes) -> Self {
+        match self {
+            DisassemblerError::Truncated { offset, needed, available, instruction: None } => {
+                DisassemblerError::Truncated { offset, needed, available, instruction: Some((start, opcode)) }
+            }
+            DisassemblerError::InvalidLength { offset, length, available, instruction: None } => {
+                DisassemblerError::InvalidLength { offset, length, available, instruction: Some((start, opcode)) }
+            }
+            err => err
+        }
+    }
+}
+
+impl Error for DisassemblerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DisassemblerError::Io(err) => Some(err),
+            #[cfg(feature = "b64")]
+            DisassemblerError::Base64(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecuteError {
+    /// What stopped the decode.
+    pub error: DisassemblerError,
+    /// Offset the disassembler had reached when it stopped.
+    pub offset: usize,
+    /// Instructions decoded by this call before the failure.
+    pub listing: Box<Listing>
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (stopped at offset {:#06x} after {} instructions)", self.error, self.offset, self.listing.instruction_count)
+    }
+}
+
+impl Error for ExecuteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ExecuteError> for DisassemblerError {
+    fn from(err: ExecuteError) -> Self {
+        err.error
+    }
+}
+
+impl From<io::Error> for DisassemblerError {
+    fn from(err: io::Error) -> Self {
+        DisassemblerError::Io(err)
+    }
+}
+
+#[cfg(feature = "b64")]
+impl From<BytecodeError> for DisassemblerError {
+    fn from(err: BytecodeError) -> Self {
+        DisassemblerError::Base64(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::Error as CrateError;
+    use crate::disassembler::testing::decode_err;
+
+    fn execute(bytes: Vec<u8>) -> CrateError {
+        decode_err(bytes).into()
+    }
+
+    #[cfg(feature = "b64")]
+    #[test]
+    fn malformed_base64_is_a_base64_error() {
+        match crate::disassemble("tQEH*pg==") {
+            Err(DisassemblerError::Base64(err)) => assert_eq!(err.position, Some(4)),
+            other => panic!("expected Base64, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn truncated_operand_names_the_instruction() {
+        // MOV IMM32 reg1 with only two of its four immediate bytes.
+        match execute(vec![181, 1, 7, 241, 1, 0, 0]) {
+            DisassemblerError::Truncated { offset: 5, needed: 4, available: 2, instruction: Some((3, OpCodes::MovImm32)) } => {}
+            other => panic!("expected Truncated, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn truncated_register_list_reports_what_was_available() {
+        // CALL FUNCTION reg1, reg2 with three arguments announced and one present.
+        let err: DisassemblerError = execute(vec![215, 1, 2, 3, 9]);
+        assert!(matches!(err, DisassemblerError::Truncated { offset: 5, needed: 1, available: 0, instruction: Some((0, OpCodes::CallFunction)) }));
+        assert_eq!(err.to_string(), "bytecode is truncated at offset 0x0005 (1 bytes needed, 0 available) (decoding CALL FUNCTION at offset 0x0000)");
+    }
+
+    #[test]
+    fn string_past_the_end_is_an_invalid_length() {
+        match execute(vec![23, 1, 0, 9, 1, 2]) {
+            DisassemblerError::InvalidLength { offset: 2, length: 9, available: 2, instruction: Some((0, OpCodes::NewValue)) } => {}
+            other => panic!("expected InvalidLength, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn unknown_opcode_reports_its_offset_and_byte() {
+        match execute(vec![181, 1, 7, 0, 166]) {
+            DisassemblerError::UnknownOpcode { offset: 3, byte: 0 } => {}
+            other => panic!("expected UnknownOpcode, got {other:?}")
+        }
+    }
+}
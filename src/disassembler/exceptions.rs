@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+
+/// The protected body, handlers and continuation of one TRY CATCH.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TryRegion {
+    /// Offset of the TRY CATCH instruction.
+    pub offset: usize,
+    /// Register the catch handler receives the exception in.
+    pub reg: u8,
+    /// From just past the TRY CATCH up to the catch handler.
+    pub try_range: Range<usize>,
+    pub catch_at: usize,
+    pub finally_at: usize,
+    pub continue_at: usize,
+    /// Index in `TryRegions::regions` of the innermost region around this one.
+    pub parent: Option<usize>,
+    /// 0 for a region that is not inside another.
+    pub depth: usize
+}
+
+impl TryRegion {
+    /// From the TRY CATCH to the continuation point.
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.continue_at
+    }
+}
+
+/// A TRY CATCH whose offsets cannot be structured as written.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TryWarning {
+    /// The catch, finally and continue offsets do not follow the body in that order; the region is left out.
+    Misordered { offset: usize },
+    /// `target` is not the start of a decoded instruction, so its marker cannot be shown.
+    NotInstruction { offset: usize, target: usize },
+    /// The region starts inside the region of the TRY CATCH at `outer` but ends past it.
+    Overlap { offset: usize, outer: usize }
+}
+
+impl TryWarning {
+    pub fn offset(&self) -> usize {
+        match self {
+            TryWarning::Misordered { offset } | TryWarning::NotInstruction { offset, .. } | TryWarning::Overlap { offset, .. } => *offset
+        }
+    }
+}
+
+impl fmt::Display for TryWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryWarning::Misordered { offset } => {
+                write!(f, "TRY CATCH at offset {offset:#06x} does not have its catch, finally and continue offsets in order after its body")
+            }
+            TryWarning::NotInstruction { offset, target } => {
+                write!(f, "TRY CATCH at offset {offset:#06x} names offset {target:#06x}, which does not start an instruction")
+            }
+            TryWarning::Overlap { offset, outer } => {
+                write!(f, "TRY CATCH at offset {offset:#06x} starts inside the try region at offset {outer:#06x} but ends after it")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TryRegions {
+    /// Sorted by offset.
+    pub regions: Vec<TryRegion>,
+    pub warnings: Vec<TryWarning>
+}
+
+impl TryRegions {
+    /// Reads every TRY CATCH of `listing`. A region nests inside another when its whole span, from the
+    /// TRY CATCH to the continuation point, lies in the other's.
+    pub fn new(listing: &Listing) -> Self {
+        let starts: BTreeSet<usize> = listing.instructions.iter().map(|instruction| instruction.offset).collect();
+        let mut instructions: Vec<_> = listing.instructions.iter().collect();
+        instructions.sort_by_key(|instruction| instruction.offset);
+        instructions.dedup_by_key(|instruction| instruction.offset);
+
+        let mut found: TryRegions = TryRegions::default();
+        let mut open: Vec<usize> = Vec::new();
+        for instruction in instructions {
+            let (OpCodes::TryCatch, [Operand::Reg(reg), Operand::Target(catch), Operand::Target(finally), Operand::Target(next)]) =
+                (instruction.opcode, instruction.operands.as_slice())
+            else {
+                continue;
+            };
+            let offset: usize = instruction.offset;
+            let (catch_at, finally_at, continue_at): (usize, usize, usize) = (*catch as usize, *finally as usize, *next as usize);
+            if !(instruction.end() <= catch_at && catch_at <= finally_at && finally_at <= continue_at) {
+                found.warnings.push(TryWarning::Misordered { offset });
+                continue;
+            }
+            for target in [catch_at, finally_at, continue_at] {
+                if !starts.contains(&target) && !found.warnings.contains(&TryWarning::NotInstruction { offset, target }) {
+                    found.warnings.push(TryWarning::NotInstruction { offset, target });
+                }
+            }
+
+            while open.last().is_some_and(|&outer| found.regions[outer].continue_at <= offset) {
+                open.pop();
+            }
+            let parent: Option<usize> = open.last().copied();
+            if let Some(outer) = parent
+                && continue_at > found.regions[outer].continue_at
+            {
+                found.warnings.push(TryWarning::Overlap { offset, outer: found.regions[outer].offset });
+            }
+            open.push(found.regions.len());
+            found.regions.push(TryRegion {
+                offset,
+                reg: *reg,
+                try_range: instruction.end()..catch_at,
+                catch_at,
+                finally_at,
+                continue_at,
+                parent,
+                depth: parent.map_or(0, |parent| found.regions[parent].depth + 1)
+            });
+        }
+        found
+    }
+
+    /// The region opened by the TRY CATCH at `offset`.
+    pub fn at(&self, offset: usize) -> Option<&TryRegion> {
+        self.regions.binary_search_by_key(&offset, |region| region.offset).ok().map(|index| &self.regions[index])
+    }
+
+    /// The innermost region whose try body holds `offset`.
+    pub fn protecting(&self, offset: usize) -> Option<&TryRegion> {
+        self.regions.iter().rev().find(|region| region.try_range.contains(&offset))
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    /// TRY CATCH `reg` with the catch, finally and continue offsets, 14 bytes.
+    fn try_catch(reg: u8, catch: u8, finally: u8, next: u8) -> Vec<u8> {
+        vec![115, reg, 0, 0, 0, catch, 0, 0, 0, finally, 0, 0, 0, next]
+    }
+
+    fn regions(bytes: Vec<u8>) -> TryRegions {
+        let listing: Listing = decode(bytes);
+        TryRegions::new(&listing)
+    }
+
+    #[test]
+    fn nested_regions_get_their_parent() {
+        // The outer region's body is the inner TRY CATCH at 14 and its LOAD IMM8 at 28; LOAD IMM8s at 31, 34
+        // and 37 are the inner handler, the outer catch and the outer finally; HALT at 40.
+        let loads: Vec<u8> = [3, 4, 5, 6].into_iter().flat_map(|reg| [181, reg, 1]).collect();
+        let found: TryRegions = regions([try_catch(1, 34, 37, 40), try_catch(2, 31, 31, 34), loads, vec![166]].concat());
+        assert!(found.warnings.is_empty());
+        let spans: Vec<(usize, Range<usize>, Option<usize>, usize)> =
+            found.regions.iter().map(|region| (region.offset, region.try_range.clone(), region.parent, region.depth)).collect();
+        assert_eq!(spans, [(0, 14..34, None, 0), (14, 28..31, Some(0), 1)]);
+        assert_eq!(found.protecting(29).map(|region| region.offset), Some(14));
+        assert_eq!(found.protecting(32).map(|region| region.offset), Some(0));
+        assert_eq!(found.at(14).map(|region| region.reg), Some(2));
+    }
+
+    #[test]
+    fn bad_offsets_are_reported() {
+        let found: TryRegions = regions([try_catch(1, 0, 0, 0), vec![166]].concat());
+        assert!(found.is_empty());
+        assert_eq!(found.warnings, [TryWarning::Misordered { offset: 0 }]);
+
+        let found: TryRegions = regions([try_catch(1, 14, 14, 15), vec![166]].concat());
+        assert_eq!(found.warnings, [TryWarning::NotInstruction { offset: 0, target: 15 }]);
+        assert_eq!(found.warnings[0].to_string(), "TRY CATCH at offset 0x0000 names offset 0x000f, which does not start an instruction");
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::cfg::{Block, Cfg};
+use crate::disassembler::codec::{format_double, quote_bytes, quote_string};
+use crate::disassembler::instruction::{binary_symbol, Instruction};
+use crate::disassembler::listing::Listing;
+use crate::disassembler::liveness::{Liveness, RegSet};
+use crate::disassembler::names::RegisterNames;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Access, Operand};
+use crate::disassembler::registers::{destination, RegValue};
+
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// An instruction with the single-use temporaries it reads collapsed into it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expression {
+    /// Offset of the instruction the expression is rooted at.
+    pub offset: usize,
+    /// e.g. `reg12 = reg2["navigator"]["userAgent"](reg5)`.
+    pub text: String,
+    /// Offsets of the instructions substituted into it, in listing order.
+    pub inlined: Vec<usize>
+}
+
+/// Expression trees rebuilt from chains of temporaries. A register written in a basic block, read exactly
+/// once there and dead afterwards (overwritten, or not live out of the block per `Liveness`) is replaced by the expression
+/// that defined it. Reads and writes come from `Instruction::accesses`, the table behind `--xref`.
+/// Only literals move across SET PROPERTY, calls, JUMP FRAME, TRY CATCH and THROW, a call does not move
+/// across a GET PROPERTY, and nothing moves across a write to a register it reads.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expressions {
+    /// Sorted by offset; only instructions that collapsed at least one temporary.
+    pub expressions: Vec<Expression>
+}
+
+struct Expr {
+    text: String,
+    depth: usize,
+    binary: bool,
+    /// Literals are safe to move across side effects.
+    literal: bool,
+    /// Whether a call is nested in it; those must not move across a property read either.
+    calls: bool,
+    reads: BTreeSet<u8>,
+    inlined: Vec<usize>
+}
+
+impl Expr {
+    fn atom(text: String, literal: bool) -> Self {
+        Self { text, depth: 0, binary: false, literal, calls: false, reads: BTreeSet::new(), inlined: Vec::new() }
+    }
+
+    /// The text to use where it is an operand of something else.
+    fn operand(&self) -> String {
+        if self.binary { format!("({})", self.text) } else { self.text.clone() }
+    }
+}
+
+struct Pending {
+    offset: usize,
+    expr: Expr
+}
+
+fn has_side_effects(opcode: OpCodes) -> bool {
+    matches!(opcode, OpCodes::SetProperty | OpCodes::CallFunction | OpCodes::CallApply | OpCodes::JumpFrame | OpCodes::TryCatch | OpCodes::Throw)
+}
+
+/// Whether the instruction has an expression or statement form that temporaries can be collapsed into.
+fn collapses(opcode: OpCodes) -> bool {
+    binary_symbol(opcode).is_some()
+        || matches!(
+            opcode,
+            OpCodes::GetProperty
+                | OpCodes::SetProperty
+                | OpCodes::CallFunction
+                | OpCodes::CallApply
+                | OpCodes::PushArgs
+                | OpCodes::JumpIfFalse
+                | OpCodes::JumpIfTrue
+                | OpCodes::Throw
+        )
+}
+
+fn literal(value: &RegValue) -> String {
+    match value {
+        RegValue::Str(value) => quote_string(value),
+        value => value.to_string()
+    }
+}
+
+fn reads(instruction: &Instruction, reg: u8) -> usize {
+    instruction.accesses().filter(|&(read, access)| read == reg && access.reads()).count()
+}
+
+fn writes(instruction: &Instruction, reg: u8) -> bool {
+    instruction.accesses().any(|(written, access)| written == reg && access == Access::Def)
+}
+
+/// Index of the instruction in `block` that consumes the value `block.instructions[index]` defines, if the
+/// value is read exactly once and is dead afterwards.
+fn consumer(block: &Block, index: usize, reg: u8, live_out: RegSet) -> Option<usize> {
+    let rest: &[Instruction] = &block.instructions[index + 1..];
+    let use_index: usize = rest.iter().position(|instruction| instruction.accesses().any(|(accessed, _)| accessed == reg))?;
+    let user: &Instruction = &rest[use_index];
+    if reads(user, reg) != 1 || !collapses(user.opcode) {
+        return None;
+    }
+    let dead: bool = writes(user, reg)
+        || match rest[use_index + 1..].iter().find(|instruction| instruction.accesses().any(|(accessed, _)| accessed == reg)) {
+            Some(next) => reads(next, reg) == 0,
+            None => !live_out.contains(reg)
+        };
+    dead.then_some(index + 1 + use_index)
+}
+
+struct Collapser<'a> {
+    max_depth: usize,
+    /// Def offset to the offset of the instruction planned to consume it.
+    plan: &'a BTreeMap<usize, usize>,
+    names: &'a RegisterNames,
+    pending: BTreeMap<u8, Pending>,
+    expressions: Vec<Expression>
+}
+
+impl Collapser<'_> {
+    fn flush(&mut self, reg: u8) {
+        if let Some(pending) = self.pending.remove(&reg)
+            && !pending.expr.inlined.is_empty()
+        {
+            let mut inlined: Vec<usize> = pending.expr.inlined;
+            inlined.sort_unstable();
+            self.expressions.push(Expression { offset: pending.offset, text: format!("{} = {}", self.names.display(pending.offset, reg), pending.expr.text), inlined });
+        }
+    }
+
+    fn flush_where(&mut self, condition: impl Fn(&Pending) -> bool) {
+        let regs: Vec<u8> = self.pending.iter().filter(|(_, pending)| condition(pending)).map(|(&reg, _)| reg).collect();
+        for reg in regs {
+            self.flush(reg);
+        }
+    }
+
+    /// The expression for register operand `reg` at operand `index` of `instruction`, consuming a
+    /// pending temporary planned for it.
+    fn take(&mut self, instruction: &Instruction, index: usize, reg: u8, children: &mut Vec<Expr>) -> usize {
+        if let Some(pending) = self.pending.get(&reg)
+            && self.plan.get(&pending.offset) == Some(&instruction.offset)
+        {
+            if pending.expr.depth < self.max_depth {
+                let pending: Pending = self.pending.remove(&reg).expect("checked above");
+                let mut expr: Expr = pending.expr;
+                expr.inlined.push(pending.offset);
+                children.push(expr);
+                return children.len() - 1;
+            }
+            self.flush(reg);
+        }
+        let mut expr: Expr = match instruction.resolved.get(index) {
+            Some(Some(value)) => Expr::atom(literal(value), false),
+            _ => Expr::atom(self.names.display(instruction.offset, reg), false)
+        };
+        expr.reads.insert(reg);
+        children.push(expr);
+        children.len() - 1
+    }
+
+    fn list(&mut self, instruction: &Instruction, index: usize, regs: &[u8], children: &mut Vec<Expr>) -> String {
+        let items: Vec<usize> = regs.iter().map(|&reg| self.take(instruction, index, reg, children)).collect();
+        items.iter().map(|&item| children[item].operand()).collect::<Vec<String>>().join(", ")
+    }
+
+    /// The expression an instruction computes, or its statement form, with planned temporaries collapsed.
+    fn expression(&mut self, instruction: &Instruction) -> Option<Expr> {
+        let mut children: Vec<Expr> = Vec::new();
+        let (text, binary): (String, bool) = match (instruction.opcode, instruction.operands.as_slice()) {
+            (OpCodes::NewValue, [_, Operand::Str(_, bytes)]) => return Some(Expr::atom(quote_bytes(bytes), true)),
+            (OpCodes::MovImm32, [_, Operand::Int(value)]) => return Some(Expr::atom(value.to_string(), true)),
+            (OpCodes::InitMemory | OpCodes::LoadImm8, [_, Operand::Byte(value)]) => return Some(Expr::atom(value.to_string(), true)),
+            (OpCodes::LoadDouble, [_, Operand::Double(value)]) => return Some(Expr::atom(format_double(*value), true)),
+            (OpCodes::NewFunction, [_, Operand::Target(entry), _]) => return Some(Expr::atom(format!("function_{entry:04x}"), true)),
+            (opcode, [_, Operand::Reg(left), Operand::Reg(right)]) if binary_symbol(opcode).is_some() => {
+                let (left, right): (usize, usize) = (self.take(instruction, 1, *left, &mut children), self.take(instruction, 2, *right, &mut children));
+                let symbol: &str = binary_symbol(opcode).expect("guarded above");
+                (format!("{} {symbol} {}", children[left].operand(), children[right].operand()), true)
+            }
+            (OpCodes::GetProperty, [_, Operand::Reg(obj), Operand::Reg(key)]) => {
+                let (obj, key): (usize, usize) = (self.take(instruction, 1, *obj, &mut children), self.take(instruction, 2, *key, &mut children));
+                (format!("{}[{}]", children[obj].operand(), children[key].text), false)
+            }
+            (OpCodes::SetProperty, [Operand::Reg(obj), Operand::Reg(key), Operand::Reg(value)]) => {
+                let obj: usize = self.take(instruction, 0, *obj, &mut children);
+                let (key, value): (usize, usize) = (self.take(instruction, 1, *key, &mut children), self.take(instruction, 2, *value, &mut children));
+                (format!("{}[{}] = {}", children[obj].operand(), children[key].text, children[value].text), false)
+            }
+            (OpCodes::CallFunction, [_, Operand::Reg(callee), Operand::RegList(args)]) => {
+                let callee: usize = self.take(instruction, 1, *callee, &mut children);
+                let args: String = self.list(instruction, 2, args, &mut children);
+                (format!("{}({args})", children[callee].operand()), false)
+            }
+            (OpCodes::CallApply, [_, Operand::Reg(callee), Operand::Reg(this), Operand::RegList(args)]) => {
+                let (callee, this): (usize, usize) = (self.take(instruction, 1, *callee, &mut children), self.take(instruction, 2, *this, &mut children));
+                let args: String = self.list(instruction, 3, args, &mut children);
+                (format!("{}.apply({}, [{args}])", children[callee].operand(), children[this].text), false)
+            }
+            (OpCodes::PushArgs, [_, Operand::RegList(args)]) => (format!("[{}]", self.list(instruction, 1, args, &mut children)), false),
+            (opcode @ (OpCodes::JumpIfFalse | OpCodes::JumpIfTrue | OpCodes::Throw), [Operand::Reg(reg), ..]) => {
+                let value: usize = self.take(instruction, 0, *reg, &mut children);
+                let text: String = match opcode {
+                    OpCodes::JumpIfFalse => format!("if (!{})", children[value].operand()),
+                    OpCodes::JumpIfTrue => format!("if ({})", children[value].text),
+                    _ => format!("throw {}", children[value].text)
+                };
+                (text, false)
+            }
+            _ => return None
+        };
+
+        let calls: bool = matches!(instruction.opcode, OpCodes::CallFunction | OpCodes::CallApply);
+        let mut expr: Expr = Expr { text, depth: 0, binary, literal: false, calls, reads: BTreeSet::new(), inlined: Vec::new() };
+        for child in children {
+            expr.calls |= child.calls;
+            if !child.inlined.is_empty() {
+                expr.depth = expr.depth.max(child.depth + 1);
+            }
+            expr.reads.extend(child.reads);
+            expr.inlined.extend(child.inlined);
+        }
+        Some(expr)
+    }
+
+    fn block(&mut self, block: &Block) {
+        for instruction in &block.instructions {
+            let expr: Option<Expr> = if collapses(instruction.opcode) || self.plan.contains_key(&instruction.offset) {
+                self.expression(instruction)
+            } else {
+                None
+            };
+
+            if has_side_effects(instruction.opcode) {
+                self.flush_where(|pending| !pending.expr.literal);
+            } else if instruction.opcode == OpCodes::GetProperty {
+                self.flush_where(|pending| pending.expr.calls);
+            }
+            for (reg, access) in instruction.accesses() {
+                if access.writes() {
+                    self.flush_where(|pending| pending.expr.reads.contains(&reg));
+                    self.flush(reg);
+                }
+            }
+
+            let Some(expr) = expr else {
+                continue;
+            };
+            match destination(instruction) {
+                Some(reg) if self.plan.contains_key(&instruction.offset) => {
+                    self.pending.insert(reg, Pending { offset: instruction.offset, expr });
+                }
+                _ if expr.inlined.is_empty() => {}
+                Some(reg) => self.pending_root(instruction.offset, format!("{} = {}", self.names.display(instruction.offset, reg), expr.text), expr.inlined),
+                None => self.pending_root(instruction.offset, expr.text, expr.inlined)
+            }
+        }
+        let regs: Vec<u8> = self.pending.keys().copied().collect();
+        for reg in regs {
+            self.flush(reg);
+        }
+    }
+
+    fn pending_root(&mut self, offset: usize, text: String, mut inlined: Vec<usize>) {
+        inlined.sort_unstable();
+        self.expressions.push(Expression { offset, text, inlined });
+    }
+}
+
+impl Expressions {
+    /// Collapses temporaries at most `max_depth` levels deep; deeper chains are split into several expressions.
+    pub fn new(listing: &Listing, max_depth: usize) -> Self {
+        Self::named(listing, max_depth, &RegisterNames::default())
+    }
+
+    /// Like `new`, with registers written by their name in `names`.
+    pub fn named(listing: &Listing, max_depth: usize, names: &RegisterNames) -> Self {
+        let cfg: Cfg = Cfg::new(listing);
+        let liveness: Liveness = Liveness::new(&cfg);
+
+        let mut plan: BTreeMap<usize, usize> = BTreeMap::new();
+        for block in cfg.blocks() {
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                if let Some(reg) = destination(instruction)
+                    && instruction.opcode != OpCodes::JumpFrame
+                    && let Some(user) = consumer(block, index, reg, liveness.live_out(block.id))
+                {
+                    plan.insert(instruction.offset, block.instructions[user].offset);
+                }
+            }
+        }
+
+        let mut collapser: Collapser = Collapser { max_depth, plan: &plan, names, pending: BTreeMap::new(), expressions: Vec::new() };
+        for block in cfg.blocks() {
+            collapser.block(block);
+        }
+        let mut expressions: Vec<Expression> = collapser.expressions;
+        expressions.sort_by_key(|expression| expression.offset);
+        Self { expressions }
+    }
+
+    pub fn get(&self, offset: usize) -> Option<&Expression> {
+        self.expressions.binary_search_by_key(&offset, |expression| expression.offset).ok().map(|index| &self.expressions[index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.expressions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    fn collapse(bytes: Vec<u8>) -> Vec<String> {
+        let listing: Listing = decode(bytes);
+        Expressions::new(&listing, DEFAULT_MAX_DEPTH).expressions.into_iter().map(|expression| expression.text).collect()
+    }
+
+    #[test]
+    fn collapsed_string_keys_are_double_quoted() {
+        // NEW VALUE 'Date' -> reg2; GET PROPERTY reg10[reg2] -> reg3; HALT.
+        let texts: Vec<String> = collapse(vec![23, 2, 0, 4, 0x76, 0x53, 0x46, 0x57, 251, 3, 10, 2, 166]);
+        assert_eq!(texts, [r#"reg3 = reg10["Date"]"#]);
+    }
+
+    #[test]
+    fn tracked_string_keys_are_double_quoted() {
+        // NEW VALUE 'a"b' -> reg2, read twice so it stays a register with a tracked value:
+        // GET PROPERTY reg10[reg2] -> reg3; GET PROPERTY reg3[reg2] -> reg4; HALT.
+        let texts: Vec<String> = collapse(vec![23, 2, 0, 3, 0x53, 0x10, 0x50, 251, 3, 10, 2, 251, 4, 3, 2, 166]);
+        assert_eq!(texts, [r#"reg4 = reg10["a\"b"]["a\"b"]"#]);
+    }
+}
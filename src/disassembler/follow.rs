@@ -0,0 +1,152 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::disassembler::Disassembler;
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+
+pub struct Traversal {
+    pub instructions: BTreeMap<usize, Instruction>,
+    pub errors: Vec<DisassemblerError>,
+    pub skipped_targets: Vec<(usize, u32)>,
+    /// Set when `Disassembler::max_visited` offsets were visited before the traversal ran out of work.
+    pub exhausted: bool
+}
+
+pub(crate) fn falls_through(opcode: OpCodes) -> bool {
+    !matches!(opcode, OpCodes::Jump | OpCodes::Ret | OpCodes::Halt | OpCodes::Throw)
+}
+
+impl Traversal {
+    pub fn gaps(&self, start: usize, end: usize) -> Vec<(usize, usize)> {
+        let mut gaps: Vec<(usize, usize)> = Vec::new();
+        let mut covered: usize = start;
+        for instruction in self.instructions.values() {
+            if instruction.offset > covered {
+                gaps.push((covered, instruction.offset));
+            }
+            covered = covered.max(instruction.end());
+        }
+        if end > covered {
+            gaps.push((covered, end));
+        }
+        gaps
+    }
+
+    pub fn listing(&self, disasm: &Disassembler) -> Listing {
+        Listing::new(disasm.bytes().len(), disasm.string_key, self.instructions.values().cloned().collect())
+    }
+}
+
+pub fn follow_jumps(disasm: &mut Disassembler, entry: usize) -> Traversal {
+    let end: usize = disasm.end();
+    let mut traversal: Traversal = Traversal {
+        instructions: BTreeMap::new(),
+        errors: Vec::new(),
+        skipped_targets: Vec::new(),
+        exhausted: false
+    };
+    let mut visited: BTreeSet<usize> = BTreeSet::new();
+    let mut queue: Vec<usize> = vec![entry];
+
+    while let Some(offset) = queue.pop() {
+        if visited.contains(&offset) {
+            continue;
+        }
+        if visited.len() >= disasm.max_visited {
+            traversal.exhausted = true;
+            return traversal;
+        }
+        visited.insert(offset);
+        if disasm.seek(offset).is_err() {
+            continue;
+        }
+
+        loop {
+            if disasm.max_instructions.is_some_and(|max| traversal.instructions.len() >= max) {
+                return traversal;
+            }
+
+            let instruction: Instruction = match disasm.step() {
+                Ok(Some(_)) => disasm.instructions[disasm.instructions.len() - 1].clone(),
+                Ok(None) => break,
+                Err(err) => {
+                    traversal.errors.push(err);
+                    break;
+                }
+            };
+
+            for target in instruction.targets() {
+                if (target as usize) < end {
+                    queue.push(target as usize);
+                } else {
+                    traversal.skipped_targets.push((instruction.offset, target));
+                }
+            }
+
+            let next: usize = instruction.end();
+            let fall_through: bool = falls_through(instruction.opcode);
+            traversal.instructions.insert(instruction.offset, instruction);
+            if !fall_through || visited.contains(&next) {
+                break;
+            }
+            if visited.len() >= disasm.max_visited {
+                traversal.exhausted = true;
+                return traversal;
+            }
+            visited.insert(next);
+        }
+    }
+    traversal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// JUMP 8 over three junk bytes, then LOAD IMM8 reg1, 7 and HALT.
+    const PROGRAM: [u8; 12] = [93, 0, 0, 0, 8, 0xee, 0xee, 0xee, 181, 1, 7, 166];
+
+    fn offsets(traversal: &Traversal) -> Vec<usize> {
+        traversal.instructions.keys().copied().collect()
+    }
+
+    #[test]
+    fn follows_jumps_around_unreached_bytes() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        let traversal: Traversal = follow_jumps(&mut disasm, 0);
+        assert_eq!(offsets(&traversal), [0, 8, 11]);
+        assert_eq!(traversal.gaps(0, PROGRAM.len()), [(5, 8)]);
+        assert!(traversal.errors.is_empty());
+        assert!(!traversal.exhausted);
+    }
+
+    #[test]
+    fn visited_budget_stops_the_traversal() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(PROGRAM.to_vec());
+        disasm.max_visited = 2;
+        let traversal: Traversal = follow_jumps(&mut disasm, 0);
+        assert_eq!(offsets(&traversal), [0, 8]);
+        assert!(traversal.exhausted);
+    }
+
+    #[test]
+    fn back_edges_are_followed_once() {
+        // LOAD IMM8 reg1, 1; JUMP 0.
+        let mut disasm: Disassembler = Disassembler::from_bytes(vec![181, 1, 1, 93, 0, 0, 0, 0]);
+        let traversal: Traversal = follow_jumps(&mut disasm, 0);
+        assert_eq!(offsets(&traversal), [0, 3]);
+        assert!(!traversal.exhausted);
+        assert!(traversal.errors.is_empty());
+        let listed: Vec<usize> = traversal.listing(&disasm).instructions.iter().map(|instruction| instruction.offset).collect();
+        assert_eq!(listed, [0, 3]);
+    }
+
+    #[test]
+    fn targets_past_the_end_are_skipped() {
+        let mut disasm: Disassembler = Disassembler::from_bytes(vec![83, 1, 0, 0, 0, 99, 166]);
+        let traversal: Traversal = follow_jumps(&mut disasm, 0);
+        assert_eq!(offsets(&traversal), [0, 6]);
+        assert_eq!(traversal.skipped_targets, [(0, 99)]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+
+/// A function body found from a NEW FUNCTION or JUMP FRAME entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Function {
+    pub entry: usize,
+    /// Offset just past the RET or HALT that ends it, or past the last instruction before the next entry.
+    /// A RET that a branch inside the function jumps past is an early return and does not end it.
+    pub end: usize,
+    /// Largest argument (NEW FUNCTION) or parameter (JUMP FRAME) list naming this entry.
+    pub args: usize
+}
+
+impl Function {
+    pub fn size(&self) -> usize {
+        self.end - self.entry
+    }
+
+    pub fn name(&self) -> String {
+        format!("function_{:04x}", self.entry)
+    }
+}
+
+/// A function whose last instruction runs past the next entry, which starts inside it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Overlap {
+    pub entry: usize,
+    pub end: usize,
+    pub next: usize
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "function at {:#06x} runs to {:#06x}, past the function entry at {:#06x}", self.entry, self.end, self.next)
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Functions {
+    /// Sorted by entry.
+    pub functions: Vec<Function>,
+    pub overlaps: Vec<Overlap>
+}
+
+fn entry_args(instruction: &Instruction) -> Option<(usize, usize)> {
+    match (instruction.opcode, instruction.operands.as_slice()) {
+        (OpCodes::NewFunction, [_, Operand::Target(entry), Operand::RegList(args)])
+        | (OpCodes::JumpFrame, [Operand::Target(entry), _, Operand::RegList(args)]) => Some((*entry as usize, args.len())),
+        _ => None
+    }
+}
+
+impl Functions {
+    /// Splits `listing` at every entry that starts one of its instructions. Each function runs from its
+    /// entry to the first RET or HALT that no earlier branch in it jumps past, up to the next entry, or
+    /// to a gap in the listing. Entries inside
+    /// an instruction or past the end are left out; `Listing::target_warnings` already reports them.
+    pub fn new(listing: &Listing) -> Self {
+        let starts: BTreeMap<usize, &Instruction> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction)).collect();
+        let mut entries: BTreeMap<usize, usize> = BTreeMap::new();
+        for (entry, args) in listing.instructions.iter().filter_map(entry_args) {
+            if starts.contains_key(&entry) {
+                let known: &mut usize = entries.entry(entry).or_default();
+                *known = (*known).max(args);
+            }
+        }
+
+        let mut found: Functions = Functions::default();
+        for (&entry, &args) in &entries {
+            let next: Option<usize> = entries.range(entry + 1..).next().map(|(&next, _)| next);
+            let (mut end, mut reach): (usize, usize) = (entry, entry);
+            while let Some(instruction) = starts.get(&end)
+                && next.is_none_or(|next| end < next)
+            {
+                end = instruction.end();
+                if matches!(instruction.opcode, OpCodes::Jump | OpCodes::JumpIfFalse | OpCodes::JumpIfTrue | OpCodes::TryCatch) {
+                    let inside = instruction.targets().map(|target| target as usize).filter(|&target| next.is_none_or(|next| target < next));
+                    reach = inside.fold(reach, usize::max);
+                }
+                if matches!(instruction.opcode, OpCodes::Ret | OpCodes::Halt) && reach < end {
+                    break;
+                }
+            }
+            if let Some(next) = next
+                && end > next
+            {
+                found.overlaps.push(Overlap { entry, end, next });
+            }
+            found.functions.push(Function { entry, end, args });
+        }
+        found
+    }
+
+    /// The function whose extent covers `offset`.
+    pub fn containing(&self, offset: usize) -> Option<&Function> {
+        let index: usize = self.functions.partition_point(|function| function.entry <= offset);
+        index.checked_sub(1).map(|index| &self.functions[index]).filter(|function| offset < function.end)
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    /// NEW FUNCTION reg1 for 13 with (reg2, reg3); LOAD IMM8 0 -> reg1; HALT; then the function at 13:
+    /// JUMP IF FALSE reg2 25; RET reg2 []; LOAD IMM8 1 -> reg4; RET reg4 [].
+    const PROGRAM: [u8; 28] = [171, 1, 0, 0, 0, 13, 2, 2, 3, 181, 1, 0, 166, 39, 2, 0, 0, 0, 25, 17, 2, 0, 181, 4, 1, 17, 4, 0];
+
+    #[test]
+    fn an_early_return_does_not_end_the_function() {
+        let listing: Listing = decode(PROGRAM.to_vec());
+        let found: Functions = Functions::new(&listing);
+        assert_eq!(found.functions, [Function { entry: 13, end: 28, args: 2 }]);
+        assert_eq!(found.functions[0].name(), "function_000d");
+        assert!(found.overlaps.is_empty());
+        assert_eq!(found.containing(19).map(|function| function.entry), Some(13));
+        assert_eq!(found.containing(12), None);
+    }
+
+    #[test]
+    fn overlaps_name_both_entries() {
+        let overlap: Overlap = Overlap { entry: 13, end: 30, next: 28 };
+        assert_eq!(overlap.to_string(), "function at 0x000d runs to 0x001e, past the function entry at 0x001c");
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::HashSet;
+use crate::disassembler::codec::ByteReader;
+use crate::disassembler::disassembler::Resync;
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::instructions::OpcodeTable;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::OperandKind;
+
+/// Share of a string's characters that must be printable for it to decode cleanly, once it has more
+/// than `MAX_UNPRINTABLE` that are not; the sample loads a few short strings like '\x00\x00'.
+const MIN_PRINTABLE: f64 = 0.9;
+const MAX_UNPRINTABLE: usize = 2;
+/// Longest register list a clean instruction has; the sample's longest is 19.
+const MAX_REG_LIST: u8 = 64;
+/// Shortest fully printable string whose load is taken as a sure instruction start.
+const MIN_ANCHOR_STRING: usize = 4;
+/// Layouts the search tries out before it settles the remaining bytes on their likeliest layout.
+const MAX_GUESSES: usize = 1024;
+/// Stand-in for frequencies the reference never shows, so logarithms stay finite.
+const UNSEEN: f64 = 1e-4;
+
+/// Handlers with the same operand layout. Decoding alone cannot tell them apart.
+struct LayoutClass {
+    layout: &'static [OperandKind],
+    opcodes: Vec<OpCodes>
+}
+
+fn layout_classes() -> Vec<LayoutClass> {
+    let mut classes: Vec<LayoutClass> = Vec::new();
+    for opcode in OpCodes::ALL.into_iter().filter(|&opcode| opcode != OpCodes::Db) {
+        match classes.iter_mut().find(|class| class.layout == opcode.layout()) {
+            Some(class) => class.opcodes.push(opcode),
+            None => classes.push(LayoutClass { layout: opcode.layout(), opcodes: vec![opcode] })
+        }
+    }
+    classes
+}
+
+/// How often one handler (or opcode byte) runs, and the layout classes of the instructions right before and after it.
+#[derive(Clone)]
+struct Usage {
+    count: usize,
+    before: Vec<f64>,
+    after: Vec<f64>
+}
+
+fn tally(sequence: &[(usize, usize)], keys: usize, classes: usize) -> Vec<Usage> {
+    let mut usage: Vec<Usage> = vec![Usage { count: 0, before: vec![0.0; classes], after: vec![0.0; classes] }; keys];
+    for (index, &(key, _)) in sequence.iter().enumerate() {
+        usage[key].count += 1;
+        if let Some(&(_, class)) = index.checked_sub(1).and_then(|before| sequence.get(before)) {
+            usage[key].before[class] += 1.0;
+        }
+        if let Some(&(_, class)) = sequence.get(index + 1) {
+            usage[key].after[class] += 1.0;
+        }
+    }
+    usage
+}
+
+fn spread(a: &[f64], b: &[f64]) -> f64 {
+    let (a_total, b_total): (f64, f64) = (a.iter().sum::<f64>().max(1.0), b.iter().sum::<f64>().max(1.0));
+    a.iter().zip(b).map(|(a, b)| (a / a_total - b / b_total).abs()).sum()
+}
+
+/// How unlike a byte of the unknown sample is to a handler of the reference: the log ratio of their
+/// frequencies plus the distance between their neighbouring layouts.
+fn distance(byte: &Usage, byte_total: usize, handler: &Usage, handler_total: usize) -> f64 {
+    let byte_share: f64 = (byte.count as f64 / byte_total.max(1) as f64).max(UNSEEN);
+    let handler_share: f64 = (handler.count as f64 / handler_total.max(1) as f64).max(UNSEEN);
+    (byte_share / handler_share).ln().abs() + spread(&byte.before, &handler.before) + spread(&byte.after, &handler.after)
+}
+
+struct Decoded {
+    next: usize,
+    /// Control characters and invalid UTF-8 in the string operands, out of `chars`.
+    unprintable: usize,
+    chars: usize,
+    targets: Vec<u32>
+}
+
+/// String loads clean code is sure to have, as start and end offsets, with their opcode byte and layout class.
+struct Anchors {
+    byte: u8,
+    class: u8,
+    loads: Vec<(usize, usize)>
+}
+
+/// The unknown sample and the limits clean code in it stays within.
+struct Sample<'a> {
+    bytes: &'a [u8],
+    end: usize,
+    key: u8,
+    max_register: u8
+}
+
+impl Sample<'_> {
+    /// Decodes the operands of `layout` at `at`, or `None` when one of them cannot belong to clean code.
+    fn decode(&self, at: usize, layout: &[OperandKind]) -> Option<Decoded> {
+        let mut reader: ByteReader = ByteReader::at(&self.bytes[..self.end], at + 1);
+        let mut decoded: Decoded = Decoded { next: at, unprintable: 0, chars: 0, targets: Vec::new() };
+        for &kind in layout {
+            match kind {
+                OperandKind::Reg => {
+                    if reader.u8().ok()? > self.max_register {
+                        return None;
+                    }
+                }
+                OperandKind::RegList => {
+                    let count: u8 = reader.u8().ok()?;
+                    if count > MAX_REG_LIST || (0..count).any(|_| reader.u8().map_or(true, |reg| reg > self.max_register)) {
+                        return None;
+                    }
+                }
+                OperandKind::Str => {
+                    let (string, _) = reader.xored_string(self.key).ok()?;
+                    let chars: usize = string.chars().count();
+                    let unprintable: usize = string
+                        .chars()
+                        .filter(|&c| c == char::REPLACEMENT_CHARACTER || (c.is_control() && !matches!(c, '\t' | '\n' | '\r')))
+                        .count();
+                    if unprintable > MAX_UNPRINTABLE && (unprintable as f64) > chars as f64 * (1.0 - MIN_PRINTABLE) {
+                        return None;
+                    }
+                    decoded.unprintable += unprintable;
+                    decoded.chars += chars;
+                }
+                OperandKind::Target => {
+                    let target: u32 = reader.u32_be().ok()?;
+                    if target as usize >= self.bytes.len() {
+                        return None;
+                    }
+                    decoded.targets.push(target);
+                }
+                kind => reader.skip_operand(kind).ok()?
+            }
+        }
+        decoded.next = reader.pos;
+        Some(decoded)
+    }
+
+    /// Whether `from..to` splits into clean instructions with every opcode byte taking one of its `options`.
+    fn tiles(&self, from: usize, to: usize, options: &[Vec<u8>], classes: &[LayoutClass]) -> bool {
+        let mut reachable: Vec<bool> = vec![false; to - from + 1];
+        reachable[0] = true;
+        for at in from..to {
+            if !reachable[at - from] {
+                continue;
+            }
+            for &class in &options[self.bytes[at] as usize] {
+                if let Some(decoded) = self.decode(at, classes[class as usize].layout)
+                    && decoded.next <= to
+                {
+                    reachable[decoded.next - from] = true;
+                }
+            }
+            if reachable[to - from] {
+                return true;
+            }
+        }
+        reachable[to - from]
+    }
+
+    /// Where the byte that loads the most long, fully printable strings does so, with that byte and the
+    /// string loading class. Strings survive a reshuffle unchanged, so these are instructions any clean
+    /// decoding starts and ends exactly where they do.
+    fn anchors(&self, start: usize, classes: &[LayoutClass]) -> Option<Anchors> {
+        let class: usize = classes.iter().position(|class| class.opcodes.len() == 1 && class.layout.contains(&OperandKind::Str))?;
+        let loads = |byte: u8| -> Vec<(usize, usize)> {
+            let mut anchors: Vec<(usize, usize)> = Vec::new();
+            let mut at: usize = start;
+            while at < self.end {
+                match (self.bytes[at] == byte).then(|| self.decode(at, classes[class].layout)).flatten() {
+                    Some(decoded) if decoded.unprintable == 0 && decoded.chars >= MIN_ANCHOR_STRING => {
+                        anchors.push((at, decoded.next));
+                        at = decoded.next;
+                    }
+                    _ => at += 1
+                }
+            }
+            anchors
+        };
+        let (byte, anchors): (u8, Vec<(usize, usize)>) = (0..=u8::MAX).map(|byte| (byte, loads(byte))).max_by_key(|(_, anchors)| anchors.len())?;
+        (anchors.len() > 1).then_some(Anchors { byte, class: class as u8, loads: anchors })
+    }
+}
+
+/// Code between two sure instruction starts, decoded from the front as far as the layouts allow.
+#[derive(Clone)]
+struct Segment {
+    at: usize,
+    until: usize,
+    /// No layout left for the byte at `at` decodes the rest of the segment.
+    stuck: bool
+}
+
+/// The layout classes each opcode byte may still have, and the segments decoded with them.
+#[derive(Clone)]
+struct Layouts {
+    options: Vec<Vec<u8>>,
+    /// Bytes whose layout was picked rather than forced.
+    guessed: [bool; 256],
+    /// Bytes seen starting an instruction; only they use up a handler.
+    opcodes: [bool; 256],
+    segments: Vec<Segment>
+}
+
+struct Search<'a> {
+    sample: &'a Sample<'a>,
+    classes: &'a [LayoutClass],
+    /// How often the reference runs a handler of each class, on average.
+    shares: &'a [f64],
+    /// Layouts left to try out before the rest are taken on trust.
+    budget: usize
+}
+
+impl Search<'_> {
+    /// Rules out every layout that cannot decode where a segment is known to have an instruction, and
+    /// advances the segments past bytes left with one layout. False when a segment or class ran out of
+    /// room, which means a guess was wrong.
+    fn propagate(&self, layouts: &mut Layouts) -> bool {
+        let (sample, classes): (&Sample, &[LayoutClass]) = (self.sample, self.classes);
+        let mut consistent: bool = true;
+        loop {
+            let mut changed: bool = false;
+            // A class with as many settled bytes as it has handlers takes no more.
+            for (index, class) in classes.iter().enumerate() {
+                let settled: usize = (0..256).filter(|&byte| layouts.opcodes[byte] && layouts.options[byte].as_slice() == [index as u8]).count();
+                consistent &= settled <= class.opcodes.len();
+                if settled >= class.opcodes.len() {
+                    for options in layouts.options.iter_mut().filter(|options| options.len() > 1) {
+                        let before: usize = options.len();
+                        options.retain(|&other| other != index as u8);
+                        changed |= options.len() != before;
+                    }
+                }
+            }
+
+            for segment in layouts.segments.iter_mut().filter(|segment| !segment.stuck) {
+                while segment.at < segment.until {
+                    let byte: usize = sample.bytes[segment.at] as usize;
+                    layouts.opcodes[byte] = true;
+                    let viable: Vec<(u8, usize)> = layouts.options[byte]
+                        .iter()
+                        .filter_map(|&class| sample.decode(segment.at, classes[class as usize].layout).map(|decoded| (class, decoded.next)))
+                        .filter(|&(_, next)| next <= segment.until && sample.tiles(next, segment.until, &layouts.options, classes))
+                        .collect();
+                    if viable.is_empty() {
+                        segment.stuck = true;
+                        consistent = false;
+                        break;
+                    }
+                    if viable.len() < layouts.options[byte].len() {
+                        layouts.options[byte] = viable.iter().map(|&(class, _)| class).collect();
+                        changed = true;
+                    }
+                    match viable.as_slice() {
+                        &[(_, next)] => segment.at = next,
+                        _ => break
+                    }
+                }
+            }
+            if !changed {
+                return consistent;
+            }
+        }
+    }
+
+    /// Propagates, then settles a byte that blocks a segment, the one with the fewest layouts left and
+    /// then the most segments: each of its layouts is tried, those leading to a contradiction are
+    /// dropped, and the search goes on with the survivors, likeliest first.
+    /// `None` when none survives; a `trusted` call keeps contradictions it started with and settles for
+    /// its likeliest layout when no guess survives.
+    fn solve(&mut self, mut layouts: Layouts, trusted: bool) -> Option<Layouts> {
+        if !self.propagate(&mut layouts) && !trusted {
+            return None;
+        }
+        let mut blocking: Vec<usize> = vec![0; 256];
+        for segment in layouts.segments.iter().filter(|segment| !segment.stuck && segment.at < segment.until) {
+            blocking[self.sample.bytes[segment.at] as usize] += 1;
+        }
+        let Some(byte) = (0..256).filter(|&byte| blocking[byte] > 0).max_by_key(|&byte| (std::cmp::Reverse(layouts.options[byte].len()), blocking[byte])) else {
+            return Some(layouts);
+        };
+        let mut choices: Vec<u8> = layouts.options[byte].clone();
+        choices.sort_by(|&a, &b| self.shares[b as usize].total_cmp(&self.shares[a as usize]));
+        let guess = |class: u8| -> Layouts {
+            let mut guess: Layouts = layouts.clone();
+            guess.options[byte] = vec![class];
+            guess.guessed[byte] = true;
+            guess
+        };
+        let mut survivors: Vec<Layouts> = Vec::new();
+        for &class in &choices {
+            if self.budget == 0 {
+                break;
+            }
+            self.budget -= 1;
+            let mut layouts: Layouts = guess(class);
+            if self.propagate(&mut layouts) {
+                survivors.push(layouts);
+            }
+        }
+        let forced: bool = survivors.len() == 1;
+        for mut survivor in survivors {
+            survivor.guessed[byte] = layouts.guessed[byte] || !forced;
+            if let Some(solved) = self.solve(survivor, false) {
+                return Some(solved);
+            }
+        }
+        if trusted || self.budget == 0 { self.solve(guess(choices[0]), true) } else { None }
+    }
+}
+
+/// A byte of the unknown sample and the handler it most likely dispatches to.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InferredOpcode {
+    pub byte: u8,
+    pub opcode: OpCodes,
+    /// From 0 to 1: how often the byte was seen, whether its layout was forced or guessed, and how
+    /// clearly its handler beat the others with that layout.
+    pub confidence: f64,
+    /// Instructions in the unknown sample that start with the byte.
+    pub count: usize,
+    /// The other handlers with the same layout, closest first.
+    pub alternatives: Vec<OpCodes>
+}
+
+/// A byte→handler mapping for a build whose opcode bytes were reshuffled, found by decoding it against a
+/// reference sample decoded with a known table. Each handler is assumed to have one byte.
+///
+/// Loads of long printable strings are found first; they fix the string loading byte and split the
+/// sample into runs of code that must decode exactly from one load to the next. Each byte starts out
+/// able to take any operand layout, and a layout is ruled out when it cannot decode cleanly where the
+/// byte starts an instruction (registers in range, printable strings, targets inside the bytecode) or
+/// leaves no way to split the rest of the run. When nothing more can be ruled out, a blocking byte is
+/// tried with each layout it has left, and layouts that lead to a contradiction are dropped. Handlers
+/// sharing a layout are then told apart by how often they run and what runs around them, compared
+/// with the reference.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpcodeInference {
+    /// In byte order.
+    pub opcodes: Vec<InferredOpcode>,
+    pub instructions: usize,
+    pub decoded_bytes: usize,
+    pub range_bytes: usize,
+    /// Where decoding with the mapping stopped short of the end of the range, if it did.
+    pub stopped_at: Option<usize>,
+    pub targets: usize,
+    /// Targets that land on the start of a decoded instruction.
+    pub targets_on_instructions: usize
+}
+
+impl OpcodeInference {
+    pub fn new(reference: &[Instruction], bytes: &[u8], start: usize, end: usize, string_key: u8) -> Self {
+        let classes: Vec<LayoutClass> = layout_classes();
+        let class_of = |opcode: OpCodes| classes.iter().position(|class| class.layout == opcode.layout());
+        let reference: Vec<(usize, usize)> = reference
+            .iter()
+            .filter_map(|instruction| class_of(instruction.opcode).map(|class| (instruction.opcode as usize, class)))
+            .collect();
+        let handlers: Vec<Usage> = tally(&reference, OpCodes::ALL.len(), classes.len());
+        // How often the reference runs a handler of each class, on average.
+        let shares: Vec<f64> = classes
+            .iter()
+            .map(|class| {
+                let count: usize = class.opcodes.iter().map(|&opcode| handlers[opcode as usize].count).sum();
+                count as f64 / class.opcodes.len() as f64
+            })
+            .collect();
+
+        let sample: Sample = Sample { bytes, end: end.min(bytes.len()), key: string_key, max_register: Resync::default().max_register };
+        let end: usize = sample.end;
+        let mut options: Vec<Vec<u8>> = vec![(0..classes.len() as u8).collect(); 256];
+        let mut opcodes: [bool; 256] = [false; 256];
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut at: usize = start;
+        if let Some(anchors) = sample.anchors(start, &classes) {
+            options[anchors.byte as usize] = vec![anchors.class];
+            opcodes[anchors.byte as usize] = true;
+            for (load, next) in anchors.loads {
+                segments.push(Segment { at, until: load, stuck: false });
+                at = next;
+            }
+        }
+        segments.push(Segment { at, until: end, stuck: false });
+        let mut search: Search = Search { sample: &sample, classes: &classes, shares: &shares, budget: MAX_GUESSES };
+        let Layouts { options, guessed, .. } = search.solve(Layouts { options, guessed: [false; 256], opcodes, segments }, true).expect("a trusted search always settles");
+
+        // Decode with the settled layouts to find the instructions, then name each byte's handler.
+        let mut sequence: Vec<(usize, usize)> = Vec::new();
+        let mut starts: HashSet<usize> = HashSet::new();
+        let mut targets: Vec<u32> = Vec::new();
+        let mut ptr: usize = start;
+        while ptr < end {
+            let byte: u8 = bytes[ptr];
+            let &[class] = options[byte as usize].as_slice() else {
+                break;
+            };
+            let Some(decoded) = sample.decode(ptr, classes[class as usize].layout) else {
+                break;
+            };
+            sequence.push((byte as usize, class as usize));
+            starts.insert(ptr);
+            targets.extend(decoded.targets);
+            ptr = decoded.next;
+        }
+        let usage: Vec<Usage> = tally(&sequence, 256, classes.len());
+
+        let mut opcodes: Vec<InferredOpcode> = Vec::new();
+        for (index, class) in classes.iter().enumerate() {
+            let bytes: Vec<u8> = (0..=u8::MAX).filter(|&byte| usage[byte as usize].count > 0 && options[byte as usize].as_slice() == [index as u8]).collect();
+            let cost = |byte: u8, opcode: OpCodes| distance(&usage[byte as usize], sequence.len(), &handlers[opcode as usize], reference.len());
+
+            let mut pairs: Vec<(f64, u8, OpCodes)> = bytes.iter().flat_map(|&byte| class.opcodes.iter().map(move |&opcode| (byte, opcode))).map(|(byte, opcode)| (cost(byte, opcode), byte, opcode)).collect();
+            pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut assigned: Vec<(u8, OpCodes, f64)> = Vec::new();
+            for (best, byte, opcode) in pairs {
+                if assigned.iter().any(|&(other, handler, _)| other == byte || handler == opcode) {
+                    continue;
+                }
+                assigned.push((byte, opcode, best));
+            }
+
+            for (byte, opcode, best) in assigned {
+                let mut alternatives: Vec<(f64, OpCodes)> = class.opcodes.iter().filter(|&&other| other != opcode).map(|&other| (cost(byte, other), other)).collect();
+                alternatives.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let rival: f64 = alternatives
+                    .first()
+                    .map(|&(cost, _)| cost)
+                    .into_iter()
+                    .chain(bytes.iter().filter(|&&other| other != byte).map(|&other| cost(other, opcode)))
+                    .fold(f64::INFINITY, f64::min);
+                let margin: f64 = if rival.is_finite() { ((rival - best) / rival.max(f64::EPSILON)).clamp(0.0, 1.0) } else { 1.0 };
+                let count: usize = usage[byte as usize].count;
+                let layout: f64 = if guessed[byte as usize] { 0.5 } else { 1.0 };
+                opcodes.push(InferredOpcode {
+                    byte,
+                    opcode,
+                    confidence: margin * layout * (1.0 - 0.5f64.powi(count.min(64) as i32)),
+                    count,
+                    alternatives: alternatives.into_iter().map(|(_, other)| other).collect()
+                });
+            }
+        }
+        opcodes.sort_by_key(|inferred| inferred.byte);
+
+        Self {
+            opcodes,
+            instructions: sequence.len(),
+            decoded_bytes: ptr - start,
+            range_bytes: end - start,
+            stopped_at: (ptr < end).then_some(ptr),
+            targets: targets.len(),
+            targets_on_instructions: targets.iter().filter(|&&target| starts.contains(&(target as usize))).count()
+        }
+    }
+
+    /// The bytes inferred with at least `min_confidence`, as a table named `inferred`.
+    pub fn table(&self, min_confidence: f64) -> Result<OpcodeTable, DisassemblerError> {
+        let names: Vec<(u8, String)> = self
+            .opcodes
+            .iter()
+            .filter(|inferred| inferred.confidence >= min_confidence)
+            .map(|inferred| (inferred.byte, inferred.opcode.handler_name()))
+            .collect();
+        let pairs: Vec<(u8, &str)> = names.iter().map(|(byte, name)| (*byte, name.as_str())).collect();
+        Ok(OpcodeTable::from_pairs(&pairs)?.with_name("inferred", None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassembler::{Disassembler, DEFAULT_STRING_KEY};
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::{decode, run};
+
+    /// Four rounds of NEW VALUE "abcd" -> reg1, LOAD IMM8 5 -> reg2 and ADD reg1 + reg2 -> reg3, then HALT,
+    /// with the given bytes for NEW VALUE, LOAD IMM8, ADD and HALT.
+    fn program([new_value, load_imm8, add, halt]: [u8; 4]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        for _ in 0..4 {
+            bytes.extend([new_value, 1, 0, 4]);
+            bytes.extend(b"abcd".iter().map(|byte| byte ^ DEFAULT_STRING_KEY));
+            bytes.extend([load_imm8, 2, 5, add, 3, 1, 2]);
+        }
+        bytes.push(halt);
+        bytes
+    }
+
+    #[test]
+    fn recovers_reshuffled_bytes_from_a_reference() {
+        let reference: Listing = decode(program([23, 181, 243, 166]));
+        // The same program as the build_a profile encodes it.
+        let sample: Vec<u8> = program([87, 245, 51, 230]);
+        let inference: OpcodeInference = OpcodeInference::new(&reference.instructions, &sample, 0, sample.len(), DEFAULT_STRING_KEY);
+        let inferred: Vec<(u8, OpCodes)> = inference.opcodes.iter().map(|inferred| (inferred.byte, inferred.opcode)).collect();
+        assert_eq!(inferred, [(51, OpCodes::Add), (87, OpCodes::NewValue), (230, OpCodes::Halt), (245, OpCodes::LoadImm8)]);
+        assert_eq!((inference.instructions, inference.decoded_bytes, inference.stopped_at), (13, sample.len(), None));
+
+        let mut disasm: Disassembler = Disassembler::from_bytes(sample);
+        disasm.set_table(inference.table(0.0).unwrap());
+        let decoded: Listing = run(&mut disasm);
+        assert_eq!(decoded.instruction_count, reference.instruction_count);
+    }
+}
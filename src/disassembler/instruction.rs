@@ -0,0 +1,249 @@
+use std::fmt;
+use crate::disassembler::codec::{escape_bytes, format_double};
+use crate::disassembler::names::RegisterNames;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Access, Operand};
+use crate::disassembler::registers::{Origin, RegValue};
+use crate::disassembler::symbols::Symbols;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TargetFormat {
+    Decimal,
+    Hex,
+    Relative
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instruction {
+    pub offset: usize,
+    pub opcode: OpCodes,
+    pub length: usize,
+    pub operands: Vec<Operand>,
+    pub resolved: Vec<Option<RegValue>>,
+    /// Where each register operand's value came from, when the tracker knows; empty when none is known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub origins: Vec<Option<Origin>>,
+    /// JUMP FRAMEs decoded before this instruction and not yet closed by a RET.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub depth: usize,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>
+}
+
+fn reg_list(regs: &[u8], name: &dyn Fn(u8) -> String) -> String {
+    regs.iter().map(|&reg| name(reg)).collect::<Vec<String>>().join(",")
+}
+
+fn reg_name(reg: u8) -> String {
+    format!("reg{reg}")
+}
+
+pub(crate) fn binary_symbol(opcode: OpCodes) -> Option<&'static str> {
+    match opcode {
+        OpCodes::Add => Some("+"),
+        OpCodes::Sub => Some("-"),
+        OpCodes::Mul => Some("*"),
+        OpCodes::Div => Some("/"),
+        OpCodes::Mod => Some("%"),
+        OpCodes::Or => Some("|"),
+        OpCodes::And => Some("&"),
+        OpCodes::Xor => Some("^"),
+        OpCodes::Shl => Some("<<"),
+        OpCodes::Shr => Some(">>"),
+        OpCodes::Ushr => Some(">>>"),
+        OpCodes::LessThan | OpCodes::LessThanAlt => Some("<"),
+        OpCodes::Lte | OpCodes::LteAlt => Some("<="),
+        OpCodes::Equal => Some("=="),
+        OpCodes::NotEqual => Some("!="),
+        OpCodes::StrictEqual => Some("==="),
+        OpCodes::StrictNotEqual => Some("!=="),
+        _ => None
+    }
+}
+
+impl Instruction {
+    pub fn new(offset: usize, opcode: OpCodes, operands: Vec<Operand>, resolved: Vec<Option<RegValue>>) -> Self {
+        Self { offset, opcode, length: 0, operands, resolved, origins: Vec::new(), depth: 0, comment: None }
+    }
+
+    pub fn origin(&self, index: usize) -> Option<&Origin> {
+        self.origins.get(index).and_then(Option::as_ref)
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = u32> + '_ {
+        self.operands.iter().filter_map(|operand| match operand {
+            Operand::Target(target) => Some(*target),
+            _ => None
+        })
+    }
+
+    /// Every register operand with what the instruction does to it, per `OpCodes::access`;
+    /// register lists yield one entry per register.
+    pub fn accesses(&self) -> impl Iterator<Item = (u8, Access)> + '_ {
+        self.opcode.access().iter().zip(&self.operands).flat_map(|(&access, operand)| {
+            let regs: &[u8] = match (access, operand) {
+                (Access::None, _) => &[],
+                (_, Operand::Reg(reg) | Operand::Byte(reg)) => std::slice::from_ref(reg),
+                (_, Operand::RegList(regs)) => regs.as_slice(),
+                _ => &[]
+            };
+            regs.iter().map(move |&reg| (reg, access))
+        })
+    }
+
+    pub fn text(&self, targets: TargetFormat, raw_strings: bool) -> String {
+        self.labeled_text(targets, raw_strings, None)
+    }
+
+    /// Like `text`, but targets with a label in `symbols` render as `name (target)`.
+    pub fn labeled_text(&self, targets: TargetFormat, raw_strings: bool, symbols: Option<&Symbols>) -> String {
+        self.commented(self.labeled(raw_strings, &|target| self.target(target, targets, symbols), &reg_name))
+    }
+
+    /// Like `labeled_text`, but registers with a name in `names` are written by it instead of as `regN`.
+    pub fn named_text(&self, targets: TargetFormat, raw_strings: bool, symbols: Option<&Symbols>, names: &RegisterNames) -> String {
+        self.commented(self.labeled(raw_strings, &|target| self.target(target, targets, symbols), &|reg| names.display(self.offset, reg)))
+    }
+
+    fn commented(&self, text: String) -> String {
+        match &self.comment {
+            Some(comment) => format!("{text}    ; {comment}"),
+            None => text
+        }
+    }
+
+    fn name(&self, index: usize, raw_strings: bool, reg: &dyn Fn(u8) -> String) -> String {
+        match (&self.resolved.get(index), &self.operands.get(index)) {
+            (Some(Some(value)), _) => value.quoted(raw_strings),
+            (_, Some(operand)) => Self::operand(operand, reg),
+            _ => String::new()
+        }
+    }
+
+    /// The function a call at operand `index` invokes: `reg2["atob"]` when the tracker saw the property read
+    /// that loaded it.
+    fn callee(&self, index: usize, raw_strings: bool, reg: &dyn Fn(u8) -> String) -> String {
+        match self.origin(index) {
+            Some(origin) => format!("{}[{}]", reg(origin.object), origin.key.quoted(raw_strings)),
+            None => self.name(index, raw_strings, reg)
+        }
+    }
+
+    fn operand(operand: &Operand, reg: &dyn Fn(u8) -> String) -> String {
+        match operand {
+            Operand::Reg(value) => reg(*value),
+            Operand::RegList(regs) => format!("[{}]", reg_list(regs, reg)),
+            operand => operand.to_string()
+        }
+    }
+
+    fn target(&self, target: u32, format: TargetFormat, symbols: Option<&Symbols>) -> String {
+        let number: String = match format {
+            TargetFormat::Decimal => target.to_string(),
+            TargetFormat::Hex => format!("{target:#x}"),
+            TargetFormat::Relative => format!(".{:+}", target as i64 - self.offset as i64)
+        };
+        match symbols.and_then(|symbols| symbols.get(target as usize)) {
+            Some(label) => format!("{label} ({number})"),
+            None => number
+        }
+    }
+
+    pub fn render(&self, targets: TargetFormat, raw_strings: bool) -> String {
+        self.labeled(raw_strings, &|target| self.target(target, targets, None), &reg_name)
+    }
+
+    /// Like `render`, but every target is written by `target`.
+    pub fn render_targets(&self, raw_strings: bool, target: impl Fn(u32) -> String) -> String {
+        self.labeled(raw_strings, &target, &reg_name)
+    }
+
+    fn labeled(&self, raw_strings: bool, target: &dyn Fn(u32) -> String, name: &dyn Fn(u8) -> String) -> String {
+        let op: &str = self.opcode.as_str();
+
+        if let (Some(symbol), [Operand::Reg(reg), Operand::Reg(left), Operand::Reg(right)]) = (binary_symbol(self.opcode), self.operands.as_slice()) {
+            return format!("{op} {} {symbol} {} -> {}", name(*left), name(*right), name(*reg));
+        }
+
+        match (self.opcode, self.operands.as_slice()) {
+            (OpCodes::InitMemory | OpCodes::LoadImm8, [Operand::Reg(reg), Operand::Byte(value)]) => format!("{op} {value} -> {}", name(*reg)),
+            (OpCodes::MovImm32, [Operand::Reg(reg), Operand::Int(value)]) => format!("{op} {value} -> {}", name(*reg)),
+            (OpCodes::LoadDouble, [Operand::Reg(reg), Operand::Double(value)]) => format!("{op} {} -> {}", format_double(*value), name(*reg)),
+            (OpCodes::NewValue, [Operand::Reg(reg), Operand::Str(value, _)]) if raw_strings => format!("{op} '{value}' -> {}", name(*reg)),
+            (OpCodes::NewValue, [Operand::Reg(reg), Operand::Str(_, bytes)]) => format!("{op} '{}' -> {}", escape_bytes(bytes), name(*reg)),
+            (OpCodes::GetProperty, [Operand::Reg(reg), Operand::Reg(obj), Operand::Reg(_)]) => {
+                format!("{op} {}[{}] -> {}", name(*obj), self.name(2, raw_strings, name), name(*reg))
+            }
+            (OpCodes::SetProperty, [Operand::Reg(obj), Operand::Reg(_), Operand::Reg(_)]) => {
+                format!("{op} {}[{}] = {}", name(*obj), self.name(1, raw_strings, name), self.name(2, raw_strings, name))
+            }
+            (OpCodes::CallFunction, [Operand::Reg(reg), Operand::Reg(_), Operand::RegList(args)]) => {
+                format!("{op} {}({}) -> {}", self.callee(1, raw_strings, name), reg_list(args, name), name(*reg))
+            }
+            (OpCodes::CallApply, [Operand::Reg(reg), Operand::Reg(_), Operand::Reg(this), Operand::RegList(args)]) => {
+                format!("{op} {}.apply({}, [{}]) -> {}", self.callee(1, raw_strings, name), name(*this), reg_list(args, name), name(*reg))
+            }
+            (OpCodes::PushArgs, [Operand::Reg(reg), Operand::RegList(args)]) => format!("{op} [{}] -> {}", reg_list(args, name), name(*reg)),
+            (OpCodes::JumpFrame, [Operand::Target(entry), Operand::Byte(context), Operand::RegList(params)]) => {
+                format!("{op} entry({}), {context}, params({})", target(*entry), reg_list(params, name))
+            }
+            (OpCodes::NewFunction, [Operand::Reg(_), Operand::Target(entry), Operand::RegList(args)]) => {
+                format!("{op} entry({}), args({})", target(*entry), reg_list(args, name))
+            }
+            (OpCodes::JumpIfFalse | OpCodes::JumpIfTrue, [Operand::Reg(reg), Operand::Target(entry)]) => {
+                format!("{op} {}, entry({})", name(*reg), target(*entry))
+            }
+            (OpCodes::Jump, [Operand::Target(entry)]) => format!("{op} {}", target(*entry)),
+            (OpCodes::Ret, [Operand::Reg(reg), Operand::RegList(list)]) => format!("{op} {} [{}]", name(*reg), reg_list(list, name)),
+            (OpCodes::Throw, [Operand::Reg(reg)]) => format!("{op} {}", name(*reg)),
+            (OpCodes::TryCatch, [Operand::Reg(reg), Operand::Target(catch), Operand::Target(finally), Operand::Target(next)]) => {
+                format!("{op} [{}, {}, {}] -> {}", target(*catch), target(*finally), target(*next), name(*reg))
+            }
+            (OpCodes::Db, [Operand::Byte(byte)]) if self.length > 1 => format!("{op} 0x{byte:02x} ...    ; unknown data, {} bytes", self.length),
+            (OpCodes::Db, [Operand::Byte(byte)]) => format!("{op} 0x{byte:02x}    ; unknown opcode"),
+            (_, operands) => {
+                let operands: Vec<String> = operands.iter().map(|operand| Self::operand(operand, name)).collect();
+                format!("{op} {}", operands.join(", ")).trim_end().to_string()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(TargetFormat::Hex, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    #[test]
+    fn new_value_decodes_utf8_and_keeps_raw_bytes() {
+        let raw: [u8; 5] = [b'c', 0xc3, 0xa9, 0xff, b'\n'];
+        let mut bytes: Vec<u8> = vec![23, 1, 0, raw.len() as u8];
+        bytes.extend(raw.iter().map(|byte| byte ^ 50));
+        let instruction: &Instruction = &decode(bytes).instructions[0];
+        assert!(matches!(&instruction.operands[1], Operand::Str(text, kept) if text == "c\u{e9}\u{fffd}\n" && kept[..] == raw));
+        assert_eq!(instruction.text(TargetFormat::Hex, false), "NEW VALUE 'c\u{e9}\\xff\\n' -> reg1");
+        assert_eq!(instruction.text(TargetFormat::Hex, true), "NEW VALUE 'c\u{e9}\u{fffd}\n' -> reg1");
+    }
+
+    #[test]
+    fn ret_and_throw_render_registers() {
+        let instructions: Vec<Instruction> = decode(vec![17, 5, 2, 1, 2, 5, 3]).instructions;
+        let texts: Vec<String> = instructions.iter().map(|instruction| instruction.text(TargetFormat::Hex, false)).collect();
+        assert_eq!(texts, ["RET reg5 [reg1,reg2]", "THROW reg3"]);
+        let accesses: Vec<Vec<(u8, Access)>> = instructions.iter().map(|instruction| instruction.accesses().collect()).collect();
+        assert_eq!(accesses, [vec![(5, Access::Use), (1, Access::Use), (2, Access::Use)], vec![(3, Access::Use)]]);
+    }
+}
@@ -1,401 +1,611 @@
-use std::collections::HashMap;
-use std::path::MAIN_SEPARATOR;
-use crate::disassembler::disassembler::Disassembler;
+use std::sync::{Arc, OnceLock};
+use crate::disassembler::disassembler::{Disassembler, UnknownOpcodePolicy};
+use crate::disassembler::error::DisassemblerError;
 use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+use crate::disassembler::registers::{RegisterTracker, TrackingMode};
 
-type InstructionType = fn(&mut Disassembler);
+pub type InstructionType = fn(&mut Disassembler) -> Result<(), DisassemblerError>;
+pub type InstructionEntry = (OpCodes, InstructionType);
+/// Indexed by opcode byte, so lookups and iteration are both in byte order.
+pub type InstructionTable = [Option<InstructionEntry>; 256];
 #[derive(Debug)]
 pub struct Instructions;
 
-impl Instructions {
-    pub fn get_instructions() -> HashMap<u8, InstructionType> {
-        let mut instructions: HashMap<u8, InstructionType> = HashMap::new();
-
-        instructions.insert(124, Instructions::init_memory);
-        instructions.insert(23, Instructions::new_value);
-        instructions.insert(251, Instructions::get_property);
-        instructions.insert(215, Instructions::call_function);
-        instructions.insert(6, Instructions::mul_op);
-        instructions.insert(241, Instructions::mov_imm24);
-        instructions.insert(90, Instructions::call_apply);
-        instructions.insert(55, Instructions::div_op);
-        instructions.insert(65, Instructions::or_op);
-        instructions.insert(230, Instructions::sub_op);
-        instructions.insert(88, Instructions::push_args);
-        instructions.insert(181, Instructions::load_imm24);
-        instructions.insert(49, Instructions::jump_frame);
-        instructions.insert(171, Instructions::new_function);
-        instructions.insert(20, Instructions::less_than);
-        instructions.insert(39, Instructions::jump_if_false);
-        instructions.insert(112, Instructions::less_than);
-        instructions.insert(99, Instructions::set_property);
-        instructions.insert(243, Instructions::add_op);
-        instructions.insert(93, Instructions::jump);
-        instructions.insert(166, Instructions::halt);
-        instructions.insert(53, Instructions::shl_op);
-        instructions.insert(17, Instructions::function_ret);
-        instructions.insert(78, Instructions::equal_op);
-        instructions.insert(117, Instructions::xor_op);
-        instructions.insert(51, Instructions::load_double);
-        instructions.insert(40, Instructions::ushr_op);
-        instructions.insert(149, Instructions::shr_op);
-        instructions.insert(37, Instructions::and_op);
-        instructions.insert(156, Instructions::mod_op);
-        instructions.insert(247, Instructions::lte_op);
-        instructions.insert(214, Instructions::lte_op);
-        instructions.insert(22, Instructions::notequal_op);
-        instructions.insert(83, Instructions::jump_if_true);
-        instructions.insert(115, Instructions::try_catch);
-        instructions.insert(161, Instructions::strict_equal_op);
-        instructions.insert(220, Instructions::strict_notequal_op);
-        instructions.insert(5, Instructions::throw_op);
+#[derive(Clone)]
+pub struct OpcodeTable {
+    pub name: String,
+    pub notes: Option<String>,
+    entries: Arc<InstructionTable>
+}
 
-        instructions
+pub const DEFAULT_PROFILE: &str = "default";
+
+static BUILTIN_TABLE: OnceLock<OpcodeTable> = OnceLock::new();
+
+fn read_regs(disasm: &mut Disassembler, count: u8) -> Result<Vec<u8>, DisassemblerError> {
+    (0..count).map(|_| disasm.get_byte()).collect()
+}
+
+impl OpcodeTable {
+    pub fn from_pairs(pairs: &[(u8, &str)]) -> Result<Self, DisassemblerError> {
+        let mut entries: InstructionTable = [None; 256];
+
+        for &(byte, name) in pairs {
+            let opcode: Option<OpCodes> = name.parse::<OpCodes>().ok();
+            let entry: InstructionEntry = match opcode.and_then(|opcode| Instructions::handler(opcode).map(|handler| (opcode, handler))) {
+                Some(entry) => entry,
+                None => {
+                    let handlers: Vec<(String, InstructionEntry)> = Instructions::handlers();
+                    let valid: Vec<&str> = handlers.iter().map(|(handler, _)| handler.as_str()).collect();
+                    return Err(DisassemblerError::InvalidConfig(format!("unknown handler '{name}' for opcode {byte} (valid names: {})", valid.join(", "))));
+                }
+            };
+            if entries[byte as usize].replace(entry).is_some() {
+                return Err(DisassemblerError::InvalidConfig(format!("opcode {byte} is mapped more than once")));
+            }
+        }
+        Ok(Self { name: "custom".to_string(), notes: None, entries: Arc::new(entries) })
     }
 
-    fn init_memory(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let value: u8 = disasm.get_byte();
-        disasm.trace.push(format!("{} {value} -> reg{reg}", OpCodes::InitMemory.as_str()));
+    pub fn builtin() -> &'static OpcodeTable {
+        BUILTIN_TABLE.get_or_init(|| Self {
+            name: DEFAULT_PROFILE.to_string(),
+            notes: None,
+            entries: Arc::new(Instructions::get_instructions())
+        })
     }
 
-    fn new_value(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let value: String = disasm.decode_value();
+    pub fn default_build() -> Self {
+        Self::builtin().clone()
+    }
 
-        disasm.trace.push(format!("{} '{value}' -> reg{reg}", OpCodes::NewValue.as_str()));
-        disasm.registers[reg as usize] = value;
+    pub fn with_name(mut self, name: &str, notes: Option<String>) -> Self {
+        self.name = name.to_string();
+        self.notes = notes;
+        self
     }
 
-    fn get_property(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let obj_reg = disasm.get_byte();
-        let prop_reg = disasm.get_byte();
+    pub fn merge(&self, overrides: &OpcodeTable) -> Self {
+        let mut entries: InstructionTable = *self.entries;
+        for (slot, entry) in entries.iter_mut().zip(overrides.entries.iter()) {
+            if entry.is_some() {
+                *slot = *entry;
+            }
+        }
+        Self {
+            name: format!("{}+{}", self.name, overrides.name),
+            notes: overrides.notes.clone().or_else(|| self.notes.clone()),
+            entries: Arc::new(entries)
+        }
+    }
 
-        let val: String = if &disasm.registers[prop_reg as usize] != "_free_reg_" {
-            disasm.registers[prop_reg as usize].clone()
-        } else {
-            format!("reg{}", prop_reg)
-        };
+    pub fn coverage(&self, bytes: &[u8]) -> f32 {
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes.to_vec());
+        disasm.set_table(self.clone());
+        disasm.unknown_policy = UnknownOpcodePolicy::Lenient;
+        disasm.registers = RegisterTracker::new(TrackingMode::Off);
 
-        disasm.trace.push(format!("{} reg{obj_reg}[{val}] -> reg{reg}", OpCodes::GetProperty.as_str()));   
+        let mut positions: usize = 0;
+        while let Ok(Some(_)) = disasm.step() {
+            positions += 1;
+            disasm.trace.clear();
+            disasm.instructions.clear();
+        }
+        if positions == 0 {
+            return 0.0;
+        }
+        (positions - disasm.unknown_skipped) as f32 / positions as f32
     }
 
-    fn call_function(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let func_reg: u8 = disasm.get_byte();
-        let func: String = if &disasm.registers[func_reg as usize] != "_free_reg_" {
-            disasm.registers[func_reg as usize].clone()
-        } else {
-            format!("reg{}", func_reg)
-        };
+    pub fn get(&self, byte: u8) -> Option<InstructionEntry> {
+        self.entries[byte as usize]
+    }
 
-        let arg_len: u8 = disasm.get_byte();
-        let mut args: Vec<String> = Vec::new();
+    pub fn insert(&mut self, byte: u8, entry: InstructionEntry) -> Option<InstructionEntry> {
+        Arc::make_mut(&mut self.entries)[byte as usize].replace(entry)
+    }
 
-        for _ in 0..arg_len {
-            let arg_reg = disasm.get_byte();
-            args.push(format!("reg{}", arg_reg))
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none)
+    }
+
+    /// Mapped bytes in ascending order with the opcode each one dispatches to.
+    pub fn dump_table(&self) -> Vec<(u8, OpCodes)> {
+        (0..=u8::MAX).filter_map(|byte| self.get(byte).map(|(opcode, _)| (byte, opcode))).collect()
+    }
+}
+
+impl Default for OpcodeTable {
+    fn default() -> Self {
+        Self::default_build()
+    }
+}
+
+impl Instructions {
+    pub fn handlers() -> Vec<(String, InstructionEntry)> {
+        let mut handlers: Vec<(String, InstructionEntry)> = OpCodes::ALL
+            .into_iter()
+            .filter_map(|opcode| Instructions::handler(opcode).map(|handler| (opcode.handler_name(), (opcode, handler))))
+            .collect();
+        handlers.sort_by(|a, b| a.0.cmp(&b.0));
+        handlers
+    }
+
+    pub fn handler(opcode: OpCodes) -> Option<InstructionType> {
+        let handler: InstructionType = match opcode {
+            OpCodes::InitMemory => Instructions::init_memory,
+            OpCodes::NewValue => Instructions::new_value,
+            OpCodes::GetProperty => Instructions::get_property,
+            OpCodes::CallFunction => Instructions::call_function,
+            OpCodes::Mul => Instructions::mul_op,
+            OpCodes::MovImm32 => Instructions::mov_imm32,
+            OpCodes::CallApply => Instructions::call_apply,
+            OpCodes::Div => Instructions::div_op,
+            OpCodes::Or => Instructions::or_op,
+            OpCodes::Sub => Instructions::sub_op,
+            OpCodes::PushArgs => Instructions::push_args,
+            OpCodes::LoadImm8 => Instructions::load_imm8,
+            OpCodes::JumpFrame => Instructions::jump_frame,
+            OpCodes::NewFunction => Instructions::new_function,
+            OpCodes::LessThan => Instructions::less_than,
+            OpCodes::LessThanAlt => Instructions::less_than_alt,
+            OpCodes::JumpIfFalse => Instructions::jump_if_false,
+            OpCodes::SetProperty => Instructions::set_property,
+            OpCodes::Add => Instructions::add_op,
+            OpCodes::Jump => Instructions::jump,
+            OpCodes::Halt => Instructions::halt,
+            OpCodes::Shl => Instructions::shl_op,
+            OpCodes::Ret => Instructions::function_ret,
+            OpCodes::Equal => Instructions::equal_op,
+            OpCodes::Xor => Instructions::xor_op,
+            OpCodes::LoadDouble => Instructions::load_double,
+            OpCodes::Ushr => Instructions::ushr_op,
+            OpCodes::Shr => Instructions::shr_op,
+            OpCodes::And => Instructions::and_op,
+            OpCodes::Mod => Instructions::mod_op,
+            OpCodes::Lte => Instructions::lte_op,
+            OpCodes::LteAlt => Instructions::lte_alt,
+            OpCodes::NotEqual => Instructions::notequal_op,
+            OpCodes::JumpIfTrue => Instructions::jump_if_true,
+            OpCodes::TryCatch => Instructions::try_catch,
+            OpCodes::StrictEqual => Instructions::strict_equal_op,
+            OpCodes::StrictNotEqual => Instructions::strict_notequal_op,
+            OpCodes::Throw => Instructions::throw_op,
+            OpCodes::Db => return None
         };
+        Some(handler)
+    }
 
-        let args: String = args.join(",");
+    pub fn get_instructions() -> InstructionTable {
+        let mut instructions: InstructionTable = [None; 256];
 
-        disasm.trace.push(format!("{} {func}({args}) -> reg{reg}", OpCodes::CallFunction.as_str()));
+        for opcode in OpCodes::ALL {
+            if let Some(handler) = Instructions::handler(opcode) {
+                for &byte in opcode.bytes() {
+                    instructions[byte as usize] = Some((opcode, handler));
+                }
+            }
+        }
+        instructions
+    }
+
+    fn init_memory(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let value: u8 = disasm.get_byte()?;
+        disasm.record(OpCodes::InitMemory, vec![Operand::Reg(reg), Operand::Byte(value)]);
+        Ok(())
     }
 
-    fn mul_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn new_value(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let (value, bytes) = disasm.decode_value()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} * reg{right_reg} -> reg{reg}", OpCodes::Mul.as_str()));
+        disasm.record(OpCodes::NewValue, vec![Operand::Reg(reg), Operand::Str(value, bytes)]);
+        Ok(())
     }
 
-    fn mov_imm24(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let val_24: u32 = disasm.get_int24();
+    fn get_property(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let obj_reg = disasm.get_byte()?;
+        let prop_reg = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} {val_24} -> reg{reg}", OpCodes::MovImm24.as_str()));
+        disasm.record(OpCodes::GetProperty, vec![Operand::Reg(reg), Operand::Reg(obj_reg), Operand::Reg(prop_reg)]);
+        Ok(())
     }
 
-    fn call_apply(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let func_reg: u8 = disasm.get_byte();
+    fn call_function(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let func_reg: u8 = disasm.get_byte()?;
+        let arg_len: u8 = disasm.get_byte()?;
+        let args: Vec<u8> = read_regs(disasm, arg_len)?;
 
-        let func: String = if &disasm.registers[func_reg as usize] != "_free_reg_" {
-            disasm.registers[func_reg as usize].clone()
-        } else {
-            format!("reg{}", func_reg)
-        };
-        let this_reg: u8 = disasm.get_byte();
-        let arg_len: u8 = disasm.get_byte();
-        let mut args: Vec<String> = Vec::new();
+        disasm.record(OpCodes::CallFunction, vec![Operand::Reg(reg), Operand::Reg(func_reg), Operand::RegList(args)]);
+        Ok(())
+    }
 
-        for _ in 0..arg_len {
-            let arg_reg = disasm.get_byte();
-            args.push(format!("reg{}", arg_reg))
-        };
+    fn mul_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        let args: String = args.join(",");
-        disasm.trace.push(format!("{} {func}.apply(reg{this_reg}, [{args}]) -> reg{reg}", OpCodes::CallApply.as_str()));
+        disasm.record(OpCodes::Mul, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn div_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn mov_imm32(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let value: u32 = disasm.get_u32_be()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} / reg{right_reg} -> reg{reg}", OpCodes::Div.as_str()));
+        disasm.record(OpCodes::MovImm32, vec![Operand::Reg(reg), Operand::Int(value)]);
+        Ok(())
     }
 
-    fn or_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn call_apply(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let func_reg: u8 = disasm.get_byte()?;
+        let this_reg: u8 = disasm.get_byte()?;
+        let arg_len: u8 = disasm.get_byte()?;
+        let args: Vec<u8> = read_regs(disasm, arg_len)?;
 
-        disasm.trace.push(format!("{} reg{left_reg} | reg{right_reg} -> reg{reg}", OpCodes::Or.as_str()));
+        disasm.record(OpCodes::CallApply, vec![Operand::Reg(reg), Operand::Reg(func_reg), Operand::Reg(this_reg), Operand::RegList(args)]);
+        Ok(())
     }
 
-    fn sub_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn div_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} - reg{right_reg} -> reg{reg}", OpCodes::Sub.as_str()));
+        disasm.record(OpCodes::Div, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn push_args(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let arg_len: u8 = disasm.get_byte();
-        let mut args: Vec<String> = Vec::new();
+    fn or_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        for _ in 0..arg_len {
-            let arg_reg = disasm.get_byte();
-            args.push(format!("reg{}", arg_reg))
-        };
+        disasm.record(OpCodes::Or, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
+    }
+
+    fn sub_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        let args: String = args.join(",");
-        disasm.trace.push(format!("{} [{args}] -> reg{reg}", OpCodes::PushArgs.as_str()));
+        disasm.record(OpCodes::Sub, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn load_imm24(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let val_24: u8 = disasm.get_byte();
+    fn push_args(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let arg_len: u8 = disasm.get_byte()?;
+        let args: Vec<u8> = read_regs(disasm, arg_len)?;
 
-        disasm.trace.push(format!("{} {val_24} -> reg{reg}", OpCodes::LoadImm24.as_str()));
+        disasm.record(OpCodes::PushArgs, vec![Operand::Reg(reg), Operand::RegList(args)]);
+        Ok(())
     }
 
-    fn jump_frame(disasm: &mut Disassembler) {
-        let ptr: u32 = disasm.get_int24();
-        let context: u8 = disasm.get_byte();
-        let params_count: u8 = disasm.get_byte();
-        let mut params: Vec<String> = Vec::new();
-        
-        for _ in 0..params_count {
-            let param_reg = disasm.get_byte();
-            params.push(format!("reg{}", param_reg))
-        };
+    fn load_imm8(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let value: u8 = disasm.get_byte()?;
 
-        let params: String = params.join(",");
-        disasm.trace.push(format!("{} entry({ptr}), {context}, params({params})", OpCodes::JumpFrame.as_str()));
+        disasm.record(OpCodes::LoadImm8, vec![Operand::Reg(reg), Operand::Byte(value)]);
+        Ok(())
     }
 
-    fn new_function(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let func_entry: u32 = disasm.get_int24();
-        let args_len: u8 = disasm.get_byte();
+    fn jump_frame(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let ptr: u32 = disasm.get_u32_be()?;
+        let context: u8 = disasm.get_byte()?;
+        let params_count: u8 = disasm.get_byte()?;
+        let params: Vec<u8> = read_regs(disasm, params_count)?;
 
-        let mut args: Vec<String> = Vec::new();
+        disasm.record(OpCodes::JumpFrame, vec![Operand::Target(ptr), Operand::Byte(context), Operand::RegList(params)]);
+        Ok(())
+    }
 
-        for _ in 0..args_len {
-            let arg_reg = disasm.get_byte();
-            args.push(format!("reg{}", arg_reg))
-        };
+    fn new_function(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let func_entry: u32 = disasm.get_u32_be()?;
+        let args_len: u8 = disasm.get_byte()?;
+        let args: Vec<u8> = read_regs(disasm, args_len)?;
 
-        let args: String = args.join(",");
-        disasm.trace.push(format!("{} entry({func_entry}), args({args})", OpCodes::NewFunction.as_str()));
+        disasm.record(OpCodes::NewFunction, vec![Operand::Reg(reg), Operand::Target(func_entry), Operand::RegList(args)]);
+        Ok(())
     }
 
-    fn less_than(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn less_than(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} < reg{right_reg} -> reg{reg}", OpCodes::LessThan.as_str()));
+        disasm.record(OpCodes::LessThan, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn jump_if_false(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let ptr: u32 = disasm.get_int24();
+    fn less_than_alt(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{reg}, entry({ptr})", OpCodes::JumpIfFalse.as_str()));        
+        disasm.record(OpCodes::LessThanAlt, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn set_property(disasm: &mut Disassembler) {
-        let obj_reg = disasm.get_byte();
-        let prop_reg = disasm.get_byte();
-        let val_reg = disasm.get_byte();
+    fn jump_if_false(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let ptr: u32 = disasm.get_u32_be()?;
 
-        let val: String = if &disasm.registers[val_reg as usize] != "_free_reg_" {
-            disasm.registers[val_reg as usize].clone()
-        } else {
-            format!("reg{}", val_reg)
-        };
+        disasm.record(OpCodes::JumpIfFalse, vec![Operand::Reg(reg), Operand::Target(ptr)]);
+        Ok(())
+    }
 
-        let prop: String = if &disasm.registers[prop_reg as usize] != "_free_reg_" {
-            disasm.registers[prop_reg as usize].clone()
-        } else {
-            format!("reg{}", prop_reg)
-        };
+    fn set_property(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let obj_reg = disasm.get_byte()?;
+        let prop_reg = disasm.get_byte()?;
+        let val_reg = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{obj_reg}[{prop}] = {val}", OpCodes::SetProperty.as_str()));        
+        disasm.record(OpCodes::SetProperty, vec![Operand::Reg(obj_reg), Operand::Reg(prop_reg), Operand::Reg(val_reg)]);
+        Ok(())
     }
 
-    fn add_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn add_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} + reg{right_reg} -> reg{reg}", OpCodes::Add.as_str()));
+        disasm.record(OpCodes::Add, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn jump(disasm: &mut Disassembler) {
-        let ptr: u32 = disasm.get_int24();
-        disasm.trace.push(format!("{} {ptr}", OpCodes::Jump.as_str()));
+    fn jump(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let ptr: u32 = disasm.get_u32_be()?;
+        disasm.record(OpCodes::Jump, vec![Operand::Target(ptr)]);
+        Ok(())
     }
 
-    fn halt(disasm: &mut Disassembler) {
-        disasm.trace.push(format!("{}", OpCodes::Halt.as_str()));
+    fn halt(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        disasm.record(OpCodes::Halt, Vec::new());
+        Ok(())
     }
 
-    fn shl_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn shl_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} << reg{right_reg} -> reg{reg}", OpCodes::Shl.as_str()));
+        disasm.record(OpCodes::Shl, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn function_ret(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let count = disasm.get_byte();
-        let mut list: Vec<String> = Vec::new();
+    fn function_ret(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let count = disasm.get_byte()?;
+        let list: Vec<u8> = read_regs(disasm, count)?;
 
-        for _ in 0..count {
-            let ret_reg = disasm.get_byte();
-            list.push(format!("reg{}", ret_reg))
-        };
+        disasm.record(OpCodes::Ret, vec![Operand::Reg(reg), Operand::RegList(list)]);
+        Ok(())
+    }
+
+    fn equal_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
+
+        disasm.record(OpCodes::Equal, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
+    }
+
+    fn xor_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        let list: String = list.join(",");
-        disasm.trace.push(format!("{} {reg} [{list}]", OpCodes::Ret.as_str()));
+        disasm.record(OpCodes::Xor, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn equal_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn load_double(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let val: f64 = disasm.read_double()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} == reg{right_reg} -> reg{reg}", OpCodes::Equal.as_str()));
+        disasm.record(OpCodes::LoadDouble, vec![Operand::Reg(reg), Operand::Double(val)]);
+        Ok(())
     }
 
-    fn xor_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn ushr_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} ^ reg{right_reg} -> reg{reg}", OpCodes::Xor.as_str()));
+        disasm.record(OpCodes::Ushr, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn load_double(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let val: f64 = disasm.read_double();
+    fn shr_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} {val} -> reg{reg}", OpCodes::LoadDouble.as_str()));
+        disasm.record(OpCodes::Shr, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn ushr_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn and_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} >>> reg{right_reg} -> reg{reg}", OpCodes::Ushr.as_str()));
+        disasm.record(OpCodes::And, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn shr_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn mod_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} >> reg{right_reg} -> reg{reg}", OpCodes::Shr.as_str()));
+        disasm.record(OpCodes::Mod, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn and_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn lte_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} & reg{right_reg} -> reg{reg}", OpCodes::And.as_str()));
+        disasm.record(OpCodes::Lte, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn mod_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn lte_alt(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} % reg{right_reg} -> reg{reg}", OpCodes::Mod.as_str()));
+        disasm.record(OpCodes::LteAlt, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn lte_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn notequal_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} <= reg{right_reg} -> reg{reg}", OpCodes::Lte.as_str()));
+        disasm.record(OpCodes::NotEqual, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn notequal_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn jump_if_true(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let ptr: u32 = disasm.get_u32_be()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} != reg{right_reg} -> reg{reg}", OpCodes::NotEqual.as_str()));
+        disasm.record(OpCodes::JumpIfTrue, vec![Operand::Reg(reg), Operand::Target(ptr)]);
+        Ok(())
     }
 
-    fn jump_if_true(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let ptr: u32 = disasm.get_int24();
+    fn try_catch(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let catch_offset = disasm.get_u32_be()?;
+        let finally_offset = disasm.get_u32_be()?;
+        let continue_offset = disasm.get_u32_be()?;
 
-        disasm.trace.push(format!("{} reg{reg}, entry({ptr})", OpCodes::JumpIfTrue.as_str()));        
+        disasm.record(
+            OpCodes::TryCatch,
+            vec![Operand::Reg(reg), Operand::Target(catch_offset), Operand::Target(finally_offset), Operand::Target(continue_offset)]
+        );
+        Ok(())
     }
 
-    fn try_catch(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let catch_offset = disasm.get_int24();
-        let finally_offset = disasm.get_int24();
-        let continue_offset = disasm.get_int24();
+    fn strict_equal_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} [{catch_offset}, {finally_offset}, {continue_offset}] -> reg{reg}", OpCodes::TryCatch.as_str()));   
+        disasm.record(OpCodes::StrictEqual, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn strict_equal_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn strict_notequal_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
+        let left_reg: u8 = disasm.get_byte()?;
+        let right_reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} === reg{right_reg} -> reg{reg}", OpCodes::StrictEqual.as_str()));
+        disasm.record(OpCodes::StrictNotEqual, vec![Operand::Reg(reg), Operand::Reg(left_reg), Operand::Reg(right_reg)]);
+        Ok(())
     }
 
-    fn strict_notequal_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-        let left_reg: u8 = disasm.get_byte();
-        let right_reg: u8 = disasm.get_byte();
+    fn throw_op(disasm: &mut Disassembler) -> Result<(), DisassemblerError> {
+        let reg: u8 = disasm.get_byte()?;
 
-        disasm.trace.push(format!("{} reg{left_reg} !== reg{right_reg} -> reg{reg}", OpCodes::StrictNotEqual.as_str()));
+        disasm.record(OpCodes::Throw, vec![Operand::Reg(reg)]);
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::instruction::{Instruction, TargetFormat};
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::{decode, run};
+
+    #[test]
+    fn mov_imm32_reads_all_four_immediate_bytes() {
+        let listing: Listing = decode(vec![241, 7, 0xfe, 0xdc, 0xba, 0x98]);
+        let instruction: &Instruction = &listing.instructions[0];
+        assert_eq!(instruction.length, 6);
+        assert!(matches!(instruction.operands[..], [Operand::Reg(7), Operand::Int(0xfedcba98)]));
+        assert_eq!(instruction.text(TargetFormat::Hex, false), "MOV IMM32 4275878552 -> reg7");
+    }
+
+    #[test]
+    fn load_imm8_takes_a_one_byte_immediate() {
+        let listing: Listing = decode(vec![181, 3, 0xff, 181, 4, 0]);
+        let layout: Vec<(usize, usize)> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction.length)).collect();
+        assert_eq!(layout, [(0, 3), (3, 3)]);
+        assert!(matches!(listing.instructions[0].operands[..], [Operand::Reg(3), Operand::Byte(255)]));
+        assert_eq!(listing.instructions[1].text(TargetFormat::Hex, false), "LOAD IMM8 0 -> reg4");
+    }
+
+    #[test]
+    fn old_load_imm24_name_maps_to_load_imm8() {
+        assert_eq!("load_imm24".parse::<OpCodes>(), Ok(OpCodes::LoadImm8));
+        assert_eq!("LOAD IMM24".parse::<OpCodes>(), Ok(OpCodes::LoadImm8));
+    }
+
+    #[test]
+    fn alternate_comparison_bytes_have_their_own_opcodes() {
+        let listing: Listing = decode(vec![112, 1, 2, 3, 20, 1, 2, 3, 214, 4, 5, 6, 247, 4, 5, 6]);
+        let decoded: Vec<(OpCodes, String)> = listing.instructions.iter().map(|instruction| (instruction.opcode, instruction.text(TargetFormat::Hex, false))).collect();
+        assert_eq!(
+            decoded,
+            [
+                (OpCodes::LessThanAlt, "LESS THAN ALT reg2 < reg3 -> reg1".to_string()),
+                (OpCodes::LessThan, "LESS THAN reg2 < reg3 -> reg1".to_string()),
+                (OpCodes::LteAlt, "LTE ALT reg5 <= reg6 -> reg4".to_string()),
+                (OpCodes::Lte, "LTE reg5 <= reg6 -> reg4".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_table_dispatches_by_byte_and_dumps_in_byte_order() {
+        let table: OpcodeTable = OpcodeTable::from_pairs(&[(200, "halt"), (0, "load_imm8"), (17, "ret")]).unwrap();
+        assert_eq!(table.dump_table(), [(0, OpCodes::LoadImm8), (17, OpCodes::Ret), (200, OpCodes::Halt)]);
+        assert_eq!(table.len(), 3);
+
+        let mut disasm: Disassembler = Disassembler::from_bytes(vec![0, 1, 7, 200]);
+        disasm.set_table(table);
+        let listing: Listing = run(&mut disasm);
+        assert_eq!(listing.instructions.iter().map(|instruction| instruction.opcode).collect::<Vec<OpCodes>>(), [OpCodes::LoadImm8, OpCodes::Halt]);
+    }
+
+    #[test]
+    fn merge_overrides_only_the_mapped_bytes() {
+        let overrides: OpcodeTable = OpcodeTable::from_pairs(&[(166, "throw"), (1, "halt")]).unwrap();
+        let merged: OpcodeTable = OpcodeTable::builtin().merge(&overrides);
+        assert_eq!(merged.get(166).map(|(opcode, _)| opcode), Some(OpCodes::Throw));
+        assert_eq!(merged.get(1).map(|(opcode, _)| opcode), Some(OpCodes::Halt));
+        assert_eq!(merged.get(181).map(|(opcode, _)| opcode), Some(OpCodes::LoadImm8));
+        assert_eq!(merged.len(), OpcodeTable::builtin().len() + 1);
+        assert_eq!(merged.name, "default+custom");
+    }
+
+    #[test]
+    fn duplicate_and_unknown_mappings_are_rejected() {
+        assert!(matches!(OpcodeTable::from_pairs(&[(1, "halt"), (1, "ret")]), Err(DisassemblerError::InvalidConfig(message)) if message == "opcode 1 is mapped more than once"));
+        assert!(matches!(OpcodeTable::from_pairs(&[(1, "nop")]), Err(DisassemblerError::InvalidConfig(message)) if message.starts_with("unknown handler 'nop' for opcode 1")));
     }
 
-    fn throw_op(disasm: &mut Disassembler) {
-        let reg: u8 = disasm.get_byte();
-    
-        disasm.trace.push(format!("{} {reg}", OpCodes::Throw.as_str()));
+    #[test]
+    fn old_mov_imm24_name_maps_to_mov_imm32() {
+        let table: OpcodeTable = OpcodeTable::from_pairs(&[(1, "mov_imm24")]).unwrap();
+        assert_eq!(table.dump_table(), [(1, OpCodes::MovImm32)]);
     }
-}
\ No newline at end of file
+}
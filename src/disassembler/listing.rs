@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::disassembler::exceptions::TryRegions;
+use crate::disassembler::functions::Functions;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Access, Operand};
+use crate::disassembler::reachability::Reachability;
+use crate::disassembler::symbols::Symbols;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Termination {
+    /// The last instruction ended exactly at the end of the range.
+    Boundary { offset: usize },
+    /// The last instruction decoded was HALT.
+    Halt { offset: usize, remaining: usize },
+    /// Decoding stopped on an instruction boundary before the end.
+    Stopped { offset: usize, remaining: usize },
+    /// The range ends partway through the instruction at `offset`.
+    MidInstruction { offset: usize, remaining: usize }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Boundary { offset } => write!(f, "ended on an instruction boundary at offset {offset:#06x}"),
+            Termination::Halt { offset, remaining: 0 } => write!(f, "ended at HALT at offset {offset:#06x}"),
+            Termination::Halt { offset, remaining } => write!(f, "ended at HALT at offset {offset:#06x}, {remaining} bytes left"),
+            Termination::Stopped { offset, remaining } => write!(f, "stopped at offset {offset:#06x}, {remaining} bytes left"),
+            Termination::MidInstruction { offset, remaining } => {
+                write!(f, "ended mid-instruction at offset {offset:#06x}, {remaining} bytes left")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TargetWarning {
+    /// `target` is at or past the end of the bytecode.
+    OutOfRange { offset: usize, target: u32 },
+    /// `target` lands inside the instruction that starts at `instruction`.
+    MidInstruction { offset: usize, target: u32, instruction: usize }
+}
+
+impl TargetWarning {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TargetWarning::OutOfRange { .. } => "out_of_range",
+            TargetWarning::MidInstruction { .. } => "mid_instruction"
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        match self {
+            TargetWarning::OutOfRange { offset, .. } | TargetWarning::MidInstruction { offset, .. } => *offset
+        }
+    }
+
+    pub fn target(&self) -> u32 {
+        match self {
+            TargetWarning::OutOfRange { target, .. } | TargetWarning::MidInstruction { target, .. } => *target
+        }
+    }
+}
+
+impl fmt::Display for TargetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetWarning::OutOfRange { offset, target } => {
+                write!(f, "instruction at offset {offset:#06x} targets {target:#x}, past the end of the bytecode")
+            }
+            TargetWarning::MidInstruction { offset, target, instruction } => {
+                write!(f, "instruction at offset {offset:#06x} targets {target:#x}, inside the instruction at offset {instruction:#06x}")
+            }
+        }
+    }
+}
+
+/// A JUMP FRAME / RET pairing that does not add up; see `Instruction::depth`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameWarning {
+    /// A RET at depth 0, with no JUMP FRAME to close.
+    UnmatchedRet { offset: usize },
+    /// HALT while `depth` JUMP FRAMEs are still open.
+    OpenAtHalt { offset: usize, depth: usize },
+    /// The listing ends at `offset` with `depth` JUMP FRAMEs still open.
+    OpenAtEnd { offset: usize, depth: usize }
+}
+
+impl FrameWarning {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FrameWarning::UnmatchedRet { .. } => "unmatched_ret",
+            FrameWarning::OpenAtHalt { .. } => "open_frames",
+            FrameWarning::OpenAtEnd { .. } => "open_frames_at_end"
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        match self {
+            FrameWarning::UnmatchedRet { offset } | FrameWarning::OpenAtHalt { offset, .. } | FrameWarning::OpenAtEnd { offset, .. } => *offset
+        }
+    }
+}
+
+impl fmt::Display for FrameWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameWarning::UnmatchedRet { offset } => write!(f, "RET at offset {offset:#06x} has no open JUMP FRAME to return from"),
+            FrameWarning::OpenAtHalt { offset, depth: 1 } => write!(f, "HALT at offset {offset:#06x} with 1 JUMP FRAME still open"),
+            FrameWarning::OpenAtHalt { offset, depth } => write!(f, "HALT at offset {offset:#06x} with {depth} JUMP FRAMEs still open"),
+            FrameWarning::OpenAtEnd { offset, depth: 1 } => write!(f, "listing ends at offset {offset:#06x} with 1 JUMP FRAME still open"),
+            FrameWarning::OpenAtEnd { offset, depth } => write!(f, "listing ends at offset {offset:#06x} with {depth} JUMP FRAMEs still open")
+        }
+    }
+}
+
+/// Sets `Instruction::depth` in decoding order, starting from the depth the first instruction was decoded
+/// at. A JUMP FRAME's body is laid out elsewhere, so the count is reset to 1 at every function entry, the
+/// frame its caller opened, and to 0 after HALT; otherwise a linear listing only ever goes deeper.
+fn frame_depths(instructions: &mut [Instruction], entry_points: &BTreeSet<usize>) -> Vec<FrameWarning> {
+    let mut warnings: Vec<FrameWarning> = Vec::new();
+    let mut depth: usize = instructions.first().map_or(0, |instruction| instruction.depth);
+    for (index, instruction) in instructions.iter_mut().enumerate() {
+        if index > 0 && entry_points.contains(&instruction.offset) {
+            depth = 1;
+        }
+        instruction.depth = depth;
+        match instruction.opcode {
+            OpCodes::JumpFrame => depth += 1,
+            OpCodes::Ret if depth == 0 => warnings.push(FrameWarning::UnmatchedRet { offset: instruction.offset }),
+            OpCodes::Ret => depth -= 1,
+            OpCodes::Halt => {
+                if depth > 0 {
+                    warnings.push(FrameWarning::OpenAtHalt { offset: instruction.offset, depth });
+                }
+                depth = 0;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0
+        && let Some(last) = instructions.last()
+    {
+        warnings.push(FrameWarning::OpenAtEnd { offset: last.end(), depth });
+    }
+    warnings
+}
+
+fn target_warnings(input_length: usize, instructions: &[Instruction]) -> Vec<TargetWarning> {
+    let ends: BTreeMap<usize, usize> = instructions.iter().map(|instruction| (instruction.offset, instruction.end())).collect();
+    let mut warnings: Vec<TargetWarning> = Vec::new();
+
+    for instruction in instructions {
+        for target in instruction.targets() {
+            let offset: usize = instruction.offset;
+            if target as usize >= input_length {
+                warnings.push(TargetWarning::OutOfRange { offset, target });
+            } else if let Some((&start, &end)) = ends.range(..=target as usize).next_back()
+                && start < target as usize
+                && (target as usize) < end
+            {
+                warnings.push(TargetWarning::MidInstruction { offset, target, instruction: start });
+            }
+        }
+    }
+    warnings
+}
+
+/// An instruction reading or writing a register, from `Listing::xrefs`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterXref {
+    pub offset: usize,
+    pub opcode: OpCodes,
+    pub access: Access
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Listing {
+    /// Length in bytes of the decoded bytecode.
+    pub input_length: usize,
+    /// XOR key used to decode string constants.
+    pub string_key: u8,
+    /// Number of entries in `instructions`.
+    pub instruction_count: usize,
+    /// Decoded instructions in the order they were reached.
+    pub instructions: Vec<Instruction>,
+    /// Every offset targeted by a jump, call, function or try/catch operand, sorted.
+    pub labels: Vec<usize>,
+    /// `(offset, value)` of every string loaded by NEW VALUE.
+    pub strings: Vec<(usize, String)>,
+    /// Function entries named by NEW FUNCTION and JUMP FRAME, sorted.
+    pub entry_points: Vec<usize>,
+    /// `(start, end)` byte ranges skipped as unknown opcodes in lenient mode.
+    pub unknown_regions: Vec<(usize, usize)>,
+    /// Targets past the end of the bytecode or inside a decoded instruction.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub target_warnings: Vec<TargetWarning>,
+    /// RETs without a JUMP FRAME, and HALTs or the end of the listing with frames still open.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frame_warnings: Vec<FrameWarning>,
+    /// How decoding ended; only set on listings returned by `execute`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub termination: Option<Termination>
+}
+
+impl Listing {
+    pub fn new(input_length: usize, string_key: u8, mut instructions: Vec<Instruction>) -> Self {
+        let mut labels: BTreeSet<usize> = BTreeSet::new();
+        let mut entry_points: BTreeSet<usize> = BTreeSet::new();
+        let mut strings: Vec<(usize, String)> = Vec::new();
+        let mut unknown_regions: Vec<(usize, usize)> = Vec::new();
+
+        for instruction in &instructions {
+            labels.extend(instruction.targets().map(|target| target as usize));
+            match (instruction.opcode, instruction.operands.as_slice()) {
+                (OpCodes::NewFunction, [_, Operand::Target(entry), ..]) | (OpCodes::JumpFrame, [Operand::Target(entry), ..]) => {
+                    entry_points.insert(*entry as usize);
+                }
+                (OpCodes::NewValue, [_, Operand::Str(value, _)]) => strings.push((instruction.offset, value.clone())),
+                (OpCodes::Db, _) => match unknown_regions.last_mut() {
+                    Some((_, end)) if *end == instruction.offset => *end = instruction.end(),
+                    _ => unknown_regions.push((instruction.offset, instruction.end()))
+                },
+                _ => {}
+            }
+        }
+
+        let target_warnings: Vec<TargetWarning> = target_warnings(input_length, &instructions);
+        let frame_warnings: Vec<FrameWarning> = frame_depths(&mut instructions, &entry_points);
+        Self {
+            input_length,
+            string_key,
+            instruction_count: instructions.len(),
+            instructions,
+            labels: labels.into_iter().collect(),
+            strings,
+            entry_points: entry_points.into_iter().collect(),
+            unknown_regions,
+            target_warnings,
+            frame_warnings,
+            termination: None
+        }
+    }
+
+    /// Names the targets in `labels` that start an instruction of this listing.
+    pub fn symbols(&self) -> Symbols {
+        Symbols::new(&self.instructions)
+    }
+
+    /// Function extents found from the NEW FUNCTION and JUMP FRAME entries in this listing.
+    pub fn functions(&self) -> Functions {
+        Functions::new(self)
+    }
+
+    /// Dead code: instructions no path from the first instruction or a function entry reaches.
+    pub fn reachability(&self) -> Reachability {
+        Reachability::new(self)
+    }
+
+    /// The try body, handlers and continuation of every TRY CATCH, with their nesting.
+    pub fn try_regions(&self) -> TryRegions {
+        TryRegions::new(self)
+    }
+
+    pub fn instruction_at(&self, offset: usize) -> Option<&Instruction> {
+        self.instructions.iter().find(|instruction| instruction.offset == offset)
+    }
+
+    pub fn xrefs_to(&self, offset: usize) -> Vec<&Instruction> {
+        self.instructions
+            .iter()
+            .filter(|instruction| instruction.targets().any(|target| target as usize == offset))
+            .collect()
+    }
+
+    /// Every instruction that writes or reads `reg`, in listing order. An instruction using the
+    /// register in several ways (`ADD reg1 + reg1 -> reg1`) appears once per kind of access.
+    pub fn xrefs(&self, reg: u8) -> Vec<RegisterXref> {
+        self.collect_xrefs(Some(reg)).remove(&reg).unwrap_or_default()
+    }
+
+    /// `xrefs` for every register the listing touches.
+    pub fn register_xrefs(&self) -> BTreeMap<u8, Vec<RegisterXref>> {
+        self.collect_xrefs(None)
+    }
+
+    fn collect_xrefs(&self, only: Option<u8>) -> BTreeMap<u8, Vec<RegisterXref>> {
+        let mut xrefs: BTreeMap<u8, Vec<RegisterXref>> = BTreeMap::new();
+        for instruction in &self.instructions {
+            let mut accesses: Vec<(u8, Access)> = instruction.accesses().filter(|(reg, _)| only.is_none_or(|only| *reg == only)).collect();
+            accesses.sort();
+            accesses.dedup();
+            for (reg, access) in accesses {
+                xrefs.entry(reg).or_default().push(RegisterXref { offset: instruction.offset, opcode: instruction.opcode, access });
+            }
+        }
+        xrefs
+    }
+
+    pub fn strings_matching(&self, pattern: &str) -> Vec<(usize, &str)> {
+        self.strings
+            .iter()
+            .filter(|(_, value)| value.contains(pattern))
+            .map(|(offset, value)| (*offset, value.as_str()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    fn frames(bytes: Vec<u8>) -> (Vec<(usize, usize)>, Vec<FrameWarning>) {
+        let listing: Listing = decode(bytes);
+        let depths: Vec<(usize, usize)> = listing.instructions.iter().map(|instruction| (instruction.offset, instruction.depth)).collect();
+        (depths, listing.frame_warnings)
+    }
+
+    #[test]
+    fn unbalanced_ret_stays_at_depth_zero() {
+        // RET reg1 []; HALT.
+        let (depths, warnings): (Vec<(usize, usize)>, Vec<FrameWarning>) = frames(vec![17, 1, 0, 166]);
+        assert_eq!(depths, [(0, 0), (3, 0)]);
+        assert_eq!(warnings, [FrameWarning::UnmatchedRet { offset: 0 }]);
+        assert_eq!(warnings[0].to_string(), "RET at offset 0x0000 has no open JUMP FRAME to return from");
+    }
+
+    #[test]
+    fn unbalanced_jump_frames_are_reported_at_the_end() {
+        // JUMP FRAME 0xe twice, then LOAD IMM8 7 -> reg1 at the entry 0xe, with no RET or HALT.
+        let jump_frame: [u8; 7] = [49, 0, 0, 0, 14, 0, 0];
+        let bytes: Vec<u8> = [&jump_frame[..], &jump_frame, &[181, 1, 7]].concat();
+        let (depths, warnings): (Vec<(usize, usize)>, Vec<FrameWarning>) = frames(bytes);
+        assert_eq!(depths, [(0, 0), (7, 1), (14, 1)]);
+        assert_eq!(warnings, [FrameWarning::OpenAtEnd { offset: 17, depth: 1 }]);
+    }
+
+    #[test]
+    fn depth_resets_after_halt_and_at_function_entries() {
+        // JUMP FRAME 0xb; HALT; LOAD IMM8 7 -> reg1; 0xb: LOAD IMM8 7 -> reg1; RET reg1 [].
+        let bytes: Vec<u8> = vec![49, 0, 0, 0, 11, 0, 0, 166, 181, 1, 7, 181, 1, 7, 17, 1, 0];
+        let (depths, warnings): (Vec<(usize, usize)>, Vec<FrameWarning>) = frames(bytes);
+        assert_eq!(depths, [(0, 0), (7, 1), (8, 0), (11, 1), (14, 1)]);
+        assert_eq!(warnings, [FrameWarning::OpenAtHalt { offset: 7, depth: 1 }]);
+    }
+
+    #[test]
+    fn xrefs_list_each_kind_of_access_once() {
+        // LOAD IMM8 1 -> reg1; ADD reg1 + reg1 -> reg1; SET PROPERTY reg1[reg2] = reg3; NEW FUNCTION reg4 for 19
+        // with (reg1); HALT at 19.
+        let bytes: Vec<u8> = vec![181, 1, 1, 243, 1, 1, 1, 99, 1, 2, 3, 171, 4, 0, 0, 0, 19, 1, 1, 166];
+        let listing: Listing = decode(bytes);
+        let xrefs: Vec<(usize, Access)> = listing.xrefs(1).into_iter().map(|xref| (xref.offset, xref.access)).collect();
+        // NEW FUNCTION's list names parameter slots, so it does not read reg1.
+        assert_eq!(xrefs, [(0, Access::Def), (3, Access::Def), (3, Access::Use), (7, Access::Modify)]);
+        assert_eq!(listing.register_xrefs().keys().copied().collect::<Vec<u8>>(), [1, 2, 3, 4]);
+        assert_eq!(listing.xrefs_to(19).iter().map(|instruction| instruction.offset).collect::<Vec<usize>>(), [11]);
+    }
+}
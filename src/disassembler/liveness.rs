@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::disassembler::cfg::{BlockId, Cfg};
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Access, Operand};
+
+/// A set of registers, one bit for each of the 256.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegSet([u64; 4]);
+
+impl RegSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, reg: u8) {
+        self.0[reg as usize / 64] |= 1 << (reg % 64);
+    }
+
+    pub fn remove(&mut self, reg: u8) {
+        self.0[reg as usize / 64] &= !(1 << (reg % 64));
+    }
+
+    pub fn contains(&self, reg: u8) -> bool {
+        self.0[reg as usize / 64] & (1 << (reg % 64)) != 0
+    }
+
+    pub fn union(&self, other: &RegSet) -> RegSet {
+        RegSet(std::array::from_fn(|word| self.0[word] | other.0[word]))
+    }
+
+    pub fn difference(&self, other: &RegSet) -> RegSet {
+        RegSet(std::array::from_fn(|word| self.0[word] & !other.0[word]))
+    }
+
+    /// In ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|&reg| self.contains(reg))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+}
+
+impl FromIterator<u8> for RegSet {
+    fn from_iter<I: IntoIterator<Item = u8>>(regs: I) -> Self {
+        let mut set: RegSet = RegSet::new();
+        for reg in regs {
+            set.insert(reg);
+        }
+        set
+    }
+}
+
+impl fmt::Display for RegSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regs: Vec<String> = self.iter().map(|reg| format!("reg{reg}")).collect();
+        write!(f, "{{{}}}", regs.join(", "))
+    }
+}
+
+/// Registers the instruction reads (including SET PROPERTY's object) and the ones it overwrites.
+fn uses_defs(instruction: &Instruction) -> (RegSet, RegSet) {
+    let (mut uses, mut defs): (RegSet, RegSet) = (RegSet::new(), RegSet::new());
+    for (reg, access) in instruction.accesses() {
+        if access.reads() {
+            uses.insert(reg);
+        }
+        if access == Access::Def {
+            defs.insert(reg);
+        }
+    }
+    (uses, defs)
+}
+
+/// Registers live at each block and instruction of a `Cfg`: read on some path from there before being
+/// overwritten. Reads and writes come from `Instruction::accesses`.
+///
+/// Values flow over every CFG edge, so a register the callee of a JUMP FRAME or NEW FUNCTION reads is
+/// live at the call. RET has no successors: what a callee writes is not assumed to reach its caller,
+/// other than through JUMP FRAME's result register. An exception can leave a TRY CATCH body at any
+/// instruction, so throughout the blocks between a TRY CATCH and its catch offset, whatever its catch
+/// and finally handlers read stays live.
+/// CALL FUNCTION and CALL APPLY have no edge to their callee; a closure reading registers it did not get
+/// as arguments is not seen.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Liveness {
+    live_in: Vec<RegSet>,
+    live_out: Vec<RegSet>,
+    /// Instruction offset to the registers live just before and just after it.
+    instructions: BTreeMap<usize, (RegSet, RegSet)>
+}
+
+impl Liveness {
+    pub fn new(cfg: &Cfg) -> Self {
+        let blocks: usize = cfg.len();
+        let mut gen_kill: Vec<(RegSet, RegSet)> = Vec::with_capacity(blocks);
+        for block in cfg.blocks() {
+            let (mut used, mut defined): (RegSet, RegSet) = (RegSet::new(), RegSet::new());
+            for instruction in &block.instructions {
+                let (uses, defs): (RegSet, RegSet) = uses_defs(instruction);
+                used = used.union(&uses.difference(&defined));
+                defined = defined.union(&defs);
+            }
+            gen_kill.push((used, defined));
+        }
+
+        // Blocks inside a try body, with the handler blocks an exception there can reach.
+        let mut handlers: Vec<Vec<BlockId>> = vec![Vec::new(); blocks];
+        for block in cfg.blocks() {
+            let last: &Instruction = block.last();
+            if let (OpCodes::TryCatch, [_, Operand::Target(catch), Operand::Target(finally), _]) = (last.opcode, last.operands.as_slice()) {
+                let targets: Vec<BlockId> = [*catch, *finally].iter().filter_map(|&target| cfg.block_at(target as usize)).collect();
+                for body in cfg.blocks().iter().filter(|body| body.start >= block.end && body.start < *catch as usize) {
+                    handlers[body.id].extend(&targets);
+                }
+            }
+        }
+
+        let mut live_in: Vec<RegSet> = vec![RegSet::new(); blocks];
+        let mut live_out: Vec<RegSet> = vec![RegSet::new(); blocks];
+        let mut changed: bool = true;
+        while changed {
+            changed = false;
+            for block in (0..blocks).rev() {
+                let exceptional: RegSet = handlers[block].iter().fold(RegSet::new(), |live, &handler| live.union(&live_in[handler]));
+                let out: RegSet = cfg.successors(block).fold(exceptional, |out, edge| out.union(&live_in[edge.to]));
+                let (used, defined): &(RegSet, RegSet) = &gen_kill[block];
+                let input: RegSet = used.union(&out.difference(defined)).union(&exceptional);
+                if input != live_in[block] {
+                    live_in[block] = input;
+                    changed = true;
+                }
+                live_out[block] = out;
+            }
+        }
+
+        let mut instructions: BTreeMap<usize, (RegSet, RegSet)> = BTreeMap::new();
+        for block in cfg.blocks() {
+            let exceptional: RegSet = handlers[block.id].iter().fold(RegSet::new(), |live, &handler| live.union(&live_in[handler]));
+            let mut live: RegSet = live_out[block.id];
+            for instruction in block.instructions.iter().rev() {
+                let (uses, defs): (RegSet, RegSet) = uses_defs(instruction);
+                let before: RegSet = uses.union(&live.difference(&defs)).union(&exceptional);
+                instructions.insert(instruction.offset, (before, live));
+                live = before;
+            }
+        }
+        Self { live_in, live_out, instructions }
+    }
+
+    /// Registers live on entry to `block`.
+    pub fn live_in(&self, block: BlockId) -> RegSet {
+        self.live_in.get(block).copied().unwrap_or_default()
+    }
+
+    /// Registers live on exit from `block`.
+    pub fn live_out(&self, block: BlockId) -> RegSet {
+        self.live_out.get(block).copied().unwrap_or_default()
+    }
+
+    /// Registers live just before the instruction at `offset` runs.
+    pub fn live_before(&self, offset: usize) -> Option<RegSet> {
+        self.instructions.get(&offset).map(|(before, _)| *before)
+    }
+
+    /// Registers live just after the instruction at `offset`, i.e. whose current value may still be read.
+    pub fn live_after(&self, offset: usize) -> Option<RegSet> {
+        self.instructions.get(&offset).map(|(_, after)| *after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::decode;
+
+    /// LOAD IMM8 reg1; LOAD IMM8 reg2; JUMP IF FALSE reg1 -> 0x10; ADD reg1 + reg2 -> reg3; 0x10: RET reg1 [].
+    const PROGRAM: [u8; 19] = [181, 1, 7, 181, 2, 1, 39, 1, 0, 0, 0, 16, 243, 3, 1, 2, 17, 1, 0];
+
+    fn liveness() -> (Cfg, Liveness) {
+        let listing: Listing = decode(PROGRAM.to_vec());
+        let cfg: Cfg = Cfg::new(&listing);
+        let liveness: Liveness = Liveness::new(&cfg);
+        (cfg, liveness)
+    }
+
+    fn regs(set: Option<RegSet>) -> Vec<u8> {
+        set.expect("an instruction starts there").iter().collect()
+    }
+
+    #[test]
+    fn registers_stay_live_until_their_last_read() {
+        let (_, liveness): (Cfg, Liveness) = liveness();
+        assert_eq!(regs(liveness.live_before(0)), Vec::<u8>::new());
+        assert_eq!(regs(liveness.live_after(0)), [1]);
+        assert_eq!(regs(liveness.live_after(3)), [1, 2]);
+        assert_eq!(regs(liveness.live_before(12)), [1, 2]);
+        assert_eq!(liveness.live_before(13), None);
+    }
+
+    #[test]
+    fn a_write_nobody_reads_is_dead() {
+        let (_, liveness): (Cfg, Liveness) = liveness();
+        assert_eq!(regs(liveness.live_after(12)), [1]);
+        assert_eq!(regs(liveness.live_after(16)), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn block_sets_join_over_both_branch_sides() {
+        let (cfg, liveness): (Cfg, Liveness) = liveness();
+        assert_eq!(cfg.len(), 3);
+        assert_eq!(liveness.live_in(0).to_string(), "{}");
+        assert_eq!(liveness.live_out(0).to_string(), "{reg1, reg2}");
+        assert_eq!(liveness.live_in(1).to_string(), "{reg1, reg2}");
+        assert_eq!(liveness.live_in(2).to_string(), "{reg1}");
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::cfg::{BlockId, Cfg, EdgeKind};
+
+/// A natural loop: a header block and every block that can reach one of its back-edges without
+/// going through the header. Back-edges that share a header make one loop.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Loop {
+    /// Numbered from 1 in header order, as in `L3`.
+    pub id: usize,
+    pub header: BlockId,
+    /// Offset of the header's first instruction.
+    pub offset: usize,
+    /// Every block of the loop, header included, in offset order.
+    pub body_blocks: Vec<BlockId>,
+    /// Blocks outside the loop that the body branches or falls through to.
+    pub exits: Vec<BlockId>,
+    /// Blocks that end with a back-edge to the header.
+    pub latches: Vec<BlockId>,
+    /// Index in `Loops::loops` of the innermost loop around this one.
+    pub parent: Option<usize>,
+    /// 1 for a loop that is not inside another.
+    pub depth: usize,
+    /// Bytes and instructions in the body.
+    pub size: usize,
+    pub instructions: usize
+}
+
+impl Loop {
+    pub fn name(&self) -> String {
+        format!("L{}", self.id)
+    }
+}
+
+/// An edge back to a block that does not dominate its source: the cycle it closes can be entered at
+/// more than one block, so it is not a natural loop.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IrreducibleEdge {
+    pub from: BlockId,
+    pub to: BlockId,
+    /// Offset of the instruction that ends `from`.
+    pub offset: usize,
+    /// Offset of the first instruction of `to`.
+    pub target: usize
+}
+
+/// Loops of a `Cfg`, found from dominators. Only fall-through, branch and TRY CATCH edges are followed;
+/// a call is not a loop, even when it recurses. Dominators are computed from the first block, every
+/// function entry and every block nothing jumps to, so a loop in unreachable code is still found.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Loops {
+    /// In header order.
+    pub loops: Vec<Loop>,
+    pub irreducible: Vec<IrreducibleEdge>,
+    latches: BTreeMap<usize, Vec<usize>>
+}
+
+fn intra(kind: EdgeKind) -> bool {
+    kind != EdgeKind::Call
+}
+
+/// Immediate dominator of each block, or `None` for blocks no root reaches. `root` is a virtual block
+/// before all roots; the roots have it as their dominator.
+fn dominators(cfg: &Cfg, roots: &[BlockId], order: &[BlockId], root: BlockId) -> Vec<Option<BlockId>> {
+    let mut rank: Vec<usize> = vec![usize::MAX; cfg.len() + 1];
+    rank[root] = 0;
+    for (index, &block) in order.iter().enumerate() {
+        rank[block] = index + 1;
+    }
+    let mut idom: Vec<Option<BlockId>> = vec![None; cfg.len() + 1];
+    idom[root] = Some(root);
+    let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+        while a != b {
+            while rank[a] > rank[b] {
+                a = idom[a].expect("processed blocks have a dominator");
+            }
+            while rank[b] > rank[a] {
+                b = idom[b].expect("processed blocks have a dominator");
+            }
+        }
+        a
+    };
+
+    let mut changed: bool = true;
+    while changed {
+        changed = false;
+        for &block in order {
+            let mut preds: Vec<BlockId> = cfg.predecessors(block).filter(|edge| intra(edge.kind)).map(|edge| edge.from).collect();
+            if roots.contains(&block) {
+                preds.push(root);
+            }
+            let mut new: Option<BlockId> = None;
+            for pred in preds.into_iter().filter(|&pred| idom[pred].is_some()) {
+                new = Some(match new {
+                    Some(current) => intersect(&idom, pred, current),
+                    None => pred
+                });
+            }
+            if new.is_some() && new != idom[block] {
+                idom[block] = new;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+impl Loops {
+    pub fn new(cfg: &Cfg) -> Self {
+        let blocks: usize = cfg.len();
+        let root: BlockId = blocks;
+        let mut roots: BTreeSet<BlockId> = (0..blocks.min(1)).collect();
+        for edge in cfg.edges().iter().filter(|edge| !intra(edge.kind)) {
+            roots.insert(edge.to);
+        }
+        roots.extend((0..blocks).filter(|&block| cfg.predecessors(block).all(|edge| !intra(edge.kind))));
+        let roots: Vec<BlockId> = roots.into_iter().collect();
+
+        // Depth-first search for the reverse postorder and the edges back to a block still on the stack.
+        let mut visited: Vec<bool> = vec![false; blocks];
+        let mut on_stack: Vec<bool> = vec![false; blocks];
+        let mut postorder: Vec<BlockId> = Vec::with_capacity(blocks);
+        let mut retreating: Vec<(BlockId, BlockId)> = Vec::new();
+        for &start in &roots {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            on_stack[start] = true;
+            let mut stack: Vec<(BlockId, Vec<BlockId>)> = vec![(start, cfg.successors(start).filter(|edge| intra(edge.kind)).map(|edge| edge.to).collect())];
+            while let Some((block, successors)) = stack.last_mut() {
+                let block: BlockId = *block;
+                match successors.pop() {
+                    Some(next) if on_stack[next] => retreating.push((block, next)),
+                    Some(next) if !visited[next] => {
+                        visited[next] = true;
+                        on_stack[next] = true;
+                        stack.push((next, cfg.successors(next).filter(|edge| intra(edge.kind)).map(|edge| edge.to).collect()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        on_stack[block] = false;
+                        postorder.push(block);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        let order: Vec<BlockId> = postorder.iter().rev().copied().collect();
+        let idom: Vec<Option<BlockId>> = dominators(cfg, &roots, &order, root);
+        let dominates = |a: BlockId, mut b: BlockId| loop {
+            if a == b {
+                return true;
+            }
+            match idom[b] {
+                Some(parent) if parent != b => b = parent,
+                _ => return false
+            }
+        };
+
+        let mut back_edges: BTreeMap<BlockId, BTreeSet<BlockId>> = BTreeMap::new();
+        let mut irreducible: Vec<IrreducibleEdge> = Vec::new();
+        retreating.sort_unstable();
+        retreating.dedup();
+        for (from, to) in retreating {
+            if dominates(to, from) {
+                back_edges.entry(to).or_default().insert(from);
+            } else {
+                irreducible.push(IrreducibleEdge { from, to, offset: cfg.blocks()[from].last().offset, target: cfg.blocks()[to].start });
+            }
+        }
+
+        let mut loops: Vec<Loop> = Vec::new();
+        for (header, latches) in back_edges {
+            let mut body: BTreeSet<BlockId> = BTreeSet::from([header]);
+            let mut work: Vec<BlockId> = latches.iter().copied().collect();
+            while let Some(block) = work.pop() {
+                if body.insert(block) {
+                    work.extend(cfg.predecessors(block).filter(|edge| intra(edge.kind) && idom[edge.from].is_some()).map(|edge| edge.from));
+                }
+            }
+            let exits: BTreeSet<BlockId> = body
+                .iter()
+                .flat_map(|&block| cfg.successors(block).filter(|edge| intra(edge.kind)).map(|edge| edge.to))
+                .filter(|block| !body.contains(block))
+                .collect();
+            let body_blocks: Vec<BlockId> = body.into_iter().collect();
+            loops.push(Loop {
+                id: loops.len() + 1,
+                header,
+                offset: cfg.blocks()[header].start,
+                size: body_blocks.iter().map(|&block| cfg.blocks()[block].end - cfg.blocks()[block].start).sum(),
+                instructions: body_blocks.iter().map(|&block| cfg.blocks()[block].instructions.len()).sum(),
+                body_blocks,
+                exits: exits.into_iter().collect(),
+                latches: latches.into_iter().collect(),
+                parent: None,
+                depth: 1
+            });
+        }
+
+        // A loop's parent is the smallest other loop whose body holds its header. Parents are bigger, so
+        // walking from the biggest loop down sets each parent's depth before its children's.
+        let mut by_size: Vec<usize> = (0..loops.len()).collect();
+        by_size.sort_by_key(|&index| std::cmp::Reverse(loops[index].body_blocks.len()));
+        for &index in &by_size {
+            let parent: Option<usize> = (0..loops.len())
+                .filter(|&other| other != index && loops[other].body_blocks.binary_search(&loops[index].header).is_ok())
+                .min_by_key(|&other| loops[other].body_blocks.len());
+            loops[index].parent = parent;
+            loops[index].depth = parent.map_or(1, |parent| loops[parent].depth + 1);
+        }
+
+        let mut latches: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (index, found) in loops.iter().enumerate() {
+            for &latch in &found.latches {
+                latches.entry(cfg.blocks()[latch].last().offset).or_default().push(index);
+            }
+        }
+        Self { loops, irreducible, latches }
+    }
+
+    /// The loop whose header starts at `offset`.
+    pub fn header_at(&self, offset: usize) -> Option<&Loop> {
+        self.loops.iter().find(|found| found.offset == offset)
+    }
+
+    /// The loops the instruction at `offset` jumps back to the header of.
+    pub fn back_edges_at(&self, offset: usize) -> impl Iterator<Item = &Loop> {
+        self.latches.get(&offset).into_iter().flatten().map(|&index| &self.loops[index])
+    }
+
+    /// The irreducible edges leaving the instruction at `offset`.
+    pub fn irreducible_at(&self, offset: usize) -> impl Iterator<Item = &IrreducibleEdge> {
+        self.irreducible.iter().filter(move |edge| edge.offset == offset)
+    }
+
+    /// How deep the loops nest; 0 without loops.
+    pub fn max_depth(&self) -> usize {
+        self.loops.iter().map(|found| found.depth).max().unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.loops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loops.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::decode;
+
+    fn loops(bytes: Vec<u8>) -> Loops {
+        let listing: Listing = decode(bytes);
+        Loops::new(&Cfg::new(&listing))
+    }
+
+    #[test]
+    fn finds_a_natural_loop() {
+        // LOAD IMM8 1 -> reg1; ADD reg1 + reg1 -> reg1 at 3; JUMP IF TRUE reg1 3; HALT at 13.
+        let found: Loops = loops(vec![181, 1, 1, 243, 1, 1, 1, 83, 1, 0, 0, 0, 3, 166]);
+        assert_eq!(found.len(), 1);
+        let natural: &Loop = &found.loops[0];
+        assert_eq!((natural.name(), natural.offset, natural.depth, natural.parent), ("L1".to_string(), 3, 1, None));
+        assert_eq!((natural.size, natural.instructions), (10, 2));
+        assert_eq!(natural.latches, [natural.header]);
+        assert_eq!(natural.exits.len(), 1);
+        assert_eq!(found.back_edges_at(7).map(|back| back.id).collect::<Vec<usize>>(), [1]);
+        assert!(found.irreducible.is_empty());
+    }
+
+    #[test]
+    fn nested_loops_get_their_parent_and_depth() {
+        // LOAD IMM8 1 -> reg1; LOAD IMM8 1 -> reg2 at 3; ADD reg2 + reg1 -> reg2 at 6; JUMP IF TRUE reg2 6;
+        // JUMP IF TRUE reg1 3; HALT.
+        let found: Loops = loops(vec![181, 1, 1, 181, 2, 1, 243, 2, 2, 1, 83, 2, 0, 0, 0, 6, 83, 1, 0, 0, 0, 3, 166]);
+        let depths: Vec<(usize, usize, Option<usize>)> = found.loops.iter().map(|found| (found.offset, found.depth, found.parent)).collect();
+        assert_eq!(depths, [(3, 1, None), (6, 2, Some(0))]);
+        assert_eq!(found.max_depth(), 2);
+        assert!(found.loops[0].body_blocks.contains(&found.loops[1].header));
+    }
+
+    #[test]
+    fn a_cycle_with_two_entries_is_irreducible() {
+        // JUMP IF TRUE reg1 9; LOAD IMM8 1 -> reg2 at 6; LOAD IMM8 1 -> reg3 at 9; JUMP IF TRUE reg2 6; HALT.
+        let found: Loops = loops(vec![83, 1, 0, 0, 0, 9, 181, 2, 1, 181, 3, 1, 83, 2, 0, 0, 0, 6, 166]);
+        assert!(found.is_empty());
+        let edges: Vec<(usize, usize)> = found.irreducible.iter().map(|edge| (edge.offset, edge.target)).collect();
+        // The search reaches the block at 9 first, so the fall-through into it from 6 is the edge that closes the cycle.
+        assert_eq!(edges, [(6, 9)]);
+    }
+}
@@ -1,3 +1,33 @@
+pub mod apis;
+pub mod builder;
+pub mod callgraph;
+pub mod cfg;
+pub mod codec;
+pub mod derived;
+pub mod diff;
+#[allow(clippy::module_inception)]
 pub mod disassembler;
+pub mod emulator;
+pub mod error;
+pub mod exceptions;
+pub mod expressions;
+pub mod follow;
+pub mod functions;
+pub mod inference;
+pub mod instruction;
 pub mod instructions;
-pub mod opcodes;
\ No newline at end of file
+pub mod listing;
+pub mod liveness;
+pub mod loops;
+pub mod names;
+pub mod opcodes;
+pub mod operands;
+pub mod profiles;
+pub mod reachability;
+pub mod registers;
+pub mod sink;
+pub mod state;
+pub mod strings;
+pub mod symbols;
+#[cfg(test)]
+mod testing;
\ No newline at end of file
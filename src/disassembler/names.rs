@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::disassembler::cfg::Cfg;
+use crate::disassembler::functions::Functions;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::loops::{Loop, Loops};
+use crate::disassembler::opcodes::{OpCategory, OpCodes};
+use crate::disassembler::operands::{Access, Operand};
+use crate::disassembler::registers::RegValue;
+
+/// Globals a register is named after when every write to it reads that property, and the name it gets.
+pub const GLOBALS: &[(&str, &str)] = &[
+    ("window", "win"),
+    ("self", "win"),
+    ("globalThis", "win"),
+    ("document", "doc"),
+    ("navigator", "nav"),
+    ("location", "loc"),
+    ("screen", "scr"),
+    ("history", "hist"),
+    ("localStorage", "storage"),
+    ("sessionStorage", "session"),
+    ("performance", "perf"),
+    ("crypto", "crypto"),
+    ("console", "console"),
+    ("Math", "math"),
+    ("JSON", "json"),
+    ("Date", "date"),
+    ("String", "string"),
+    ("Object", "object"),
+    ("Array", "array")
+];
+
+/// Loop counter names, by loop depth in the function.
+const COUNTERS: &[&str] = &["i", "j", "k", "l", "m", "n"];
+
+/// Why a register got its name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NameKind {
+    /// From a rename map.
+    Pinned,
+    /// Every write reads a well-known global such as `document`.
+    Global,
+    /// Compared and stepped by itself in a loop.
+    Counter,
+    /// Written exactly once.
+    Temporary
+}
+
+impl NameKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NameKind::Pinned => "pinned",
+            NameKind::Global => "global",
+            NameKind::Counter => "counter",
+            NameKind::Temporary => "temporary"
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterName {
+    pub name: String,
+    pub kind: NameKind
+}
+
+/// Where a pinned name applies.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PinScope {
+    /// Every function and the code outside them.
+    All,
+    /// Code outside every function.
+    Outside,
+    /// The function with this entry.
+    Function(usize)
+}
+
+/// A name given by hand, which wins over the one the pass would pick.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pin {
+    pub scope: PinScope,
+    pub reg: u8,
+    pub name: String
+}
+
+/// Readable names for registers, chosen per function: a pin, then the global every write reads (`doc`), then
+/// a loop counter (`i`, `j`, ... by loop depth), then a register written once (`t0`, `t1`, ... in offset order).
+/// Anything else keeps its `regN` name. A register a function reads but never writes, and that is not one of
+/// its NEW FUNCTION parameters, keeps a global or pinned name it has outside functions. Names are unique
+/// within a function.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterNames {
+    functions: Functions,
+    /// By function entry; `None` for code outside every function.
+    scopes: BTreeMap<Option<usize>, BTreeMap<u8, RegisterName>>
+}
+
+/// Registers one scope reads and writes, with the writes in offset order.
+#[derive(Default)]
+struct Scope<'a> {
+    defs: BTreeMap<u8, Vec<&'a Instruction>>,
+    used: BTreeSet<u8>,
+    params: BTreeSet<u8>
+}
+
+/// The global `instruction` reads, if it is a GET PROPERTY of one.
+fn global(instruction: &Instruction) -> Option<&'static str> {
+    if instruction.opcode != OpCodes::GetProperty {
+        return None;
+    }
+    let Some(Some(RegValue::Str(key))) = instruction.resolved.get(2) else {
+        return None;
+    };
+    GLOBALS.iter().find(|(global, _)| global == key).map(|&(_, name)| name)
+}
+
+/// Registers `lp` compares and steps by themselves (`ADD reg5 + reg32 -> reg5`).
+fn counters(cfg: &Cfg, lp: &Loop) -> Vec<u8> {
+    let body: Vec<&Instruction> = lp.body_blocks.iter().filter_map(|&id| cfg.block(id)).flat_map(|block| &block.instructions).collect();
+    let mut stepped: Vec<u8> = Vec::new();
+    for instruction in &body {
+        if let (OpCodes::Add | OpCodes::Sub, [Operand::Reg(reg), Operand::Reg(left), Operand::Reg(right)]) = (instruction.opcode, instruction.operands.as_slice())
+            && (left == reg || right == reg)
+            && !stepped.contains(reg)
+        {
+            stepped.push(*reg);
+        }
+    }
+    stepped.retain(|&reg| {
+        body.iter().any(|instruction| instruction.opcode.category() == OpCategory::Comparison && instruction.accesses().any(|(read, access)| read == reg && access.reads()))
+    });
+    stepped
+}
+
+struct Namer {
+    names: BTreeMap<u8, RegisterName>,
+    taken: BTreeSet<String>
+}
+
+impl Namer {
+    /// Names `reg` `base`, or `base2`, `base3`, ... when that is taken; registers already named are left alone.
+    fn name(&mut self, reg: u8, base: &str, kind: NameKind) {
+        if self.names.contains_key(&reg) {
+            return;
+        }
+        let name: String = (1..).map(|n: usize| if n == 1 { base.to_string() } else { format!("{base}{n}") }).find(|name| !self.taken.contains(name)).expect("names are unbounded");
+        self.add(reg, name, kind);
+    }
+
+    fn add(&mut self, reg: u8, name: String, kind: NameKind) {
+        self.taken.insert(name.clone());
+        self.names.insert(reg, RegisterName { name, kind });
+    }
+}
+
+impl RegisterNames {
+    pub fn new(listing: &Listing, pins: &[Pin]) -> Self {
+        let functions: Functions = listing.functions();
+        let cfg: Cfg = Cfg::new(listing);
+        let loops: Loops = Loops::new(&cfg);
+
+        let mut scopes: BTreeMap<Option<usize>, Scope> = BTreeMap::new();
+        for instruction in &listing.instructions {
+            if let (OpCodes::NewFunction, [_, Operand::Target(entry), Operand::RegList(args)]) = (instruction.opcode, instruction.operands.as_slice()) {
+                scopes.entry(Some(*entry as usize)).or_default().params.extend(args);
+            }
+            let scope: &mut Scope = scopes.entry(functions.containing(instruction.offset).map(|function| function.entry)).or_default();
+            for (reg, access) in instruction.accesses() {
+                scope.used.insert(reg);
+                if access == Access::Def {
+                    scope.defs.entry(reg).or_default().push(instruction);
+                }
+            }
+        }
+
+        let mut names: Self = Self { functions, scopes: BTreeMap::new() };
+        // Code outside functions first: functions inherit its names.
+        let outside: Option<Scope> = scopes.remove(&None);
+        for (key, scope) in outside.map(|scope| (None, scope)).into_iter().chain(scopes) {
+            let named: BTreeMap<u8, RegisterName> = names.scope(key, &scope, &cfg, &loops, pins);
+            names.scopes.insert(key, named);
+        }
+        names
+    }
+
+    fn scope(&self, key: Option<usize>, scope: &Scope, cfg: &Cfg, loops: &Loops, pins: &[Pin]) -> BTreeMap<u8, RegisterName> {
+        let mut namer: Namer = Namer { names: BTreeMap::new(), taken: BTreeSet::new() };
+        let pinned = |pin: &&Pin| match pin.scope {
+            PinScope::All => true,
+            PinScope::Outside => key.is_none(),
+            PinScope::Function(entry) => key == Some(entry)
+        };
+        // A pin for this scope wins over one for every scope.
+        let mut pins: Vec<&Pin> = pins.iter().filter(pinned).collect();
+        pins.sort_by_key(|pin| pin.scope != PinScope::All);
+        for pin in pins {
+            namer.names.remove(&pin.reg);
+            namer.add(pin.reg, pin.name.clone(), NameKind::Pinned);
+        }
+
+        if key.is_some()
+            && let Some(outside) = self.scopes.get(&None)
+        {
+            for reg in scope.used.iter().filter(|reg| !scope.defs.contains_key(reg) && !scope.params.contains(reg)) {
+                if let Some(name) = outside.get(reg)
+                    && matches!(name.kind, NameKind::Pinned | NameKind::Global)
+                    && !namer.names.contains_key(reg)
+                    && !namer.taken.contains(&name.name)
+                {
+                    namer.add(*reg, name.name.clone(), name.kind);
+                }
+            }
+        }
+
+        for (&reg, defs) in &scope.defs {
+            let first: Option<&str> = global(defs[0]);
+            if let Some(name) = first
+                && defs.iter().all(|instruction| global(instruction) == first)
+            {
+                namer.name(reg, name, NameKind::Global);
+            }
+        }
+
+        let mut inside: Vec<&Loop> = loops
+            .loops
+            .iter()
+            .filter(|lp| self.functions.containing(lp.offset).map(|function| function.entry) == key)
+            .collect();
+        inside.sort_by_key(|lp| (lp.depth, lp.offset));
+        let base: usize = inside.first().map_or(1, |lp| lp.depth);
+        for lp in inside {
+            for reg in counters(cfg, lp) {
+                namer.name(reg, COUNTERS[(lp.depth - base).min(COUNTERS.len() - 1)], NameKind::Counter);
+            }
+        }
+
+        let mut once: Vec<(usize, u8)> = scope.defs.iter().filter(|(_, defs)| defs.len() == 1).map(|(&reg, defs)| (defs[0].offset, reg)).collect();
+        once.sort_unstable();
+        let mut next: usize = 0;
+        for (_, reg) in once {
+            if namer.names.contains_key(&reg) {
+                continue;
+            }
+            while namer.taken.contains(&format!("t{next}")) {
+                next += 1;
+            }
+            namer.add(reg, format!("t{next}"), NameKind::Temporary);
+        }
+        namer.names
+    }
+
+    /// The name of `reg` in the function containing `offset`, or outside functions.
+    pub fn get(&self, offset: usize, reg: u8) -> Option<&RegisterName> {
+        let key: Option<usize> = self.functions.containing(offset).map(|function| function.entry);
+        self.scopes.get(&key).and_then(|names| names.get(&reg))
+    }
+
+    /// The name of `reg` at `offset`, `regN` when it has none.
+    pub fn display(&self, offset: usize, reg: u8) -> String {
+        match self.get(offset, reg) {
+            Some(name) => name.name.clone(),
+            None => format!("reg{reg}")
+        }
+    }
+
+    /// Every named register, by function entry (`None` outside functions) and register.
+    pub fn names(&self) -> impl Iterator<Item = (Option<usize>, u8, &RegisterName)> {
+        self.scopes.iter().flat_map(|(&scope, names)| names.iter().map(move |(&reg, name)| (scope, reg, name)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.scopes.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::testing::{decode, new_value};
+
+    /// reg2 = "document", GET PROPERTY reg1[reg2] -> reg3, reg4 = 0, reg5 = 1, reg6 = 3, then the loop at 25:
+    /// ADD reg4 + reg5 -> reg4; LESS THAN reg4 < reg6 -> reg7; JUMP IF TRUE reg7 25; HALT.
+    fn program() -> Vec<u8> {
+        let mut bytes: Vec<u8> = new_value(2, "document");
+        bytes.extend([251, 3, 1, 2, 181, 4, 0, 181, 5, 1, 181, 6, 3, 243, 4, 4, 5, 20, 7, 4, 6, 83, 7, 0, 0, 0, 25, 166]);
+        bytes
+    }
+
+    fn names(pins: &[Pin]) -> Vec<(u8, String, NameKind)> {
+        let listing: Listing = decode(program());
+        RegisterNames::new(&listing, pins).names().map(|(scope, reg, name)| {
+            assert_eq!(scope, None);
+            (reg, name.name.clone(), name.kind)
+        }).collect()
+    }
+
+    #[test]
+    fn names_globals_counters_and_temporaries() {
+        assert_eq!(names(&[]), [
+            (2, "t0".to_string(), NameKind::Temporary),
+            (3, "doc".to_string(), NameKind::Global),
+            (4, "i".to_string(), NameKind::Counter),
+            (5, "t1".to_string(), NameKind::Temporary),
+            (6, "t2".to_string(), NameKind::Temporary),
+            (7, "t3".to_string(), NameKind::Temporary)
+        ]);
+    }
+
+    #[test]
+    fn a_pin_wins_and_takes_its_name() {
+        let pins: Vec<Pin> = vec![
+            Pin { scope: PinScope::All, reg: 4, name: "doc".to_string() },
+            Pin { scope: PinScope::Outside, reg: 5, name: "step".to_string() }
+        ];
+        assert_eq!(names(&pins), [
+            (2, "t0".to_string(), NameKind::Temporary),
+            (3, "doc2".to_string(), NameKind::Global),
+            (4, "doc".to_string(), NameKind::Pinned),
+            (5, "step".to_string(), NameKind::Pinned),
+            (6, "t1".to_string(), NameKind::Temporary),
+            (7, "t2".to_string(), NameKind::Temporary)
+        ]);
+    }
+}
@@ -1,21 +1,39 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::disassembler::operands::{Access, OperandKind};
 
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OpCategory {
+    Constant,
+    Property,
+    Call,
+    Arithmetic,
+    Bitwise,
+    Comparison,
+    ControlFlow,
+    Data
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OpCodes {
     InitMemory,
     NewValue,
     GetProperty,
     CallFunction,
     Mul,
-    MovImm24,
+    MovImm32,
     CallApply,
     Div,
     Or,
     Sub,
     PushArgs,
-    LoadImm24,
+    LoadImm8,
     JumpFrame,
     NewFunction,
     LessThan,
+    LessThanAlt,
     JumpIfFalse,
     SetProperty,
     Add,
@@ -31,15 +49,198 @@ pub enum OpCodes {
     And,
     Mod,
     Lte,
+    LteAlt,
     NotEqual,
     JumpIfTrue,
     TryCatch,
     StrictEqual,
     StrictNotEqual,
-    Throw
+    Throw,
+    Db
 }
 
 impl OpCodes {
+    pub const ALL: [OpCodes; 39] = [
+        OpCodes::InitMemory,
+        OpCodes::NewValue,
+        OpCodes::GetProperty,
+        OpCodes::CallFunction,
+        OpCodes::Mul,
+        OpCodes::MovImm32,
+        OpCodes::CallApply,
+        OpCodes::Div,
+        OpCodes::Or,
+        OpCodes::Sub,
+        OpCodes::PushArgs,
+        OpCodes::LoadImm8,
+        OpCodes::JumpFrame,
+        OpCodes::NewFunction,
+        OpCodes::LessThan,
+        OpCodes::LessThanAlt,
+        OpCodes::JumpIfFalse,
+        OpCodes::SetProperty,
+        OpCodes::Add,
+        OpCodes::Jump,
+        OpCodes::Halt,
+        OpCodes::Shl,
+        OpCodes::Ret,
+        OpCodes::Equal,
+        OpCodes::Xor,
+        OpCodes::LoadDouble,
+        OpCodes::Ushr,
+        OpCodes::Shr,
+        OpCodes::And,
+        OpCodes::Mod,
+        OpCodes::Lte,
+        OpCodes::LteAlt,
+        OpCodes::NotEqual,
+        OpCodes::JumpIfTrue,
+        OpCodes::TryCatch,
+        OpCodes::StrictEqual,
+        OpCodes::StrictNotEqual,
+        OpCodes::Throw,
+        OpCodes::Db
+    ];
+
+    pub fn bytes(&self) -> &'static [u8] {
+        match self {
+            OpCodes::InitMemory => &[124],
+            OpCodes::NewValue => &[23],
+            OpCodes::GetProperty => &[251],
+            OpCodes::CallFunction => &[215],
+            OpCodes::Mul => &[6],
+            OpCodes::MovImm32 => &[241],
+            OpCodes::CallApply => &[90],
+            OpCodes::Div => &[55],
+            OpCodes::Or => &[65],
+            OpCodes::Sub => &[230],
+            OpCodes::PushArgs => &[88],
+            OpCodes::LoadImm8 => &[181],
+            OpCodes::JumpFrame => &[49],
+            OpCodes::NewFunction => &[171],
+            OpCodes::LessThan => &[20],
+            OpCodes::LessThanAlt => &[112],
+            OpCodes::JumpIfFalse => &[39],
+            OpCodes::SetProperty => &[99],
+            OpCodes::Add => &[243],
+            OpCodes::Jump => &[93],
+            OpCodes::Halt => &[166],
+            OpCodes::Shl => &[53],
+            OpCodes::Ret => &[17],
+            OpCodes::Equal => &[78],
+            OpCodes::Xor => &[117],
+            OpCodes::LoadDouble => &[51],
+            OpCodes::Ushr => &[40],
+            OpCodes::Shr => &[149],
+            OpCodes::And => &[37],
+            OpCodes::Mod => &[156],
+            OpCodes::Lte => &[247],
+            OpCodes::LteAlt => &[214],
+            OpCodes::NotEqual => &[22],
+            OpCodes::JumpIfTrue => &[83],
+            OpCodes::TryCatch => &[115],
+            OpCodes::StrictEqual => &[161],
+            OpCodes::StrictNotEqual => &[220],
+            OpCodes::Throw => &[5],
+            OpCodes::Db => &[]
+        }
+    }
+
+    pub fn layout(&self) -> &'static [OperandKind] {
+        use OperandKind::*;
+
+        match self {
+            OpCodes::InitMemory | OpCodes::LoadImm8 => &[Reg, Byte],
+            OpCodes::NewValue => &[Reg, Str],
+            OpCodes::MovImm32 => &[Reg, Int],
+            OpCodes::LoadDouble => &[Reg, Double],
+            OpCodes::GetProperty
+            | OpCodes::SetProperty
+            | OpCodes::Mul
+            | OpCodes::Div
+            | OpCodes::Or
+            | OpCodes::Sub
+            | OpCodes::LessThan
+            | OpCodes::LessThanAlt
+            | OpCodes::Add
+            | OpCodes::Shl
+            | OpCodes::Equal
+            | OpCodes::Xor
+            | OpCodes::Ushr
+            | OpCodes::Shr
+            | OpCodes::And
+            | OpCodes::Mod
+            | OpCodes::Lte
+            | OpCodes::LteAlt
+            | OpCodes::NotEqual
+            | OpCodes::StrictEqual
+            | OpCodes::StrictNotEqual => &[Reg, Reg, Reg],
+            OpCodes::CallFunction => &[Reg, Reg, RegList],
+            OpCodes::CallApply => &[Reg, Reg, Reg, RegList],
+            OpCodes::PushArgs => &[Reg, RegList],
+            OpCodes::JumpFrame => &[Target, Byte, RegList],
+            OpCodes::NewFunction => &[Reg, Target, RegList],
+            OpCodes::JumpIfFalse | OpCodes::JumpIfTrue => &[Reg, Target],
+            OpCodes::Jump => &[Target],
+            OpCodes::Halt => &[],
+            OpCodes::Ret => &[Reg, RegList],
+            OpCodes::TryCatch => &[Reg, Target, Target, Target],
+            OpCodes::Throw => &[Reg],
+            OpCodes::Db => &[Byte]
+        }
+    }
+
+    /// What each operand in `layout` does to its register; the two always have the same length.
+    /// JUMP FRAME's destination register is its Byte operand.
+    pub fn access(&self) -> &'static [Access] {
+        use Access::*;
+
+        match self {
+            OpCodes::InitMemory | OpCodes::LoadImm8 | OpCodes::NewValue | OpCodes::MovImm32 | OpCodes::LoadDouble => &[Def, None],
+            OpCodes::SetProperty => &[Modify, Use, Use],
+            OpCodes::GetProperty
+            | OpCodes::Mul
+            | OpCodes::Div
+            | OpCodes::Or
+            | OpCodes::Sub
+            | OpCodes::LessThan
+            | OpCodes::LessThanAlt
+            | OpCodes::Add
+            | OpCodes::Shl
+            | OpCodes::Equal
+            | OpCodes::Xor
+            | OpCodes::Ushr
+            | OpCodes::Shr
+            | OpCodes::And
+            | OpCodes::Mod
+            | OpCodes::Lte
+            | OpCodes::LteAlt
+            | OpCodes::NotEqual
+            | OpCodes::StrictEqual
+            | OpCodes::StrictNotEqual => &[Def, Use, Use],
+            OpCodes::CallFunction => &[Def, Use, Use],
+            OpCodes::CallApply => &[Def, Use, Use, Use],
+            OpCodes::PushArgs => &[Def, Use],
+            OpCodes::JumpFrame => &[None, Def, Use],
+            OpCodes::NewFunction => &[Def, None, None],
+            OpCodes::JumpIfFalse | OpCodes::JumpIfTrue => &[Use, None],
+            OpCodes::Jump => &[None],
+            OpCodes::Halt => &[],
+            OpCodes::Ret => &[Use, Use],
+            OpCodes::TryCatch => &[Def, None, None, None],
+            OpCodes::Throw => &[Use],
+            OpCodes::Db => &[None]
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<OpCodes> {
+        OpCodes::ALL.into_iter().find(|opcode| opcode.bytes().contains(&byte))
+    }
+
+    pub fn handler_name(&self) -> String {
+        self.as_str().to_lowercase().replace(' ', "_")
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             OpCodes::InitMemory => "INIT MEMORY",
@@ -47,16 +248,17 @@ impl OpCodes {
             OpCodes::GetProperty => "GET PROPERTY",
             OpCodes::CallFunction => "CALL FUNCTION",
             OpCodes::Mul => "MUL",
-            OpCodes::MovImm24 => "MOV Imm24",
+            OpCodes::MovImm32 => "MOV IMM32",
             OpCodes::CallApply => "CALL APPLY",
             OpCodes::Div => "DIV",
             OpCodes::Or => "OR",
             OpCodes::Sub => "SUB",
             OpCodes::PushArgs => "PUSH ARGS",
-            OpCodes::LoadImm24 => "LOAD IMM24",
+            OpCodes::LoadImm8 => "LOAD IMM8",
             OpCodes::JumpFrame => "JUMP FRAME",
             OpCodes::NewFunction => "NEW FUNCTION",
             OpCodes::LessThan => "LESS THAN",
+            OpCodes::LessThanAlt => "LESS THAN ALT",
             OpCodes::JumpIfFalse => "JUMP IF FALSE",
             OpCodes::SetProperty => "SET PROPERTY",
             OpCodes::Add => "ADD",
@@ -72,13 +274,115 @@ impl OpCodes {
             OpCodes::And => "AND",
             OpCodes::Mod => "MOD",
             OpCodes::Lte => "LTE",
+            OpCodes::LteAlt => "LTE ALT",
             OpCodes::NotEqual => "NOT EQUAL",
             OpCodes::JumpIfTrue => "JUMP IF TRUE",
             OpCodes::TryCatch => "TRY CATCH",
             OpCodes::StrictEqual => "STRICT EQUAL",
             OpCodes::StrictNotEqual => "STRICT NOT EQUAL",
-            OpCodes::Throw => "THROW"
+            OpCodes::Throw => "THROW",
+            OpCodes::Db => "DB"
         }
     }
+
+    pub fn category(&self) -> OpCategory {
+        match self {
+            OpCodes::InitMemory
+            | OpCodes::NewValue
+            | OpCodes::MovImm32
+            | OpCodes::LoadImm8
+            | OpCodes::LoadDouble => OpCategory::Constant,
+            OpCodes::GetProperty
+            | OpCodes::SetProperty => OpCategory::Property,
+            OpCodes::CallFunction
+            | OpCodes::CallApply
+            | OpCodes::PushArgs
+            | OpCodes::NewFunction => OpCategory::Call,
+            OpCodes::Add
+            | OpCodes::Sub
+            | OpCodes::Mul
+            | OpCodes::Div
+            | OpCodes::Mod => OpCategory::Arithmetic,
+            OpCodes::Or
+            | OpCodes::And
+            | OpCodes::Xor
+            | OpCodes::Shl
+            | OpCodes::Shr
+            | OpCodes::Ushr => OpCategory::Bitwise,
+            OpCodes::LessThan
+            | OpCodes::LessThanAlt
+            | OpCodes::Lte
+            | OpCodes::LteAlt
+            | OpCodes::Equal
+            | OpCodes::NotEqual
+            | OpCodes::StrictEqual
+            | OpCodes::StrictNotEqual => OpCategory::Comparison,
+            OpCodes::Jump
+            | OpCodes::JumpIfFalse
+            | OpCodes::JumpIfTrue
+            | OpCodes::JumpFrame
+            | OpCodes::Ret
+            | OpCodes::Halt
+            | OpCodes::TryCatch
+            | OpCodes::Throw => OpCategory::ControlFlow,
+            OpCodes::Db => OpCategory::Data
+        }
+    }
+}
+
+impl fmt::Display for OpCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for OpCodes {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        // Opcode maps written before MOV IMM32 and LOAD IMM8 were renamed still use their old names.
+        let name: &str = match name {
+            "mov_imm24" | "MOV Imm24" => "mov_imm32",
+            "load_imm24" | "LOAD IMM24" => "load_imm8",
+            name => name
+        };
+        OpCodes::ALL
+            .into_iter()
+            .find(|opcode| opcode.handler_name() == name || opcode.as_str() == name)
+            .ok_or_else(|| format!("unknown opcode name '{name}'"))
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::instructions::OpcodeTable;
+
+    #[test]
+    fn every_byte_round_trips() {
+        for byte in 0..=u8::MAX {
+            match OpCodes::from_byte(byte) {
+                Some(opcode) => assert!(opcode.bytes().contains(&byte), "{byte} decodes to {opcode}, which does not list it"),
+                None => assert!(OpCodes::ALL.iter().all(|opcode| !opcode.bytes().contains(&byte)), "{byte} is listed but does not decode")
+            }
+        }
+    }
+
+    #[test]
+    fn every_opcode_round_trips() {
+        for opcode in OpCodes::ALL {
+            for &byte in opcode.bytes() {
+                assert_eq!(OpCodes::from_byte(byte), Some(opcode), "byte {byte} of {opcode}");
+            }
+        }
+        assert!(OpCodes::Db.bytes().is_empty());
+    }
+
+    #[test]
+    fn builtin_table_dispatches_by_the_same_bytes() {
+        let table: Vec<(u8, OpCodes)> = OpcodeTable::builtin().dump_table();
+        let expected: Vec<(u8, OpCodes)> = (0..=u8::MAX).filter_map(|byte| OpCodes::from_byte(byte).map(|opcode| (byte, opcode))).collect();
+        assert_eq!(table, expected);
+        assert_eq!(table.len(), OpCodes::ALL.len() - 1);
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use crate::disassembler::codec::{escape_bytes, format_double};
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operand {
+    Reg(u8),
+    RegList(Vec<u8>),
+    Byte(u8),
+    Int(u32),
+    #[cfg_attr(feature = "serde", serde(with = "double"))]
+    Double(f64),
+    /// The decoded text (invalid UTF-8 replaced) and the bytes after XOR it was decoded from.
+    Str(String, Vec<u8>),
+    Target(u32)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OperandKind {
+    Reg,
+    RegList,
+    Byte,
+    Int,
+    Double,
+    Str,
+    Target
+}
+
+/// How an instruction treats a register operand, per `OpCodes::access`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    /// Not a register: a constant, a target, or NEW FUNCTION's parameter slots.
+    None,
+    /// Written with a new value.
+    Def,
+    Use,
+    /// Read and changed in place without being reassigned (SET PROPERTY's object).
+    Modify
+}
+
+impl Access {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::None => "none",
+            Access::Def => "def",
+            Access::Use => "use",
+            Access::Modify => "modify"
+        }
+    }
+
+    pub fn writes(self) -> bool {
+        matches!(self, Access::Def | Access::Modify)
+    }
+
+    pub fn reads(self) -> bool {
+        matches!(self, Access::Use | Access::Modify)
+    }
+}
+
+impl Operand {
+    pub fn kind(&self) -> OperandKind {
+        match self {
+            Operand::Reg(_) => OperandKind::Reg,
+            Operand::RegList(_) => OperandKind::RegList,
+            Operand::Byte(_) => OperandKind::Byte,
+            Operand::Int(_) => OperandKind::Int,
+            Operand::Double(_) => OperandKind::Double,
+            Operand::Str(..) => OperandKind::Str,
+            Operand::Target(_) => OperandKind::Target
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "reg{reg}"),
+            Operand::RegList(regs) => {
+                let regs: Vec<String> = regs.iter().map(|reg| format!("reg{reg}")).collect();
+                write!(f, "[{}]", regs.join(","))
+            }
+            Operand::Byte(value) => write!(f, "{value}"),
+            Operand::Int(value) => write!(f, "{value}"),
+            Operand::Double(value) => write!(f, "{}", format_double(*value)),
+            Operand::Str(_, bytes) => write!(f, "'{}'", escape_bytes(bytes)),
+            Operand::Target(target) => write!(f, "entry({target:#x})")
+        }
+    }
+}
+
+/// JSON has no NaN or infinities, so those are written as the strings `format_double` prints for them
+/// and every double survives a round trip.
+#[cfg(feature = "serde")]
+pub(crate) mod double {
+    use std::fmt;
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use crate::disassembler::codec::format_double;
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(&format_double(*value))
+        }
+    }
+
+    struct DoubleVisitor;
+
+    impl Visitor<'_> for DoubleVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a number, \"NaN\", \"Infinity\" or \"-Infinity\"")
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+            match value {
+                "NaN" => Ok(f64::NAN),
+                "Infinity" => Ok(f64::INFINITY),
+                "-Infinity" => Ok(f64::NEG_INFINITY),
+                _ => Err(E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        deserializer.deserialize_any(DoubleVisitor)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::Operand;
+
+    fn round_trip(operand: &Operand) -> Operand {
+        let json: String = serde_json::to_string(operand).unwrap();
+        serde_json::from_str(&json).unwrap_or_else(|err| panic!("{json}: {err}"))
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let operands: Vec<Operand> = vec![
+            Operand::Reg(7),
+            Operand::RegList(vec![0, 3, 255]),
+            Operand::RegList(Vec::new()),
+            Operand::Byte(200),
+            Operand::Int(u32::MAX),
+            Operand::Str("caf\u{fffd}".to_string(), vec![0x63, 0x61, 0x66, 0xff]),
+            Operand::Target(0x1234)
+        ];
+        for operand in &operands {
+            let back: Operand = round_trip(operand);
+            assert_eq!(format!("{back:?}"), format!("{operand:?}"));
+        }
+    }
+
+    #[test]
+    fn doubles_round_trip_bit_for_bit() {
+        for value in [0.0, -0.0, 1.5, -2.25e300, f64::MIN_POSITIVE, f64::MAX, f64::INFINITY, f64::NEG_INFINITY] {
+            match round_trip(&Operand::Double(value)) {
+                Operand::Double(back) => assert_eq!(back.to_bits(), value.to_bits(), "{value}"),
+                other => panic!("{value} came back as {other:?}")
+            }
+        }
+        match round_trip(&Operand::Double(f64::NAN)) {
+            Operand::Double(back) => assert!(back.is_nan()),
+            other => panic!("NaN came back as {other:?}")
+        }
+    }
+
+    #[test]
+    fn non_finite_doubles_serialize_as_strings() {
+        assert_eq!(serde_json::to_string(&Operand::Double(f64::NAN)).unwrap(), r#"{"Double":"NaN"}"#);
+        assert_eq!(serde_json::to_string(&Operand::Double(f64::NEG_INFINITY)).unwrap(), r#"{"Double":"-Infinity"}"#);
+        assert_eq!(serde_json::to_string(&Operand::Double(0.5)).unwrap(), r#"{"Double":0.5}"#);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::disassembler::disassembler::{Disassembler, UnknownOpcodePolicy};
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::instructions::OpcodeTable;
+use crate::disassembler::registers::{RegisterTracker, TrackingMode};
+
+/// Profile name that picks the profile by decoding the sample with each.
+pub const AUTO_PROFILE: &str = "auto";
+/// Bytes from the start offset that `--profile auto` decodes with each profile.
+pub const DETECT_WINDOW: usize = 512;
+
+/// The opcode bytes of one VM build, by handler name as in an opcode map.
+#[derive(Clone, Copy, Debug)]
+pub struct Profile {
+    pub name: &'static str,
+    pub notes: &'static str,
+    pub pairs: &'static [(u8, &'static str)]
+}
+
+impl Profile {
+    pub fn table(&self) -> Result<OpcodeTable, DisassemblerError> {
+        let notes: Option<String> = (!self.notes.is_empty()).then(|| self.notes.to_string());
+        Ok(OpcodeTable::from_pairs(self.pairs)?.with_name(self.name, notes))
+    }
+}
+
+/// Built-in profiles besides `default`, whose bytes are the ones `OpCodes::bytes` gives.
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "build_a",
+        notes: "every default opcode byte plus 0x40",
+        pairs: &[
+            (22, "lte_alt"), (23, "call_function"), (28, "strict_not_equal"), (38, "sub"), (49, "mov_imm32"),
+            (51, "add"), (55, "lte"), (59, "get_property"), (69, "throw"), (70, "mul"), (81, "ret"),
+            (84, "less_than"), (86, "not_equal"), (87, "new_value"), (101, "and"), (103, "jump_if_false"),
+            (104, "ushr"), (113, "jump_frame"), (115, "load_double"), (117, "shl"), (119, "div"), (129, "or"),
+            (142, "equal"), (147, "jump_if_true"), (152, "push_args"), (154, "call_apply"), (157, "jump"),
+            (163, "set_property"), (176, "less_than_alt"), (179, "try_catch"), (181, "xor"), (188, "init_memory"),
+            (213, "shr"), (220, "mod"), (225, "strict_equal"), (230, "halt"), (235, "new_function"),
+            (245, "load_imm8")
+        ]
+    },
+    Profile {
+        name: "build_b",
+        notes: "every default opcode byte XOR 0x5a",
+        pairs: &[
+            (0, "call_apply"), (2, "push_args"), (7, "jump"), (9, "jump_if_true"), (20, "equal"), (27, "or"),
+            (38, "init_memory"), (41, "try_catch"), (42, "less_than_alt"), (47, "xor"), (57, "set_property"),
+            (75, "ret"), (76, "not_equal"), (77, "new_value"), (78, "less_than"), (92, "mul"), (95, "throw"),
+            (105, "load_double"), (107, "jump_frame"), (109, "div"), (111, "shl"), (114, "ushr"),
+            (125, "jump_if_false"), (127, "and"), (134, "strict_not_equal"), (140, "lte_alt"), (141, "call_function"),
+            (161, "get_property"), (169, "add"), (171, "mov_imm32"), (173, "lte"), (188, "sub"), (198, "mod"),
+            (207, "shr"), (239, "load_imm8"), (241, "new_function"), (251, "strict_equal"), (252, "halt")
+        ]
+    }
+];
+
+/// Every built-in profile, `default` first.
+pub fn builtin() -> Result<Vec<OpcodeTable>, DisassemblerError> {
+    let mut tables: Vec<OpcodeTable> = vec![OpcodeTable::default_build()];
+    for profile in PROFILES {
+        tables.push(profile.table()?);
+    }
+    Ok(tables)
+}
+
+/// How cleanly a profile decodes the start of a sample.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileScore {
+    pub name: String,
+    /// Share of the window decoded before the first unknown opcode or malformed instruction.
+    pub clean: f32,
+    /// `OpcodeTable::coverage` of the window.
+    pub coverage: f32,
+    /// Share of the string characters decoded on the way that are printable.
+    pub printable: f32,
+    /// The mean of `clean` and `coverage`, scaled by `printable`. 1 for a profile that decodes the whole
+    /// window into printable strings.
+    pub score: f32
+}
+
+/// Scores `table` on `window` bytes of `bytes` from `start`.
+pub fn score(table: &OpcodeTable, bytes: &[u8], start: usize, window: usize, string_key: u8) -> Result<ProfileScore, DisassemblerError> {
+    let end: usize = bytes.len().min(start.saturating_add(window));
+    let mut disasm: Disassembler = Disassembler::from_bytes(bytes.to_vec());
+    disasm.set_range(start, Some(end))?;
+    disasm.set_table(table.clone());
+    disasm.string_key = string_key;
+    disasm.registers = RegisterTracker::new(TrackingMode::Off);
+    disasm.unknown_policy = UnknownOpcodePolicy::FailFast;
+
+    let mut decoded: usize = end - start;
+    loop {
+        match disasm.step() {
+            Ok(Some(_)) => {
+                disasm.trace.clear();
+                disasm.instructions.clear();
+            }
+            Ok(None) => break,
+            Err(_) => {
+                decoded = disasm.position() - start;
+                break;
+            }
+        }
+    }
+    let clean: f32 = decoded as f32 / (end - start) as f32;
+    let coverage: f32 = table.coverage(&bytes[start..end]);
+    let printable: f32 = 1.0 - disasm.unprintable_ratio().unwrap_or(0.0) as f32;
+    Ok(ProfileScore { name: table.name.clone(), clean, coverage, printable, score: printable * (clean + coverage) / 2.0 })
+}
+
+/// Scores every candidate, best first; candidates that score the same keep their order.
+pub fn detect(candidates: &[OpcodeTable], bytes: &[u8], start: usize, window: usize, string_key: u8) -> Result<Vec<ProfileScore>, DisassemblerError> {
+    let mut scores: Vec<ProfileScore> = candidates
+        .iter()
+        .map(|table| score(table, bytes, start, window, string_key))
+        .collect::<Result<_, _>>()?;
+    scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassembler::DEFAULT_STRING_KEY;
+    use crate::disassembler::instructions::DEFAULT_PROFILE;
+    use crate::disassembler::opcodes::OpCodes;
+    use crate::disassembler::testing::run;
+
+    /// NEW VALUE "ok" -> reg1, LOAD IMM8 7 -> reg2, ADD reg3 = reg1 + reg2, HALT.
+    const OPCODES: [OpCodes; 4] = [OpCodes::NewValue, OpCodes::LoadImm8, OpCodes::Add, OpCodes::Halt];
+    const BUILD_A: [u8; 14] = [87, 1, 0, 2, 93, 89, 245, 2, 7, 51, 3, 1, 2, 230];
+    const BUILD_B: [u8; 14] = [77, 1, 0, 2, 93, 89, 239, 2, 7, 169, 3, 1, 2, 252];
+
+    fn profile(name: &str) -> OpcodeTable {
+        PROFILES.iter().find(|profile| profile.name == name).unwrap().table().unwrap()
+    }
+
+    fn check_fixture(name: &str, bytes: &[u8]) {
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes.to_vec());
+        disasm.set_table(profile(name));
+        run(&mut disasm);
+        let opcodes: Vec<OpCodes> = disasm.instructions.iter().map(|instruction| instruction.opcode).collect();
+        assert_eq!(opcodes, OPCODES);
+
+        let scores: Vec<ProfileScore> = detect(&builtin().unwrap(), bytes, 0, DETECT_WINDOW, DEFAULT_STRING_KEY).unwrap();
+        assert_eq!(scores[0].name, name);
+        assert_eq!(scores[0].score, 1.0);
+        assert!(scores[1].score < 1.0);
+    }
+
+    #[test]
+    fn builtin_profiles_are_named_and_complete() {
+        let tables: Vec<OpcodeTable> = builtin().unwrap();
+        let names: Vec<&str> = tables.iter().map(|table| table.name.as_str()).collect();
+        assert_eq!(names, [DEFAULT_PROFILE, "build_a", "build_b"]);
+        for table in &tables {
+            assert_eq!(table.len(), OpcodeTable::default().len(), "{}", table.name);
+        }
+    }
+
+    #[test]
+    fn build_a_decodes_its_fixture() {
+        check_fixture("build_a", &BUILD_A);
+    }
+
+    #[test]
+    fn build_b_decodes_its_fixture() {
+        check_fixture("build_b", &BUILD_B);
+    }
+
+    #[test]
+    fn detect_prefers_the_map_that_decodes_the_sample() {
+        // LOAD IMM8 7 -> reg1 and HALT under a build that moved them to bytes 1 and 2.
+        let bytes: Vec<u8> = [1, 1, 7].repeat(4).into_iter().chain([2]).collect();
+        let moved: OpcodeTable = OpcodeTable::from_pairs(&[(1, "load_imm8"), (2, "halt")]).unwrap().with_name("moved", None);
+        let mut candidates: Vec<OpcodeTable> = builtin().unwrap();
+        candidates.push(moved);
+        let scores: Vec<ProfileScore> = detect(&candidates, &bytes, 0, DETECT_WINDOW, DEFAULT_STRING_KEY).unwrap();
+        assert_eq!(scores[0].name, "moved");
+        assert_eq!(scores[0].score, 1.0);
+        assert!(scores[1..].iter().all(|score| score.clean < 1.0));
+    }
+}
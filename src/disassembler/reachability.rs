@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+use crate::disassembler::cfg::{BlockId, Cfg};
+use crate::disassembler::functions::Functions;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+
+/// A run of adjacent instructions that no entry reaches.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeadRegion {
+    pub start: usize,
+    /// Offset just past the last instruction.
+    pub end: usize,
+    pub instructions: usize
+}
+
+impl DeadRegion {
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// Which instructions of a listing can run. Execution is followed over every `Cfg` edge, including the
+/// catch and finally offsets of TRY CATCH, from the listing's first instruction and from every function
+/// entry. DB bytes are not counted as dead code: `Listing::unknown_regions` already reports them.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reachability {
+    reachable: BTreeSet<usize>,
+    /// Sorted by start.
+    pub regions: Vec<DeadRegion>
+}
+
+impl Reachability {
+    pub fn new(listing: &Listing) -> Self {
+        let cfg: Cfg = Cfg::new(listing);
+        let functions: Functions = listing.functions();
+        let mut roots: Vec<usize> = functions.functions.iter().map(|function| function.entry).collect();
+        roots.extend(listing.instructions.first().map(|instruction| instruction.offset));
+
+        let mut seen: BTreeSet<BlockId> = BTreeSet::new();
+        let mut queue: Vec<BlockId> = roots.into_iter().filter_map(|offset| cfg.block_at(offset)).collect();
+        while let Some(block) = queue.pop() {
+            if seen.insert(block) {
+                queue.extend(cfg.successors(block).map(|edge| edge.to));
+            }
+        }
+        let reachable: BTreeSet<usize> = seen
+            .iter()
+            .flat_map(|&block| cfg.blocks()[block].instructions.iter().map(|instruction| instruction.offset))
+            .collect();
+
+        let mut instructions: Vec<&Instruction> = listing.instructions.iter().collect();
+        instructions.sort_by_key(|instruction| instruction.offset);
+        instructions.dedup_by_key(|instruction| instruction.offset);
+        let mut regions: Vec<DeadRegion> = Vec::new();
+        for instruction in instructions {
+            if reachable.contains(&instruction.offset) || instruction.opcode == OpCodes::Db {
+                continue;
+            }
+            match regions.last_mut() {
+                Some(region) if region.end == instruction.offset => {
+                    region.end = instruction.end();
+                    region.instructions += 1;
+                }
+                _ => regions.push(DeadRegion { start: instruction.offset, end: instruction.end(), instructions: 1 })
+            }
+        }
+        Self { reachable, regions }
+    }
+
+    pub fn is_reachable(&self, offset: usize) -> bool {
+        self.reachable.contains(&offset)
+    }
+
+    /// Whether the instruction at `offset` is dead code, i.e. inside one of `regions`.
+    pub fn is_dead(&self, offset: usize) -> bool {
+        let index: usize = self.regions.partition_point(|region| region.start <= offset);
+        index.checked_sub(1).is_some_and(|index| offset < self.regions[index].end)
+    }
+
+    pub fn dead_bytes(&self) -> usize {
+        self.regions.iter().map(DeadRegion::size).sum()
+    }
+
+    /// The biggest region, the first one on ties.
+    pub fn largest(&self) -> Option<&DeadRegion> {
+        self.regions.iter().rev().max_by_key(|region| region.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::decode;
+
+    #[test]
+    fn code_after_a_jump_or_halt_is_dead_unless_it_is_an_entry() {
+        // JUMP 11; LOAD IMM8 1 -> reg1; LOAD IMM8 2 -> reg2; NEW FUNCTION reg3 for 22; HALT; LOAD IMM8 4 -> reg4;
+        // RET reg4 [] at 22.
+        let bytes: Vec<u8> = vec![93, 0, 0, 0, 11, 181, 1, 1, 181, 2, 2, 171, 3, 0, 0, 0, 22, 0, 166, 181, 4, 4, 17, 4, 0];
+        let listing: Listing = decode(bytes);
+        let reachability: Reachability = Reachability::new(&listing);
+        assert_eq!(
+            reachability.regions,
+            [DeadRegion { start: 5, end: 11, instructions: 2 }, DeadRegion { start: 19, end: 22, instructions: 1 }]
+        );
+        assert_eq!(reachability.dead_bytes(), 9);
+        assert_eq!(reachability.largest().map(|region| region.start), Some(5));
+        assert!(reachability.is_dead(8));
+        assert!(!reachability.is_dead(18));
+        assert!(reachability.is_reachable(22));
+    }
+}
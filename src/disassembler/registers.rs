@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use log::warn;
+use crate::disassembler::codec::{escape_string, format_double, quote_string};
+use crate::disassembler::follow::falls_through;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::{Access, Operand};
+
+pub const DEFAULT_REGISTER_COUNT: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrackingMode {
+    Off,
+    Strings,
+    Constants
+}
+
+impl TrackingMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(TrackingMode::Off),
+            "strings" => Some(TrackingMode::Strings),
+            "constants" => Some(TrackingMode::Constants),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegValue {
+    Str(String),
+    Int(u32),
+    Double(#[cfg_attr(feature = "serde", serde(with = "crate::disassembler::operands::double"))] f64)
+}
+
+impl RegValue {
+    pub fn render(&self, raw_strings: bool) -> String {
+        match self {
+            RegValue::Str(value) if raw_strings => value.clone(),
+            RegValue::Str(value) => escape_string(value),
+            RegValue::Int(value) => value.to_string(),
+            RegValue::Double(value) => format_double(*value)
+        }
+    }
+
+    /// Like `render`, with strings in double quotes so a key can't be taken for an identifier.
+    pub fn quoted(&self, raw_strings: bool) -> String {
+        match self {
+            RegValue::Str(value) if raw_strings => format!("\"{value}\""),
+            RegValue::Str(value) => quote_string(value),
+            value => value.render(raw_strings)
+        }
+    }
+}
+
+/// How a register got its value: a GET PROPERTY of `object` with a tracked `key`, as in `reg2["atob"]`.
+/// Forgotten once the register or `object` is overwritten.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Origin {
+    pub object: u8,
+    pub key: RegValue
+}
+
+impl fmt::Display for RegValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+pub(crate) fn destination(instruction: &Instruction) -> Option<u8> {
+    instruction.accesses().find_map(|(reg, access)| (access == Access::Def).then_some(reg))
+}
+
+// Jump targets mapped to the number of JUMP, JUMP IF FALSE and JUMP IF TRUE
+// edges into them, or to None when a call, function or try/catch edge also
+// lands there.
+pub type Labels = BTreeMap<usize, Option<usize>>;
+
+pub fn count_edges(labels: &mut Labels, instruction: &Instruction) {
+    let jump: bool = matches!(instruction.opcode, OpCodes::Jump | OpCodes::JumpIfFalse | OpCodes::JumpIfTrue);
+    for target in instruction.targets() {
+        let edges: &mut Option<usize> = labels.entry(target as usize).or_insert(Some(0));
+        *edges = edges.filter(|_| jump).map(|edges| edges + 1);
+    }
+}
+
+fn meet<T: PartialEq>(values: &mut [Option<T>], other: &[Option<T>]) {
+    for (reg, value) in values.iter_mut().enumerate() {
+        if value.as_ref() != other.get(reg).and_then(Option::as_ref) {
+            *value = None;
+        }
+    }
+}
+
+fn number(value: &RegValue) -> Option<f64> {
+    match value {
+        RegValue::Int(value) => Some(*value as f64),
+        RegValue::Double(value) => Some(*value),
+        RegValue::Str(_) => None
+    }
+}
+
+/// JavaScript's ToUint32: NaN and the infinities become 0, everything else wraps modulo 2^32.
+pub(crate) fn to_uint32(value: f64) -> u32 {
+    if value.is_finite() { value.trunc().rem_euclid(4294967296.0) as u32 } else { 0 }
+}
+
+fn to_int32(value: f64) -> i32 {
+    to_uint32(value) as i32
+}
+
+/// `lhs <op> rhs` with JavaScript number semantics, for the arithmetic and bitwise opcodes.
+pub(crate) fn evaluate(opcode: OpCodes, lhs: f64, rhs: f64) -> Option<f64> {
+    let shift: u32 = to_uint32(rhs) & 31;
+    Some(match opcode {
+        OpCodes::Add => lhs + rhs,
+        OpCodes::Sub => lhs - rhs,
+        OpCodes::Mul => lhs * rhs,
+        OpCodes::Div => lhs / rhs,
+        OpCodes::Mod => lhs % rhs,
+        OpCodes::And => (to_int32(lhs) & to_int32(rhs)) as f64,
+        OpCodes::Or => (to_int32(lhs) | to_int32(rhs)) as f64,
+        OpCodes::Xor => (to_int32(lhs) ^ to_int32(rhs)) as f64,
+        OpCodes::Shl => to_int32(lhs).wrapping_shl(shift) as f64,
+        OpCodes::Shr => (to_int32(lhs) >> shift) as f64,
+        OpCodes::Ushr => (to_uint32(lhs) >> shift) as f64,
+        _ => return None
+    })
+}
+
+fn registers(instruction: &Instruction) -> impl Iterator<Item = u8> + '_ {
+    instruction.operands.iter().flat_map(|operand| match operand {
+        Operand::Reg(reg) => std::slice::from_ref(reg),
+        Operand::RegList(regs) => regs.as_slice(),
+        _ => &[]
+    }).copied()
+}
+
+/// What the forward jumps into a target agree on, and how many of them have been seen.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pending {
+    pub values: Vec<Option<RegValue>>,
+    pub origins: Vec<Option<Origin>>,
+    pub seen: usize
+}
+
+pub struct RegisterTracker {
+    pub mode: TrackingMode,
+    pub naive: bool,
+    /// Whether arithmetic on two known constants stores its result (Constants mode only).
+    pub fold: bool,
+    values: Vec<Option<RegValue>>,
+    /// Parallel to `values`.
+    origins: Vec<Option<Origin>>,
+    max_register: Option<u8>,
+    labels: Option<Labels>,
+    pending: BTreeMap<usize, Pending>,
+    previous: Option<(usize, bool)>
+}
+
+impl RegisterTracker {
+    pub fn new(mode: TrackingMode) -> Self {
+        Self::with_count(mode, DEFAULT_REGISTER_COUNT)
+    }
+
+    /// A tracker whose register file starts with `count` registers; it grows if a higher one is written.
+    pub fn with_count(mode: TrackingMode, count: usize) -> Self {
+        Self {
+            mode,
+            naive: false,
+            fold: false,
+            values: vec![None; count],
+            origins: vec![None; count],
+            max_register: None,
+            labels: None,
+            pending: BTreeMap::new(),
+            previous: None
+        }
+    }
+
+    pub fn from_values(mode: TrackingMode, values: Vec<Option<RegValue>>) -> Self {
+        let mut tracker: RegisterTracker = Self::with_count(mode, values.len().max(DEFAULT_REGISTER_COUNT));
+        for (slot, value) in tracker.values.iter_mut().zip(values) {
+            *slot = value;
+        }
+        tracker
+    }
+
+    /// Highest register any observed instruction read or wrote.
+    pub fn max_register(&self) -> Option<u8> {
+        self.max_register
+    }
+
+    fn grow(&mut self, reg: u8) {
+        let index: usize = reg as usize;
+        if index >= self.values.len() {
+            warn!("reg{reg} is past the {}-register file, growing it", self.values.len());
+            self.values.resize(index + 1, None);
+        }
+        if index >= self.origins.len() {
+            self.origins.resize(self.values.len(), None);
+        }
+    }
+
+    fn slot(&mut self, reg: u8) -> &mut Option<RegValue> {
+        self.grow(reg);
+        &mut self.values[reg as usize]
+    }
+
+    /// Forgets where `reg` came from, and every origin that reads `reg` as its object.
+    fn overwrite(&mut self, reg: u8) {
+        self.grow(reg);
+        self.origins[reg as usize] = None;
+        for origin in &mut self.origins {
+            if origin.as_ref().is_some_and(|origin| origin.object == reg) {
+                *origin = None;
+            }
+        }
+    }
+
+    pub fn values(&self) -> &[Option<RegValue>] {
+        &self.values
+    }
+
+    pub fn origins(&self) -> &[Option<Origin>] {
+        &self.origins
+    }
+
+    /// Merges waiting at forward jump targets, keyed by target offset.
+    pub fn pending(&self) -> &BTreeMap<usize, Pending> {
+        &self.pending
+    }
+
+    /// End of the last observed instruction and whether it falls through to the next one.
+    pub fn previous(&self) -> Option<(usize, bool)> {
+        self.previous
+    }
+
+    /// Puts back the origins and control-flow state a snapshot took, after `from_values`.
+    pub fn restore_flow(&mut self, origins: Vec<Option<Origin>>, pending: BTreeMap<usize, Pending>, previous: Option<(usize, bool)>) {
+        for (slot, origin) in self.origins.iter_mut().zip(origins) {
+            *slot = origin;
+        }
+        self.pending = pending;
+        self.previous = previous;
+    }
+
+    pub fn value(&self, reg: u8) -> Option<&RegValue> {
+        self.values.get(reg as usize).and_then(Option::as_ref)
+    }
+
+    pub fn origin(&self, reg: u8) -> Option<&Origin> {
+        self.origins.get(reg as usize).and_then(Option::as_ref)
+    }
+
+    pub fn live(&self) -> Vec<(u8, &RegValue)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(reg, value)| value.as_ref().map(|value| (reg as u8, value)))
+            .collect()
+    }
+
+    pub fn needs_labels(&self) -> bool {
+        self.mode != TrackingMode::Off && !self.naive && self.labels.is_none()
+    }
+
+    pub fn set_labels(&mut self, labels: Labels) {
+        self.labels = Some(labels);
+    }
+
+    pub fn enter(&mut self, offset: usize) {
+        let (Some(labels), Some((end, falls_through))) = (&self.labels, self.previous) else {
+            return;
+        };
+        let linear: bool = falls_through && end == offset;
+        let incoming: Option<Pending> = match (labels.get(&offset), self.pending.remove(&offset)) {
+            (None, _) if linear => return,
+            (Some(Some(edges)), Some(pending)) if pending.seen == *edges => Some(pending),
+            _ => None
+        };
+
+        match incoming {
+            Some(incoming) if linear => {
+                meet(&mut self.values, &incoming.values);
+                meet(&mut self.origins, &incoming.origins);
+            }
+            Some(Pending { mut values, mut origins, .. }) if end == offset => {
+                values.resize(self.values.len().max(values.len()), None);
+                origins.resize(values.len(), None);
+                self.values = values;
+                self.origins = origins;
+            }
+            _ => {
+                self.values.fill(None);
+                self.origins.fill(None);
+            }
+        }
+    }
+
+    pub fn resolve(&self, operands: &[Operand]) -> Vec<Option<RegValue>> {
+        if self.mode == TrackingMode::Off {
+            return vec![None; operands.len()];
+        }
+        operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Reg(reg) => self.value(*reg).cloned(),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// The origin of each register operand, like `resolve`; empty when no operand has one.
+    pub fn resolve_origins(&self, operands: &[Operand]) -> Vec<Option<Origin>> {
+        let origins: Vec<Option<Origin>> = operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Reg(reg) if self.mode != TrackingMode::Off => self.origin(*reg).cloned(),
+                _ => None
+            })
+            .collect();
+        if origins.iter().all(Option::is_none) { Vec::new() } else { origins }
+    }
+
+    /// The value an arithmetic or bitwise instruction computes when folding is on and both of its
+    /// inputs are tracked numbers. Whole results that fit a u32 are kept as `Int`, the rest as `Double`.
+    pub fn fold(&self, instruction: &Instruction) -> Option<RegValue> {
+        if !self.fold || self.mode != TrackingMode::Constants {
+            return None;
+        }
+        let [_, Operand::Reg(lhs), Operand::Reg(rhs)] = instruction.operands.as_slice() else {
+            return None;
+        };
+        let result: f64 = evaluate(instruction.opcode, number(self.value(*lhs)?)?, number(self.value(*rhs)?)?)?;
+        if result.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&result) && !(result == 0.0 && result.is_sign_negative()) {
+            Some(RegValue::Int(result as u32))
+        } else {
+            Some(RegValue::Double(result))
+        }
+    }
+
+    pub fn observe(&mut self, instruction: &Instruction) {
+        let highest: Option<u8> = registers(instruction).chain(destination(instruction)).max();
+        self.max_register = self.max_register.max(highest);
+        if self.mode == TrackingMode::Off {
+            return;
+        }
+        let folded: Option<RegValue> = self.fold(instruction);
+        let origin: Option<Origin> = match (instruction.opcode, instruction.operands.as_slice()) {
+            (OpCodes::GetProperty, [Operand::Reg(reg), Operand::Reg(object), Operand::Reg(key)]) if reg != object => {
+                self.value(*key).map(|key| Origin { object: *object, key: key.clone() })
+            }
+            _ => None
+        };
+        if let Some(reg) = destination(instruction) {
+            *self.slot(reg) = None;
+            self.overwrite(reg);
+            self.origins[reg as usize] = origin;
+        }
+
+        let constants: bool = self.mode == TrackingMode::Constants;
+        let loaded: Option<(u8, RegValue)> = match (instruction.opcode, instruction.operands.as_slice()) {
+            (_, [Operand::Reg(reg), ..]) if folded.is_some() => folded.map(|value| (*reg, value)),
+            (OpCodes::NewValue, [Operand::Reg(reg), Operand::Str(value, _)]) => Some((*reg, RegValue::Str(value.clone()))),
+            (OpCodes::MovImm32, [Operand::Reg(reg), Operand::Int(value)]) if constants => Some((*reg, RegValue::Int(*value))),
+            (OpCodes::LoadImm8, [Operand::Reg(reg), Operand::Byte(value)]) if constants => Some((*reg, RegValue::Int(*value as u32))),
+            (OpCodes::LoadDouble, [Operand::Reg(reg), Operand::Double(value)]) if constants => Some((*reg, RegValue::Double(*value))),
+            _ => None
+        };
+        if let Some((reg, value)) = loaded {
+            *self.slot(reg) = Some(value);
+        }
+
+        if self.naive || self.labels.is_none() {
+            return;
+        }
+        if matches!(instruction.opcode, OpCodes::Jump | OpCodes::JumpIfFalse | OpCodes::JumpIfTrue) {
+            for target in instruction.targets().filter(|&target| target as usize > instruction.offset) {
+                match self.pending.get_mut(&(target as usize)) {
+                    Some(pending) => {
+                        meet(&mut pending.values, &self.values);
+                        meet(&mut pending.origins, &self.origins);
+                        pending.seen += 1;
+                    }
+                    None => {
+                        self.pending.insert(target as usize, Pending { values: self.values.clone(), origins: self.origins.clone(), seen: 1 });
+                    }
+                }
+            }
+        }
+        self.previous = Some((instruction.end(), falls_through(instruction.opcode)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassembler::Disassembler;
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::{new_value, run};
+
+    /// reg1 = 'a', reg2 = 'k'; JUMP IF FALSE reg1 over `reg2 = 'j'`; then GET PROPERTY reg3, reg1, reg2 at the join.
+    fn program(skipped: &str) -> Vec<u8> {
+        let mut bytes: Vec<u8> = new_value(1, "a");
+        bytes.extend(new_value(2, "k"));
+        let join: u8 = (bytes.len() + 6 + 4 + skipped.len()) as u8;
+        bytes.extend([39, 1, 0, 0, 0, join]);
+        bytes.extend(new_value(2, skipped));
+        bytes.extend([251, 3, 1, 2, 166]);
+        bytes
+    }
+
+    fn join_operands(bytes: Vec<u8>, naive: bool) -> Vec<Option<RegValue>> {
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes);
+        disasm.registers.naive = naive;
+        let listing: Listing = run(&mut disasm);
+        let read: &Instruction = listing.instructions.iter().find(|instruction| instruction.opcode == OpCodes::GetProperty).unwrap();
+        read.resolved.clone()
+    }
+
+    fn string(value: &str) -> Option<RegValue> {
+        Some(RegValue::Str(value.to_string()))
+    }
+
+    #[test]
+    fn join_keeps_values_both_paths_agree_on() {
+        assert_eq!(join_operands(program("k"), false), [None, string("a"), string("k")]);
+    }
+
+    #[test]
+    fn join_forgets_values_the_paths_disagree_on() {
+        assert_eq!(join_operands(program("j"), false), [None, string("a"), None]);
+    }
+
+    #[test]
+    fn naive_tracking_keeps_the_fall_through_value() {
+        assert_eq!(join_operands(program("j"), true), [None, string("a"), string("j")]);
+    }
+
+    /// Decodes `bytes` with constant folding on, returning each instruction's comment.
+    fn folded(bytes: Vec<u8>) -> (Vec<Option<String>>, RegisterTracker) {
+        let mut disasm: Disassembler = Disassembler::from_bytes(bytes);
+        disasm.registers = RegisterTracker::new(TrackingMode::Constants);
+        disasm.registers.fold = true;
+        let listing: Listing = run(&mut disasm);
+        let comments: Vec<Option<String>> = listing.instructions.into_iter().map(|instruction| instruction.comment).collect();
+        (comments, disasm.registers)
+    }
+
+    #[test]
+    fn evaluate_follows_javascript_number_semantics() {
+        assert_eq!(evaluate(OpCodes::Or, 4294967297.0, 0.0), Some(1.0));
+        assert_eq!(evaluate(OpCodes::Or, 2147483648.0, 0.0), Some(-2147483648.0));
+        assert_eq!(evaluate(OpCodes::Ushr, -1.0, 0.0), Some(4294967295.0));
+        assert_eq!(evaluate(OpCodes::Div, 7.0, 2.0), Some(3.5));
+        // Shift counts only use their low five bits.
+        assert_eq!(evaluate(OpCodes::Shl, 1.0, 33.0), Some(2.0));
+        assert_eq!(evaluate(OpCodes::Shr, -8.0, 33.0), Some(-4.0));
+        assert_eq!(evaluate(OpCodes::Ushr, 8.0, 32.0), Some(8.0));
+        assert_eq!(evaluate(OpCodes::StrictEqual, 1.0, 1.0), None);
+    }
+
+    #[test]
+    fn fold_keeps_whole_u32_results_as_ints_and_the_rest_as_doubles() {
+        // LOAD IMM8 7 -> reg1; LOAD IMM8 2 -> reg2; DIV reg1 / reg2 -> reg3; OR reg1 | reg2 -> reg4;
+        // MOV IMM32 0xffffffff -> reg5; LOAD IMM8 0 -> reg6; USHR reg5 >>> reg6 -> reg7; OR reg5 | reg6 -> reg8; HALT.
+        let bytes: Vec<u8> = vec![
+            181, 1, 7, 181, 2, 2, 55, 3, 1, 2, 65, 4, 1, 2, 241, 5, 255, 255, 255, 255, 181, 6, 0, 40, 7, 5, 6, 65, 8, 5, 6, 166
+        ];
+        let (comments, registers): (Vec<Option<String>>, RegisterTracker) = folded(bytes);
+        assert_eq!(comments[2].as_deref(), Some("= 3.5"));
+        assert_eq!(registers.value(3), Some(&RegValue::Double(3.5)));
+        assert_eq!(registers.value(4), Some(&RegValue::Int(7)));
+        assert_eq!(registers.value(7), Some(&RegValue::Int(4294967295)));
+        assert_eq!(registers.value(8), Some(&RegValue::Double(-1.0)));
+    }
+
+    #[test]
+    fn fold_does_not_carry_values_into_a_loop_header() {
+        // LOAD IMM8 1 -> reg1; LOAD IMM8 2 -> reg2; ADD reg1 + reg2 -> reg1 at 6; JUMP IF TRUE reg1 6; HALT.
+        let bytes: Vec<u8> = vec![181, 1, 1, 181, 2, 2, 243, 1, 1, 2, 83, 1, 0, 0, 0, 6, 166];
+        let (comments, registers): (Vec<Option<String>>, RegisterTracker) = folded(bytes);
+        assert_eq!(comments[2], None);
+        assert_eq!(registers.value(1), None);
+    }
+
+    #[test]
+    fn fold_skips_values_the_paths_into_a_join_disagree_on() {
+        // LOAD IMM8 1 -> reg1; LOAD IMM8 2 -> reg2; JUMP IF FALSE reg1 15; LOAD IMM8 5 -> reg1;
+        // ADD reg1 + reg2 -> reg3 at 15; HALT.
+        let bytes: Vec<u8> = vec![181, 1, 1, 181, 2, 2, 39, 1, 0, 0, 0, 15, 181, 1, 5, 243, 3, 1, 2, 166];
+        let (comments, registers): (Vec<Option<String>>, RegisterTracker) = folded(bytes);
+        assert_eq!(comments[4], None);
+        assert_eq!(registers.value(2), Some(&RegValue::Int(2)));
+        assert_eq!(registers.value(3), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn non_finite_doubles_round_trip_through_json() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.0, 1.5] {
+            let origin: Origin = Origin { object: 2, key: RegValue::Double(value) };
+            let json: String = serde_json::to_string(&origin).unwrap();
+            let Origin { key: RegValue::Double(decoded), .. } = serde_json::from_str(&json).unwrap_or_else(|err| panic!("{json}: {err}")) else {
+                panic!("{json}: expected a double key");
+            };
+            assert_eq!(decoded.to_bits(), value.to_bits(), "{json}");
+        }
+        assert_eq!(serde_json::to_string(&RegValue::Double(f64::NAN)).unwrap(), r#"{"Double":"NaN"}"#);
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use crate::disassembler::error::DisassemblerError;
+use crate::disassembler::instruction::Instruction;
+
+pub trait InstructionSink {
+    fn on_instruction(&mut self, offset: usize, instruction: &Instruction) -> io::Result<()>;
+
+    fn on_error(&mut self, _error: &DisassemblerError) {}
+
+    fn on_finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct VecSink {
+    pub lines: Vec<String>
+}
+
+impl InstructionSink for VecSink {
+    fn on_instruction(&mut self, _offset: usize, instruction: &Instruction) -> io::Result<()> {
+        self.lines.push(instruction.to_string());
+        Ok(())
+    }
+}
+
+pub struct WriterSink<W: Write> {
+    out: W
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> InstructionSink for WriterSink<W> {
+    fn on_instruction(&mut self, offset: usize, instruction: &Instruction) -> io::Result<()> {
+        writeln!(self.out, "{offset:#06x}    {instruction}")
+    }
+
+    fn on_error(&mut self, _error: &DisassemblerError) {
+        let _ = self.out.flush();
+    }
+
+    fn on_finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+use crate::disassembler::disassembler::{Resync, UnknownOpcodePolicy};
+use crate::disassembler::instruction::TargetFormat;
+use crate::disassembler::registers::{Origin, Pending, RegValue, TrackingMode};
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisassemblerState {
+    /// Offset of the next instruction to decode.
+    pub ptr: usize,
+    /// End of the decoded range.
+    pub end: usize,
+    /// XOR key used to decode string constants.
+    pub string_key: u8,
+    /// How unknown opcode bytes are handled.
+    pub unknown_policy: UnknownOpcodePolicy,
+    /// How branch targets are rendered.
+    pub targets: TargetFormat,
+    /// Whether strings are rendered unescaped.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub raw_strings: bool,
+    /// Register tracking mode and the tracked value of each register.
+    pub tracking: TrackingMode,
+    pub registers: Vec<Option<RegValue>>,
+    /// Where each tracked value was read from, parallel to `registers`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub origins: Vec<Option<Origin>>,
+    /// Register values forward jumps carry to targets not decoded yet, and whether the last
+    /// instruction falls through, so merges at those targets come out as in one run.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pending: BTreeMap<usize, Pending>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub previous: Option<(usize, bool)>,
+    /// Whether tracked values survive jump targets and unconditional jumps.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub naive_tracking: bool,
+    /// Whether arithmetic on known constants is folded into the tracked values.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fold_constants: bool,
+    /// Unknown opcode bytes skipped so far in lenient mode.
+    pub unknown_skipped: usize,
+    /// Resynchronization settings for lenient mode, if enabled.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resync: Option<Resync>,
+    /// Decoded string characters, and how many of them were unprintable.
+    pub string_chars: usize,
+    pub unprintable_chars: usize,
+    /// JUMP FRAMEs decoded and not yet closed by a RET.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frames: usize,
+    /// Name of the opcode table the snapshot was decoded with; `restore` must be given the same one.
+    #[cfg_attr(feature = "serde", serde(default = "default_table"))]
+    pub table: String
+}
+
+#[cfg(feature = "serde")]
+fn default_table() -> String {
+    crate::disassembler::instructions::DEFAULT_PROFILE.to_string()
+}
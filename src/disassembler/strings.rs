@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::listing::Listing;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+use crate::disassembler::registers::{count_edges, Labels, RegValue, RegisterTracker, TrackingMode};
+
+/// Which operand of the consuming instruction held the string.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UseRole {
+    Object,
+    Key,
+    Value,
+    Callee,
+    This,
+    Argument
+}
+
+impl UseRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UseRole::Object => "object",
+            UseRole::Key => "key",
+            UseRole::Value => "value",
+            UseRole::Callee => "callee",
+            UseRole::This => "this",
+            UseRole::Argument => "argument"
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Definition {
+    /// Offset of the NEW VALUE.
+    pub offset: usize,
+    pub reg: u8
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Use {
+    /// Offset of the consuming instruction.
+    pub offset: usize,
+    pub opcode: OpCodes,
+    pub role: UseRole
+}
+
+impl fmt::Display for Use {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.opcode.handler_name(), self.role.as_str())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringEntry {
+    pub value: String,
+    pub bytes: Vec<u8>,
+    /// Every NEW VALUE loading these bytes, in listing order.
+    pub definitions: Vec<Definition>,
+    /// Every GET/SET PROPERTY, CALL FUNCTION and CALL APPLY reading a register known to hold it.
+    pub uses: Vec<Use>
+}
+
+fn roles(instruction: &Instruction) -> Vec<(u8, UseRole)> {
+    match (instruction.opcode, instruction.operands.as_slice()) {
+        (OpCodes::GetProperty, [_, Operand::Reg(obj), Operand::Reg(key)]) => vec![(*obj, UseRole::Object), (*key, UseRole::Key)],
+        (OpCodes::SetProperty, [Operand::Reg(obj), Operand::Reg(key), Operand::Reg(value)]) => {
+            vec![(*obj, UseRole::Object), (*key, UseRole::Key), (*value, UseRole::Value)]
+        }
+        (OpCodes::CallFunction, [_, Operand::Reg(callee), Operand::RegList(args)]) => {
+            std::iter::once((*callee, UseRole::Callee)).chain(args.iter().map(|arg| (*arg, UseRole::Argument))).collect()
+        }
+        (OpCodes::CallApply, [_, Operand::Reg(callee), Operand::Reg(this), Operand::RegList(args)]) => [(*callee, UseRole::Callee), (*this, UseRole::This)]
+            .into_iter()
+            .chain(args.iter().map(|arg| (*arg, UseRole::Argument)))
+            .collect(),
+        _ => Vec::new()
+    }
+}
+
+/// Every distinct string a listing loads, where it was loaded and where it was consumed. Uses are
+/// found with the same `RegisterTracker` rules as the listing's own substitutions, so a string
+/// survives straight-line code and joins where every incoming path agrees on it.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringTable {
+    /// In order of first definition.
+    pub entries: Vec<StringEntry>
+}
+
+impl StringTable {
+    pub fn new(listing: &Listing) -> Self {
+        let mut labels: Labels = Labels::new();
+        for instruction in &listing.instructions {
+            count_edges(&mut labels, instruction);
+        }
+        let mut tracker: RegisterTracker = RegisterTracker::new(TrackingMode::Strings);
+        tracker.set_labels(labels);
+
+        let mut table: StringTable = StringTable::default();
+        let mut by_bytes: HashMap<&[u8], usize> = HashMap::new();
+        let mut by_value: HashMap<&str, usize> = HashMap::new();
+        for instruction in &listing.instructions {
+            tracker.enter(instruction.offset);
+            for (reg, role) in roles(instruction) {
+                if let Some(RegValue::Str(value)) = tracker.value(reg)
+                    && let Some(&index) = by_value.get(value.as_str())
+                {
+                    table.entries[index].uses.push(Use { offset: instruction.offset, opcode: instruction.opcode, role });
+                }
+            }
+
+            if let (OpCodes::NewValue, [Operand::Reg(reg), Operand::Str(value, bytes)]) = (instruction.opcode, instruction.operands.as_slice()) {
+                let definition: Definition = Definition { offset: instruction.offset, reg: *reg };
+                match by_bytes.get(bytes.as_slice()) {
+                    Some(&index) => table.entries[index].definitions.push(definition),
+                    None => {
+                        by_bytes.insert(bytes, table.entries.len());
+                        by_value.entry(value).or_insert(table.entries.len());
+                        table.entries.push(StringEntry { value: value.clone(), bytes: bytes.clone(), definitions: vec![definition], uses: Vec::new() });
+                    }
+                }
+            }
+            tracker.observe(instruction);
+        }
+        table
+    }
+
+    pub fn get(&self, value: &str) -> Option<&StringEntry> {
+        self.entries.iter().find(|entry| entry.value == value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::testing::{decode, new_value};
+
+    #[test]
+    fn collects_definitions_and_uses_of_each_string() {
+        let bytes: Vec<u8> = [
+            new_value(1, "key"),
+            new_value(2, "val"),
+            vec![99, 0, 1, 2], // SET PROPERTY reg0[reg1] = reg2 at 14
+            new_value(3, "key"),
+            vec![251, 4, 0, 3, 215, 5, 4, 1, 2, 166] // GET PROPERTY reg0[reg3] -> reg4 at 25; CALL FUNCTION reg4(reg2) -> reg5 at 29; HALT
+        ]
+        .concat();
+        let listing: Listing = decode(bytes);
+        let table: StringTable = StringTable::new(&listing);
+        assert_eq!(table.len(), 2);
+
+        let key: &StringEntry = table.get("key").unwrap();
+        assert_eq!(key.definitions, [Definition { offset: 0, reg: 1 }, Definition { offset: 18, reg: 3 }]);
+        let uses: Vec<(usize, String)> = key.uses.iter().map(|usage| (usage.offset, usage.to_string())).collect();
+        assert_eq!(uses, [(14, "set_property.key".to_string()), (25, "get_property.key".to_string())]);
+
+        let value: &StringEntry = table.get("val").unwrap();
+        let roles: Vec<(usize, UseRole)> = value.uses.iter().map(|usage| (usage.offset, usage.role)).collect();
+        assert_eq!(roles, [(14, UseRole::Value), (29, UseRole::Argument)]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::disassembler::instruction::Instruction;
+use crate::disassembler::opcodes::OpCodes;
+use crate::disassembler::operands::Operand;
+
+/// Why an offset is labelled; when several apply, the first one here wins.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LabelKind {
+    Function,
+    Catch,
+    Finally,
+    Branch
+}
+
+impl LabelKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LabelKind::Function => "function",
+            LabelKind::Catch => "catch",
+            LabelKind::Finally => "finally",
+            LabelKind::Branch => "branch"
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            LabelKind::Function => "func",
+            LabelKind::Catch => "catch",
+            LabelKind::Finally => "finally",
+            LabelKind::Branch => "L"
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Label {
+    pub offset: usize,
+    pub kind: LabelKind,
+    pub name: String
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+fn target_kinds(instruction: &Instruction) -> Vec<(u32, LabelKind)> {
+    match (instruction.opcode, instruction.operands.as_slice()) {
+        (OpCodes::NewFunction, [_, Operand::Target(entry), ..]) | (OpCodes::JumpFrame, [Operand::Target(entry), ..]) => {
+            vec![(*entry, LabelKind::Function)]
+        }
+        (OpCodes::TryCatch, [_, Operand::Target(catch), Operand::Target(finally), Operand::Target(next)]) => {
+            vec![(*catch, LabelKind::Catch), (*finally, LabelKind::Finally), (*next, LabelKind::Branch)]
+        }
+        _ => instruction.targets().map(|target| (target, LabelKind::Branch)).collect()
+    }
+}
+
+/// Names for every branch, call and try/catch target that starts a decoded instruction.
+/// Targets inside an instruction or outside the listing get no label.
+#[derive(Clone, Default, Debug)]
+pub struct Symbols {
+    labels: BTreeMap<usize, Label>
+}
+
+impl Symbols {
+    pub fn new(instructions: &[Instruction]) -> Self {
+        let starts: BTreeSet<usize> = instructions.iter().map(|instruction| instruction.offset).collect();
+        let mut kinds: BTreeMap<usize, LabelKind> = BTreeMap::new();
+
+        for (target, kind) in instructions.iter().flat_map(target_kinds) {
+            let target: usize = target as usize;
+            if starts.contains(&target) {
+                let known: &mut LabelKind = kinds.entry(target).or_insert(kind);
+                *known = (*known).min(kind);
+            }
+        }
+
+        let labels: BTreeMap<usize, Label> = kinds
+            .into_iter()
+            .map(|(offset, kind)| (offset, Label { offset, kind, name: format!("{}_{offset:04x}", kind.prefix()) }))
+            .collect();
+        Self { labels }
+    }
+
+    pub fn get(&self, offset: usize) -> Option<&Label> {
+        self.labels.get(&offset)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &Label> {
+        self.labels.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    use crate::disassembler::listing::Listing;
+    use crate::disassembler::testing::decode;
+
+    #[test]
+    fn labels_targets_that_start_instructions() {
+        // JUMP IF FALSE reg1 29; JUMP IF TRUE reg1 22; JUMP 99; JUMP 18, inside itself; JUMP FRAME 22 at 22; HALT at 29.
+        let bytes: Vec<u8> = vec![
+            39, 1, 0, 0, 0, 29, 83, 1, 0, 0, 0, 22, 93, 0, 0, 0, 99, 93, 0, 0, 0, 18, 49, 0, 0, 0, 22, 0, 0, 166
+        ];
+        let listing: Listing = decode(bytes);
+        let symbols: Symbols = Symbols::new(&listing.instructions);
+        let labels: Vec<(usize, LabelKind, &str)> = symbols.labels().map(|label| (label.offset, label.kind, label.name.as_str())).collect();
+        // 22 is both a branch and a function target, and the function wins.
+        assert_eq!(labels, [(22, LabelKind::Function, "func_0016"), (29, LabelKind::Branch, "L_001d")]);
+        assert!(symbols.get(18).is_none());
+    }
+}
@@ -0,0 +1,25 @@
+use crate::disassembler::disassembler::{Disassembler, DEFAULT_STRING_KEY};
+use crate::disassembler::error::ExecuteError;
+use crate::disassembler::listing::Listing;
+
+/// Decodes `bytes` with the default table.
+pub(crate) fn decode(bytes: Vec<u8>) -> Listing {
+    run(&mut Disassembler::from_bytes(bytes))
+}
+
+/// Runs an already configured disassembler to the end.
+pub(crate) fn run(disasm: &mut Disassembler) -> Listing {
+    disasm.execute(|_| Ok(())).unwrap()
+}
+
+/// The error decoding `bytes` with the default table stops on.
+pub(crate) fn decode_err(bytes: Vec<u8>) -> ExecuteError {
+    Disassembler::from_bytes(bytes).execute(|_| Ok(())).unwrap_err()
+}
+
+/// NEW VALUE `text` -> `reg`, with `text` XORed with the default key.
+pub(crate) fn new_value(reg: u8, text: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![23, reg, 0, text.len() as u8];
+    bytes.extend(text.bytes().map(|byte| byte ^ DEFAULT_STRING_KEY));
+    bytes
+}
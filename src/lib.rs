@@ -0,0 +1,95 @@
+#[cfg(feature = "b64")]
+pub mod bytecode;
+pub mod disassembler;
+
+use std::iter::Enumerate;
+use std::sync::Mutex;
+use std::thread;
+use std::vec::IntoIter;
+use crate::disassembler::disassembler::Disassembler;
+use crate::disassembler::listing::Listing;
+#[cfg(feature = "b64")]
+use crate::disassembler::sink::VecSink;
+
+pub use crate::disassembler::error::DisassemblerError as Error;
+
+#[cfg(feature = "b64")]
+pub fn disassemble(bytecode_b64: &str) -> Result<Vec<String>, Error> {
+    let mut disasm: Disassembler = Disassembler::new(bytecode_b64.to_string(), None)?;
+    let mut sink: VecSink = VecSink::default();
+    disasm.execute_with(&mut sink)?;
+    Ok(sink.lines)
+}
+
+pub fn disassemble_many(inputs: Vec<Vec<u8>>, threads: usize) -> Vec<Result<Listing, Error>> {
+    let jobs: Mutex<Enumerate<IntoIter<Vec<u8>>>> = Mutex::new(inputs.into_iter().enumerate());
+    let results: Mutex<Vec<(usize, Result<Listing, Error>)>> = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                loop {
+                    let Some((index, bytes)) = jobs.lock().unwrap().next() else {
+                        break;
+                    };
+                    let mut disasm: Disassembler = Disassembler::from_bytes(bytes);
+                    let result: Result<Listing, Error> = disasm.execute(|_| Ok(())).map_err(Error::from);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results: Vec<(usize, Result<Listing, Error>)> = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::instruction::TargetFormat;
+
+    /// A LOAD IMM8, a NEW VALUE of `index % 7` characters and a HALT; every tenth blob ends in an unknown opcode instead.
+    fn blob(index: usize) -> Vec<u8> {
+        let text: Vec<u8> = (0..index % 7).map(|i| (b'a' + i as u8) ^ 50).collect();
+        let mut bytes: Vec<u8> = vec![181, (index % 200) as u8, index as u8, 23, 1, 0, text.len() as u8];
+        bytes.extend(text);
+        bytes.push(if index % 10 == 9 { 0 } else { 166 });
+        bytes
+    }
+
+    fn summary(result: &Result<Listing, Error>) -> String {
+        match result {
+            Ok(listing) => format!("{:?}", listing.instructions),
+            Err(err) => format!("error: {err}")
+        }
+    }
+
+    #[test]
+    fn disassemble_many_matches_sequential_runs() {
+        let blobs: Vec<Vec<u8>> = (0..100).map(blob).collect();
+        let sequential: Vec<String> = blobs
+            .iter()
+            .map(|bytes| summary(&Disassembler::from_bytes(bytes.clone()).execute(|_| Ok(())).map_err(Error::from)))
+            .collect();
+        let parallel: Vec<String> = disassemble_many(blobs, 8).iter().map(summary).collect();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.iter().filter(|summary| summary.starts_with("error")).count(), 10);
+    }
+
+    /// Runs under `--no-default-features` too, where it is the only way in.
+    #[test]
+    fn raw_bytes_need_no_features() {
+        let results: Vec<Result<Listing, Error>> = disassemble_many(vec![vec![181, 1, 7, 166]], 1);
+        let listing: &Listing = results[0].as_ref().unwrap();
+        assert_eq!(listing.instruction_count, 2);
+        assert_eq!(listing.instructions[0].text(TargetFormat::Hex, false), "LOAD IMM8 7 -> reg1");
+    }
+
+    #[cfg(feature = "b64")]
+    #[test]
+    fn disassemble_decodes_base64() {
+        assert_eq!(disassemble("tQEHpg==").unwrap(), ["LOAD IMM8 7 -> reg1", "HALT"]);
+    }
+}
@@ -1,15 +1,41 @@
-use std::fs;
-use std::time::Instant;
-mod disassembler;
-mod bytecode;
+use std::process;
+mod cli;
+
+use cli::args::{self, Args};
+use cli::error::CliError;
+use cli::input::Input;
+use cli::{interactive, run, watch};
 
 
 fn main() {
-    let bytec: String = fs::read_to_string("src/bytecode/bytecode.txt").expect("not found");
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("off")).init();
+
+    let args: Args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}");
+            eprintln!("run with --help for usage");
+            process::exit(2);
+        }
+    };
+
+    if args.help {
+        print!("{}", args::usage());
+        return;
+    }
+
+    let inputs: Vec<Input> = args.resolve_inputs();
 
-    let start = Instant::now();
-    let mut disasm: disassembler::disassembler::Disassembler = disassembler::disassembler::Disassembler::new(bytec);
-    disasm.execute();
+    let result: Result<(), CliError> = if args.interactive {
+        interactive::run(&args, &inputs[0])
+    } else if args.watch {
+        watch::watch(&args, &inputs[0])
+    } else {
+        run::run_all(&args, &inputs)
+    };
 
-    println!("disassemble took: {:?}", start.elapsed());
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        process::exit(err.exit_code());
+    }
 }